@@ -0,0 +1,480 @@
+//! writing the site to a single `.tar`, `.tar.gz`, or `.zip` file, for
+//! `autost render --archive` and `autost export`.
+//!
+//! archives are reproducible: entries are sorted by path, and have fixed timestamps, owners, and
+//! permissions, so the same site always makes the same archive.
+
+use std::{
+    borrow::Cow,
+    collections::BTreeMap,
+    fs::{read_dir, remove_file, rename, File},
+    io::{self, BufWriter, Write},
+    path::{Path, PathBuf},
+};
+
+use flate2::{
+    write::{DeflateEncoder, GzEncoder},
+    Compression,
+};
+use jane_eyre::eyre::{self, bail, Context, OptionExt};
+use tracing::info;
+
+use crate::path::SitePath;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ArchiveFormat {
+    Tar,
+    TarGz,
+    Zip,
+}
+
+impl ArchiveFormat {
+    /// returns the format for the given archive path, by its extension.
+    pub fn from_path(path: &Path) -> eyre::Result<Self> {
+        let filename = path
+            .file_name()
+            .and_then(|filename| filename.to_str())
+            .unwrap_or_default();
+        if filename.ends_with(".tar.gz") || filename.ends_with(".tgz") {
+            Ok(Self::TarGz)
+        } else if filename.ends_with(".tar.zst") || filename.ends_with(".tzst") {
+            // there’s no zstd compressor in our dependencies.
+            bail!("zstd archives are not supported, use .tar.gz instead: {path:?}")
+        } else if filename.ends_with(".tar") {
+            Ok(Self::Tar)
+        } else if filename.ends_with(".zip") {
+            Ok(Self::Zip)
+        } else {
+            bail!("archive must end with .tar, .tar.gz, .tgz, or .zip: {path:?}")
+        }
+    }
+}
+
+/// the files to put in an archive, by their paths in the archive, like `tagged/photography.html`.
+#[derive(Debug, Default)]
+pub struct ArchiveEntries {
+    inner: BTreeMap<String, ArchiveEntry>,
+}
+
+/// the content of a file in an archive.
+#[derive(Debug)]
+enum ArchiveEntry {
+    File(PathBuf),
+    Data(Vec<u8>),
+}
+
+impl ArchiveEntries {
+    pub fn insert(&mut self, path: &SitePath) {
+        self.insert_file(&path.rsync_deploy_line(), path);
+    }
+
+    /// adds a file at the given path in the archive, like `posts/1.md`, which must use `/` as the
+    /// separator.
+    pub fn insert_file(&mut self, archive_path: &str, fs_path: impl AsRef<Path>) {
+        self.inner.insert(
+            archive_path.to_owned(),
+            ArchiveEntry::File(fs_path.as_ref().to_owned()),
+        );
+    }
+
+    /// adds a file with the given content, like a manifest.
+    pub fn insert_data(&mut self, archive_path: &str, content: Vec<u8>) {
+        self.inner
+            .insert(archive_path.to_owned(), ArchiveEntry::Data(content));
+    }
+
+    /// adds every file in the given directory and its subdirectories.
+    pub fn insert_dir(&mut self, dir: &SitePath) -> eyre::Result<()> {
+        let mut dirs = vec![dir.clone()];
+        while let Some(dir) = dirs.pop() {
+            let Ok(entries) = read_dir(&dir) else {
+                continue;
+            };
+            for entry in entries {
+                let entry = entry?;
+                let path = dir.join_dir_entry(&entry)?;
+                // not `entry.metadata()`, which would not follow symlinks.
+                if path.as_ref().is_dir() {
+                    dirs.push(path);
+                } else {
+                    self.insert(&path);
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// writes the entries to an archive at `path`, in the format for its extension.
+///
+/// the archive is written to a temporary file next to it, then renamed into place, so if anything
+/// goes wrong, any archive that was already there is left alone.
+pub fn write_archive(path: &Path, entries: &ArchiveEntries) -> eyre::Result<()> {
+    let format = ArchiveFormat::from_path(path)?;
+    let filename = path
+        .file_name()
+        .ok_or_eyre("archive path has no filename")?
+        .to_string_lossy();
+    // same directory, so the rename never crosses filesystems.
+    let temp_path = path.with_file_name(format!(".{filename}.tmp"));
+    let result = (|| -> eyre::Result<()> {
+        let file =
+            File::create(&temp_path).wrap_err_with(|| format!("failed to create {temp_path:?}"))?;
+        let file = match format {
+            ArchiveFormat::Tar => write_tar(BufWriter::new(file), entries)?,
+            ArchiveFormat::TarGz => write_tar(
+                GzEncoder::new(BufWriter::new(file), Compression::default()),
+                entries,
+            )?
+            .finish()?,
+            ArchiveFormat::Zip => write_zip(BufWriter::new(file), entries)?,
+        };
+        file.into_inner()
+            .map_err(|error| error.into_error())?
+            .sync_all()?;
+        rename(&temp_path, path)
+            .wrap_err_with(|| format!("failed to rename {temp_path:?} to {path:?}"))?;
+        Ok(())
+    })();
+    if result.is_err() {
+        _ = remove_file(&temp_path);
+    }
+    result?;
+    info!("wrote {} files to {path:?}", entries.inner.len());
+
+    Ok(())
+}
+
+impl ArchiveEntry {
+    fn read(&self) -> io::Result<Cow<'_, [u8]>> {
+        match self {
+            Self::File(path) => Ok(Cow::Owned(std::fs::read(path)?)),
+            Self::Data(content) => Ok(Cow::Borrowed(content)),
+        }
+    }
+}
+
+/// the permissions of files in archives, with execute permission for `deploy.sh`.
+fn mode(path: &str) -> u32 {
+    if path == "deploy.sh" {
+        0o755
+    } else {
+        0o644
+    }
+}
+
+/// writes a ustar archive, returning the writer.
+fn write_tar<W: Write>(mut output: W, entries: &ArchiveEntries) -> eyre::Result<W> {
+    for (path, entry) in entries.inner.iter() {
+        let content = entry.read()?;
+        output.write_all(&tar_header(path, content.len() as u64)?)?;
+        output.write_all(&content)?;
+        let padding = (512 - content.len() % 512) % 512;
+        output.write_all(&vec![0; padding])?;
+    }
+    // two empty blocks mark the end of the archive.
+    output.write_all(&[0; 1024])?;
+
+    Ok(output)
+}
+
+fn tar_header(path: &str, size: u64) -> eyre::Result<[u8; 512]> {
+    // paths longer than 100 bytes are split into `prefix` and `name` at a slash.
+    let (prefix, name) = if path.len() <= 100 {
+        ("", path)
+    } else {
+        match path
+            .char_indices()
+            .filter(|&(i, c)| c == '/' && i <= 155 && path.len() - i - 1 <= 100)
+            .map(|(i, _)| (&path[..i], &path[i + 1..]))
+            .next()
+        {
+            Some(split) => split,
+            None => bail!("path is too long for a tar archive: {path:?}"),
+        }
+    };
+
+    let mut result = [0u8; 512];
+    let mut field =
+        |offset: usize, value: &[u8]| result[offset..][..value.len()].copy_from_slice(value);
+    field(0, name.as_bytes());
+    field(100, format!("{:07o}\0", mode(path)).as_bytes());
+    field(108, b"0000000\0"); // uid
+    field(116, b"0000000\0"); // gid
+    field(124, format!("{size:011o}\0").as_bytes());
+    field(136, b"00000000000\0"); // mtime
+    field(148, b"        "); // checksum, as spaces while computing it
+    field(156, b"0"); // regular file
+    field(257, b"ustar\0");
+    field(263, b"00");
+    field(345, prefix.as_bytes());
+    let checksum = result.iter().map(|&byte| u32::from(byte)).sum::<u32>();
+    result[148..][..8].copy_from_slice(format!("{checksum:06o}\0 ").as_bytes());
+
+    Ok(result)
+}
+
+/// writes a zip archive, with deflate compression for files that aren’t empty, returning the
+/// writer. zip64 fields are only used where they are needed, for sites with more than 65535
+/// files or more than 4 GiB of them.
+fn write_zip<W: Write>(output: W, entries: &ArchiveEntries) -> eyre::Result<W> {
+    // 1980-01-01 00:00:00, the earliest time in ms-dos format.
+    const DOS_TIME: u16 = 0;
+    const DOS_DATE: u16 = (1 << 5) | 1;
+    // the value of a field that is too big, and is in the zip64 extra field instead.
+    const ZIP64_U32: u32 = u32::MAX;
+    const ZIP64_U16: u16 = u16::MAX;
+
+    let mut output = CountingWriter {
+        inner: output,
+        count: 0,
+    };
+    let mut central_directory = vec![];
+    for (path, entry) in entries.inner.iter() {
+        let content = entry.read()?;
+        let crc = crc32fast::hash(&content);
+        // empty files are stored, since deflate would only make them bigger.
+        let (method, compressed) = if content.is_empty() {
+            (0u16, vec![])
+        } else {
+            let mut encoder = DeflateEncoder::new(vec![], Compression::default());
+            encoder.write_all(&content)?;
+            (8, encoder.finish()?)
+        };
+        let size = content.len() as u64;
+        let compressed_size = compressed.len() as u64;
+        let offset = output.count;
+        let name = path.as_bytes();
+        let name_len = u16::try_from(name.len())?;
+
+        // the local header has both sizes in its zip64 extra field, or neither.
+        let local_zip64 = size >= ZIP64_U32.into() || compressed_size >= ZIP64_U32.into();
+        let mut local_extra = vec![];
+        if local_zip64 {
+            local_extra.extend(1u16.to_le_bytes()); // zip64 extended information
+            local_extra.extend(16u16.to_le_bytes());
+            local_extra.extend(size.to_le_bytes());
+            local_extra.extend(compressed_size.to_le_bytes());
+        }
+        // the central directory header only has the fields that are too big.
+        let mut central_extra = vec![];
+        for value in [size, compressed_size, offset] {
+            if value >= ZIP64_U32.into() {
+                central_extra.extend(value.to_le_bytes());
+            }
+        }
+        if !central_extra.is_empty() {
+            let len = u16::try_from(central_extra.len())?.to_le_bytes();
+            central_extra.splice(0..0, [1, 0, len[0], len[1]]);
+        }
+        let version = if central_extra.is_empty() { 20u16 } else { 45 };
+        let narrow = |value: u64| u32::try_from(value).unwrap_or(ZIP64_U32);
+
+        let mut local = vec![];
+        local.extend(0x04034b50u32.to_le_bytes());
+        local.extend(version.to_le_bytes()); // version needed to extract (2.0, or 4.5 for zip64)
+        local.extend(0x0800u16.to_le_bytes()); // flags: names are utf-8
+        local.extend(method.to_le_bytes()); // compression method: stored or deflate
+        local.extend(DOS_TIME.to_le_bytes());
+        local.extend(DOS_DATE.to_le_bytes());
+        local.extend(crc.to_le_bytes());
+        let sizes_start = local.len();
+        match local_zip64 {
+            true => local.extend([0xFF; 8]),
+            false => {
+                local.extend(narrow(compressed_size).to_le_bytes());
+                local.extend(narrow(size).to_le_bytes());
+            }
+        }
+        local.extend(name_len.to_le_bytes());
+        local.extend(u16::try_from(local_extra.len())?.to_le_bytes());
+        local.extend(name);
+        local.extend(&local_extra);
+        output.write_all(&local)?;
+        output.write_all(&compressed)?;
+
+        central_directory.extend(0x02014b50u32.to_le_bytes());
+        central_directory.extend((3u16 << 8 | version).to_le_bytes()); // version made by (unix)
+        central_directory.extend(local[4..sizes_start].iter());
+        central_directory.extend(narrow(compressed_size).to_le_bytes());
+        central_directory.extend(narrow(size).to_le_bytes());
+        central_directory.extend(name_len.to_le_bytes());
+        central_directory.extend(u16::try_from(central_extra.len())?.to_le_bytes());
+        central_directory.extend(0u16.to_le_bytes()); // comment length
+        central_directory.extend(0u16.to_le_bytes()); // disk number
+        central_directory.extend(0u16.to_le_bytes()); // internal attributes
+        central_directory.extend(((0o100000 | mode(path)) << 16).to_le_bytes()); // unix mode
+        central_directory.extend(narrow(offset).to_le_bytes());
+        central_directory.extend(name);
+        central_directory.extend(&central_extra);
+    }
+
+    let count = entries.inner.len() as u64;
+    let size = central_directory.len() as u64;
+    let offset = output.count;
+    output.write_all(&central_directory)?;
+    let (narrow_count, narrow_size, narrow_offset) = (
+        u16::try_from(count).unwrap_or(ZIP64_U16),
+        u32::try_from(size).unwrap_or(ZIP64_U32),
+        u32::try_from(offset).unwrap_or(ZIP64_U32),
+    );
+    let mut end = vec![];
+    if narrow_count == ZIP64_U16 || narrow_size == ZIP64_U32 || narrow_offset == ZIP64_U32 {
+        let zip64_end_offset = output.count;
+        end.extend(0x06064b50u32.to_le_bytes());
+        end.extend(44u64.to_le_bytes()); // size of the rest of this record
+        end.extend((3u16 << 8 | 45).to_le_bytes()); // version made by (unix, 4.5)
+        end.extend(45u16.to_le_bytes()); // version needed to extract (4.5)
+        end.extend(0u32.to_le_bytes()); // this disk
+        end.extend(0u32.to_le_bytes()); // disk with the central directory
+        end.extend(count.to_le_bytes());
+        end.extend(count.to_le_bytes());
+        end.extend(size.to_le_bytes());
+        end.extend(offset.to_le_bytes());
+        end.extend(0x07064b50u32.to_le_bytes());
+        end.extend(0u32.to_le_bytes()); // disk with the zip64 end of central directory
+        end.extend(zip64_end_offset.to_le_bytes());
+        end.extend(1u32.to_le_bytes()); // number of disks
+    }
+    end.extend(0x06054b50u32.to_le_bytes());
+    end.extend(0u16.to_le_bytes()); // this disk
+    end.extend(0u16.to_le_bytes()); // disk with the central directory
+    end.extend(narrow_count.to_le_bytes());
+    end.extend(narrow_count.to_le_bytes());
+    end.extend(narrow_size.to_le_bytes());
+    end.extend(narrow_offset.to_le_bytes());
+    end.extend(0u16.to_le_bytes()); // comment length
+    output.write_all(&end)?;
+    output.flush()?;
+
+    Ok(output.inner)
+}
+
+/// counts the bytes written, for the offsets in zip files.
+struct CountingWriter<W> {
+    inner: W,
+    count: u64,
+}
+
+impl<W: Write> Write for CountingWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let result = self.inner.write(buf)?;
+        self.count += result as u64;
+        Ok(result)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+#[test]
+fn test_archive_format() -> eyre::Result<()> {
+    assert_eq!(
+        ArchiveFormat::from_path(Path::new("site.tar.gz"))?,
+        ArchiveFormat::TarGz
+    );
+    assert_eq!(
+        ArchiveFormat::from_path(Path::new("out/site.tgz"))?,
+        ArchiveFormat::TarGz
+    );
+    assert_eq!(
+        ArchiveFormat::from_path(Path::new("site.tar"))?,
+        ArchiveFormat::Tar
+    );
+    assert_eq!(
+        ArchiveFormat::from_path(Path::new("site.zip"))?,
+        ArchiveFormat::Zip
+    );
+    assert!(ArchiveFormat::from_path(Path::new("backup.tar.zst")).is_err());
+    assert!(ArchiveFormat::from_path(Path::new("site.rar")).is_err());
+
+    Ok(())
+}
+
+#[test]
+fn test_write_archive() -> eyre::Result<()> {
+    let dir = std::env::temp_dir().join(format!("autost-test-archive-{}", uuid::Uuid::new_v4()));
+    std::fs::create_dir_all(&dir)?;
+    let path = dir.join("backup.tar");
+    let mut entries = ArchiveEntries::default();
+    entries.insert_data("index.html", b"hello".to_vec());
+    write_archive(&path, &entries)?;
+    let old = std::fs::read(&path)?;
+    assert_eq!(old.len(), 512 * 4);
+
+    // if a file can’t be read, the old archive is left alone, with no temporary file.
+    entries.insert_file("missing.html", dir.join("missing.html"));
+    assert!(write_archive(&path, &entries).is_err());
+    assert_eq!(std::fs::read(&path)?, old);
+    assert_eq!(read_dir(&dir)?.count(), 1);
+
+    std::fs::remove_dir_all(&dir)?;
+    Ok(())
+}
+
+#[test]
+fn test_tar_header() -> eyre::Result<()> {
+    let header = tar_header("index.html", 1234)?;
+    assert_eq!(&header[..10], b"index.html");
+    assert_eq!(&header[124..136], b"00000002322\0");
+    assert_eq!(&header[257..263], b"ustar\0");
+    let checksum = header
+        .iter()
+        .enumerate()
+        .map(|(i, &byte)| {
+            if (148..156).contains(&i) {
+                32
+            } else {
+                u32::from(byte)
+            }
+        })
+        .sum::<u32>();
+    assert_eq!(&header[148..156], format!("{checksum:06o}\0 ").as_bytes());
+
+    // long paths are split at a slash.
+    let long = format!("attachments/{}/{}", "a".repeat(36), "b".repeat(80));
+    let header = tar_header(&long, 0)?;
+    assert_eq!(&header[..80], "b".repeat(80).as_bytes());
+    assert_eq!(
+        &header[345..][..48],
+        format!("attachments/{}", "a".repeat(36)).as_bytes()
+    );
+    assert!(tar_header(&"c".repeat(101), 0).is_err());
+
+    Ok(())
+}
+
+#[test]
+fn test_write_zip() -> eyre::Result<()> {
+    let end_of_central_directory = |zip: &[u8]| zip[zip.len() - 22..].to_vec();
+    let mut entries = ArchiveEntries::default();
+    entries.insert_data("index.html", b"hello".to_vec());
+    let mut zip = vec![];
+    write_zip(&mut zip, &entries)?;
+    assert_eq!(zip[..4], 0x04034b50u32.to_le_bytes());
+    let end = end_of_central_directory(&zip);
+    assert_eq!(end[..4], 0x06054b50u32.to_le_bytes());
+    assert_eq!(end[10..12], 1u16.to_le_bytes());
+    // no zip64 end of central directory locator.
+    assert_ne!(zip[zip.len() - 42..][..4], 0x07064b50u32.to_le_bytes());
+
+    // too many files for the end of central directory record, so they are counted in zip64.
+    let mut entries = ArchiveEntries::default();
+    for i in 0..70_000 {
+        entries.insert_data(&format!("{i}.html"), vec![]);
+    }
+    let mut zip = vec![];
+    write_zip(&mut zip, &entries)?;
+    let end = end_of_central_directory(&zip);
+    assert_eq!(end[10..12], u16::MAX.to_le_bytes());
+    let locator = &zip[zip.len() - 42..][..20];
+    assert_eq!(locator[..4], 0x07064b50u32.to_le_bytes());
+    let zip64_end_offset = u64::from_le_bytes(locator[8..16].try_into()?) as usize;
+    let zip64_end = &zip[zip64_end_offset..][..56];
+    assert_eq!(zip64_end[..4], 0x06064b50u32.to_le_bytes());
+    assert_eq!(zip64_end[32..40], 70_000u64.to_le_bytes());
+
+    Ok(())
+}
@@ -1,43 +1,130 @@
 use std::{
-    fs::{copy, create_dir_all, read_dir, File},
-    io::{Read, Write},
+    collections::{BTreeMap, BTreeSet},
+    fs::{copy, create_dir_all, exists, metadata, read_dir, remove_file, rename, DirEntry, File},
+    io::{ErrorKind, Read, Write},
+    mem::take,
     path::Path,
+    sync::{Arc, LazyLock, Mutex, PoisonError},
+    thread::sleep,
+    time::Duration,
 };
 
 use jane_eyre::eyre::{self, bail, OptionExt};
-use reqwest::redirect::Policy;
+use rayon::{
+    iter::{IntoParallelRefIterator, ParallelIterator},
+    ThreadPool, ThreadPoolBuilder,
+};
+use reqwest::{
+    blocking::Client,
+    header::{
+        CONTENT_DISPOSITION, CONTENT_TYPE, ETAG, IF_MODIFIED_SINCE, IF_NONE_MATCH, LAST_MODIFIED,
+    },
+    redirect::Policy,
+    StatusCode,
+};
+use serde::{Deserialize, Serialize};
 use sha2::{digest::generic_array::functional::FunctionalSequence, Digest, Sha256};
-use tracing::{debug, trace, warn};
+use tracing::{debug, info, trace, warn};
+use url::Url;
 use uuid::Uuid;
 
 use crate::{
     cohost::{attachment_id_to_url, Cacheable},
+    dom::sanitise_svg,
+    downscale::{apply_jpeg_orientation, downscale_image},
+    error::{self, Error},
     path::AttachmentsPath,
+    SETTINGS,
 };
 
+/// stores and caches attachments. the methods return [`error::Result`], so library users can tell
+/// network errors apart from io errors and others.
 pub trait AttachmentsContext {
-    fn store(&self, input_path: &Path) -> eyre::Result<AttachmentsPath>;
-    fn cache_imported(&self, url: &str, post_basename: &str) -> eyre::Result<AttachmentsPath>;
-    fn cache_cohost_resource(&self, cacheable: &Cacheable) -> eyre::Result<AttachmentsPath>;
-    fn cache_cohost_thumb(&self, id: &str) -> eyre::Result<AttachmentsPath>;
+    fn store(&self, input_path: &Path) -> error::Result<AttachmentsPath>;
+    fn cache_imported(&self, url: &str, post_basename: &str) -> error::Result<AttachmentsPath>;
+    fn cache_cohost_resource(&self, cacheable: &Cacheable) -> error::Result<AttachmentsPath>;
+    fn cache_cohost_thumb(&self, id: &str) -> error::Result<AttachmentsPath>;
+
+    /// like [`AttachmentsContext::cache_cohost_resource`], but for many resources at once, which
+    /// some contexts may download concurrently. results are in the same order as `cacheables`.
+    fn cache_many_cohost_resources(
+        &self,
+        cacheables: &[Cacheable],
+    ) -> Vec<error::Result<AttachmentsPath>> {
+        cacheables
+            .iter()
+            .map(|cacheable| self.cache_cohost_resource(cacheable))
+            .collect()
+    }
+
+    /// like [`AttachmentsContext::cache_imported`], but for many urls at once, which some
+    /// contexts may download concurrently. results are in the same order as `urls`.
+    fn cache_many_imported(
+        &self,
+        urls: &[String],
+        post_basename: &str,
+    ) -> Vec<error::Result<AttachmentsPath>> {
+        urls.iter()
+            .map(|url| self.cache_imported(url, post_basename))
+            .collect()
+    }
+
+    /// returns true iff html rendered with this context can be reused from the render cache
+    /// ([`crate::render_cache`]), rather than calling the other methods again.
+    fn uses_render_cache(&self) -> bool {
+        false
+    }
 }
 
-pub struct RealAttachmentsContext;
+#[derive(Default)]
+pub struct RealAttachmentsContext {
+    /// if true, check if cached cohost static files, avatars, and headers have changed, using
+    /// the validators saved from the original response. attachments never change, so they are
+    /// never refreshed.
+    pub refresh_resources: bool,
+    /// if true, html rendered with this context can be reused from the render cache, unless
+    /// `refresh_resources` is also true.
+    pub render_cache: bool,
+    /// if true, never download anything. cached files are used as usual, but anything that is
+    /// not cached returns [`Error::Offline`], and is counted for [`report_offline_misses`].
+    /// cached files are never refreshed either, even with `refresh_resources`.
+    pub offline: bool,
+}
 impl AttachmentsContext for RealAttachmentsContext {
     #[tracing::instrument(skip(self))]
-    fn store(&self, input_path: &Path) -> eyre::Result<AttachmentsPath> {
+    fn store(&self, input_path: &Path) -> error::Result<AttachmentsPath> {
         let dir = AttachmentsPath::ROOT.join(&Uuid::new_v4().to_string())?;
         create_dir_all(&dir)?;
-        let filename = input_path.file_name().ok_or_eyre("no filename")?;
-        let filename = filename.to_str().ok_or_eyre("unsupported filename")?;
-        let path = dir.join(filename)?;
+        let filename = input_path
+            .file_name()
+            .ok_or_else(|| Error::Parse("no filename".to_owned()))?;
+        let filename = filename
+            .to_str()
+            .ok_or_else(|| Error::Parse("unsupported filename".to_owned()))?;
+        let filename = attachment_filename(filename, &BTreeSet::default());
+        let path = dir.join(&filename)?;
         copy(input_path, &path)?;
+        if SETTINGS.strip_exif() {
+            let mut input = vec![];
+            File::open(&path)?.read_to_end(&mut input)?;
+            match strip_jpeg_metadata(&input) {
+                Ok(Some(output)) => {
+                    debug!(?path, "stripped exif metadata");
+                    // write then rename, so a failed write can’t leave the attachment truncated.
+                    write_download(&path, &output)?;
+                }
+                Ok(None) => {}
+                Err(error) => warn!(?path, "failed to strip exif metadata, keeping it: {error}"),
+            }
+        }
+        Dimensions::probe_and_save(&path)?;
+        scaled_copy(&path)?;
 
         Ok(path)
     }
 
     #[tracing::instrument(skip(self))]
-    fn cache_imported(&self, url: &str, post_basename: &str) -> eyre::Result<AttachmentsPath> {
+    fn cache_imported(&self, url: &str, post_basename: &str) -> error::Result<AttachmentsPath> {
         let mut hash = Sha256::new();
         hash.update(url);
         let hash = hash.finalize().map(|o| format!("{o:02x}")).join("");
@@ -45,20 +132,25 @@ impl AttachmentsContext for RealAttachmentsContext {
         trace!(?path);
         create_dir_all(&path)?;
 
-        cache_imported_attachment(url, &path)
+        let path = cache_imported_attachment(url, &path, self.offline)?;
+        scaled_copy(&path)?;
+
+        Ok(path)
     }
 
     #[tracing::instrument(skip(self))]
-    fn cache_cohost_resource(&self, cacheable: &Cacheable) -> eyre::Result<AttachmentsPath> {
+    fn cache_cohost_resource(&self, cacheable: &Cacheable) -> error::Result<AttachmentsPath> {
         match cacheable {
             Cacheable::Attachment { id } => {
                 let url = attachment_id_to_url(id);
                 let dir = &*AttachmentsPath::ROOT;
                 let path = dir.join(id)?;
                 create_dir_all(&path)?;
-                cache_cohost_attachment(&url, &path, None)?;
+                cache_cohost_attachment(&url, &path, None, self.offline)?;
+                let path = cached_attachment_url(id, dir)?;
+                scaled_copy(&path)?;
 
-                cached_attachment_url(id, dir)
+                Ok(path)
             }
 
             Cacheable::Static { filename, url } => {
@@ -67,7 +159,7 @@ impl AttachmentsContext for RealAttachmentsContext {
                 let path = dir.join(filename)?;
                 trace!(?path);
 
-                cache_other_cohost_resource(url, &path)
+                cache_other_cohost_resource(url, &path, self.refresh_resources, self.offline)
             }
 
             Cacheable::Avatar { filename, url } => {
@@ -76,7 +168,7 @@ impl AttachmentsContext for RealAttachmentsContext {
                 let path = dir.join(filename)?;
                 trace!(?path);
 
-                cache_other_cohost_resource(url, &path)
+                cache_other_cohost_resource(url, &path, self.refresh_resources, self.offline)
             }
 
             Cacheable::Header { filename, url } => {
@@ -85,13 +177,13 @@ impl AttachmentsContext for RealAttachmentsContext {
                 let path = dir.join(filename)?;
                 trace!(?path);
 
-                cache_other_cohost_resource(url, &path)
+                cache_other_cohost_resource(url, &path, self.refresh_resources, self.offline)
             }
         }
     }
 
     #[tracing::instrument(skip(self))]
-    fn cache_cohost_thumb(&self, id: &str) -> eyre::Result<AttachmentsPath> {
+    fn cache_cohost_thumb(&self, id: &str) -> error::Result<AttachmentsPath> {
         fn thumb(url: &str) -> String {
             format!("{url}?width=675")
         }
@@ -100,64 +192,909 @@ impl AttachmentsContext for RealAttachmentsContext {
         let dir = &*AttachmentsPath::THUMBS;
         let path = dir.join(id)?;
         create_dir_all(&path)?;
-        cache_cohost_attachment(&url, &path, Some(thumb))?;
+        if self.offline && cached_attachment(&url, &path)?.is_none() {
+            return Err(offline_miss(&url));
+        }
+
+        // hold the lock while transcoding too, since that replaces the downloaded thumb.
+        with_download_lock(&path, || {
+            download_cohost_attachment(&url, &path, Some(thumb))?;
+            let thumb_path = cached_attachment_url(id, dir)?;
+
+            // thumbs link to the original attachment, so we can replace the thumb with a jpeg
+            // copy, and the original stays available as a download. the thumb dir must only
+            // contain one file, for `cached_attachment_url`.
+            let transcoded_path = transcode_if_needed(&thumb_path, &path)?;
+            if transcoded_path != thumb_path {
+                remove_cached_file(&thumb_path)?;
+                Dimensions::probe_and_save(&transcoded_path)?;
+            }
+
+            Ok(transcoded_path)
+        })
+    }
+
+    fn cache_many_cohost_resources(
+        &self,
+        cacheables: &[Cacheable],
+    ) -> Vec<error::Result<AttachmentsPath>> {
+        DOWNLOAD_POOL.install(|| {
+            cacheables
+                .par_iter()
+                .map(|cacheable| self.cache_cohost_resource(cacheable))
+                .collect()
+        })
+    }
+
+    fn cache_many_imported(
+        &self,
+        urls: &[String],
+        post_basename: &str,
+    ) -> Vec<error::Result<AttachmentsPath>> {
+        DOWNLOAD_POOL.install(|| {
+            urls.par_iter()
+                .map(|url| self.cache_imported(url, post_basename))
+                .collect()
+        })
+    }
+
+    fn uses_render_cache(&self) -> bool {
+        // refreshing resources means calling the other methods again.
+        self.render_cache && !self.refresh_resources
+    }
+}
+
+/// if `transcode_images` is enabled and `path` is a heif image (like heic or avif), returns the
+/// path of a jpeg copy of it in `output_dir`, transcoding it if needed. otherwise, or if it can’t
+/// be transcoded, returns `path`.
+fn transcode_if_needed(
+    path: &AttachmentsPath,
+    output_dir: &AttachmentsPath,
+) -> eyre::Result<AttachmentsPath> {
+    if !SETTINGS.transcode_images() || !cfg!(feature = "heif") {
+        return Ok(path.clone());
+    }
+    let (stem, extension) = path
+        .filename()
+        .rsplit_once(".")
+        .unwrap_or((path.filename(), ""));
+    if extension == "jpg" {
+        return Ok(path.clone());
+    }
+    let mut input = vec![];
+    File::open(path)?.read_to_end(&mut input)?;
+    if !is_heif(&input) {
+        return Ok(path.clone());
+    }
+
+    let output_path = output_dir.join(&format!("{stem}.jpg"))?;
+    if exists(&output_path)? {
+        return Ok(output_path);
+    }
+    debug!(?path, "transcoding heif image to jpeg");
+    match transcode_heif_to_jpeg(&input) {
+        Ok(output) => {
+            write_download(&output_path, &output)?;
+            Ok(output_path)
+        }
+        Err(error) => {
+            warn!(
+                ?path,
+                "failed to transcode heif image, keeping original: {error}"
+            );
+            Ok(path.clone())
+        }
+    }
+}
+
+/// removes the exif and xmp metadata from a jpeg image, like gps coordinates and camera details,
+/// without decoding or re-encoding it. if the exif metadata had an orientation other than the
+/// default, the image is re-encoded with that rotation applied to its pixels, so it is still shown
+/// the right way up. if we can’t decode it, like a progressive jpeg, a minimal exif segment with
+/// only that orientation is kept instead. returns None if the image is not a jpeg, or has no
+/// metadata to remove.
+fn strip_jpeg_metadata(input: &[u8]) -> eyre::Result<Option<Vec<u8>>> {
+    const EXIF: &[u8] = b"Exif\0\0";
+    const XMP: &[u8] = b"http://ns.adobe.com/xap/1.0/\0";
+
+    if !input.starts_with(&[0xFF, 0xD8]) {
+        return Ok(None);
+    }
+    let mut result = input[..2].to_vec();
+    let mut stripped = false;
+    // the orientation, and where its segment would go if we can’t apply it.
+    let mut orientation = None;
+    let mut rest = &input[2..];
+    loop {
+        let Some((&[0xFF, marker], after)) = rest.split_first_chunk::<2>() else {
+            bail!("bad jpeg: expected marker");
+        };
+        // fill bytes before a marker.
+        if marker == 0xFF {
+            rest = &rest[1..];
+            continue;
+        }
+        // markers without a length, like restart markers.
+        if matches!(marker, 0x01 | 0xD0..=0xD8) {
+            result.extend_from_slice(&rest[..2]);
+            rest = after;
+            continue;
+        }
+        // start of scan, or end of image: the rest is entropy-coded data, which has no metadata.
+        if matches!(marker, 0xDA | 0xD9) {
+            result.extend_from_slice(rest);
+            break;
+        }
+        let Some((&len, _)) = after.split_first_chunk::<2>() else {
+            bail!("bad jpeg: truncated segment");
+        };
+        let len = usize::from(u16::from_be_bytes(len));
+        let Some(segment) = rest.get(..2 + len).filter(|_| len >= 2) else {
+            bail!("bad jpeg: truncated segment");
+        };
+        let payload = &segment[4..];
+        rest = &rest[2 + len..];
+        if marker == 0xE1 && payload.starts_with(EXIF) {
+            stripped = true;
+            if let Some(value) =
+                exif_orientation(&payload[EXIF.len()..]).filter(|&value| value != 1)
+            {
+                orientation = Some((value, result.len()));
+            }
+        } else if marker == 0xE1 && payload.starts_with(XMP) {
+            stripped = true;
+        } else {
+            result.extend_from_slice(segment);
+        }
+    }
 
-        cached_attachment_url(id, dir)
+    if let Some((orientation, index)) = orientation {
+        match apply_jpeg_orientation(&result, orientation) {
+            Ok(Some(output)) => return Ok(Some(output)),
+            Ok(None) => debug!(
+                orientation,
+                "can’t decode jpeg, keeping its exif orientation"
+            ),
+            Err(error) => {
+                warn!(
+                    orientation,
+                    "failed to decode jpeg, keeping its exif orientation: {error}"
+                )
+            }
+        }
+        result.splice(index..index, orientation_segment(orientation));
     }
+
+    Ok(stripped.then_some(result))
 }
 
-fn cached_attachment_url(id: &str, dir: &AttachmentsPath) -> eyre::Result<AttachmentsPath> {
+/// returns the orientation tag in the first ifd of the exif tiff data, if any.
+pub(crate) fn exif_orientation(tiff: &[u8]) -> Option<u16> {
+    let big_endian = match tiff.get(..4)? {
+        b"MM\0\x2A" => true,
+        b"II\x2A\0" => false,
+        _ => return None,
+    };
+    let u16_at = |offset: usize| {
+        let bytes = *tiff.get(offset..offset + 2)?.first_chunk::<2>()?;
+        Some(if big_endian {
+            u16::from_be_bytes(bytes)
+        } else {
+            u16::from_le_bytes(bytes)
+        })
+    };
+    let u32_at = |offset: usize| {
+        let bytes = *tiff.get(offset..offset + 4)?.first_chunk::<4>()?;
+        Some(if big_endian {
+            u32::from_be_bytes(bytes)
+        } else {
+            u32::from_le_bytes(bytes)
+        })
+    };
+
+    let ifd = usize::try_from(u32_at(4)?).ok()?;
+    let count = usize::from(u16_at(ifd)?);
+    (0..count)
+        .map(|i| ifd + 2 + i * 12)
+        .find(|&entry| u16_at(entry) == Some(0x0112) && u16_at(entry + 2) == Some(3))
+        .and_then(|entry| u16_at(entry + 8))
+}
+
+/// an app1 exif segment with only an orientation tag.
+pub(crate) fn orientation_segment(orientation: u16) -> Vec<u8> {
+    let mut payload = b"Exif\0\0MM\0\x2A\0\0\0\x08".to_vec();
+    payload.extend_from_slice(&1u16.to_be_bytes());
+    // tag, type (short), count, value (padded to four bytes).
+    payload.extend_from_slice(&0x0112u16.to_be_bytes());
+    payload.extend_from_slice(&3u16.to_be_bytes());
+    payload.extend_from_slice(&1u32.to_be_bytes());
+    payload.extend_from_slice(&orientation.to_be_bytes());
+    payload.extend_from_slice(&[0, 0]);
+    // no next ifd.
+    payload.extend_from_slice(&0u32.to_be_bytes());
+
+    let mut result = vec![0xFF, 0xE1];
+    result.extend_from_slice(
+        &u16::try_from(payload.len() + 2)
+            .expect("tiny")
+            .to_be_bytes(),
+    );
+    result.extend_from_slice(&payload);
+    result
+}
+
+#[test]
+fn test_strip_jpeg_metadata() -> eyre::Result<()> {
+    fn segment(marker: u8, payload: &[u8]) -> Vec<u8> {
+        let mut result = vec![0xFF, marker];
+        result.extend_from_slice(&u16::try_from(payload.len() + 2).unwrap().to_be_bytes());
+        result.extend_from_slice(payload);
+        result
+    }
+    // little-endian exif with a made-up gps ifd pointer and orientation 6 (rotate 90° cw).
+    let mut exif = b"Exif\0\0II\x2A\0\x08\0\0\0\x02\0".to_vec();
+    exif.extend_from_slice(&[0x25, 0x88, 4, 0, 1, 0, 0, 0, 0x26, 0, 0, 0]);
+    exif.extend_from_slice(&[0x12, 0x01, 3, 0, 1, 0, 0, 0, 6, 0, 0, 0]);
+    exif.extend_from_slice(&[0, 0, 0, 0]);
+    let jfif = segment(0xE0, b"JFIF\0\x01\x02\0\0\x01\0\x01\0\0");
+    let xmp = segment(0xE1, b"http://ns.adobe.com/xap/1.0/\0<x:xmpmeta/>");
+    let scan = [0xFF, 0xDA, 0, 2, 0x12, 0xFF, 0x00, 0x34, 0xFF, 0xD9];
+    let jpeg = |segments: &[&[u8]]| -> Vec<u8> {
+        [&[0xFF, 0xD8][..]]
+            .into_iter()
+            .chain(segments.iter().copied())
+            .chain([&scan[..]])
+            .flatten()
+            .copied()
+            .collect()
+    };
+
+    // the scan here is not a real image, so the orientation can’t be applied to its pixels, and
+    // only the orientation is kept. see `downscale::test::test_orient` for images we can decode.
+    assert_eq!(
+        strip_jpeg_metadata(&jpeg(&[&jfif, &segment(0xE1, &exif), &xmp]))?,
+        Some(jpeg(&[&jfif, &orientation_segment(6)]))
+    );
+    assert_eq!(exif_orientation(&orientation_segment(6)[10..]), Some(6));
+
+    // orientation 1 is the default, so no exif segment is needed at all.
+    let last = exif.len() - 8;
+    exif[last] = 1;
+    assert_eq!(
+        strip_jpeg_metadata(&jpeg(&[&segment(0xE1, &exif), &jfif]))?,
+        Some(jpeg(&[&jfif]))
+    );
+
+    assert_eq!(strip_jpeg_metadata(&jpeg(&[&jfif]))?, None);
+    assert_eq!(strip_jpeg_metadata(b"\x89PNG\r\n")?, None);
+
+    Ok(())
+}
+
+/// returns true iff `bytes` start with an iso bmff `ftyp` box with a heif or avif brand.
+fn is_heif(bytes: &[u8]) -> bool {
+    bytes.get(4..8) == Some(b"ftyp")
+        && matches!(
+            bytes.get(8..12),
+            Some(
+                b"heic"
+                    | b"heix"
+                    | b"hevc"
+                    | b"hevx"
+                    | b"heim"
+                    | b"heis"
+                    | b"mif1"
+                    | b"msf1"
+                    | b"avif"
+                    | b"avis"
+            )
+        )
+}
+
+#[cfg(feature = "heif")]
+fn transcode_heif_to_jpeg(input: &[u8]) -> eyre::Result<Vec<u8>> {
+    use jpeg_encoder::{ColorType, Encoder};
+    use libheif_rs::{ColorSpace, HeifContext, LibHeif, RgbChroma};
+
+    let context = HeifContext::read_from_bytes(input)?;
+    let handle = context.primary_image_handle()?;
+    let image = LibHeif::new().decode(&handle, ColorSpace::Rgb(RgbChroma::Rgb), None)?;
+    let planes = image.planes();
+    let plane = planes
+        .interleaved
+        .ok_or_eyre("decoded image has no interleaved plane")?;
+
+    // rows may be padded, but the encoder wants them packed.
+    let row_len = usize::try_from(plane.width)? * 3;
+    let pixels = plane
+        .data
+        .chunks(plane.stride)
+        .flat_map(|row| &row[..row_len])
+        .copied()
+        .collect::<Vec<_>>();
+    let mut result = vec![];
+    Encoder::new(&mut result, 90).encode(
+        &pixels,
+        plane.width.try_into()?,
+        plane.height.try_into()?,
+        ColorType::Rgb,
+    )?;
+
+    Ok(result)
+}
+
+#[cfg(not(feature = "heif"))]
+fn transcode_heif_to_jpeg(_input: &[u8]) -> eyre::Result<Vec<u8>> {
+    bail!("autost was built without the heif feature")
+}
+
+fn cached_attachment_url(id: &str, dir: &AttachmentsPath) -> error::Result<AttachmentsPath> {
     let path = dir.join(id)?;
-    let mut entries = read_dir(&path)?;
-    let Some(entry) = entries.next() else {
-        bail!("directory is empty: {path:?}");
+    let Some(entry) = cached_file_entry(&path)? else {
+        return Err(Error::MissingReference(format!(
+            "directory is empty: {path:?}"
+        )));
+    };
+
+    Ok(path.join_dir_entry(&entry)?)
+}
+
+/// returns the cached file in an attachment directory, skipping any [`Dimensions`] or
+/// [`Integrity`] sidecar, any download that is still being written, and the directory of any
+/// [`scaled_copy`].
+fn cached_file_entry(dir: &AttachmentsPath) -> error::Result<Option<DirEntry>> {
+    for entry in read_dir(dir)? {
+        let entry = entry?;
+        if entry.file_type()?.is_dir() {
+            continue;
+        }
+        if !entry.file_name().to_str().is_some_and(is_sidecar_filename) {
+            return Ok(Some(entry));
+        }
+    }
+
+    Ok(None)
+}
+
+/// returns true iff the filename is for one of the files we save next to cached attachments, or a
+/// download that is still being written.
+fn is_sidecar_filename(filename: &str) -> bool {
+    filename.ends_with(DIMENSIONS_SUFFIX)
+        || filename.ends_with(INTEGRITY_SUFFIX)
+        || filename.ends_with(DOWNLOAD_SUFFIX)
+}
+
+/// returns the cached file in an attachment directory, if there is one and it looks complete.
+fn cached_attachment(url: &str, dir: &AttachmentsPath) -> error::Result<Option<AttachmentsPath>> {
+    // if the attachment id directory exists, and the directory contains a file...
+    if let Ok(Some(entry)) = cached_file_entry(dir) {
+        // and the file is not empty or truncated...
+        // TODO: move this logic into path module
+        let path = dir.join_dir_entry(&entry)?;
+        if Integrity::check(&path) {
+            trace!("cache hit: {url}");
+            return Ok(Some(path));
+        }
+    }
+
+    Ok(None)
+}
+
+/// removes any cached files in an attachment directory that are empty or truncated, so they can be
+/// downloaded again. the caller must hold the [`with_download_lock`] for `dir`.
+fn remove_broken_cached_files(dir: &AttachmentsPath) -> error::Result<()> {
+    for entry in read_dir(dir)? {
+        let entry = entry?;
+        if entry.file_type()?.is_dir()
+            || entry.file_name().to_str().is_some_and(is_sidecar_filename)
+        {
+            continue;
+        }
+        let path = dir.join_dir_entry(&entry)?;
+        if !Integrity::check(&path) {
+            warn!(
+                ?path,
+                "cached attachment is empty or truncated, downloading it again"
+            );
+            remove_cached_file(&path)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// removes a cached file and the files we saved next to it.
+fn remove_cached_file(path: &AttachmentsPath) -> error::Result<()> {
+    for path in [
+        path.clone(),
+        Dimensions::path(path)?,
+        Integrity::path(path)?,
+    ] {
+        match remove_file(&path) {
+            Err(error) if error.kind() == ErrorKind::NotFound => {}
+            result => result?,
+        }
+    }
+
+    Ok(())
+}
+
+/// suffix of the temporary files that downloads are written to, before being renamed into place.
+const DOWNLOAD_SUFFIX: &str = ".download";
+
+/// writes a downloaded file via a temporary file in the same directory, so threads checking the
+/// cache without [`with_download_lock`] never see a partial download.
+///
+/// the [`Integrity`] is saved first, so if we are interrupted, the next run either finds no file,
+/// or a file it can check.
+fn write_download(path: &AttachmentsPath, content: &[u8]) -> error::Result<()> {
+    let temp_path = path
+        .parent()
+        .ok_or_eyre("path has no parent")?
+        .join(&format!(".{}{DOWNLOAD_SUFFIX}", Uuid::new_v4()))?;
+    Integrity::of(content).save(path)?;
+    File::create(&temp_path)?.write_all(content)?;
+    rename(temp_path, path)?;
+
+    Ok(())
+}
+
+/// the thread pool for [`AttachmentsContext::cache_many_cohost_resources`] and
+/// [`AttachmentsContext::cache_many_imported`], with `max_concurrent_downloads` threads.
+static DOWNLOAD_POOL: LazyLock<ThreadPool> = LazyLock::new(|| {
+    ThreadPoolBuilder::new()
+        .num_threads(SETTINGS.max_concurrent_downloads())
+        .thread_name(|i| format!("download-{i}"))
+        .build()
+        .expect("failed to create download thread pool")
+});
+
+/// the locks for [`with_download_lock`], for each attachment path that is being downloaded.
+static DOWNLOAD_LOCKS: Mutex<BTreeMap<AttachmentsPath, Arc<Mutex<()>>>> =
+    Mutex::new(BTreeMap::new());
+
+/// runs `f` while holding a lock for the given attachment path, so two threads never download the
+/// same attachment at once. the second thread waits, then should find the first one’s download in
+/// the cache.
+fn with_download_lock<T>(path: &AttachmentsPath, f: impl FnOnce() -> T) -> T {
+    let lock = DOWNLOAD_LOCKS
+        .lock()
+        .unwrap()
+        .entry(path.clone())
+        .or_default()
+        .clone();
+    let result = {
+        // a panic while downloading leaves nothing to clean up, so ignore the poison.
+        let _guard = lock.lock().unwrap_or_else(PoisonError::into_inner);
+        f()
     };
+    let mut locks = DOWNLOAD_LOCKS.lock().unwrap();
+    // forget the lock if no other thread is waiting for it (one in the map, one here).
+    if Arc::strong_count(&lock) == 2 {
+        locks.remove(path);
+    }
+
+    result
+}
+
+#[test]
+fn test_with_download_lock() -> eyre::Result<()> {
+    use std::sync::atomic::{AtomicUsize, Ordering::SeqCst};
+
+    let path = AttachmentsPath::ROOT.join("test-with-download-lock")?;
+    let inside = AtomicUsize::new(0);
+    let max_inside = AtomicUsize::new(0);
+    std::thread::scope(|scope| {
+        for _ in 0..8 {
+            scope.spawn(|| {
+                with_download_lock(&path, || {
+                    max_inside.fetch_max(inside.fetch_add(1, SeqCst) + 1, SeqCst);
+                    sleep(Duration::from_millis(10));
+                    inside.fetch_sub(1, SeqCst);
+                })
+            });
+        }
+    });
+    assert_eq!(max_inside.into_inner(), 1);
+
+    // the lock is forgotten once no thread is waiting for it.
+    assert!(!DOWNLOAD_LOCKS.lock().unwrap().contains_key(&path));
+
+    Ok(())
+}
+
+/// the urls that were not cached, and not downloaded because of [`RealAttachmentsContext::offline`].
+static OFFLINE_MISSES: Mutex<BTreeSet<String>> = Mutex::new(BTreeSet::new());
+
+/// returns an [`Error::Offline`] for the url, counting it for [`report_offline_misses`].
+fn offline_miss(url: &str) -> Error {
+    debug!("not downloading in offline mode: {url}");
+    OFFLINE_MISSES.lock().unwrap().insert(url.to_owned());
+
+    Error::Offline(url.to_owned())
+}
+
+/// logs how many attachments and other resources were missing in offline mode, if any, and
+/// forgets them. returns how many there were.
+pub fn report_offline_misses() -> usize {
+    let misses = take(&mut *OFFLINE_MISSES.lock().unwrap());
+    if !misses.is_empty() {
+        for url in &misses {
+            debug!("missing in offline mode: {url}");
+        }
+        warn!(
+            "{} attachments and other resources were not cached, so they are missing (offline mode)",
+            misses.len()
+        );
+    }
+
+    misses.len()
+}
 
-    Ok(path.join_dir_entry(&entry?)?)
+/// how many times to retry a download that timed out.
+const HTTP_RETRIES: u32 = 2;
+
+/// returns a blocking client for downloading attachments and other resources, with the
+/// `http_connect_timeout_secs` and `http_read_timeout_secs`.
+fn http_client(redirect: Policy) -> error::Result<Client> {
+    Ok(Client::builder()
+        .connect_timeout(SETTINGS.http_connect_timeout())
+        .timeout(SETTINGS.http_read_timeout())
+        .redirect(redirect)
+        .build()?)
+}
+
+/// returns how long to wait before the given retry (starting at 1), doubling each time.
+fn retry_delay(retry: u32) -> Duration {
+    Duration::from_secs(1 << (retry - 1).min(6))
 }
 
-fn cache_imported_attachment(url: &str, path: &AttachmentsPath) -> eyre::Result<AttachmentsPath> {
-    // if the attachment id directory exists...
-    if let Ok(mut entries) = read_dir(&path) {
-        // and the directory contains a file...
-        if let Some(entry) = entries.next() {
-            // and we can open the file...
-            // TODO: move this logic into path module
-            let path = path.join_dir_entry(&entry?)?;
-            if let Ok(mut file) = File::open(&path) {
-                trace!("cache hit: {url}");
-                // check if we can read the file.
-                let mut result = Vec::default();
-                file.read_to_end(&mut result)?;
-                return Ok(path);
+/// runs the request (including reading the body, if any), retrying with backoff if it times out.
+fn with_retries<T>(mut request: impl FnMut() -> reqwest::Result<T>) -> error::Result<T> {
+    let mut retry = 0;
+    loop {
+        match request() {
+            Err(error) if error.is_timeout() && retry < HTTP_RETRIES => {
+                retry += 1;
+                let delay = retry_delay(retry);
+                warn!(?delay, "request timed out, retrying: {error}");
+                sleep(delay);
             }
+            result => return Ok(result?),
+        }
+    }
+}
+
+fn cache_imported_attachment(
+    url: &str,
+    path: &AttachmentsPath,
+    offline: bool,
+) -> error::Result<AttachmentsPath> {
+    // check the host before the cache too, so the result doesn’t depend on what was cached.
+    let parsed_url =
+        Url::parse(url).map_err(|error| Error::Parse(format!("bad attachment url: {error}")))?;
+    if let Some(host) = parsed_url.host_str() {
+        if !SETTINGS.imported_attachment_host_is_allowed(host) {
+            info!("not downloading attachment from blocked host: {url}");
+            return Err(Error::BlockedHost(host.to_owned()));
         }
     }
 
+    if let Some(cached) = cached_attachment(url, path)? {
+        return Ok(cached);
+    }
+    if offline {
+        return Err(offline_miss(url));
+    }
+
+    with_download_lock(path, || {
+        // another thread may have downloaded it while we were waiting.
+        if let Some(cached) = cached_attachment(url, path)? {
+            return Ok(cached);
+        }
+        download_imported_attachment(url, &parsed_url, path)
+    })
+}
+
+fn download_imported_attachment(
+    url: &str,
+    parsed_url: &Url,
+    path: &AttachmentsPath,
+) -> error::Result<AttachmentsPath> {
     trace!("cache miss");
     debug!("downloading attachment");
+    remove_broken_cached_files(path)?;
 
-    let response = reqwest::blocking::get(url)?;
-    let extension = match response.headers().get("Content-Type") {
-        Some(x) if x == "image/gif" => "gif",
-        Some(x) if x == "image/jpeg" => "jpg",
-        Some(x) if x == "image/png" => "png",
-        Some(x) if x == "image/svg+xml" => "svg",
-        Some(x) if x == "image/webp" => "webp",
-        other => {
-            warn!("unknown attachment mime type: {other:?}");
-            "bin"
-        }
+    let client = http_client(imported_attachment_redirect_policy(
+        |host| SETTINGS.imported_attachment_host_is_allowed(host),
+        SETTINGS.allow_plaintext_attachment_downloads(),
+    ))?;
+    let download = |url: &str| {
+        with_retries(|| {
+            let response = client.get(url).send()?.error_for_status()?;
+            let header = |name| {
+                response
+                    .headers()
+                    .get(name)
+                    .and_then(|value| value.to_str().ok())
+                    .map(ToOwned::to_owned)
+            };
+            let headers = (header(CONTENT_DISPOSITION), header(CONTENT_TYPE));
+            let content_length = response.content_length();
+            Ok((headers, content_length, response.bytes()?))
+        })
+        .and_then(|(headers, content_length, content)| {
+            check_content_length(url, content_length, &content)?;
+            Ok((headers, content))
+        })
     };
-    let path = path.join(&format!("file.{extension}"))?;
+    let ((content_disposition, content_type), content) = match https_upgrade(parsed_url) {
+        Some(https_url) => match download(https_url.as_str()) {
+            Ok(result) => result,
+            Err(error) if SETTINGS.allow_plaintext_attachment_downloads() => {
+                warn!(
+                    ?error,
+                    "failed to upgrade attachment url to https, using http: {url}"
+                );
+                download(url)?
+            }
+            Err(error) => {
+                warn!(?error, "failed to upgrade attachment url to https, and allow_plaintext_attachment_downloads is false: {url}");
+                return Err(error);
+            }
+        },
+        None => download(url)?,
+    };
+    let filename = imported_attachment_filename(
+        content_disposition.as_deref(),
+        content_type.as_deref(),
+        parsed_url,
+    );
+    let path = path.join(&filename)?;
     debug!(?path);
 
-    let result = response.bytes()?.to_vec();
-    File::create(&path)?.write_all(&result)?;
+    let result = sanitise_svg_attachment(&path, content.to_vec());
+    write_download(&path, &result)?;
+    Dimensions::probe_and_save(&path)?;
 
     Ok(path)
 }
 
+/// returns a redirect policy for imported attachments that checks the host of each redirect, like
+/// the host of the original url, so an allowed host can’t send us to a blocked one. if
+/// `allow_plaintext` is false, redirects to http are refused too.
+fn imported_attachment_redirect_policy(
+    host_is_allowed: impl Fn(&str) -> bool + Send + Sync + 'static,
+    allow_plaintext: bool,
+) -> Policy {
+    Policy::custom(move |attempt| {
+        // the same limit as `Policy::default()`.
+        if attempt.previous().len() >= 10 {
+            return attempt.error("too many redirects");
+        }
+        if !allow_plaintext && attempt.url().scheme() == "http" {
+            let error = format!(
+                "redirected to http, and allow_plaintext_attachment_downloads is false: {}",
+                attempt.url()
+            );
+            return attempt.error(error);
+        }
+        match attempt.url().host_str() {
+            Some(host) if !host_is_allowed(host) => {
+                let error = format!("redirected to blocked host: {}", attempt.url());
+                attempt.error(error)
+            }
+            _ => attempt.follow(),
+        }
+    })
+}
+
+/// returns the filename to save an imported attachment as, which is the filename in the
+/// `Content-Disposition`, if any, or `file.<ext>`, with the extension for the `Content-Type`, or
+/// from the url, or `bin` if we can’t tell.
+fn imported_attachment_filename(
+    content_disposition: Option<&str>,
+    content_type: Option<&str>,
+    url: &Url,
+) -> String {
+    if let Some(filename) = content_disposition
+        .and_then(content_disposition_filename)
+        .and_then(|filename| sanitise_filename(&filename))
+    {
+        return attachment_filename(&filename, &BTreeSet::default());
+    }
+
+    // ignore parameters like `; charset=utf-8`.
+    let mime_type = content_type
+        .and_then(|x| x.split(';').next())
+        .map(|x| x.trim().to_ascii_lowercase());
+    let extension = match mime_type.as_deref() {
+        Some("application/pdf") => "pdf".to_owned(),
+        Some("audio/mpeg") => "mp3".to_owned(),
+        Some("image/avif") => "avif".to_owned(),
+        Some("image/gif") => "gif".to_owned(),
+        Some("image/heic") => "heic".to_owned(),
+        Some("image/heif") => "heif".to_owned(),
+        Some("image/jpeg") => "jpg".to_owned(),
+        Some("image/png") => "png".to_owned(),
+        Some("image/svg+xml") => "svg".to_owned(),
+        Some("image/webp") => "webp".to_owned(),
+        Some("text/plain") => "txt".to_owned(),
+        Some("video/mp4") => "mp4".to_owned(),
+        other => match url_extension(url) {
+            Some(extension) => extension,
+            None => {
+                warn!("unknown attachment mime type: {other:?}");
+                "bin".to_owned()
+            }
+        },
+    };
+
+    format!("file.{extension}")
+}
+
+/// returns the filename in a `Content-Disposition` header, preferring `filename*` (rfc 6266), which
+/// can be any unicode, over `filename`.
+fn content_disposition_filename(value: &str) -> Option<String> {
+    let mut filename = None;
+    for param in value.split(';').skip(1) {
+        let Some((name, value)) = param.split_once('=') else {
+            continue;
+        };
+        let value = value.trim();
+        match &*name.trim().to_ascii_lowercase() {
+            "filename*" => {
+                // like `UTF-8''na%C3%AFve.pdf`, where the middle part is an optional language.
+                let mut parts = value.splitn(3, '\'');
+                let (Some(charset), Some(_), Some(encoded)) =
+                    (parts.next(), parts.next(), parts.next())
+                else {
+                    continue;
+                };
+                if charset.eq_ignore_ascii_case("utf-8") {
+                    if let Ok(decoded) = urlencoding::decode(encoded) {
+                        return Some(decoded.into_owned());
+                    }
+                }
+            }
+            "filename" => {
+                filename = Some(match value.strip_prefix('"') {
+                    Some(quoted) => quoted
+                        .strip_suffix('"')
+                        .unwrap_or(quoted)
+                        .replace("\\\"", "\"")
+                        .replace("\\\\", "\\"),
+                    None => value.to_owned(),
+                });
+            }
+            _ => {}
+        }
+    }
+
+    filename
+}
+
+/// returns a filename from a server that is safe to save in an attachment directory, without any
+/// directories, hidden files, or characters that some filesystems don’t allow, or None if there is
+/// nothing left.
+fn sanitise_filename(filename: &str) -> Option<String> {
+    let filename = filename.rsplit(['/', '\\']).next().unwrap_or(filename);
+    let result = filename
+        .chars()
+        .filter(|c| !c.is_control())
+        .map(|c| match c {
+            ':' | '*' | '?' | '"' | '<' | '>' | '|' => '_',
+            c => c,
+        })
+        .collect::<String>();
+    let result = result
+        .trim()
+        .trim_start_matches('.')
+        .trim_end_matches(['.', ' ']);
+    if result.is_empty() || is_sidecar_filename(result) {
+        return None;
+    }
+
+    Some(result.to_owned())
+}
+
+/// returns the extension of the last segment of the url path, if it looks like one.
+fn url_extension(url: &Url) -> Option<String> {
+    let segment = url.path_segments()?.next_back()?;
+    let (stem, extension) = segment.rsplit_once('.')?;
+    if stem.is_empty()
+        || extension.is_empty()
+        || extension.len() > 16
+        || !extension.chars().all(|c| c.is_ascii_alphanumeric())
+    {
+        return None;
+    }
+
+    Some(extension.to_ascii_lowercase())
+}
+
+#[test]
+fn test_imported_attachment_filename() -> eyre::Result<()> {
+    let url = Url::parse("https://example.com/download?id=1")?;
+    let filename = imported_attachment_filename;
+    assert_eq!(
+        filename(Some("attachment; filename=\"report.pdf\""), None, &url),
+        "report.pdf"
+    );
+    assert_eq!(
+        filename(
+            Some("attachment; filename=\"naive.pdf\"; filename*=UTF-8''na%C3%AFve.pdf"),
+            Some("application/pdf"),
+            &url
+        ),
+        "naïve.pdf"
+    );
+    assert_eq!(
+        filename(Some("attachment; filename=../../etc/passwd"), None, &url),
+        "passwd"
+    );
+    assert_eq!(
+        filename(Some("attachment; filename=\"..\""), Some("video/mp4"), &url),
+        "file.mp4"
+    );
+    assert_eq!(
+        filename(
+            Some("attachment; filename=\"a.png.dimensions.json\""),
+            None,
+            &url
+        ),
+        "file.bin"
+    );
+    assert_eq!(
+        filename(Some("inline"), Some("text/plain; charset=utf-8"), &url),
+        "file.txt"
+    );
+    assert_eq!(filename(None, Some("audio/mpeg"), &url), "file.mp3");
+    assert_eq!(
+        filename(
+            None,
+            Some("application/octet-stream"),
+            &Url::parse("https://example.com/fonts/Font.WOFF2?v=1")?
+        ),
+        "file.woff2"
+    );
+    assert_eq!(filename(None, None, &url), "file.bin");
+
+    Ok(())
+}
+
+/// returns the https version of the url, if it’s an http url, to try before downloading the
+/// attachment over plaintext.
+fn https_upgrade(url: &Url) -> Option<Url> {
+    if url.scheme() != "http" {
+        return None;
+    }
+    let mut result = url.clone();
+    result.set_scheme("https").ok()?;
+
+    Some(result)
+}
+
+/// if the attachment is an svg, removes anything that could run scripts or load other resources
+/// if the svg is inlined. if that fails, keeps the original, which is still safe in `<img>`.
+fn sanitise_svg_attachment(path: &AttachmentsPath, content: Vec<u8>) -> Vec<u8> {
+    if !path.filename().to_ascii_lowercase().ends_with(".svg") {
+        return content;
+    }
+    match sanitise_svg(&content) {
+        Ok((result, removed)) => {
+            for removed in removed {
+                info!(?path, "removed from svg: {removed}");
+            }
+            result
+        }
+        Err(error) => {
+            warn!(?path, ?error, "failed to sanitise svg, keeping original");
+            content
+        }
+    }
+}
+
 /// given a cohost attachment redirect (`url`) and path to a uuid dir (`path`),
 /// return the cached attachment path (`path/original-filename.ext`).
 ///
@@ -167,51 +1104,68 @@ fn cache_cohost_attachment(
     url: &str,
     path: &AttachmentsPath,
     transform_redirect_target: Option<fn(&str) -> String>,
-) -> eyre::Result<AttachmentsPath> {
-    // if the attachment id directory exists...
-    if let Ok(mut entries) = read_dir(path) {
-        // and the directory contains a file...
-        if let Some(entry) = entries.next() {
-            // and we can open the file...
-            // TODO: move this logic into path module
-            let path = path.join_dir_entry(&entry?)?;
-            if let Ok(mut file) = File::open(&path) {
-                trace!("cache hit: {url}");
-                // check if we can read the file.
-                let mut result = Vec::default();
-                file.read_to_end(&mut result)?;
-                return Ok(path);
-            }
-        }
+    offline: bool,
+) -> error::Result<AttachmentsPath> {
+    if let Some(cached) = cached_attachment(url, path)? {
+        return Ok(cached);
+    }
+    if offline {
+        return Err(offline_miss(url));
+    }
+
+    with_download_lock(path, || {
+        download_cohost_attachment(url, path, transform_redirect_target)
+    })
+}
+
+/// like [`cache_cohost_attachment`], but the caller must hold the [`with_download_lock`] for
+/// `path`. checks the cache again, since another thread may have downloaded it while we were
+/// waiting for the lock.
+fn download_cohost_attachment(
+    url: &str,
+    path: &AttachmentsPath,
+    transform_redirect_target: Option<fn(&str) -> String>,
+) -> error::Result<AttachmentsPath> {
+    if let Some(cached) = cached_attachment(url, path)? {
+        return Ok(cached);
     }
 
     trace!("cache miss: {url}");
     debug!("downloading attachment");
+    // otherwise the new download would get another filename, and the broken one would still win.
+    remove_broken_cached_files(path)?;
 
-    let client = reqwest::blocking::Client::builder()
-        .redirect(Policy::none())
-        .build()?;
+    let client = http_client(Policy::none())?;
 
     // attachment redirect endpoint occasionally returns 406 Not Acceptable
-    let mut retries = 2;
+    let mut retry = 0;
     let mut redirect;
     let url = loop {
-        redirect = client.head(url).send()?;
+        redirect = with_retries(|| client.head(url).send())?;
         let Some(url) = redirect.headers().get("location") else {
-            if retries == 0 {
-                bail!("expected redirect but got {}: {url}", redirect.status());
+            if retry == HTTP_RETRIES {
+                return Err(Error::MissingReference(format!(
+                    "expected redirect but got {}: {url}",
+                    redirect.status()
+                )));
             } else {
-                retries -= 1;
+                retry += 1;
+                sleep(retry_delay(retry));
                 continue;
             }
         };
-        break url.to_str()?;
+        break url
+            .to_str()
+            .map_err(|error| Error::Parse(format!("bad redirect target: {error}")))?;
     };
 
     let Some((_, original_filename)) = url.rsplit_once("/") else {
-        bail!("redirect target has no slashes: {url}");
+        return Err(Error::Parse(format!(
+            "redirect target has no slashes: {url}"
+        )));
     };
-    let original_filename = urlencoding::decode(original_filename)?;
+    let original_filename = urlencoding::decode(original_filename)
+        .map_err(|error| Error::Parse(format!("bad filename in redirect target: {error}")))?;
     trace!("original filename: {original_filename}");
 
     // cohost attachment redirects don’t preserve query params, so if we want to add any,
@@ -225,29 +1179,617 @@ fn cache_cohost_attachment(
         url.to_owned()
     };
 
-    let path = path.join(original_filename.as_ref())?;
-    let result = reqwest::blocking::get(url)?.bytes()?.to_vec();
-    File::create(&path)?.write_all(&result)?;
+    let mut taken = BTreeSet::default();
+    for entry in read_dir(path)? {
+        if let Some(filename) = entry?.file_name().to_str() {
+            taken.insert(filename.to_owned());
+        }
+    }
+    let path = path.join(&attachment_filename(&original_filename, &taken))?;
+    let client = http_client(Policy::default())?;
+    let (content_length, content) = with_retries(|| {
+        let response = client.get(&url).send()?;
+        Ok((response.content_length(), response.bytes()?))
+    })?;
+    check_content_length(&url, content_length, &content)?;
+    let result = sanitise_svg_attachment(&path, content.to_vec());
+    write_download(&path, &result)?;
+    Dimensions::probe_and_save(&path)?;
 
     Ok(path)
 }
 
-fn cache_other_cohost_resource(url: &str, path: &AttachmentsPath) -> eyre::Result<AttachmentsPath> {
-    // if we can open the cached file...
-    if let Ok(mut file) = File::open(path) {
-        trace!("cache hit: {url}");
-        // check if we can read the file.
-        let mut result = Vec::default();
-        file.read_to_end(&mut result)?;
-        return Ok(path.clone());
+/// returns the filename to save an attachment as, given its original filename, with
+/// [`attachment_filename_with`].
+fn attachment_filename(original: &str, taken: &BTreeSet<String>) -> String {
+    attachment_filename_with(original, SETTINGS.attachment_filename_max_bytes(), taken)
+}
+
+/// shortens the filename to at most `max_bytes`, keeping its extension, and if that filename is
+/// already `taken` in the directory, adds a short hash of the original filename before the
+/// extension. the result only depends on the arguments, so re-runs give the same filenames.
+fn attachment_filename_with(original: &str, max_bytes: usize, taken: &BTreeSet<String>) -> String {
+    let (stem, extension) = match original.rsplit_once(".") {
+        // very long “extensions” are more likely to be part of the name.
+        Some((stem, extension)) if !stem.is_empty() && extension.len() <= 16 => {
+            (stem, format!(".{extension}"))
+        }
+        _ => (original, "".to_owned()),
+    };
+    let shorten = |suffix: &str| {
+        let mut end = max_bytes
+            .saturating_sub(suffix.len() + extension.len())
+            .min(stem.len());
+        while !stem.is_char_boundary(end) {
+            end -= 1;
+        }
+        format!("{}{suffix}{extension}", &stem[..end])
+    };
+
+    let result = shorten("");
+    if !taken.contains(&result) {
+        return result;
     }
+    let hash = Sha256::digest(original)
+        .map(|o| format!("{o:02x}"))
+        .join("");
+    shorten(&format!("-{}", &hash[..8]))
+}
 
-    trace!("cache miss");
-    debug!("downloading resource");
+/// returns the mime type of an audio or video attachment, or None if it’s not a known media type.
+pub fn media_type(filename: &str) -> Option<&'static str> {
+    let (_, extension) = filename.rsplit_once('.')?;
+    Some(match &*extension.to_ascii_lowercase() {
+        "flac" => "audio/flac",
+        "m4a" => "audio/mp4",
+        "mp3" => "audio/mpeg",
+        "ogg" | "opus" => "audio/ogg",
+        "wav" => "audio/wav",
+        "m4v" | "mp4" => "video/mp4",
+        "mov" => "video/quicktime",
+        "webm" => "video/webm",
+        _ => return None,
+    })
+}
+
+/// suffix of the files next to cached and stored attachments, where we save their
+/// [`Dimensions`], so we can render `<img width height>` without reading every image.
+pub const DIMENSIONS_SUFFIX: &str = ".dimensions.json";
+
+/// the intrinsic size of an image attachment, in pixels.
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Serialize)]
+pub struct Dimensions {
+    pub width: usize,
+    pub height: usize,
+}
+
+impl Dimensions {
+    fn path(path: &AttachmentsPath) -> eyre::Result<AttachmentsPath> {
+        let filename = format!("{}{DIMENSIONS_SUFFIX}", path.filename());
+        path.parent()
+            .ok_or_eyre("path has no parent")?
+            .join(&filename)
+    }
+
+    /// returns the saved dimensions of an attachment, or probes and saves them if there are none.
+    /// returns None if the attachment is not an image we can probe.
+    pub fn load_or_probe(path: &AttachmentsPath) -> eyre::Result<Option<Self>> {
+        let saved = File::open(Self::path(path)?)
+            .ok()
+            .and_then(|file| serde_json::from_reader(file).ok());
+        if let Some(saved) = saved {
+            return Ok(Some(saved));
+        }
+
+        Self::probe_and_save(path)
+    }
+
+    /// reads the dimensions from the image header, and saves them next to the attachment.
+    fn probe_and_save(path: &AttachmentsPath) -> eyre::Result<Option<Self>> {
+        let Ok(size) = imagesize::size(path) else {
+            trace!(?path, "not an image, or unknown image type");
+            return Ok(None);
+        };
+        let result = Self {
+            width: size.width,
+            height: size.height,
+        };
+        // write then rename, since posts rendered in parallel may probe the same attachment.
+        let dimensions_path = Self::path(path)?;
+        let temp_path = dimensions_path
+            .parent()
+            .ok_or_eyre("path has no parent")?
+            .join(&format!(".{}{DIMENSIONS_SUFFIX}", Uuid::new_v4()))?;
+        serde_json::to_writer(File::create(&temp_path)?, &result)?;
+        rename(temp_path, dimensions_path)?;
 
-    let response = reqwest::blocking::get(url)?;
-    let result = response.bytes()?.to_vec();
-    File::create(path)?.write_all(&result)?;
+        Ok(Some(result))
+    }
+}
+
+/// name of the directory next to an image attachment where we save its [`scaled_copy`].
+const SCALED_DIR: &str = "scaled";
+
+/// returns a copy of the image attachment scaled down to `max_image_dimension`, making it if
+/// needed, or None if the attachment is not an image larger than that, or we can’t scale it.
+pub fn scaled_copy(path: &AttachmentsPath) -> eyre::Result<Option<AttachmentsPath>> {
+    let Some(max_dimension) = SETTINGS.max_image_dimension() else {
+        return Ok(None);
+    };
+    // thumbs are already small, and their directories must only contain one file.
+    if path.as_ref().starts_with(&*AttachmentsPath::THUMBS) {
+        return Ok(None);
+    }
+    let Some(original) = Dimensions::load_or_probe(path)? else {
+        return Ok(None);
+    };
+    if original.width.max(original.height) <= max_dimension {
+        return Ok(None);
+    }
+
+    // reuse the scaled copy, unless it was scaled for a different `max_image_dimension`.
+    let dir = path
+        .parent()
+        .ok_or_eyre("path has no parent")?
+        .join(SCALED_DIR)?;
+    let scaled_path = dir.join(path.filename())?;
+    if let Some(scaled) = Dimensions::load_or_probe(&scaled_path)? {
+        if scaled.width.max(scaled.height) == max_dimension {
+            return Ok(Some(scaled_path));
+        }
+    }
+
+    let mut input = vec![];
+    File::open(path)?.read_to_end(&mut input)?;
+    let output = match downscale_image(&input, max_dimension) {
+        Ok(Some(output)) => output,
+        Ok(None) => {
+            trace!(?path, "not an image we can scale");
+            return Ok(None);
+        }
+        Err(error) => {
+            warn!(?path, "failed to scale image: {error}");
+            return Ok(None);
+        }
+    };
+    // write then rename, since posts rendered in parallel may scale the same attachment.
+    create_dir_all(&dir)?;
+    let temp_path = dir.join(&format!(".{}.{}", Uuid::new_v4(), path.filename()))?;
+    File::create(&temp_path)?.write_all(&output)?;
+    rename(temp_path, &scaled_path)?;
+    debug!(?scaled_path, ?original, "scaled image");
+    Dimensions::probe_and_save(&scaled_path)?;
+
+    Ok(Some(scaled_path))
+}
+
+/// returns an error if the response had a `Content-Length`, but we got fewer or more bytes.
+fn check_content_length(url: &str, expected: Option<u64>, content: &[u8]) -> error::Result<()> {
+    if let Some(expected) = expected {
+        let actual = content.len() as u64;
+        if actual != expected {
+            return Err(eyre::eyre!(
+                "download is incomplete: expected {expected} bytes, got {actual}: {url}"
+            )
+            .into());
+        }
+    }
+
+    Ok(())
+}
+
+/// suffix of the files next to downloaded attachments and other resources, where we save their
+/// [`Integrity`], so we can tell if a cached file was truncated without downloading it again.
+pub const INTEGRITY_SUFFIX: &str = ".integrity.json";
+
+/// the size of a complete download, as it was written to the cache.
+#[derive(Debug, Deserialize, PartialEq, Serialize)]
+struct Integrity {
+    size: u64,
+}
+
+impl Integrity {
+    fn path(path: &AttachmentsPath) -> eyre::Result<AttachmentsPath> {
+        let filename = format!("{}{INTEGRITY_SUFFIX}", path.filename());
+        path.parent()
+            .ok_or_eyre("path has no parent")?
+            .join(&filename)
+    }
+
+    fn of(content: &[u8]) -> Self {
+        Self {
+            size: content.len() as u64,
+        }
+    }
+
+    /// returns true iff the cached file exists, is not empty, and has the saved size, if any.
+    /// files cached before we saved sizes only need to be non-empty. this never downloads anything.
+    fn check(path: &AttachmentsPath) -> bool {
+        let Ok(metadata) = metadata(path) else {
+            return false;
+        };
+        if metadata.len() == 0 {
+            return false;
+        }
+        let saved = Self::path(path)
+            .ok()
+            .and_then(|path| File::open(path).ok())
+            .and_then(|file| serde_json::from_reader::<_, Self>(file).ok());
+
+        match saved {
+            Some(saved) => saved.size == metadata.len(),
+            None => true,
+        }
+    }
+
+    fn save(&self, path: &AttachmentsPath) -> eyre::Result<()> {
+        // write then rename, like the download itself.
+        let integrity_path = Self::path(path)?;
+        let temp_path = integrity_path
+            .parent()
+            .ok_or_eyre("path has no parent")?
+            .join(&format!(".{}{INTEGRITY_SUFFIX}", Uuid::new_v4()))?;
+        serde_json::to_writer(File::create(&temp_path)?, self)?;
+        rename(temp_path, integrity_path)?;
+
+        Ok(())
+    }
+}
+
+/// suffix of the files next to cached cohost static files, avatars, and headers, where we save
+/// the [`Validators`] from the response.
+pub const VALIDATORS_SUFFIX: &str = ".validators.json";
+
+/// the `ETag` and `Last-Modified` of a cached resource, for conditional requests.
+#[derive(Debug, Default, Deserialize, Serialize)]
+struct Validators {
+    etag: Option<String>,
+    last_modified: Option<String>,
+}
+
+impl Validators {
+    fn path(path: &AttachmentsPath) -> eyre::Result<AttachmentsPath> {
+        let filename = format!("{}{VALIDATORS_SUFFIX}", path.filename());
+        path.parent()
+            .ok_or_eyre("path has no parent")?
+            .join(&filename)
+    }
+
+    /// returns the saved validators, or no validators if there are none or we can’t read them.
+    fn load(path: &AttachmentsPath) -> Self {
+        Self::path(path)
+            .ok()
+            .and_then(|path| File::open(path).ok())
+            .and_then(|file| serde_json::from_reader(file).ok())
+            .unwrap_or_default()
+    }
+
+    fn from_response(response: &reqwest::blocking::Response) -> Self {
+        let header = |name| {
+            response
+                .headers()
+                .get(name)
+                .and_then(|value| value.to_str().ok())
+                .map(ToOwned::to_owned)
+        };
+
+        Self {
+            etag: header(ETAG),
+            last_modified: header(LAST_MODIFIED),
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.etag.is_none() && self.last_modified.is_none()
+    }
+
+    fn save(&self, path: &AttachmentsPath) -> eyre::Result<()> {
+        if !self.is_empty() {
+            serde_json::to_writer(File::create(Self::path(path)?)?, self)?;
+        }
+
+        Ok(())
+    }
+}
+
+fn cache_other_cohost_resource(
+    url: &str,
+    path: &AttachmentsPath,
+    refresh: bool,
+    offline: bool,
+) -> error::Result<AttachmentsPath> {
+    // if the cached file is not empty or truncated...
+    if Integrity::check(path) {
+        if !refresh || offline {
+            trace!("cache hit: {url}");
+            return Ok(path.clone());
+        }
+
+        // refreshing writes in place, so only one thread may refresh the file at a time.
+        return with_download_lock(path, || refresh_other_cohost_resource(url, path));
+    }
+    if offline {
+        return Err(offline_miss(url));
+    }
+
+    with_download_lock(path, || {
+        // another thread may have downloaded it while we were waiting.
+        if Integrity::check(path) {
+            trace!("cache hit: {url}");
+            return Ok(path.clone());
+        }
+
+        trace!("cache miss");
+        debug!("downloading resource");
+
+        let client = http_client(Policy::default())?;
+        let (validators, content_length, content) = with_retries(|| {
+            let response = client.get(url).send()?;
+            Ok((
+                Validators::from_response(&response),
+                response.content_length(),
+                response.bytes()?,
+            ))
+        })?;
+        check_content_length(url, content_length, &content)?;
+        // replaces any empty or truncated file, since the download is renamed into place.
+        let result = content.to_vec();
+        write_download(path, &result)?;
+        validators.save(path)?;
+
+        Ok(path.clone())
+    })
+}
+
+/// checks if a cached cohost static file, avatar, or header has changed, and if so, downloads it
+/// again. the caller must hold the [`with_download_lock`] for `path`.
+fn refresh_other_cohost_resource(
+    url: &str,
+    path: &AttachmentsPath,
+) -> error::Result<AttachmentsPath> {
+    let validators = Validators::load(path);
+    if validators.is_empty() {
+        debug!("no validators, downloading resource again");
+    } else {
+        debug!("checking if resource has changed");
+    }
+    let client = http_client(Policy::default())?;
+    let (status, new_validators, content_length, content) = with_retries(|| {
+        let mut request = client.get(url);
+        if let Some(etag) = &validators.etag {
+            request = request.header(IF_NONE_MATCH, etag);
+        }
+        if let Some(last_modified) = &validators.last_modified {
+            request = request.header(IF_MODIFIED_SINCE, last_modified);
+        }
+        let response = request.send()?;
+        let status = response.status();
+        let new_validators = Validators::from_response(&response);
+        let content_length = response.content_length();
+        let content = if status == StatusCode::OK {
+            Some(response.bytes()?)
+        } else {
+            None
+        };
+        Ok((status, new_validators, content_length, content))
+    })?;
+    match (status, content) {
+        (StatusCode::NOT_MODIFIED, _) => {
+            trace!("not modified: {url}");
+        }
+        (StatusCode::OK, Some(content)) => {
+            debug!("resource has changed");
+            check_content_length(url, content_length, &content)?;
+            let result = content.to_vec();
+            // write in place, so hard links in the site directory see the new file too.
+            Integrity::of(&result).save(path)?;
+            File::create(path)?.write_all(&result)?;
+            new_validators.save(path)?;
+        }
+        (status, _) => {
+            warn!(%status, "failed to refresh resource, keeping cached file: {url}");
+        }
+    }
 
     Ok(path.clone())
 }
+
+#[test]
+fn test_attachment_filename() {
+    let none = BTreeSet::default();
+    assert_eq!(
+        attachment_filename_with("eggbug.png", 200, &none),
+        "eggbug.png"
+    );
+
+    let long = format!("{}.png", "a".repeat(296));
+    let result = attachment_filename_with(&long, 200, &none);
+    assert_eq!(result, format!("{}.png", "a".repeat(196)));
+    assert_eq!(attachment_filename_with(&long, 200, &none), result);
+
+    // never splits a character.
+    let result = attachment_filename_with(&format!("{}.png", "é".repeat(150)), 200, &none);
+    assert_eq!(result, format!("{}.png", "é".repeat(98)));
+
+    let taken = [format!("{}.png", "a".repeat(196))].into_iter().collect();
+    let long2 = format!("{}b.png", "a".repeat(295));
+    let result = attachment_filename_with(&long2, 200, &taken);
+    assert_eq!(result.len(), 200);
+    assert!(result.ends_with(".png"));
+    assert_ne!(result, attachment_filename_with(&long, 200, &taken));
+    assert_eq!(attachment_filename_with(&long2, 200, &taken), result);
+
+    let taken = ["eggbug.png".to_owned()].into_iter().collect();
+    let result = attachment_filename_with("eggbug.png", 200, &taken);
+    assert!(result.starts_with("eggbug-") && result.ends_with(".png"));
+    assert_eq!(result.len(), "eggbug-12345678.png".len());
+}
+
+#[test]
+fn test_integrity() -> eyre::Result<()> {
+    let dir = AttachmentsPath::ROOT.join(&format!("test-integrity-{}", Uuid::new_v4()))?;
+    create_dir_all(&dir)?;
+    let path = dir.join("file.png")?;
+    write_download(&path, b"complete")?;
+    assert!(Integrity::check(&path));
+    assert_eq!(cached_attachment("", &dir)?, Some(path.clone()));
+
+    // a truncated file is not a cache hit, and is removed before downloading again.
+    File::create(&path)?.write_all(b"comp")?;
+    assert!(!Integrity::check(&path));
+    assert_eq!(cached_attachment("", &dir)?, None);
+    remove_broken_cached_files(&dir)?;
+    assert_eq!(read_dir(&dir)?.count(), 0);
+
+    // files cached before we saved sizes only need to be non-empty.
+    File::create(&path)?.write_all(b"old")?;
+    assert!(Integrity::check(&path));
+    File::create(&path)?;
+    assert!(!Integrity::check(&path));
+
+    assert!(check_content_length("", Some(4), b"comp").is_ok());
+    assert!(check_content_length("", None, b"comp").is_ok());
+    assert!(check_content_length("", Some(8), b"comp").is_err());
+
+    std::fs::remove_dir_all(&dir)?;
+    // and the attachments directory, if this test created it.
+    let _ = std::fs::remove_dir(&*AttachmentsPath::ROOT);
+    Ok(())
+}
+
+#[test]
+fn test_offline() -> eyre::Result<()> {
+    let context = RealAttachmentsContext {
+        offline: true,
+        ..Default::default()
+    };
+    let id = Uuid::new_v4().to_string();
+    let cacheable = Cacheable::attachment(&id);
+    let result = context.cache_cohost_resource(&cacheable);
+    assert!(result.is_err_and(|error| error.is_offline()));
+    assert_eq!(report_offline_misses(), 1);
+
+    // cached files are used as usual.
+    let dir = AttachmentsPath::ROOT.join(&id)?;
+    let path = dir.join("eggbug.png")?;
+    write_download(&path, b"eggbug")?;
+    assert_eq!(context.cache_cohost_resource(&cacheable)?, path);
+    assert_eq!(report_offline_misses(), 0);
+
+    std::fs::remove_dir_all(&dir)?;
+    let _ = std::fs::remove_dir(&*AttachmentsPath::ROOT);
+    Ok(())
+}
+
+#[test]
+fn test_media_type() {
+    assert_eq!(media_type("file.MP3"), Some("audio/mpeg"));
+    assert_eq!(media_type("file.webm"), Some("video/webm"));
+    assert_eq!(media_type("file.png"), None);
+    assert_eq!(media_type("mp3"), None);
+}
+
+#[test]
+fn test_is_heif() {
+    assert!(is_heif(b"\0\0\0\x1cftypheic\0\0\0\0mif1heic"));
+    assert!(is_heif(b"\0\0\0\x20ftypavif\0\0\0\0avifmif1"));
+    assert!(!is_heif(b"\0\0\0\x18ftypmp42\0\0\0\0mp42isom"));
+    assert!(!is_heif(b"\x89PNG\r\n\x1a\n"));
+    assert!(!is_heif(b""));
+}
+
+#[test]
+fn test_with_retries() {
+    assert_eq!(retry_delay(1), Duration::from_secs(1));
+    assert_eq!(retry_delay(2), Duration::from_secs(2));
+
+    // errors other than timeouts are not retried.
+    let mut attempts = 0;
+    let result = with_retries(|| {
+        attempts += 1;
+        reqwest::blocking::get("not a url")
+    });
+    assert!(result.is_err());
+    assert_eq!(attempts, 1);
+}
+
+/// serves a few canned responses on localhost for testing redirects, returning the base url.
+#[cfg(test)]
+fn redirect_test_server() -> eyre::Result<String> {
+    use std::{io::Read, net::TcpListener};
+
+    let listener = TcpListener::bind("127.0.0.1:0")?;
+    let base_url = format!("http://{}", listener.local_addr()?);
+    let ok_url = format!("{base_url}/ok");
+    std::thread::spawn(move || {
+        for stream in listener.incoming() {
+            let Ok(mut stream) = stream else { continue };
+            let mut request = [0; 1024];
+            let Ok(len) = stream.read(&mut request) else {
+                continue;
+            };
+            let request = String::from_utf8_lossy(&request[..len]);
+            let location = match request.split(' ').nth(1) {
+                Some("/blocked") => Some("http://blocked.invalid/a.png"),
+                Some("/allowed") => Some(&*ok_url),
+                _ => None,
+            };
+            let response = match location {
+                Some(location) => format!("HTTP/1.1 302 Found\r\nLocation: {location}\r\nContent-Length: 0\r\nConnection: close\r\n\r\n"),
+                None => "HTTP/1.1 200 OK\r\nContent-Length: 2\r\nConnection: close\r\n\r\nok".to_owned(),
+            };
+            _ = stream.write_all(response.as_bytes());
+        }
+    });
+
+    Ok(base_url)
+}
+
+#[test]
+fn test_imported_attachment_redirect_policy() -> eyre::Result<()> {
+    let base_url = redirect_test_server()?;
+    let client = http_client(imported_attachment_redirect_policy(
+        |host| host != "blocked.invalid",
+        true,
+    ))?;
+
+    // redirects to allowed hosts are followed.
+    let response = client.get(format!("{base_url}/allowed")).send()?;
+    assert_eq!(response.text()?, "ok");
+
+    // redirects to blocked hosts are refused, without connecting to them.
+    let error = client
+        .get(format!("{base_url}/blocked"))
+        .send()
+        .expect_err("redirect to blocked host should fail");
+    assert!(error.is_redirect(), "{error:?}");
+
+    // redirects to http are refused if plaintext downloads are not allowed.
+    let client = http_client(imported_attachment_redirect_policy(|_| true, false))?;
+    let error = client
+        .get(format!("{base_url}/allowed"))
+        .send()
+        .expect_err("redirect to http should fail");
+    assert!(error.is_redirect(), "{error:?}");
+
+    Ok(())
+}
+
+#[test]
+fn test_https_upgrade() -> eyre::Result<()> {
+    let upgrade = |url: &str| -> eyre::Result<Option<String>> {
+        Ok(https_upgrade(&Url::parse(url)?).map(String::from))
+    };
+    assert_eq!(
+        upgrade("http://example.com/a.png?b#c")?.as_deref(),
+        Some("https://example.com/a.png?b#c")
+    );
+    assert_eq!(
+        upgrade("http://example.com:8080/a.png")?.as_deref(),
+        Some("https://example.com:8080/a.png")
+    );
+    assert_eq!(upgrade("https://example.com/a.png")?, None);
+    assert_eq!(upgrade("data:image/png;base64,")?, None);
+
+    Ok(())
+}
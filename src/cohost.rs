@@ -1,8 +1,10 @@
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 
+use jane_eyre::eyre::{self, bail, OptionExt};
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
 use tracing::warn;
+use url::Url;
 
 use crate::Author;
 
@@ -24,6 +26,8 @@ pub struct Post {
     pub publishedAt: String,
     pub headline: String,
     pub tags: Vec<String>,
+    #[serde(default)]
+    pub cws: Vec<String>,
     pub postingProject: PostingProject,
     pub shareTree: Vec<Post>,
 
@@ -35,6 +39,98 @@ pub struct Post {
 
     /// fully rendered versions of markdown blocks.
     pub astMap: AstMap,
+
+    /// engagement counts, if the export has them.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub numLikes: Option<usize>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub numComments: Option<usize>,
+
+    /// comments on the chost, if the export has them. comments in an unexpected shape are
+    /// skipped with a warning, rather than failing the whole chost.
+    #[serde(
+        default,
+        deserialize_with = "deserialize_comments",
+        skip_serializing_if = "Vec::is_empty"
+    )]
+    pub comments: Vec<CommentWrapper>,
+
+    /// any other fields, which autost doesn’t model (yet).
+    #[serde(flatten)]
+    pub extra: HashMap<String, Value>,
+}
+
+/// a comment on a chost, and who posted it, like in `posts.singlePost` responses.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct CommentWrapper {
+    pub comment: Comment,
+    /// None if the commenter’s page was deleted, or if either of you blocked the other.
+    #[serde(default)]
+    pub poster: Option<CommentPoster>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+#[allow(non_snake_case)]
+pub struct Comment {
+    pub postedAtISO: String,
+    #[serde(default)]
+    pub deleted: bool,
+    #[serde(default)]
+    pub hidden: bool,
+    /// markdown source.
+    #[serde(default)]
+    pub body: String,
+    /// replies to the comment, oldest first.
+    #[serde(default)]
+    pub children: Vec<CommentWrapper>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+#[allow(non_snake_case)]
+pub struct CommentPoster {
+    pub handle: String,
+    #[serde(default)]
+    pub displayName: String,
+}
+
+fn deserialize_comments<'de, D: serde::Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Vec<CommentWrapper>, D::Error> {
+    let value = Value::deserialize(deserializer)?;
+    if value.is_null() {
+        return Ok(vec![]);
+    }
+
+    Ok(serde_json::from_value(value).unwrap_or_else(|error| {
+        warn!(?error, "skipping comments in unexpected shape");
+        vec![]
+    }))
+}
+
+impl Post {
+    /// returns true iff the chost is a share with nothing added but maybe tags. these have both
+    /// `transparentShareOfPostId` and `shareOfPostId`, whereas shares with commentary only have
+    /// `shareOfPostId`.
+    pub fn is_transparent_share(&self) -> bool {
+        self.transparentShareOfPostId.is_some()
+    }
+
+    /// returns the `extra` fields with string, number, or boolean values, as strings. null values,
+    /// arrays, and objects are skipped.
+    pub fn extra_meta(&self) -> BTreeMap<String, String> {
+        self.extra
+            .iter()
+            .filter_map(|(key, value)| {
+                let value = match value {
+                    Value::String(value) => value.clone(),
+                    Value::Number(value) => value.to_string(),
+                    Value::Bool(value) => value.to_string(),
+                    Value::Null | Value::Array(_) | Value::Object(_) => return None,
+                };
+                Some((key.clone(), value))
+            })
+            .collect()
+    }
 }
 
 #[derive(Debug, Deserialize, Serialize)]
@@ -94,6 +190,15 @@ pub enum Attachment {
         title: String,
     },
 
+    #[serde(rename = "video")]
+    Video {
+        attachmentId: String,
+        width: Option<usize>,
+        height: Option<usize>,
+        #[serde(alias = "previewUrl")]
+        previewURL: Option<String>,
+    },
+
     #[serde(untagged)]
     Unknown {
         #[serde(flatten)]
@@ -131,6 +236,49 @@ pub struct Span {
     pub endIndex: usize,
 }
 
+impl AstMap {
+    /// returns the spans that can replace blocks `startIndex..endIndex` in a post with the given
+    /// number of blocks, in order of their blocks. spans that are empty, backwards, or past the
+    /// last block are skipped, as are spans that overlap an earlier span, so no block is rendered
+    /// twice or dropped. blocks not covered by any span need to be rendered from their markdown.
+    pub fn usable_spans(&self, block_count: usize) -> Vec<&Span> {
+        let mut spans = self
+            .spans
+            .iter()
+            .filter(|span| {
+                let usable = span.startIndex < span.endIndex && span.endIndex <= block_count;
+                if !usable {
+                    warn!(
+                        span.startIndex,
+                        span.endIndex, block_count, "skipping astMap span with bad indices"
+                    );
+                }
+                usable
+            })
+            .collect::<Vec<_>>();
+        spans.sort_by_key(|span| (span.startIndex, span.endIndex));
+
+        let mut result: Vec<&Span> = vec![];
+        for span in spans {
+            if let Some(last) = result.last() {
+                if span.startIndex < last.endIndex {
+                    warn!(
+                        span.startIndex,
+                        span.endIndex,
+                        last.startIndex,
+                        last.endIndex,
+                        "skipping astMap span that overlaps another span"
+                    );
+                    continue;
+                }
+            }
+            result.push(span);
+        }
+
+        result
+    }
+}
+
 #[derive(Debug, Deserialize)]
 #[allow(non_snake_case)]
 pub struct TrpcResponse<T> {
@@ -143,6 +291,15 @@ pub struct TrpcResult<T> {
     pub data: T,
 }
 
+#[derive(Debug, Deserialize)]
+#[allow(non_snake_case)]
+pub struct SinglePostResponse {
+    pub post: Post,
+    /// comments on the chost and the chosts in its share tree, by post id.
+    #[serde(default)]
+    pub comments: HashMap<String, Vec<CommentWrapper>>,
+}
+
 #[derive(Debug, Deserialize)]
 #[allow(non_snake_case)]
 pub struct ListEditedProjectsResponse {
@@ -183,6 +340,17 @@ pub struct FollowedProject {
     pub handle: String,
 }
 
+/// the tags and chosts you bookmarked on cohost, from the bookmarks file in your data export.
+#[derive(Debug, Default, Deserialize)]
+#[allow(non_snake_case)]
+pub struct Bookmarks {
+    #[serde(default)]
+    pub bookmarkedTags: Vec<String>,
+    /// urls of the chosts, like `https://cohost.org/project/post/123456-slug`.
+    #[serde(default)]
+    pub bookmarkedPosts: Vec<String>,
+}
+
 #[derive(Debug, Deserialize)]
 pub struct LikedPostsState {
     #[serde(rename = "liked-posts-feed")]
@@ -350,6 +518,26 @@ pub fn attachment_id_to_url(id: &str) -> String {
     format!("https://cohost.org/rc/attachment-redirect/{id}")
 }
 
+/// returns the project handle and post id in a chost url, like `https://cohost.org/staff/post/123456-slug`.
+pub fn parse_chost_url(url: &str) -> eyre::Result<(String, usize)> {
+    let url = Url::parse(url)?;
+    if url.host_str() != Some("cohost.org") {
+        bail!("not a cohost.org url: {url}");
+    }
+    let mut segments = url.path_segments().ok_or_eyre("url has no path")?;
+    let (Some(handle), Some("post"), Some(filename)) =
+        (segments.next(), segments.next(), segments.next())
+    else {
+        bail!("not a chost url: {url}");
+    };
+    let post_id = filename
+        .split_once('-')
+        .map_or(filename, |(post_id, _)| post_id)
+        .parse()?;
+
+    Ok((handle.to_owned(), post_id))
+}
+
 #[test]
 fn test_cacheable() {
     assert_eq!(
@@ -404,19 +592,84 @@ fn test_cacheable() {
 
 impl From<&PostingProject> for Author {
     fn from(project: &PostingProject) -> Self {
-        Self {
-            href: format!("https://cohost.org/{}", project.handle),
-            name: if project.displayName.is_empty() {
-                format!("@{}", project.handle)
-            } else {
-                format!("{} (@{})", project.displayName, project.handle)
-            },
-            display_name: project.displayName.clone(),
-            display_handle: format!("@{}", project.handle),
-        }
+        cohost_author(&project.handle, &project.displayName)
     }
 }
 
+impl From<&CommentPoster> for Author {
+    fn from(poster: &CommentPoster) -> Self {
+        cohost_author(&poster.handle, &poster.displayName)
+    }
+}
+
+fn cohost_author(handle: &str, display_name: &str) -> Author {
+    Author {
+        href: format!("https://cohost.org/{handle}"),
+        name: if display_name.is_empty() {
+            format!("@{handle}")
+        } else {
+            format!("{display_name} (@{handle})")
+        },
+        display_name: display_name.to_owned(),
+        display_handle: format!("@{handle}"),
+    }
+}
+
+#[test]
+fn test_ast_map_usable_spans() {
+    let span = |start, end| Span {
+        ast: format!("{start}..{end}"),
+        startIndex: start,
+        endIndex: end,
+    };
+    let usable = |spans, block_count| {
+        AstMap { spans }
+            .usable_spans(block_count)
+            .into_iter()
+            .map(|span| span.ast.clone())
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(usable(vec![], 3), Vec::<String>::default());
+    assert_eq!(usable(vec![span(2, 3), span(0, 2)], 3), ["0..2", "2..3"]);
+    // empty, backwards, and out of range spans are skipped.
+    assert_eq!(
+        usable(vec![span(1, 1), span(2, 1), span(2, 4), span(0, 1)], 3),
+        ["0..1"]
+    );
+    // overlapping spans are skipped, keeping the one that starts first.
+    assert_eq!(
+        usable(vec![span(1, 3), span(0, 2), span(0, 1), span(2, 3)], 3),
+        ["0..1", "1..3"]
+    );
+}
+
+#[test]
+fn test_attachment_video() -> eyre::Result<()> {
+    let block = serde_json::from_str::<Block>(
+        r#"{"type": "attachment", "attachment": {"kind": "video", "fileURL": "https://staging.cohostcdn.org/attachment/55555555-5555-5555-5555-555555555555/clip.mp4", "previewURL": "https://staging.cohostcdn.org/attachment/55555555-5555-5555-5555-555555555555/clip.mp4", "attachmentId": "55555555-5555-5555-5555-555555555555", "width": 1280, "height": 720}}"#,
+    )?;
+    let Block::Attachment { attachment } = block else {
+        panic!("not an attachment block: {block:?}");
+    };
+    let Attachment::Video {
+        attachmentId,
+        width,
+        height,
+        previewURL,
+    } = attachment
+    else {
+        panic!("not a video attachment: {attachment:?}");
+    };
+    assert_eq!(attachmentId, "55555555-5555-5555-5555-555555555555");
+    assert_eq!((width, height), (Some(1280), Some(720)));
+    assert_eq!(
+        previewURL.as_deref(),
+        Some("https://staging.cohostcdn.org/attachment/55555555-5555-5555-5555-555555555555/clip.mp4")
+    );
+
+    Ok(())
+}
+
 #[test]
 fn test_author_from_posting_project() {
     assert_eq!(
@@ -448,3 +701,20 @@ fn test_author_from_posting_project() {
         }
     );
 }
+
+#[test]
+fn test_parse_chost_url() -> eyre::Result<()> {
+    assert_eq!(
+        parse_chost_url("https://cohost.org/staff/post/123456-hello-world")?,
+        ("staff".to_owned(), 123456)
+    );
+    assert_eq!(
+        parse_chost_url("https://cohost.org/staff/post/123456")?,
+        ("staff".to_owned(), 123456)
+    );
+    assert!(parse_chost_url("https://cohost.org/staff").is_err());
+    assert!(parse_chost_url("https://cohost.org/staff/tagged/photography").is_err());
+    assert!(parse_chost_url("https://example.com/staff/post/123456-hello-world").is_err());
+
+    Ok(())
+}
@@ -19,7 +19,7 @@ pub async fn main(args: Attach) -> eyre::Result<()> {
     create_dir_all(&*AttachmentsPath::ROOT)?;
 
     for path in args.paths {
-        let attachment_path = RealAttachmentsContext.store(&Path::new(&path))?;
+        let attachment_path = RealAttachmentsContext::default().store(&Path::new(&path))?;
         info!(
             "created attachment: <{}>",
             attachment_path.site_path()?.base_relative_url()
@@ -1,10 +1,12 @@
 use std::{
     cell::RefCell,
-    collections::VecDeque,
+    collections::{BTreeMap, BTreeSet, HashMap, VecDeque},
     ffi::OsString,
-    fs::{create_dir_all, read_dir, DirEntry, File},
-    io::Write,
+    fs::{create_dir_all, read, read_dir, remove_file, write, DirEntry, File, OpenOptions},
+    io::{sink, ErrorKind, Write},
+    mem::take,
     path::Path,
+    sync::Mutex,
 };
 
 use askama::Template;
@@ -13,28 +15,67 @@ use jane_eyre::eyre::{self, bail, eyre, Context};
 use markup5ever_rcdom::{Node, NodeData, RcDom};
 use rayon::iter::{IntoParallelIterator, ParallelIterator};
 use serde::Deserialize;
-use tracing::{info, trace, warn};
+use serde_json::Value;
+use tracing::{debug, info, trace, warn};
 
 use crate::{
-    attachments::{AttachmentsContext, RealAttachmentsContext},
-    cohost::{attachment_id_to_url, Ask, AskingProject, Ast, Attachment, Block, Cacheable, Post},
+    attachments::{report_offline_misses, AttachmentsContext, RealAttachmentsContext},
+    cohost::{
+        attachment_id_to_url, Ask, AskingProject, Ast, AstMap, Attachment, Block, Cacheable, Post,
+    },
+    comments::{write_comments, ArchivedComment},
     css::{parse_inline_style, serialise_inline_style, InlineStyleToken},
     dom::{
         convert_idl_to_content_attribute, create_element, create_fragment, debug_attributes_seen,
         debug_not_known_good_attributes_seen, html_attributes_with_urls, parse_html_fragment,
         serialize_html_fragment, AttrsMutExt, AttrsRefExt, QualNameExt, TendrilExt, Transform,
     },
+    error::Error,
     migrations::run_migrations,
     path::{PostsPath, SitePath},
-    render_markdown, PostMeta,
+    progress::Progress,
+    render_cache::render_cached,
+    render_markdown,
+    settings::RedactLoggedInOnly,
+    Author, Engagement, PostMeta, TemplatedPost, SETTINGS,
 };
 
 #[derive(clap::Args, Debug)]
 pub struct Cohost2autost {
     pub path_to_chosts: String,
     pub specific_chost_filenames: Vec<String>,
+
+    #[arg(
+        long,
+        help = "ignore the checkpoint from an interrupted run, and convert everything"
+    )]
+    pub restart: bool,
+
+    #[arg(
+        long,
+        help = "check if cached cohost static files, avatars, and headers have changed, and download them again if so"
+    )]
+    pub refresh_resources: bool,
+
+    #[arg(
+        long,
+        help = "the chosts are ones you liked, not your own, so keep them out of your main page (see publish_liked_posts)"
+    )]
+    pub liked: bool,
+
+    #[arg(
+        long,
+        help = "never download anything, and skip chosts that need attachments that are not cached, instead of failing"
+    )]
+    pub offline: bool,
 }
 
+/// chosts that were fully converted by an interrupted run, so we can skip them next time.
+const CHECKPOINT_PATH: &str = "cohost2autost.checkpoint";
+
+/// counts of each unknown block type or attachment kind, for the summary at the end.
+static UNKNOWN_BLOCKS_SEEN: Mutex<BTreeMap<String, usize>> = Mutex::new(BTreeMap::new());
+
 pub fn main(args: Cohost2autost) -> eyre::Result<()> {
     run_migrations()?;
 
@@ -49,27 +90,58 @@ pub fn main(args: Cohost2autost) -> eyre::Result<()> {
     create_dir_all(&*SitePath::ATTACHMENTS)?;
     create_dir_all(&*SitePath::THUMBS)?;
 
+    // only use the checkpoint when converting all chosts. if you ask for specific chosts, you
+    // probably want them converted again.
+    let checkpoint = if specific_post_filenames.is_empty() {
+        Some(Checkpoint::open(Path::new(CHECKPOINT_PATH), args.restart)?)
+    } else {
+        None
+    };
+
+    let context = RealAttachmentsContext {
+        refresh_resources: args.refresh_resources,
+        render_cache: true,
+        offline: args.offline,
+    };
     let span = tracing::Span::current();
+    let progress = Progress::new("converting", dir_entries.len());
     let results = dir_entries
         .into_par_iter()
         .map(|entry| -> eyre::Result<()> {
             // enter the main thread’s current span, if any, so that `autost cohost-archive` can
             // prefix logs with the project_name we’re currently converting chosts for.
             let _guard = span.enter();
+            let _progress = ProgressOnDrop(&progress);
             let entry = entry?;
             if !specific_post_filenames.is_empty() {
                 if !specific_post_filenames.contains(&entry.file_name()) {
                     return Ok(());
                 }
             }
-            convert_chost(&entry, &RealAttachmentsContext)
+            convert_chost(&entry, &context, checkpoint.as_ref(), args.liked)
                 .wrap_err_with(|| eyre!("{:?}: failed to convert", entry.path()))?;
             Ok(())
         })
         .collect::<Vec<_>>();
 
+    let mut skipped_offline = 0;
     for result in results {
-        result?;
+        match result {
+            Err(error) if args.offline && is_offline_error(&error) => {
+                warn!("{error:?}");
+                skipped_offline += 1;
+            }
+            result => result?,
+        }
+    }
+    report_offline_misses();
+
+    if skipped_offline > 0 {
+        // keep the checkpoint, so the next run only converts the chosts we skipped.
+        warn!("skipped {skipped_offline} chosts that need attachments that are not cached (offline mode)");
+    } else if checkpoint.is_some() {
+        // we converted everything, so the next run should start from scratch.
+        remove_file(CHECKPOINT_PATH)?;
     }
 
     trace!("saw html attributes: {:?}", debug_attributes_seen());
@@ -82,17 +154,148 @@ pub fn main(args: Cohost2autost) -> eyre::Result<()> {
         let attributes = attributes.join(" ");
         warn!("saw attributes not on known-good-attributes list! check if output is correct for: {attributes}");
     }
+    let unknown_blocks_seen = UNKNOWN_BLOCKS_SEEN.lock().unwrap();
+    if !unknown_blocks_seen.is_empty() {
+        warn!(
+            "saw unknown blocks or attachments! consider reporting these: {unknown_blocks_seen:?}"
+        );
+    }
 
     Ok(())
 }
 
-#[tracing::instrument(level = "error", skip(context))]
-fn convert_chost(entry: &DirEntry, context: &dyn AttachmentsContext) -> eyre::Result<()> {
+/// returns true iff the error was caused by an attachment that was not cached in offline mode.
+fn is_offline_error(error: &eyre::Report) -> bool {
+    error
+        .chain()
+        .any(|error| error.downcast_ref::<Error>().is_some_and(Error::is_offline))
+}
+
+/// counts a chost as done when dropped, whether it was converted, skipped, or failed.
+struct ProgressOnDrop<'progress>(&'progress Progress);
+
+impl Drop for ProgressOnDrop<'_> {
+    fn drop(&mut self) {
+        self.0.increment();
+    }
+}
+
+struct Checkpoint {
+    done: BTreeSet<usize>,
+    file: Mutex<File>,
+}
+
+impl Checkpoint {
+    fn open(path: &Path, restart: bool) -> eyre::Result<Self> {
+        let mut done = BTreeSet::default();
+        if !restart {
+            match read(path) {
+                Ok(content) => {
+                    // if we crashed while writing the last line, it may be missing digits, like
+                    // `1234` for `123456`, so only trust lines that end in a newline. remove the
+                    // rest, so we don’t append the next post id to it.
+                    let complete_len = content
+                        .iter()
+                        .rposition(|&byte| byte == b'\n')
+                        .map_or(0, |index| index + 1);
+                    let (complete, incomplete) = content.split_at(complete_len);
+                    if !incomplete.is_empty() {
+                        warn!(?path, "ignoring incomplete last line in checkpoint");
+                        OpenOptions::new()
+                            .write(true)
+                            .open(path)?
+                            .set_len(complete_len.try_into()?)?;
+                    }
+                    for line in complete.split(|&byte| byte == b'\n') {
+                        if line.is_empty() {
+                            continue;
+                        }
+                        match std::str::from_utf8(line).map(str::parse) {
+                            Ok(Ok(post_id)) => {
+                                done.insert(post_id);
+                            }
+                            _ => warn!(?path, "ignoring bad line in checkpoint: {line:?}"),
+                        }
+                    }
+                    info!(
+                        "resuming from {path:?}: skipping {} chosts that were already converted",
+                        done.len()
+                    );
+                }
+                Err(error) if error.kind() == ErrorKind::NotFound => {}
+                Err(other) => Err(other)?,
+            }
+        }
+        let file = if restart {
+            File::create(path)?
+        } else {
+            OpenOptions::new().create(true).append(true).open(path)?
+        };
+
+        Ok(Self {
+            done,
+            file: Mutex::new(file),
+        })
+    }
+
+    fn is_done(&self, post_id: usize) -> bool {
+        self.done.contains(&post_id)
+    }
+
+    fn mark_done(&self, post_id: usize) -> eyre::Result<()> {
+        let mut file = self.file.lock().unwrap();
+        writeln!(file, "{post_id}")?;
+        file.flush()?;
+
+        Ok(())
+    }
+}
+
+#[tracing::instrument(level = "error", skip(context, checkpoint))]
+fn convert_chost(
+    entry: &DirEntry,
+    context: &dyn AttachmentsContext,
+    checkpoint: Option<&Checkpoint>,
+    liked: bool,
+) -> eyre::Result<()> {
     let input_path = entry.path();
 
     trace!("parsing");
-    let mut post: Post = serde_json::from_reader(File::open(&input_path)?)?;
+    let post: Post = serde_json::from_reader(File::open(&input_path)?)?;
     let post_id = post.postId;
+    if checkpoint.is_some_and(|checkpoint| checkpoint.is_done(post_id)) {
+        trace!("skipping chost that was already converted");
+        return Ok(());
+    }
+
+    convert_post(post, context, liked)?;
+
+    // the post file is written and its attachments are cached, so we can skip it if interrupted.
+    if let Some(checkpoint) = checkpoint {
+        checkpoint.mark_done(post_id)?;
+    }
+
+    Ok(())
+}
+
+/// converts a chost and the chosts in its share tree, returning the path of the converted chost.
+/// if `liked`, the chost is one you liked, which never replaces a chost that was converted as one
+/// of your own.
+pub fn convert_post(
+    mut post: Post,
+    context: &dyn AttachmentsContext,
+    liked: bool,
+) -> eyre::Result<PostsPath> {
+    let post_id = post.postId;
+    let output_path = PostsPath::generated_post_path(post_id);
+    if liked {
+        if let Ok(old) = TemplatedPost::load(&output_path) {
+            if !old.meta.is_liked {
+                info!("skipping liked chost that was already converted as your own: {post_id}");
+                return Ok(output_path);
+            }
+        }
+    }
 
     // each post has a “share tree”, a flat array of every post this post is in
     // reply to, from top to bottom.
@@ -108,23 +311,83 @@ fn convert_chost(entry: &DirEntry, context: &dyn AttachmentsContext) -> eyre::Re
     }
 
     for (shared_post, output_path) in shared_posts.into_iter().zip(shared_post_filenames.iter()) {
-        convert_single_chost(shared_post, vec![], &output_path, context)?;
+        convert_single_chost(shared_post, vec![], output_path, false, context)?;
     }
 
-    let output_path = PostsPath::generated_post_path(post_id);
-    convert_single_chost(post, shared_post_filenames, &output_path, context)?;
+    // comments and source are only kept for the chost itself, since that’s the page they are
+    // shown on.
+    let comments = take(&mut post.comments);
+    let source = post.plainTextBody.clone();
+    convert_single_chost(post, shared_post_filenames, &output_path, liked, context)?;
+    if SETTINGS.cohost_comments() {
+        write_comments(&output_path, &ArchivedComment::from_cohost(&comments))?;
+    }
+    if SETTINGS.embed_source.is_some() && !source.is_empty() {
+        if let Some(source_path) = output_path.source_path()? {
+            debug!("writing source: {source_path:?}");
+            write(source_path, source)?;
+        }
+    }
 
-    Ok(())
+    Ok(output_path)
 }
 
 fn convert_single_chost(
     post: Post,
     shared_post_filenames: Vec<PostsPath>,
     output_path: &PostsPath,
+    liked: bool,
+    context: &dyn AttachmentsContext,
+) -> eyre::Result<()> {
+    // if we’ve converted this chost before, and it was published at a different time back then,
+    // keep the original publish time, and record the new one as the time it was edited.
+    let (published, updated) = match TemplatedPost::load(output_path).ok() {
+        Some(old) => match old.meta.published {
+            Some(first_published) if first_published != post.publishedAt => {
+                (first_published, Some(post.publishedAt.clone()))
+            }
+            _ => (post.publishedAt.clone(), old.meta.updated),
+        },
+        None => (post.publishedAt.clone(), None),
+    };
+
+    // write the post only once it has been converted, so a chost that fails to convert (like in
+    // offline mode) never replaces the post from an earlier run with half of a post.
+    let mut output = vec![];
+    write_chost(
+        post,
+        shared_post_filenames,
+        published,
+        updated,
+        liked,
+        &mut output,
+        context,
+    )?;
+    debug!("writing: {output_path:?}");
+    write(output_path, output)?;
+
+    Ok(())
+}
+
+/// writes the chost as a post, with the given references and dates. if `liked`, the post is marked
+/// as one you liked (`<meta name="is_liked">`).
+pub fn write_chost(
+    post: Post,
+    shared_post_filenames: Vec<PostsPath>,
+    published: String,
+    updated: Option<String>,
+    liked: bool,
+    output: &mut dyn Write,
     context: &dyn AttachmentsContext,
 ) -> eyre::Result<()> {
-    info!("writing: {output_path:?}");
-    let mut output = File::create(output_path)?;
+    let is_redacted = chost_should_be_redacted(&post);
+    let is_transparent_share = post.is_transparent_share();
+    let post_id = post.postId;
+    let extra = post.extra_meta();
+    let engagement = Engagement {
+        likes: post.numLikes,
+        comments: post.numComments,
+    };
 
     let meta = PostMeta {
         archived: Some(format!(
@@ -132,37 +395,88 @@ fn convert_single_chost(
             post.postingProject.handle, post.filename
         )),
         references: shared_post_filenames,
-        title: Some(post.headline),
-        published: Some(post.publishedAt),
+        remote_references: vec![],
+        // cohost uses an empty headline for untitled posts.
+        title: Some(post.headline).filter(|headline| !headline.is_empty()),
+        published: Some(published),
+        updated,
         author: Some((&post.postingProject).into()),
+        contributors: vec![],
         tags: post.tags,
-        is_transparent_share: post.transparentShareOfPostId.is_some(),
+        is_transparent_share,
+        content_warning: Some(post.cws.join(", ")).filter(|cws| !cws.is_empty()),
+        is_redacted,
+        slug: None,
+        lang: None,
+        series: None,
+        series_index: None,
+        is_unlisted: false,
+        is_liked: liked,
+        extra,
+        engagement,
+        edits: vec![],
+        fediverse_url: None,
     };
     output.write_all(meta.render()?.as_bytes())?;
     output.write_all(b"\n\n")?;
 
-    let mut spans = post
-        .astMap
-        .spans
-        .iter()
-        .map(|span| -> eyre::Result<(Ast, usize, usize)> {
-            let mut deserializer = serde_json::Deserializer::from_str(&span.ast);
-
-            // allow trees more than 128 levels deep. since we don’t actually use serde_stacker or
-            // a custom Drop impl, it may lead to stack overflow, but i haven’t seen this so far.
-            deserializer.disable_recursion_limit();
-
-            Ok((
-                Ast::deserialize(&mut deserializer)?,
-                span.startIndex,
-                span.endIndex,
-            ))
-        })
-        .collect::<Result<Vec<_>, _>>()?;
-    spans.sort_by_key(|(_ast, start, end)| (*start, *end));
-    let mut spans = VecDeque::from(spans);
+    if is_redacted {
+        info!("redacting logged-in-only chost: {post_id}");
+        output.write_all(CohostRedactedTemplate {}.render()?.as_bytes())?;
+        return Ok(());
+    }
+
+    write_chost_body(post_id, post.astMap, post.blocks, output, context)
+}
+
+/// returns true iff the chost was hidden from logged-out users, and `redact_logged_in_only_chosts`
+/// says we should not archive its content.
+fn chost_should_be_redacted(post: &Post) -> bool {
+    if post.postingProject.loggedOutPostVisibility == "public" {
+        return false;
+    }
+    let author = Author::from(&post.postingProject);
+    let is_self = SETTINGS.is_any_self_author(&author);
+
+    match SETTINGS.redact_logged_in_only_chosts() {
+        RedactLoggedInOnly::None => false,
+        RedactLoggedInOnly::Others => !is_self,
+        RedactLoggedInOnly::All => true,
+    }
+}
+
+/// collects every cohost resource that converting the given chost would cache, by running the
+/// conversion with the given `context`, but throwing away the output.
+pub fn collect_cohost_resources(
+    input_path: &Path,
+    context: &dyn AttachmentsContext,
+) -> eyre::Result<()> {
+    let mut post: Post = serde_json::from_reader(File::open(input_path)?)?;
+    let shared_posts = take(&mut post.shareTree);
+    for post in shared_posts.into_iter().chain([post]) {
+        if chost_should_be_redacted(&post) {
+            continue;
+        }
+        write_chost_body(post.postId, post.astMap, post.blocks, &mut sink(), context)?;
+    }
 
-    for (i, block) in post.blocks.into_iter().enumerate() {
+    Ok(())
+}
+
+fn write_chost_body(
+    post_id: usize,
+    ast_map: AstMap,
+    blocks: Vec<Block>,
+    output: &mut dyn Write,
+    context: &dyn AttachmentsContext,
+) -> eyre::Result<()> {
+    let mut spans = ast_map
+        .usable_spans(blocks.len())
+        .into_iter()
+        .map(|span| (&*span.ast, span.startIndex, span.endIndex))
+        .collect::<VecDeque<_>>();
+
+    for (i, block) in blocks.into_iter().enumerate() {
         // posts in the cohost api provide an `astMap` that contains the perfect rendering of
         // markdown blocks. since our own markdown rendering is far from perfect, we use their
         // rendering instead of our own when available.
@@ -175,8 +489,17 @@ fn convert_single_chost(
             _ => None,
         } {
             trace!("replacing blocks {start}..{end} with ast");
-            let dom = process_ast(ast);
-            let html = process_chost_fragment(dom, context)?;
+            let html = render_cached("ast", ast, context, |context| {
+                let mut deserializer = serde_json::Deserializer::from_str(ast);
+
+                // allow trees more than 128 levels deep. since we don’t actually use serde_stacker
+                // or a custom Drop impl, it may lead to stack overflow, but i haven’t seen this so
+                // far.
+                deserializer.disable_recursion_limit();
+
+                let dom = process_ast(Ast::deserialize(&mut deserializer)?);
+                process_chost_fragment(dom, context)
+            })?;
             output.write_all(html.as_bytes())?;
             continue;
         }
@@ -189,13 +512,25 @@ fn convert_single_chost(
                     width,
                     height,
                 } => {
+                    // overrides take precedence, even over alt text that was imported.
+                    let alt = match SETTINGS.alt_text_override(&attachmentId) {
+                        Some(alt) => Some(alt.to_owned()),
+                        None => altText,
+                    };
+                    if alt.as_deref().map_or(true, |alt| alt.trim().is_empty()) {
+                        warn!(
+                            post_id,
+                            attachmentId,
+                            "image has no alt text; you can add it with alt_text_overrides"
+                        );
+                    }
                     let template = CohostImgTemplate {
                         data_cohost_src: attachment_id_to_url(&attachmentId),
                         thumb_src: context.cache_cohost_thumb(&attachmentId)?.site_path()?,
                         src: context
                             .cache_cohost_resource(&Cacheable::attachment(&attachmentId))?
                             .site_path()?,
-                        alt: altText,
+                        alt,
                         width,
                         height,
                     };
@@ -206,18 +541,58 @@ fn convert_single_chost(
                     artist,
                     title,
                 } => {
+                    let data_cohost_src = attachment_id_to_url(&attachmentId);
+                    // if we can’t cache the audio, keep the original url, so the player still
+                    // works for as long as cohost serves it.
+                    let src = match context
+                        .cache_cohost_resource(&Cacheable::attachment(&attachmentId))
+                    {
+                        Ok(path) => path.site_path()?.base_relative_url(),
+                        Err(error) => {
+                            warn!(post_id, attachmentId, "failed to cache audio: {error:?}");
+                            data_cohost_src.clone()
+                        }
+                    };
+                    let caption = [artist, title]
+                        .into_iter()
+                        .filter(|part| !part.is_empty())
+                        .collect::<Vec<_>>()
+                        .join(" — ");
                     let template = CohostAudioTemplate {
-                        data_cohost_src: attachment_id_to_url(&attachmentId),
-                        src: context
-                            .cache_cohost_resource(&Cacheable::attachment(&attachmentId))?
-                            .site_path()?,
-                        artist,
-                        title,
+                        data_cohost_src,
+                        src,
+                        caption,
+                    };
+                    output.write_all(template.render()?.as_bytes())?;
+                }
+                Attachment::Video {
+                    attachmentId,
+                    width,
+                    height,
+                    previewURL: _,
+                } => {
+                    let data_cohost_src = attachment_id_to_url(&attachmentId);
+                    // like audio, keep the original url if we can’t cache the video.
+                    let src = match context
+                        .cache_cohost_resource(&Cacheable::attachment(&attachmentId))
+                    {
+                        Ok(path) => path.site_path()?.base_relative_url(),
+                        Err(error) => {
+                            warn!(post_id, attachmentId, "failed to cache video: {error:?}");
+                            data_cohost_src.clone()
+                        }
+                    };
+                    let template = CohostVideoTemplate {
+                        data_cohost_src,
+                        src,
+                        width,
+                        height,
                     };
                     output.write_all(template.render()?.as_bytes())?;
                 }
                 Attachment::Unknown { fields } => {
-                    warn!("unknown attachment kind: {fields:?}");
+                    let html = handle_unknown_block(post_id, "attachment", "kind", &fields)?;
+                    output.write_all(html.as_bytes())?;
                 }
             }
             Ok(())
@@ -230,17 +605,10 @@ fn convert_single_chost(
                 continue;
             }
             Block::Attachment { attachment } => handle_attachment(attachment)?,
-            Block::Ask {
-                ask:
-                    Ask {
-                        askingProject,
-                        content,
-                        ..
-                    },
-            } => {
-                let html = render_markdown_block(&content, context)?;
+            Block::Ask { ask } => {
+                let html = render_markdown_block(&ask.content, context)?;
                 let template = AskTemplate {
-                    author: askingProject,
+                    asker: Asker::from(ask),
                     content: html,
                 };
                 output.write_all(template.render()?.as_bytes())?;
@@ -255,7 +623,8 @@ fn convert_single_chost(
                 }
             }
             Block::Unknown { fields } => {
-                warn!("unknown block type: {fields:?}");
+                let html = handle_unknown_block(post_id, "block", "type", &fields)?;
+                output.write_all(html.as_bytes())?;
             }
         }
         output.write_all(b"\n\n")?;
@@ -264,6 +633,38 @@ fn convert_single_chost(
     Ok(())
 }
 
+/// records an unknown block or attachment for the summary, and returns a placeholder for it (if
+/// enabled in the settings).
+fn handle_unknown_block(
+    post_id: usize,
+    what: &str,
+    kind_field: &str,
+    fields: &HashMap<String, Value>,
+) -> eyre::Result<String> {
+    let kind = fields
+        .get(kind_field)
+        .and_then(|kind| kind.as_str())
+        .unwrap_or("?")
+        .to_owned();
+    let keys = fields.keys().collect::<BTreeSet<_>>();
+    warn!(post_id, ?keys, "unknown {what} {kind_field}: {kind}");
+    *UNKNOWN_BLOCKS_SEEN
+        .lock()
+        .unwrap()
+        .entry(format!("{what} {kind_field} {kind}"))
+        .or_default() += 1;
+
+    if !SETTINGS.render_unknown_cohost_blocks() {
+        return Ok("".to_owned());
+    }
+
+    Ok(CohostUnknownTemplate {
+        what: what.to_owned(),
+        kind,
+    }
+    .render()?)
+}
+
 fn process_ast(root: Ast) -> RcDom {
     let (dom, html_root) = create_fragment();
     let mut ast_queue = VecDeque::from([(root, html_root.clone())]);
@@ -340,23 +741,72 @@ struct CohostImgTemplate {
 #[template(path = "cohost-audio.html")]
 struct CohostAudioTemplate {
     data_cohost_src: String,
-    src: SitePath,
-    artist: String,
-    title: String,
+    src: String,
+    /// “{artist} — {title}”, or whichever of those are not empty.
+    caption: String,
+}
+
+#[derive(Template)]
+#[template(path = "cohost-video.html")]
+struct CohostVideoTemplate {
+    data_cohost_src: String,
+    src: String,
+    width: Option<usize>,
+    height: Option<usize>,
 }
 
+#[derive(Template)]
+#[template(path = "cohost-unknown.html")]
+struct CohostUnknownTemplate {
+    what: String,
+    kind: String,
+}
+
+#[derive(Template)]
+#[template(path = "cohost-redacted.html")]
+struct CohostRedactedTemplate {}
+
 #[derive(Template)]
 #[template(path = "ask.html")]
 struct AskTemplate {
-    author: Option<AskingProject>,
+    asker: Asker,
     content: String,
 }
 
+/// who asked an ask, in a form that the template can attribute without guessing.
+#[derive(Debug)]
+enum Asker {
+    Project(AskingProject),
+    /// `anon` is true. `logged_in` distinguishes “anonymous user” from “anonymous guest”.
+    Anonymous {
+        logged_in: bool,
+    },
+    /// `anon` is false, but there is no `askingProject`, which happens when the asker’s page has
+    /// since been deleted (or is private).
+    DeletedProject,
+}
+
+impl From<Ask> for Asker {
+    fn from(ask: Ask) -> Self {
+        match (ask.anon, ask.askingProject) {
+            // anon asks sometimes come with a project anyway. the asker wanted to be anonymous, so
+            // don’t reveal who they were.
+            (true, _) => Self::Anonymous {
+                logged_in: ask.loggedIn,
+            },
+            (false, Some(project)) => Self::Project(project),
+            (false, None) => Self::DeletedProject,
+        }
+    }
+}
+
 fn render_markdown_block(markdown: &str, context: &dyn AttachmentsContext) -> eyre::Result<String> {
-    let html = render_markdown(markdown);
-    let dom = parse_html_fragment(html.as_bytes())?;
+    render_cached("markdown", markdown, context, |context| {
+        let html = render_markdown(markdown);
+        let dom = parse_html_fragment(html.as_bytes())?;
 
-    process_chost_fragment(dom, context)
+        process_chost_fragment(dom, context)
+    })
 }
 
 fn process_chost_fragment(
@@ -471,17 +921,26 @@ fn process_chost_fragment(
                         });
                     }
                     if let Some(url) = url {
-                        if let Some(cacheable) = Cacheable::from_url(url) {
-                            trace!(url, "found cohost resource url in <CustomEmoji url>");
-                            attrs.borrow_mut().push(Attribute {
-                                name: QualName::attribute("src"),
-                                value: context
-                                    .cache_cohost_resource(&cacheable)?
-                                    .site_path()?
-                                    .base_relative_url()
-                                    .into(),
-                            });
-                        }
+                        // emoji are cached like other static assets, so each emoji is only
+                        // downloaded once, however many chosts use it. if we can’t cache it, keep
+                        // the original url, so the emoji still works for as long as it’s served.
+                        let src = match Cacheable::from_url(url) {
+                            Some(cacheable) => {
+                                trace!(url, "found cohost resource url in <CustomEmoji url>");
+                                match context.cache_cohost_resource(&cacheable) {
+                                    Ok(path) => path.site_path()?.base_relative_url(),
+                                    Err(error) => {
+                                        warn!(url, "failed to cache emoji: {error:?}");
+                                        url.to_owned()
+                                    }
+                                }
+                            }
+                            None => url.to_owned(),
+                        };
+                        attrs.borrow_mut().push(Attribute {
+                            name: QualName::attribute("src"),
+                            value: src.into(),
+                        });
                         attrs.borrow_mut().push(Attribute {
                             name: QualName::attribute("data-cohost-url"),
                             value: url.into(),
@@ -501,39 +960,238 @@ fn process_chost_fragment(
 }
 
 #[test]
-fn test_render_markdown_block() -> eyre::Result<()> {
-    use crate::path::AttachmentsPath;
-    struct TestAttachmentsContext {}
-    impl AttachmentsContext for TestAttachmentsContext {
-        fn store(&self, _input_path: &Path) -> eyre::Result<AttachmentsPath> {
-            unreachable!()
-        }
-        fn cache_imported(
-            &self,
-            _url: &str,
-            _post_basename: &str,
-        ) -> eyre::Result<AttachmentsPath> {
-            unreachable!();
-        }
-        fn cache_cohost_resource(&self, cacheable: &Cacheable) -> eyre::Result<AttachmentsPath> {
-            Ok(match cacheable {
-                Cacheable::Attachment { id } => AttachmentsPath::ROOT.join(&format!("{id}"))?,
-                Cacheable::Static { filename, .. } => {
-                    AttachmentsPath::COHOST_STATIC.join(&format!("{filename}"))?
-                }
-                Cacheable::Avatar { filename, .. } => {
-                    AttachmentsPath::COHOST_AVATAR.join(&format!("{filename}"))?
-                }
-                Cacheable::Header { filename, .. } => {
-                    AttachmentsPath::COHOST_HEADER.join(&format!("{filename}"))?
-                }
-            })
-        }
-        fn cache_cohost_thumb(&self, id: &str) -> eyre::Result<AttachmentsPath> {
-            Ok(AttachmentsPath::THUMBS.join(&format!("{id}"))?)
-        }
+fn test_checkpoint() -> eyre::Result<()> {
+    let dir = std::env::temp_dir().join(format!("autost-test-checkpoint-{}", uuid::Uuid::new_v4()));
+    create_dir_all(&dir)?;
+    let path = dir.join(CHECKPOINT_PATH);
+
+    // chosts marked done are skipped when resuming.
+    let checkpoint = Checkpoint::open(&path, false)?;
+    assert!(!checkpoint.is_done(123456));
+    checkpoint.mark_done(123456)?;
+    checkpoint.mark_done(7)?;
+    drop(checkpoint);
+    let checkpoint = Checkpoint::open(&path, false)?;
+    assert!(checkpoint.is_done(123456));
+    assert!(checkpoint.is_done(7));
+    drop(checkpoint);
+
+    // `--restart` forgets them.
+    let checkpoint = Checkpoint::open(&path, true)?;
+    assert!(!checkpoint.is_done(123456));
+    drop(checkpoint);
+    assert_eq!(std::fs::read_to_string(&path)?, "");
+
+    // a line cut short by a crash is ignored and removed, so it can’t skip the wrong chost or be
+    // appended to, and bad lines are ignored too.
+    write(&path, "123456\nnonsense\n1234")?;
+    let checkpoint = Checkpoint::open(&path, false)?;
+    assert!(checkpoint.is_done(123456));
+    assert!(!checkpoint.is_done(1234));
+    checkpoint.mark_done(99)?;
+    drop(checkpoint);
+    assert_eq!(std::fs::read_to_string(&path)?, "123456\nnonsense\n99\n");
+    let checkpoint = Checkpoint::open(&path, false)?;
+    assert!(checkpoint.is_done(99));
+    assert!(!checkpoint.is_done(1234));
+
+    std::fs::remove_dir_all(&dir)?;
+    Ok(())
+}
+
+#[test]
+fn test_cohost_audio_template() -> eyre::Result<()> {
+    let template = CohostAudioTemplate {
+        data_cohost_src:
+            "https://cohost.org/rc/attachment-redirect/44444444-4444-4444-4444-444444444444"
+                .to_owned(),
+        src: "attachments/44444444-4444-4444-4444-444444444444/song.mp3".to_owned(),
+        caption: "artist — title".to_owned(),
+    };
+    let html = template.render()?;
+    assert!(html.contains(r#"<audio controls data-cohost-src="https://cohost.org/rc/attachment-redirect/44444444-4444-4444-4444-444444444444" src="attachments/44444444-4444-4444-4444-444444444444/song.mp3"></audio>"#));
+    assert!(html.contains("<figcaption>artist — title</figcaption>"));
+
+    let template = CohostAudioTemplate {
+        caption: "".to_owned(),
+        ..template
+    };
+    assert!(!template.render()?.contains("<figcaption>"));
+
+    Ok(())
+}
+
+#[test]
+fn test_cohost_video_template() -> eyre::Result<()> {
+    let template = CohostVideoTemplate {
+        data_cohost_src:
+            "https://cohost.org/rc/attachment-redirect/55555555-5555-5555-5555-555555555555"
+                .to_owned(),
+        src: "attachments/55555555-5555-5555-5555-555555555555/clip.mp4".to_owned(),
+        width: Some(1280),
+        height: Some(720),
+    };
+    let html = template.render()?;
+    assert!(html.contains(r#"<video controls data-cohost-src="https://cohost.org/rc/attachment-redirect/55555555-5555-5555-5555-555555555555" src="attachments/55555555-5555-5555-5555-555555555555/clip.mp4" width="1280" height="720"></video>"#));
+
+    // the dimensions survive filtering, so the page doesn’t jump when the video loads.
+    let post = TemplatedPost::filter(&html, None)?;
+    assert!(post.safe_html.contains(r#"width="1280" height="720""#));
+
+    Ok(())
+}
+
+#[test]
+fn test_ask_template() -> eyre::Result<()> {
+    fn render(anon: bool, logged_in: bool, project: Option<&str>) -> eyre::Result<String> {
+        let ask = Ask {
+            content: "hi".to_owned(),
+            askingProject: project.map(|handle| AskingProject {
+                handle: handle.to_owned(),
+                displayName: "Staff".to_owned(),
+            }),
+            anon,
+            loggedIn: logged_in,
+        };
+        let template = AskTemplate {
+            asker: Asker::from(ask),
+            content: "<p>hi</p>".to_owned(),
+        };
+        Ok(template.render()?)
     }
 
+    let html = render(false, true, Some("staff"))?;
+    assert!(html.contains(r#"<a href="https://cohost.org/staff">Staff</a>"#));
+    assert!(html.contains(r#"<a href="https://cohost.org/staff" class="handle">@staff</a>"#));
+    assert!(render(true, true, None)?.contains("anonymous user asked…"));
+    assert!(render(true, false, None)?.contains("anonymous guest asked…"));
+    assert!(render(false, true, None)?.contains("a deleted or private page asked…"));
+    let html = render(true, true, Some("staff"))?;
+    assert!(html.contains("anonymous user asked…"));
+    assert!(!html.contains("staff"));
+
+    Ok(())
+}
+
+#[test]
+fn test_process_ast_attributes() -> eyre::Result<()> {
+    let ast = serde_json::from_str::<Ast>(
+        r#"{"type": "root", "children": [{"type": "element", "tagName": "div", "properties": {"dataFoo": "bar", "ariaLabel": "baz", "onClick": "alert(1)", "onmouseover": "alert(2)"}, "children": []}]}"#,
+    )?;
+    let html = serialize_html_fragment(process_ast(ast))?;
+    assert_eq!(html, r#"<div aria-label="baz" data-foo="bar"></div>"#);
+
+    Ok(())
+}
+
+#[cfg(test)]
+use crate::path::AttachmentsPath;
+
+/// resolves attachments to their cached paths without downloading anything.
+#[cfg(test)]
+struct TestAttachmentsContext {}
+#[cfg(test)]
+impl AttachmentsContext for TestAttachmentsContext {
+    fn store(&self, _input_path: &Path) -> crate::error::Result<AttachmentsPath> {
+        unreachable!()
+    }
+    fn cache_imported(
+        &self,
+        _url: &str,
+        _post_basename: &str,
+    ) -> crate::error::Result<AttachmentsPath> {
+        unreachable!();
+    }
+    fn cache_cohost_resource(
+        &self,
+        cacheable: &Cacheable,
+    ) -> crate::error::Result<AttachmentsPath> {
+        Ok(match cacheable {
+            Cacheable::Attachment { id } => AttachmentsPath::ROOT.join(&format!("{id}"))?,
+            Cacheable::Static { filename, .. } => {
+                AttachmentsPath::COHOST_STATIC.join(&format!("{filename}"))?
+            }
+            Cacheable::Avatar { filename, .. } => {
+                AttachmentsPath::COHOST_AVATAR.join(&format!("{filename}"))?
+            }
+            Cacheable::Header { filename, .. } => {
+                AttachmentsPath::COHOST_HEADER.join(&format!("{filename}"))?
+            }
+        })
+    }
+    fn cache_cohost_thumb(&self, id: &str) -> crate::error::Result<AttachmentsPath> {
+        Ok(AttachmentsPath::THUMBS.join(&format!("{id}"))?)
+    }
+}
+
+#[test]
+fn test_write_chost_body() -> eyre::Result<()> {
+    use crate::cohost::{Markdown, Span};
+    let markdown = |content: &str| Block::Markdown {
+        markdown: Markdown {
+            content: content.to_owned(),
+        },
+    };
+    let span = |text: &str, start, end| Span {
+        ast: format!(
+            r#"{{"type": "root", "children": [{{"type": "element", "tagName": "p", "properties": {{}}, "children": [{{"type": "text", "value": "{text}"}}]}}]}}"#
+        ),
+        startIndex: start,
+        endIndex: end,
+    };
+    let body = |spans, blocks| -> eyre::Result<String> {
+        let mut output = vec![];
+        write_chost_body(
+            1,
+            AstMap { spans },
+            blocks,
+            &mut output,
+            &TestAttachmentsContext {},
+        )?;
+        Ok(String::from_utf8(output)?)
+    };
+    let blocks = || {
+        vec![
+            markdown("one"),
+            markdown("two"),
+            markdown("three"),
+            markdown("four"),
+        ]
+    };
+
+    // spans replace the blocks they cover, and blocks without spans are rendered from markdown.
+    assert_eq!(
+        body(
+            vec![span("one and two", 0, 2), span("four", 3, 4)],
+            blocks()
+        )?,
+        "<p>one and two</p><p>three</p>\n<p>four</p>"
+    );
+    // with no spans, every block is rendered from markdown.
+    assert_eq!(
+        body(vec![], blocks())?,
+        "<p>one</p>\n<p>two</p>\n<p>three</p>\n<p>four</p>\n"
+    );
+    // overlapping, empty, and out of range spans are skipped, and their blocks rendered from
+    // markdown instead.
+    assert_eq!(
+        body(
+            vec![
+                span("one", 0, 1),
+                span("one and two", 0, 2),
+                span("nothing", 2, 2),
+                span("four and more", 3, 5),
+            ],
+            blocks()
+        )?,
+        "<p>one</p><p>two</p>\n<p>three</p>\n<p>four</p>\n"
+    );
+
+    Ok(())
+}
+
+#[test]
+fn test_render_markdown_block() -> eyre::Result<()> {
     let n = "\n";
     let context = TestAttachmentsContext {};
     assert_eq!(
@@ -549,5 +1207,62 @@ fn test_render_markdown_block() -> eyre::Result<()> {
     assert_eq!(render_markdown_block("<a href=https://cohost.org/rc/attachment-redirect/44444444-4444-4444-4444-444444444444>text</a>", &context)?,
         format!(r#"<p><a href="attachments/44444444-4444-4444-4444-444444444444" data-cohost-href="https://cohost.org/rc/attachment-redirect/44444444-4444-4444-4444-444444444444">text</a></p>{n}"#));
 
+    let emoji = |url: &str| -> eyre::Result<String> {
+        let ast = serde_json::from_value::<Ast>(serde_json::json!({
+            "type": "root",
+            "children": [{"type": "element", "tagName": "CustomEmoji", "properties": {"name": "eggbug", "url": url}, "children": []}],
+        }))?;
+        process_chost_fragment(process_ast(ast), &context)
+    };
+    assert_eq!(
+        emoji("https://cohost.org/static/f0c56e99113f1a0731b4.svg")?,
+        r#"<img alt=":eggbug:" title=":eggbug:" src="attachments/cohost-static/f0c56e99113f1a0731b4.svg" data-cohost-url="https://cohost.org/static/f0c56e99113f1a0731b4.svg">"#
+    );
+    assert_eq!(
+        emoji("https://example.com/eggbug.png")?,
+        r#"<img alt=":eggbug:" title=":eggbug:" src="https://example.com/eggbug.png" data-cohost-url="https://example.com/eggbug.png">"#
+    );
+
+    // blocks covered by an astMap span use cohost’s rendering, and the gaps use our own.
+    let body = |spans: serde_json::Value| -> eyre::Result<String> {
+        let blocks = serde_json::from_value::<Vec<Block>>(serde_json::json!([
+            {"type": "markdown", "markdown": {"content": "*a*"}},
+            {"type": "markdown", "markdown": {"content": "b"}},
+            {"type": "markdown", "markdown": {"content": "c"}},
+        ]))?;
+        let ast_map = serde_json::from_value::<AstMap>(serde_json::json!({ "spans": spans }))?;
+        let mut output = vec![];
+        write_chost_body(1, ast_map, blocks, &mut output, &context)?;
+        Ok(String::from_utf8(output)?)
+    };
+    let ast = |text: &str| {
+        serde_json::json!({
+            "type": "root",
+            "children": [{"type": "element", "tagName": "p", "properties": {}, "children": [{"type": "text", "value": text}]}],
+        })
+        .to_string()
+    };
+    assert_eq!(
+        body(serde_json::json!([]))?,
+        format!("<p><em>a</em></p>{n}<p>b</p>{n}<p>c</p>{n}")
+    );
+    assert_eq!(
+        body(serde_json::json!([
+            {"ast": ast("cohost c"), "startIndex": 2, "endIndex": 3},
+            {"ast": ast("cohost a"), "startIndex": 0, "endIndex": 1},
+        ]))?,
+        format!("<p>cohost a</p><p>b</p>{n}<p>cohost c</p>")
+    );
+    // bad and overlapping spans are skipped, rather than dropping or repeating blocks.
+    assert_eq!(
+        body(serde_json::json!([
+            {"ast": ast("cohost a"), "startIndex": 0, "endIndex": 1},
+            {"ast": ast("overlap"), "startIndex": 0, "endIndex": 2},
+            {"ast": ast("empty"), "startIndex": 1, "endIndex": 1},
+            {"ast": ast("past the end"), "startIndex": 2, "endIndex": 4},
+        ]))?,
+        format!("<p>cohost a</p><p>b</p>{n}<p>c</p>{n}")
+    );
+
     Ok(())
 }
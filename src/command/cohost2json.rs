@@ -1,7 +1,9 @@
 use std::{
+    collections::BTreeSet,
     env::{self},
     fs::{create_dir_all, File},
     path::Path,
+    time::Duration,
 };
 
 use jane_eyre::eyre::{self, bail, OptionExt};
@@ -10,7 +12,8 @@ use reqwest::{
     Client,
 };
 use scraper::{selector::Selector, Html};
-use tracing::{info, warn};
+use serde_json::Value;
+use tracing::{debug, info, warn};
 
 use crate::cohost::{
     LikedPostsState, ListEditedProjectsResponse, LoggedInResponse, Post, PostsResponse,
@@ -24,6 +27,13 @@ pub struct Cohost2json {
 
     #[arg(long, help = "dump liked posts (requires COHOST_COOKIE)")]
     pub liked: bool,
+
+    #[arg(
+        long,
+        default_value_t = 100,
+        help = "delay between requests, in milliseconds"
+    )]
+    pub delay_ms: u64,
 }
 
 pub async fn main(args: Cohost2json) -> eyre::Result<()> {
@@ -104,25 +114,12 @@ pub async fn main(args: Cohost2json) -> eyre::Result<()> {
         Client::builder().build()?
     };
 
-    for page in 0.. {
-        let url =
-            format!("https://cohost.org/api/v1/project/{requested_project}/posts?page={page}");
-        info!("GET {url}");
-        let response: PostsResponse = client.get(url).send().await?.json().await?;
-
-        // nItems may be zero if none of the posts on this page are currently visible,
-        // but nPages will only be zero when we have run out of pages.
-        if response.nPages == 0 {
-            break;
-        }
-
-        for post_value in response.items {
-            let post: Post = serde_json::from_value(post_value.clone())?;
-            let path = output_path.join(format!("{}.json", post.postId));
-            info!("Writing {path:?}");
-            let output_file = File::create(path)?;
-            serde_json::to_writer(output_file, &post_value)?;
-        }
+    let delay = Duration::from_millis(args.delay_ms);
+    for (post, post_value) in fetch_all_posts(&client, &requested_project, delay).await? {
+        let path = output_path.join(format!("{}.json", post.postId));
+        info!("Writing {path:?}");
+        let output_file = File::create(path)?;
+        serde_json::to_writer(output_file, &post_value)?;
     }
 
     if dump_liked {
@@ -163,3 +160,51 @@ pub async fn main(args: Cohost2json) -> eyre::Result<()> {
 
     Ok(())
 }
+
+/// fetch every page of posts for the given project, waiting `delay` between requests.
+///
+/// posts are deduplicated by `postId`, since the pages may shift under us if the project publishes
+/// new posts while we are fetching. returns each post along with its original json.
+pub async fn fetch_all_posts(
+    client: &Client,
+    project_name: &str,
+    delay: Duration,
+) -> eyre::Result<Vec<(Post, Value)>> {
+    let mut result = vec![];
+    let mut seen_post_ids = BTreeSet::default();
+
+    for page in 0.. {
+        if page > 0 {
+            tokio::time::sleep(delay).await;
+        }
+        let url = format!("https://cohost.org/api/v1/project/{project_name}/posts?page={page}");
+        info!("GET {url}");
+        let response: PostsResponse = client.get(url).send().await?.json().await?;
+
+        // nItems may be zero if none of the posts on this page are currently visible,
+        // but nPages will only be zero when we have run out of pages.
+        if response.nPages == 0 {
+            break;
+        }
+
+        let mut new_posts = 0;
+        for post_value in response.items {
+            let post: Post = serde_json::from_value(post_value.clone())?;
+            if !seen_post_ids.insert(post.postId) {
+                debug!(post.postId, "skipping duplicate post (pages shifted?)");
+                continue;
+            }
+            result.push((post, post_value));
+            new_posts += 1;
+        }
+        info!(
+            page,
+            nItems = response.nItems,
+            nPages = response.nPages,
+            "fetched {new_posts} new posts ({} total)",
+            result.len()
+        );
+    }
+
+    Ok(result)
+}
@@ -153,6 +153,7 @@ async fn archive_cohost_project(project_name: &str, archive_likes: bool) -> eyre
             project_name: project_name.to_owned(),
             path_to_chosts: "chosts".to_owned(),
             liked: archive_likes,
+            delay_ms: 100,
         })
         .await?;
         File::create("cohost2json.done")?;
@@ -163,6 +164,10 @@ async fn archive_cohost_project(project_name: &str, archive_likes: bool) -> eyre
         crate::command::cohost2autost::main(Cohost2autost {
             path_to_chosts: "chosts".to_owned(),
             specific_chost_filenames: vec![],
+            restart: false,
+            refresh_resources: false,
+            liked: false,
+            offline: false,
         })?;
         File::create("cohost2autost.done")?;
     }
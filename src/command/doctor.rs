@@ -0,0 +1,147 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fs::{exists, read_dir},
+};
+
+use jane_eyre::eyre::{self, bail};
+
+use crate::{
+    command::validate_config::{check_interesting_tags, check_settings},
+    path::{AttachmentsPath, PostsPath},
+    TemplatedPost, SETTINGS,
+};
+
+#[derive(clap::Args, Debug)]
+pub struct Doctor {}
+
+#[derive(Default)]
+pub(crate) struct Report {
+    /// category → (is_error, message)
+    inner: BTreeMap<&'static str, Vec<(bool, String)>>,
+}
+
+impl Report {
+    pub(crate) fn error(&mut self, category: &'static str, message: String) {
+        self.inner
+            .entry(category)
+            .or_default()
+            .push((true, message));
+    }
+
+    pub(crate) fn warning(&mut self, category: &'static str, message: String) {
+        self.inner
+            .entry(category)
+            .or_default()
+            .push((false, message));
+    }
+
+    pub(crate) fn ok(&mut self, category: &'static str) {
+        self.inner.entry(category).or_default();
+    }
+
+    pub(crate) fn error_count(&self) -> usize {
+        self.inner
+            .values()
+            .flatten()
+            .filter(|(is_error, _)| *is_error)
+            .count()
+    }
+
+    pub(crate) fn print(&self) {
+        for (category, problems) in self.inner.iter() {
+            if problems.is_empty() {
+                println!("[{category}] ok");
+            }
+            for (is_error, message) in problems {
+                let level = if *is_error { "error" } else { "warning" };
+                println!("[{category}] {level}: {message}");
+            }
+        }
+    }
+}
+
+pub fn main(_args: Doctor) -> eyre::Result<()> {
+    let mut report = Report::default();
+
+    check_posts(&mut report)?;
+    check_attachments(&mut report)?;
+    check_settings(&mut report, &SETTINGS);
+
+    report.print();
+    let error_count = report.error_count();
+    if error_count > 0 {
+        bail!("found {error_count} errors");
+    }
+
+    Ok(())
+}
+
+fn check_posts(report: &mut Report) -> eyre::Result<()> {
+    report.ok("posts");
+    report.ok("references");
+    report.ok("tags");
+
+    let entries = match read_dir(&*PostsPath::ROOT) {
+        Ok(entries) => entries,
+        Err(error) => {
+            report.error("posts", format!("failed to read posts directory: {error}"));
+            return Ok(());
+        }
+    };
+
+    let mut tags_seen = BTreeSet::default();
+    for entry in entries {
+        let entry = entry?;
+        if entry.metadata()?.is_dir() {
+            continue;
+        }
+        let path = PostsPath::ROOT.join_dir_entry(&entry)?;
+        if !path.is_post() {
+            continue;
+        }
+        let post = match TemplatedPost::load(&path) {
+            Ok(post) => post,
+            Err(error) => {
+                report.error("posts", format!("{path:?}: failed to load: {error}"));
+                continue;
+            }
+        };
+        for reference in post.meta.references.iter() {
+            if !exists(reference)? {
+                report.error(
+                    "references",
+                    format!("{path:?}: references missing post: {reference:?}"),
+                );
+            }
+        }
+        let tags = SETTINGS.extra_archived_thread_tags(&post).to_vec();
+        let tags = tags.into_iter().chain(post.meta.tags).collect();
+        tags_seen.extend(SETTINGS.resolve_tags(tags));
+    }
+    check_interesting_tags(report, &SETTINGS, &tags_seen);
+
+    Ok(())
+}
+
+fn check_attachments(report: &mut Report) -> eyre::Result<()> {
+    report.ok("attachments");
+    if !exists(&*AttachmentsPath::ROOT)? {
+        return Ok(());
+    }
+
+    let mut dirs = vec![AttachmentsPath::ROOT.to_owned()];
+    while let Some(dir) = dirs.pop() {
+        for entry in read_dir(&dir)? {
+            let entry = entry?;
+            let path = dir.join_dir_entry(&entry)?;
+            let metadata = entry.metadata()?;
+            if metadata.is_dir() {
+                dirs.push(path);
+            } else if metadata.len() == 0 {
+                report.error("attachments", format!("{path:?}: file is empty"));
+            }
+        }
+    }
+
+    Ok(())
+}
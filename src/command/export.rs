@@ -0,0 +1,136 @@
+use std::{
+    collections::BTreeMap,
+    fs::{exists, read, read_dir},
+    path::{Path, PathBuf},
+};
+
+use jane_eyre::eyre::{self, bail, Context};
+use serde::Serialize;
+use sha2::{digest::generic_array::functional::FunctionalSequence, Digest, Sha256};
+use tracing::{info, warn};
+
+use crate::{
+    archive::{write_archive, ArchiveEntries, ArchiveFormat},
+    command::gc::{referenced_attachments, shared_attachment_dirs},
+    path::PostsPath,
+};
+
+#[derive(clap::Args, Debug)]
+pub struct Export {
+    #[arg(help = "path to the backup, ending in .tar, .tar.gz, .tgz, or .zip")]
+    path: PathBuf,
+
+    #[arg(
+        long,
+        help = "also include cohost emoji, avatars, and headers (shared by many posts, and left out by default)"
+    )]
+    shared: bool,
+}
+
+/// the name of the manifest in the backup.
+const MANIFEST_PATH: &str = "autost-export.json";
+
+/// `autost-export.json`, which lists every other file in the backup, so you can check it.
+#[derive(Debug, Serialize)]
+struct ExportManifest {
+    autost_version: &'static str,
+    files: Vec<ExportedFile>,
+}
+
+#[derive(Debug, Serialize)]
+struct ExportedFile {
+    /// the path in the backup, which is also the path in the site, like `posts/1.md`.
+    path: String,
+    bytes: u64,
+    sha256: String,
+}
+
+pub fn main(args: Export) -> eyre::Result<()> {
+    if !exists(&*PostsPath::ROOT)? {
+        bail!("no posts directory; run autost export in your site directory");
+    }
+    // check the extension first, so we don’t read every file only to fail at the end.
+    ArchiveFormat::from_path(&args.path)?;
+
+    // archive path → path in the site, which are the same, but the archive always uses `/`.
+    let mut files = BTreeMap::default();
+    if exists("autost.toml")? {
+        files.insert("autost.toml".to_owned(), PathBuf::from("autost.toml"));
+    }
+    let mut dirs = vec![PostsPath::ROOT.to_owned()];
+    while let Some(dir) = dirs.pop() {
+        for entry in read_dir(&dir)? {
+            let entry = entry?;
+            let path = dir.join_dir_entry(&entry)?;
+            if entry.metadata()?.is_dir() {
+                dirs.push(path);
+            } else {
+                files.insert(archive_path(path.as_ref()), path.as_ref().to_owned());
+            }
+        }
+    }
+    let post_count = files.len();
+
+    // like `autost gc`, if any post fails to load, we can’t know what it references.
+    let shared_dirs = shared_attachment_dirs();
+    let referenced =
+        referenced_attachments().wrap_err("failed to collect referenced attachments")?;
+    let mut skipped_shared = 0;
+    for path in referenced {
+        if !args.shared && shared_dirs.iter().any(|dir| path.as_ref().starts_with(dir)) {
+            skipped_shared += 1;
+            continue;
+        }
+        if !exists(&path)? {
+            warn!("skipping missing attachment: {path:?}");
+            continue;
+        }
+        files.insert(archive_path(path.as_ref()), path.as_ref().to_owned());
+    }
+    info!(
+        "exporting {post_count} files from posts/ and autost.toml, and {} attachments",
+        files.len() - post_count
+    );
+    if skipped_shared > 0 {
+        info!("skipped {skipped_shared} cohost emoji, avatars, and headers (use --shared to include them)");
+    }
+
+    let mut entries = ArchiveEntries::default();
+    let mut manifest = ExportManifest {
+        autost_version: env!("CARGO_PKG_VERSION"),
+        files: vec![],
+    };
+    for (archive_path, fs_path) in files {
+        let content = read(&fs_path).wrap_err_with(|| format!("failed to read {fs_path:?}"))?;
+        manifest.files.push(ExportedFile {
+            path: archive_path.clone(),
+            bytes: content.len() as u64,
+            sha256: Sha256::digest(&content)
+                .map(|o| format!("{o:02x}"))
+                .join(""),
+        });
+        entries.insert_file(&archive_path, fs_path);
+    }
+    let mut manifest = serde_json::to_vec_pretty(&manifest)?;
+    manifest.push(b'\n');
+    entries.insert_data(MANIFEST_PATH, manifest);
+    write_archive(&args.path, &entries)?;
+
+    Ok(())
+}
+
+/// returns the path with `/` as the separator, for paths in the archive.
+fn archive_path(path: &Path) -> String {
+    path.components()
+        .map(|component| component.as_os_str().to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+#[test]
+fn test_archive_path() {
+    assert_eq!(
+        archive_path(&Path::new("attachments").join("1").join("a.png")),
+        "attachments/1/a.png"
+    );
+}
@@ -0,0 +1,185 @@
+use std::{
+    collections::BTreeSet,
+    fs::{exists, metadata, read_dir, remove_dir_all, remove_file},
+};
+
+use jane_eyre::eyre::{self, Context};
+use tracing::{info, warn};
+
+use crate::{
+    attachments::{INTEGRITY_SUFFIX, VALIDATORS_SUFFIX},
+    path::{AttachmentsPath, PostsPath},
+    TemplatedPost,
+};
+
+#[derive(clap::Args, Debug)]
+pub struct Gc {
+    #[arg(long, help = "list what would be removed, without removing anything")]
+    dry_run: bool,
+
+    #[arg(
+        long,
+        help = "also remove unreferenced cohost emoji, avatars, and headers (cheap to re-fetch, but shared by many posts)"
+    )]
+    shared: bool,
+}
+
+pub fn main(args: Gc) -> eyre::Result<()> {
+    if !exists(&*AttachmentsPath::ROOT)? {
+        info!("no attachments directory, nothing to do");
+        return Ok(());
+    }
+
+    // if any post fails to load, we can’t know what it references, so don’t remove anything.
+    let referenced =
+        referenced_attachments().wrap_err("failed to collect referenced attachments")?;
+    info!("posts reference {} attachment files", referenced.len());
+
+    let garbage = unreferenced_attachments(&referenced, args.shared)?;
+    let mut total_bytes = 0;
+    for path in garbage.iter() {
+        total_bytes += disk_usage(path)?;
+        if args.dry_run {
+            println!("{}", path.as_ref().display());
+        } else {
+            info!("removing {path:?}");
+            if metadata(path)?.is_dir() {
+                remove_dir_all(path)?;
+            } else {
+                remove_file(path)?;
+            }
+        }
+    }
+
+    let verb = if args.dry_run {
+        "would remove"
+    } else {
+        "removed"
+    };
+    info!(
+        "{verb} {} unreferenced attachments ({total_bytes} bytes)",
+        garbage.len()
+    );
+
+    Ok(())
+}
+
+/// returns the attachment files needed by any post in `posts/`, including posts in the
+/// subdirectories created by `autost cohost2autost` and `autost import`.
+pub(crate) fn referenced_attachments() -> eyre::Result<BTreeSet<AttachmentsPath>> {
+    let mut result = BTreeSet::default();
+    if !exists(&*PostsPath::ROOT)? {
+        warn!("no posts directory, so no attachments are referenced");
+        return Ok(result);
+    }
+
+    let mut dirs = vec![PostsPath::ROOT.to_owned()];
+    while let Some(dir) = dirs.pop() {
+        for entry in read_dir(&dir)? {
+            let entry = entry?;
+            let path = dir.join_dir_entry(&entry)?;
+            if entry.metadata()?.is_dir() {
+                dirs.push(path);
+                continue;
+            }
+            let filename = path.filename();
+            if !filename.ends_with(".html") && !filename.ends_with(".md") {
+                continue;
+            }
+            let post = TemplatedPost::load(&path).wrap_err_with(|| format!("{path:?}"))?;
+            for site_path in post.needs_attachments.iter() {
+                if let Some(attachments_path) = site_path.attachments_path()? {
+                    result.insert(attachments_path);
+                }
+            }
+        }
+    }
+
+    Ok(result)
+}
+
+/// returns the attachment directories (and shared cache files, if `shared` is true) that contain
+/// no referenced attachment files.
+///
+/// attachments and thumbs are cached in a directory per attachment id, like
+/// `attachments/<id>/<filename>` and `attachments/thumbs/<id>/<filename>`, so we remove whole
+/// directories. the shared cohost caches are flat directories of files, so we remove files.
+fn unreferenced_attachments(
+    referenced: &BTreeSet<AttachmentsPath>,
+    shared: bool,
+) -> eyre::Result<Vec<AttachmentsPath>> {
+    let shared_dirs = shared_attachment_dirs();
+    let is_referenced = |dir: &AttachmentsPath| {
+        referenced
+            .iter()
+            .any(|path| path.as_ref().starts_with(dir.as_ref()))
+    };
+
+    let mut result = vec![];
+    for entry in read_dir(&*AttachmentsPath::ROOT)? {
+        let entry = entry?;
+        let path = AttachmentsPath::ROOT.join_dir_entry(&entry)?;
+        if !entry.metadata()?.is_dir() {
+            // not created by autost, so leave it alone.
+            continue;
+        }
+        if shared_dirs.contains(&path) {
+            if shared {
+                for entry in read_dir(&path)? {
+                    let file = path.join_dir_entry(&entry?)?;
+                    // keep the saved validators and sizes of referenced files too.
+                    let filename = file.filename();
+                    let cached_file = match filename
+                        .strip_suffix(VALIDATORS_SUFFIX)
+                        .or_else(|| filename.strip_suffix(INTEGRITY_SUFFIX))
+                    {
+                        Some(filename) => path.join(filename)?,
+                        None => file.clone(),
+                    };
+                    if !referenced.contains(&cached_file) {
+                        result.push(file);
+                    }
+                }
+            }
+        } else if path == *AttachmentsPath::THUMBS {
+            for entry in read_dir(&path)? {
+                let dir = path.join_dir_entry(&entry?)?;
+                if !is_referenced(&dir) {
+                    result.push(dir);
+                }
+            }
+        } else if !is_referenced(&path) {
+            result.push(path);
+        }
+    }
+
+    Ok(result)
+}
+
+/// the caches of cohost emoji, avatars, and headers, which are shared by many posts.
+pub(crate) fn shared_attachment_dirs() -> [AttachmentsPath; 4] {
+    #[allow(deprecated)]
+    [
+        AttachmentsPath::COHOST_STATIC.to_owned(),
+        AttachmentsPath::COHOST_AVATAR.to_owned(),
+        AttachmentsPath::COHOST_HEADER.to_owned(),
+        AttachmentsPath::EMOJI.to_owned(),
+    ]
+}
+
+fn disk_usage(path: &AttachmentsPath) -> eyre::Result<u64> {
+    let mut result = 0;
+    let mut paths = vec![path.to_owned()];
+    while let Some(path) = paths.pop() {
+        let metadata = metadata(&path)?;
+        if metadata.is_dir() {
+            for entry in read_dir(&path)? {
+                paths.push(path.join_dir_entry(&entry?)?);
+            }
+        } else {
+            result += metadata.len();
+        }
+    }
+
+    Ok(result)
+}
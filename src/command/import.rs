@@ -1,7 +1,9 @@
 use std::{
-    fs::{create_dir_all, File},
+    collections::{BTreeMap, BTreeSet},
+    fs::{create_dir_all, exists, rename, File},
     io::{self, Write},
     rc::Rc,
+    time::Duration,
 };
 
 use askama::Template;
@@ -10,6 +12,7 @@ use jane_eyre::eyre::{self, bail, OptionExt};
 use markup5ever_rcdom::{Handle, NodeData};
 use tracing::{debug, info, trace};
 use url::Url;
+use uuid::Uuid;
 
 use crate::{
     attachments::{AttachmentsContext, RealAttachmentsContext},
@@ -18,6 +21,7 @@ use crate::{
         parse_html_document, parse_html_fragment, serialize_html_fragment, serialize_node_contents,
         text_content, AttrsRefExt, BreadthTraverse, QualName, QualNameExt, TendrilExt,
     },
+    error::Error,
     migrations::run_migrations,
     path::PostsPath,
     Author, PostMeta, TemplatedPost,
@@ -69,7 +73,8 @@ pub async fn main(args: Import) -> eyre::Result<()> {
     }
 
     let (path, file) = result.ok_or_eyre("too many posts :(")?;
-    write_post(file, meta, e_content, base_href, path)?;
+    write_post(file, meta, e_content, base_href, path.clone())?;
+    info!("click here to reply: {}", path.compose_reply_url());
 
     Ok(())
 }
@@ -91,16 +96,61 @@ pub async fn reimport(args: Reimport) -> eyre::Result<()> {
 
     info!("updating existing post: {path:?}");
     let file = File::create(&path)?;
-    write_post(file, meta, e_content, base_href, path)?;
+    write_post(file, meta, e_content, base_href, path.clone())?;
+    info!("click here to reply: {}", path.compose_reply_url());
 
     Ok(())
 }
 
+/// returns the cached copy of the remote post at `url`, for `<link rel=references>` to posts on
+/// other sites, fetching and importing it if needed.
+pub fn cache_remote_reference(url: &str) -> eyre::Result<PostsPath> {
+    let path = PostsPath::remote_reference_path(url)?;
+    if exists(&path)? {
+        trace!("cache hit: {url}");
+        return Ok(path);
+    }
+
+    trace!("cache miss: {url}");
+    info!("GET {url}");
+    let client = reqwest::blocking::Client::builder()
+        .timeout(REMOTE_REFERENCE_TIMEOUT)
+        .build()?;
+    let response = client.get(url).send()?.error_for_status()?;
+    let FetchPostResult {
+        base_href,
+        e_content,
+        u_url: _,
+        meta,
+    } = parse_post(url, &response.bytes()?)?;
+
+    // write then rename, since threads rendered in parallel may reference the same post.
+    create_dir_all(&*PostsPath::IMPORTED)?;
+    let temp_path = PostsPath::IMPORTED.join(&format!(".{}.tmp", Uuid::new_v4()))?;
+    write_post(
+        File::create(&temp_path)?,
+        meta,
+        e_content,
+        base_href,
+        path.clone(),
+    )?;
+    rename(temp_path, &path)?;
+
+    Ok(path)
+}
+
+const REMOTE_REFERENCE_TIMEOUT: Duration = Duration::from_secs(10);
+
 async fn fetch_post(url: &str) -> eyre::Result<FetchPostResult> {
     info!("GET {url}");
     let client = reqwest::Client::new();
     let response = client.get(url).send().await?;
-    let dom = parse_html_document(&response.bytes().await?)?;
+
+    parse_post(url, &response.bytes().await?)
+}
+
+fn parse_post(url: &str, body: &[u8]) -> eyre::Result<FetchPostResult> {
+    let dom = parse_html_document(body)?;
     let mut base_href = Url::parse(&url)?;
     for node in BreadthTraverse::elements(dom.document.clone()) {
         let NodeData::Element { name, attrs, .. } = &node.data else {
@@ -121,6 +171,7 @@ async fn fetch_post(url: &str) -> eyre::Result<FetchPostResult> {
 
     let u_url = mf2_u(h_entry.clone(), "u-url", &base_href)?;
     let dt_published = mf2_dt(h_entry.clone(), "dt-published")?;
+    let dt_updated = mf2_dt(h_entry.clone(), "dt-updated")?;
     let p_name = mf2_p(h_entry.clone(), "p-name")?;
     let p_author = mf2_find(h_entry.clone(), "p-author").ok_or_eyre(".h-entry has no .p-author")?;
     let p_category = mf2_find_all(h_entry.clone(), "p-category");
@@ -174,11 +225,26 @@ async fn fetch_post(url: &str) -> eyre::Result<FetchPostResult> {
     let meta = PostMeta {
         archived: Some(u_url.to_string()),
         references: vec![], // TODO: define a cohost-like h-entry extension for this?
+        remote_references: vec![],
         title: p_name,
         published: dt_published,
+        updated: dt_updated,
         author: Some(author),
+        contributors: vec![],
         tags,
         is_transparent_share: false,
+        content_warning: None,
+        is_redacted: false,
+        slug: None,
+        lang: None,
+        series: None,
+        series_index: None,
+        is_unlisted: false,
+        is_liked: false,
+        extra: Default::default(),
+        engagement: Default::default(),
+        edits: vec![],
+        fediverse_url: None,
     };
     debug!(?meta);
 
@@ -201,10 +267,14 @@ fn write_post(
     file.write_all(meta.render()?.as_bytes())?;
     file.write_all(b"\n\n")?;
     let basename = path.basename().ok_or_eyre("path has no basename")?;
-    let unsafe_html = process_content(&e_content, basename, &base_href, &RealAttachmentsContext)?;
-    let post = TemplatedPost::filter(&unsafe_html, Some(path.clone()))?;
+    let unsafe_html = process_content(
+        &e_content,
+        basename,
+        &base_href,
+        &RealAttachmentsContext::default(),
+    )?;
+    let post = TemplatedPost::filter(&unsafe_html, Some(path))?;
     file.write_all(post.safe_html.as_bytes())?;
-    info!("click here to reply: {}", path.compose_reply_url());
 
     Ok(())
 }
@@ -224,6 +294,26 @@ fn process_content(
 ) -> eyre::Result<String> {
     let dom = parse_html_fragment(content.as_bytes())?;
 
+    // find the attachments first, so they can be cached all at once, which may be concurrent.
+    let mut fetch_urls = BTreeSet::default();
+    for node in BreadthTraverse::nodes(dom.document.clone()) {
+        if let NodeData::Element { name, attrs, .. } = &node.data {
+            if let Some(attr_names) = html_attributes_with_embedding_urls().get(name) {
+                for attr in attrs.borrow().iter() {
+                    if attr_names.contains(&attr.name) {
+                        fetch_urls.insert(base_href.join(attr.value.to_str())?.to_string());
+                    }
+                }
+            }
+        }
+    }
+    let fetch_urls = fetch_urls.into_iter().collect::<Vec<_>>();
+    let mut cached_attachments = fetch_urls
+        .iter()
+        .cloned()
+        .zip(context.cache_many_imported(&fetch_urls, post_basename))
+        .collect::<BTreeMap<_, _>>();
+
     for node in BreadthTraverse::nodes(dom.document.clone()) {
         match &node.data {
             NodeData::Element { name, attrs, .. } => {
@@ -240,11 +330,20 @@ fn process_content(
                                 name.local,
                                 attr.name.local
                             );
-                            attr.value = context
-                                .cache_imported(&fetch_url.to_string(), post_basename)?
-                                .site_path()?
-                                .base_relative_url()
-                                .into();
+                            let fetch_url_string = fetch_url.to_string();
+                            let cached =
+                                match cached_attachments.remove(&fetch_url_string).unwrap_or_else(
+                                    || context.cache_imported(&fetch_url_string, post_basename),
+                                ) {
+                                    Ok(cached) => cached,
+                                    // leave the url in place, made absolute like the urls in links.
+                                    Err(Error::BlockedHost(_) | Error::Offline(_)) => {
+                                        attr.value = fetch_url.to_string().into();
+                                        continue;
+                                    }
+                                    Err(error) => Err(error)?,
+                                };
+                            attr.value = cached.site_path()?.base_relative_url().into();
                             extra_attrs.push(Attribute {
                                 name: QualName::attribute(&format!(
                                     "data-import-{}",
@@ -0,0 +1,83 @@
+use std::{env, fs::create_dir_all};
+
+use jane_eyre::eyre;
+use reqwest::{
+    header::{self, HeaderMap, HeaderValue},
+    Client,
+};
+use tracing::info;
+use url::Url;
+
+use crate::{
+    attachments::RealAttachmentsContext,
+    cohost::{parse_chost_url, SinglePostResponse, TrpcResponse},
+    command::cohost2autost::convert_post,
+    migrations::run_migrations,
+    path::{PostsPath, SitePath},
+};
+
+#[derive(clap::Args, Debug)]
+pub struct ImportPost {
+    /// like `https://cohost.org/staff/post/123456-slug`.
+    url: String,
+}
+
+pub async fn main(args: ImportPost) -> eyre::Result<()> {
+    run_migrations()?;
+
+    let (handle, post_id) = parse_chost_url(&args.url)?;
+    let client = if let Ok(connect_sid) = env::var("COHOST_COOKIE") {
+        info!("COHOST_COOKIE is set; output may include private or logged-in-only chosts!");
+        let mut cookie_value = HeaderValue::from_str(&format!("connect.sid={connect_sid}"))?;
+        cookie_value.set_sensitive(true);
+        let mut headers = HeaderMap::new();
+        headers.insert(header::COOKIE, cookie_value);
+        Client::builder().default_headers(headers).build()?
+    } else {
+        Client::builder().build()?
+    };
+
+    // the share tree comes with the chost, so we don’t need to fetch the chosts it replies to.
+    let input = serde_json::json!({ "handle": handle, "postId": post_id });
+    let mut url = Url::parse("https://cohost.org/api/v1/trpc/posts.singlePost")?;
+    url.query_pairs_mut()
+        .append_pair("input", &input.to_string());
+    info!("GET {url}");
+    let SinglePostResponse {
+        mut post,
+        mut comments,
+    } = client
+        .get(url)
+        .send()
+        .await?
+        .error_for_status()?
+        .json::<TrpcResponse<SinglePostResponse>>()
+        .await?
+        .result
+        .data;
+    if post.comments.is_empty() {
+        post.comments = comments
+            .remove(&post.postId.to_string())
+            .unwrap_or_default();
+    }
+    info!(
+        "fetched chost {post_id} by @{handle}, with {} chosts in its share tree",
+        post.shareTree.len()
+    );
+
+    create_dir_all(&*PostsPath::ROOT)?;
+    create_dir_all(&*SitePath::ATTACHMENTS)?;
+    create_dir_all(&*SitePath::THUMBS)?;
+    let context = RealAttachmentsContext {
+        refresh_resources: false,
+        render_cache: true,
+        // we need the network to fetch the chost anyway.
+        offline: false,
+    };
+    // attachments are downloaded with the blocking client, which can’t run on the async runtime.
+    let path = tokio::task::spawn_blocking(move || convert_post(post, &context, false)).await??;
+    info!("wrote {path:?}");
+    info!("click here to reply: {}", path.compose_reply_url());
+
+    Ok(())
+}
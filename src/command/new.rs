@@ -4,12 +4,22 @@ use std::{
     path::Path,
 };
 
+use askama::Template;
+use chrono::{SecondsFormat, Utc};
 use jane_eyre::eyre::{self, bail};
-use tracing::info;
+use tracing::{info, warn};
+
+use crate::{command::render::STATIC_FILES, settings::Settings, PostMeta};
 
 #[derive(clap::Args, Debug)]
 pub struct New {
     path: Option<String>,
+
+    #[arg(
+        long,
+        help = "create the site even if the directory is not empty, overwriting autost.toml and any other files we create"
+    )]
+    force: bool,
 }
 
 pub fn main(args: New) -> eyre::Result<()> {
@@ -18,11 +28,68 @@ pub fn main(args: New) -> eyre::Result<()> {
     info!("creating new site in {path:?}");
 
     create_dir_all(path)?;
-    for entry in read_dir(path)? {
-        bail!("directory is not empty: {:?}", entry?.path());
+    if let Some(entry) = read_dir(path)?.next() {
+        if !args.force {
+            bail!("directory is not empty: {:?}", entry?.path());
+        }
+        warn!("directory is not empty, but continuing because of --force");
     }
-    let mut settings = File::create_new(path.join("autost.toml"))?;
-    settings.write_all(include_bytes!("../../autost.toml.example"))?;
+    let create = |path: &Path| {
+        if args.force {
+            File::create(path)
+        } else {
+            File::create_new(path)
+        }
+    };
+
+    let settings_path = path.join("autost.toml");
+    create(&settings_path)?.write_all(new_site_settings().as_bytes())?;
+
+    // a copy of the built-in static files, so you can tinker with them if you set `path_to_static`.
+    let static_path = path.join("static");
+    create_dir_all(&static_path)?;
+    for file in STATIC_FILES.iter() {
+        let (filename, content) = (file.0, file.1);
+        create(&static_path.join(filename))?.write_all(content)?;
+    }
+
+    // a sample post, so `autost render` works immediately.
+    let posts_path = path.join("posts");
+    create_dir_all(&posts_path)?;
+    let settings = Settings::load(&settings_path)?;
+    let meta = PostMeta {
+        title: Some("hello world".to_owned()),
+        published: Some(Utc::now().to_rfc3339_opts(SecondsFormat::Millis, true)),
+        author: settings.self_author,
+        tags: vec!["autost".to_owned()],
+        ..Default::default()
+    };
+    writeln!(
+        create(&posts_path.join("10000000.md"))?,
+        "{}\n\n{}",
+        meta.render()?.trim_end(),
+        SAMPLE_POST_BODY,
+    )?;
+
+    info!("done! now edit autost.toml, then run `autost render` or `autost server` in {path:?}");
 
     Ok(())
 }
+
+/// the example settings point `path_to_static` somewhere else, but new sites get their own copy.
+fn new_site_settings() -> String {
+    include_str!("../../autost.toml.example").replace(
+        r#"# path_to_static = "/home/me/autost/static2""#,
+        r#"# path_to_static = "static""#,
+    )
+}
+
+const SAMPLE_POST_BODY: &str =
+    "this is your first post! posts live in `posts/`, and you can write them in markdown or html.
+
+edit or delete this post, then run `autost render` to update your site in `site/`.";
+
+#[test]
+fn test_new_site_settings() {
+    assert!(new_site_settings().contains(r#"# path_to_static = "static""#));
+}
@@ -0,0 +1,239 @@
+use std::{
+    collections::{BTreeSet, HashMap},
+    fs::{metadata, read_dir},
+    path::Path,
+    sync::Mutex,
+    thread::sleep,
+    time::{Duration, Instant},
+};
+
+use jane_eyre::eyre::{self, bail, eyre, Context};
+use rayon::{
+    iter::{IntoParallelIterator, ParallelIterator},
+    ThreadPoolBuilder,
+};
+use tracing::{info, warn};
+use url::Url;
+
+use crate::{
+    attachments::{AttachmentsContext, RealAttachmentsContext},
+    cohost::{attachment_id_to_url, Cacheable},
+    command::cohost2autost::collect_cohost_resources,
+    error::{self, Error},
+    path::AttachmentsPath,
+    progress::Progress,
+};
+
+#[derive(clap::Args, Debug)]
+pub struct Prefetch {
+    path_to_chosts: String,
+
+    #[arg(
+        long,
+        default_value_t = 8,
+        help = "maximum number of downloads at once"
+    )]
+    concurrency: usize,
+
+    #[arg(
+        long,
+        default_value_t = 100,
+        help = "minimum delay between requests to the same host, in milliseconds"
+    )]
+    per_host_delay_ms: u64,
+
+    #[arg(
+        long,
+        help = "check if cached cohost static files, avatars, and headers have changed, and download them again if so"
+    )]
+    refresh_resources: bool,
+}
+
+/// a cohost resource that `autost cohost2autost` would need to cache.
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+enum Resource {
+    /// anything that can be cached with [`AttachmentsContext::cache_cohost_resource`].
+    Cacheable { url: String, path: AttachmentsPath },
+    /// a thumbnail that can be cached with [`AttachmentsContext::cache_cohost_thumb`].
+    Thumb { id: String, path: AttachmentsPath },
+}
+
+impl Resource {
+    fn url(&self) -> String {
+        match self {
+            Resource::Cacheable { url, .. } => url.clone(),
+            Resource::Thumb { id, .. } => attachment_id_to_url(id),
+        }
+    }
+
+    /// returns true iff the resource seems to be cached already, so we can skip the rate limit.
+    /// attachments and thumbs are cached in a directory, and other resources in a file, which
+    /// will be requested again if `refresh_resources` is true.
+    fn is_cached(&self, refresh_resources: bool) -> bool {
+        let (Resource::Cacheable { path, .. } | Resource::Thumb { path, .. }) = self;
+        match metadata(path) {
+            Ok(metadata) if metadata.is_dir() => {
+                read_dir(path).is_ok_and(|mut entries| entries.next().is_some())
+            }
+            Ok(_) => !refresh_resources,
+            Err(_) => false,
+        }
+    }
+}
+
+/// attachments context that records what would be cached, without caching anything.
+#[derive(Default)]
+struct CollectingAttachmentsContext {
+    resources: Mutex<BTreeSet<Resource>>,
+}
+
+impl AttachmentsContext for CollectingAttachmentsContext {
+    fn store(&self, _input_path: &Path) -> error::Result<AttachmentsPath> {
+        Err(eyre!("not supported when prefetching").into())
+    }
+
+    fn cache_imported(&self, _url: &str, _post_basename: &str) -> error::Result<AttachmentsPath> {
+        Err(eyre!("not supported when prefetching").into())
+    }
+
+    fn cache_cohost_resource(&self, cacheable: &Cacheable) -> error::Result<AttachmentsPath> {
+        let (url, path) = match cacheable {
+            Cacheable::Attachment { id } => {
+                (attachment_id_to_url(id), AttachmentsPath::ROOT.join(id)?)
+            }
+            Cacheable::Static { filename, url } => (
+                url.to_string(),
+                AttachmentsPath::COHOST_STATIC.join(filename)?,
+            ),
+            Cacheable::Avatar { filename, url } => (
+                url.to_string(),
+                AttachmentsPath::COHOST_AVATAR.join(filename)?,
+            ),
+            Cacheable::Header { filename, url } => (
+                url.to_string(),
+                AttachmentsPath::COHOST_HEADER.join(filename)?,
+            ),
+        };
+        self.resources.lock().unwrap().insert(Resource::Cacheable {
+            url,
+            path: path.clone(),
+        });
+
+        Ok(path)
+    }
+
+    fn cache_cohost_thumb(&self, id: &str) -> error::Result<AttachmentsPath> {
+        let path = AttachmentsPath::THUMBS.join(id)?;
+        self.resources.lock().unwrap().insert(Resource::Thumb {
+            id: id.to_owned(),
+            path: path.clone(),
+        });
+
+        Ok(path)
+    }
+}
+
+/// enforces a minimum delay between requests to each host, across all download threads.
+struct HostRateLimiter {
+    delay: Duration,
+    next_request: Mutex<HashMap<String, Instant>>,
+}
+
+impl HostRateLimiter {
+    fn new(delay: Duration) -> Self {
+        Self {
+            delay,
+            next_request: Mutex::default(),
+        }
+    }
+
+    /// waits until we can send a request to the host of `url`.
+    fn wait(&self, url: &str) {
+        let host = Url::parse(url)
+            .ok()
+            .and_then(|url| url.host_str().map(|host| host.to_owned()))
+            .unwrap_or_default();
+        let now = Instant::now();
+        let slot = {
+            let mut next_request = self.next_request.lock().unwrap();
+            let next = next_request.entry(host).or_insert(now);
+            let slot = (*next).max(now);
+            *next = slot + self.delay;
+            slot
+        };
+        sleep(slot - now);
+    }
+}
+
+pub fn main(args: Prefetch) -> eyre::Result<()> {
+    let input_path = Path::new(&args.path_to_chosts);
+    let dir_entries = read_dir(input_path)?.collect::<Vec<_>>();
+
+    info!("scanning chosts in {input_path:?}");
+    let context = CollectingAttachmentsContext::default();
+    dir_entries
+        .into_par_iter()
+        .map(|entry| -> eyre::Result<()> {
+            let entry = entry?;
+            collect_cohost_resources(&entry.path(), &context)
+                .wrap_err_with(|| eyre!("{:?}: failed to scan", entry.path()))
+        })
+        .collect::<eyre::Result<Vec<_>>>()?;
+    let resources = context.resources.into_inner().unwrap();
+    info!("found {} unique resources", resources.len());
+
+    let attachments = RealAttachmentsContext {
+        refresh_resources: args.refresh_resources,
+        render_cache: false,
+        offline: false,
+    };
+    let limiter = HostRateLimiter::new(Duration::from_millis(args.per_host_delay_ms));
+    let progress = Progress::new("prefetching", resources.len());
+    let pool = ThreadPoolBuilder::new()
+        .num_threads(args.concurrency.max(1))
+        .build()?;
+    let failures = pool.install(|| {
+        resources
+            .into_par_iter()
+            .filter_map(|resource| {
+                if !resource.is_cached(args.refresh_resources) {
+                    limiter.wait(&resource.url());
+                }
+                let result = match &resource {
+                    Resource::Cacheable { url, .. } => match Cacheable::from_url(url) {
+                        Some(cacheable) => attachments.cache_cohost_resource(&cacheable),
+                        None => Err(Error::Parse("not a cohost resource url".to_owned())),
+                    },
+                    Resource::Thumb { id, .. } => attachments.cache_cohost_thumb(id),
+                };
+                progress.increment();
+                result
+                    .err()
+                    .map(|error| (resource, eyre::Report::from(error)))
+            })
+            .collect::<Vec<_>>()
+    });
+
+    for (resource, error) in failures.iter() {
+        warn!(?resource, "failed to prefetch: {error:?}");
+    }
+    if !failures.is_empty() {
+        bail!(
+            "failed to prefetch {} resources; run `autost prefetch` again to retry them",
+            failures.len()
+        );
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_host_rate_limiter() {
+    let limiter = HostRateLimiter::new(Duration::from_millis(50));
+    let start = Instant::now();
+    limiter.wait("https://cohost.org/static/a.svg");
+    limiter.wait("https://staging.cohostcdn.org/avatar/b.png");
+    assert!(start.elapsed() < Duration::from_millis(50));
+    limiter.wait("https://cohost.org/static/c.svg");
+    assert!(start.elapsed() >= Duration::from_millis(50));
+}
@@ -1,342 +1,4100 @@
 use std::{
+    borrow::Cow,
     collections::{BTreeMap, BTreeSet, HashMap},
-    fs::{create_dir_all, read_dir, File},
-    io::Write,
+    fs::{create_dir_all, exists, read_dir, read_to_string, remove_file, rename, File},
+    io::{self, BufWriter, Read, Seek, SeekFrom, Write},
+    ops::Range,
+    path::{Path, PathBuf},
+    process::Command,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Mutex, RwLock,
+    },
+    thread::sleep,
+    time::{Duration, Instant, SystemTime},
 };
 
-use chrono::{SecondsFormat, Utc};
-use jane_eyre::eyre::{self, bail, OptionExt};
-use rayon::iter::{IntoParallelIterator, ParallelIterator};
-use tracing::{debug, info};
+use chrono::{DateTime, NaiveDate, SecondsFormat, Utc};
+use jane_eyre::eyre::{self, bail, Context, OptionExt};
+use rayon::iter::{IntoParallelRefIterator, ParallelIterator};
+use serde::{Deserialize, Serialize};
+use sha2::{digest::generic_array::functional::FunctionalSequence, Digest, Sha256};
+use tracing::{debug, error, info, trace, warn, Level};
+use url::Url;
+use uuid::Uuid;
 
 use crate::{
-    meta::hard_link_attachments_into_site,
+    archive::{write_archive, ArchiveEntries, ArchiveFormat},
+    attachments::{report_offline_misses, RealAttachmentsContext},
+    cohost::parse_chost_url,
+    comments::load_comments,
+    date::{display_day, parse_date, SortDate},
+    meta::{
+        add_image_dimensions, hard_link_attachments_into_site, inline_small_images,
+        localise_cohost_post_links, localise_cohost_urls, threads_content_text, use_scaled_images,
+    },
     migrations::run_migrations,
-    output::{AtomFeedTemplate, ThreadsContentTemplate, ThreadsPageTemplate},
+    og_image::render_og_image,
+    output::{
+        fix_relative_urls_in_threads_content, AdjacentThreads, AllInOnePageTemplate,
+        AllInOneSection, AtomFeedEntryTemplate, AtomFeedTemplate, ColophonTemplate,
+        CommentsTemplate, EmbeddedSource, InlineHashes, JsonFeed, JsonFeedItem, Pagination,
+        RedirectPageTemplate, SeriesThreads, ThreadPageParts, ThreadPrintPageTemplate,
+        ThreadsContentTemplate, ThreadsPageTemplate,
+    },
     path::{PostsPath, SitePath},
-    TemplatedPost, Thread, SETTINGS,
+    progress::Progress,
+    render_cache::RENDER_CACHE_DIR,
+    routing::{decide_collections, thread_has_own_input, Route},
+    settings::{
+        tag_parent, CohostPostLinks, CollectionLayout, Colophon, Crawlers, CustomCollection,
+        EmbedSource, Favicon, OutputFilenamesFormat, RedirectFormat, Settings, TagPage,
+        TitleOnlyPosts,
+    },
+    Author, TemplatedPost, Thread, SETTINGS,
 };
 
 #[derive(clap::Args, Debug)]
 pub struct Render {
     specific_post_paths: Vec<String>,
+
+    #[arg(
+        long,
+        help = "fail if any thread references a post that does not exist"
+    )]
+    strict: bool,
+
+    #[arg(
+        long,
+        help = "delete any .html, .feed.xml, or .feed.json files that were written by an earlier render but not by this one"
+    )]
+    prune: bool,
+
+    #[arg(
+        long,
+        default_value_t = 10,
+        help = "number of slowest posts to list at the end, with RUST_LOG=autost=debug or higher"
+    )]
+    slowest_posts: usize,
+
+    #[arg(
+        long,
+        value_name = "YYYY-MM-DD",
+        value_parser = parse_day,
+        help = "only include threads whose newest post is on or after this day"
+    )]
+    since: Option<NaiveDate>,
+
+    #[arg(
+        long,
+        value_name = "YYYY-MM-DD",
+        value_parser = parse_day,
+        help = "only include threads whose newest post is on or before this day"
+    )]
+    until: Option<NaiveDate>,
+
+    #[arg(
+        long,
+        help = "render again whenever anything in posts/ or autost.toml changes, until interrupted"
+    )]
+    watch: bool,
+
+    #[arg(
+        long,
+        value_name = "PATH",
+        help = "also write the site and its attachments to a .tar, .tar.gz, .tgz, or .zip file"
+    )]
+    archive: Option<PathBuf>,
+
+    #[arg(
+        long,
+        help = "use less memory for huge sites, by keeping only a summary of each thread between steps, at the cost of loading posts more than once"
+    )]
+    low_memory: bool,
+
+    #[arg(
+        long,
+        value_name = "COLLECTION",
+        num_args = 0..=1,
+        default_missing_value = "index",
+        help = "also write every thread in a collection (default index) to all-in-one.html, with a table of contents, for reading offline"
+    )]
+    all_in_one: Option<String>,
+
+    #[arg(
+        long,
+        help = "write nothing, but list the files in the site that the render would add, change, or remove, and exit with status 1 if there are any"
+    )]
+    diff: bool,
+
+    #[arg(
+        long,
+        help = "like --diff, but also show the lines that would change in each file"
+    )]
+    diff_content: bool,
+
+    #[arg(
+        long,
+        help = "write every post page, even if nothing it depends on has changed since the last render"
+    )]
+    force: bool,
+
+    #[arg(
+        long,
+        help = "never download anything, leaving cohost urls that are not cached in place, and count them at the end"
+    )]
+    offline: bool,
+}
+
+/// a file that autost writes to the site output directory, unless `path_to_static` is set.
+pub struct StaticFile(pub &'static str, pub &'static [u8]);
+
+pub const STATIC_FILES: &[StaticFile] = &[
+    StaticFile("deploy.sh", include_bytes!("../../static/deploy.sh")),
+    StaticFile("style.css", include_bytes!("../../static/style.css")),
+    StaticFile("script.js", include_bytes!("../../static/script.js")),
+    StaticFile(
+        "Atkinson-Hyperlegible-Font-License-2020-1104.pdf",
+        include_bytes!("../../static/Atkinson-Hyperlegible-Font-License-2020-1104.pdf"),
+    ),
+    StaticFile(
+        "Atkinson-Hyperlegible-Regular-102.woff2",
+        include_bytes!("../../static/Atkinson-Hyperlegible-Regular-102.woff2"),
+    ),
+    StaticFile(
+        "Atkinson-Hyperlegible-Italic-102.woff2",
+        include_bytes!("../../static/Atkinson-Hyperlegible-Italic-102.woff2"),
+    ),
+    StaticFile(
+        "Atkinson-Hyperlegible-Bold-102.woff2",
+        include_bytes!("../../static/Atkinson-Hyperlegible-Bold-102.woff2"),
+    ),
+    StaticFile(
+        "Atkinson-Hyperlegible-BoldItalic-102.woff2",
+        include_bytes!("../../static/Atkinson-Hyperlegible-BoldItalic-102.woff2"),
+    ),
+];
+
+/// the output filenames of the static files written by the last render, by their original
+/// filenames. these only differ if `hashed_static_files` is enabled.
+static STATIC_FILE_NAMES: RwLock<BTreeMap<&'static str, String>> = RwLock::new(BTreeMap::new());
+
+/// returns the output filename of the given static file, like `style.0123456789.css` for
+/// `style.css` if `hashed_static_files` is enabled, or None if it’s not a static file that was
+/// written by the last render.
+pub fn static_file_name(filename: &str) -> Option<String> {
+    STATIC_FILE_NAMES
+        .read()
+        .expect("poisoned")
+        .get(filename)
+        .cloned()
+}
+
+/// writes the static files to the site output directory, returning the files that were written.
+///
+/// if `hashed_static_files` is enabled, css, js, and font files get a hash of their content in
+/// their filenames, and references to fonts in css files are rewritten to match.
+fn write_static_files() -> eyre::Result<BTreeSet<SitePath>> {
+    let mut files = vec![];
+    for StaticFile(filename, content) in STATIC_FILES.iter() {
+        let content = if let Some(static_path) = SETTINGS.path_to_static() {
+            let path = static_path.join(filename);
+            match std::fs::read(&path) {
+                Ok(content) => content,
+                Err(error) if error.kind() == io::ErrorKind::NotFound => {
+                    warn!(
+                        ?path,
+                        "static file not found, skipping; references to it will be left as is"
+                    );
+                    continue;
+                }
+                Err(error) => Err(error).wrap_err_with(|| format!("failed to read {path:?}"))?,
+            }
+        } else {
+            content.to_vec()
+        };
+        files.push((*filename, content));
+    }
+
+    // css files refer to fonts, so they need to be hashed after the fonts.
+    files.sort_by_key(|(filename, _)| filename.ends_with(".css"));
+    let mut names = BTreeMap::default();
+    let mut result = BTreeSet::default();
+    for (filename, mut content) in files {
+        let mut output_filename = filename.to_owned();
+        if SETTINGS.hashed_static_files() && is_hashable_static_file(filename) {
+            if filename.ends_with(".css") {
+                content = rewrite_css_urls(&String::from_utf8(content)?, &names).into_bytes();
+            }
+            output_filename = hashed_static_file_name(filename, &content);
+        }
+        let path = SitePath::ROOT.join(&output_filename)?;
+        write_atomic(&path, &content)?;
+        result.insert(path);
+        names.insert(filename, output_filename);
+    }
+    *STATIC_FILE_NAMES.write().expect("poisoned") = names;
+
+    Ok(result)
+}
+
+fn is_hashable_static_file(filename: &str) -> bool {
+    [".css", ".js", ".woff2"]
+        .iter()
+        .any(|extension| filename.ends_with(extension))
+}
+
+/// returns a filename like `style.0123456789.css`, for `style.css` with the given content.
+fn hashed_static_file_name(filename: &str, content: &[u8]) -> String {
+    let hash = Sha256::digest(content).map(|o| format!("{o:02x}")).join("");
+    let hash = &hash[..10];
+    match filename.rsplit_once('.') {
+        Some((stem, extension)) => format!("{stem}.{hash}.{extension}"),
+        None => format!("{filename}.{hash}"),
+    }
+}
+
+/// rewrites `url(...)` references in css to the output filenames of static files, leaving any
+/// other urls as is.
+fn rewrite_css_urls(css: &str, names: &BTreeMap<&str, String>) -> String {
+    let mut result = css.to_owned();
+    for (filename, output_filename) in names {
+        for quote in ["", "\"", "'"] {
+            result = result.replace(
+                &format!("url({quote}{filename}{quote})"),
+                &format!("url({quote}{output_filename}{quote})"),
+            );
+        }
+    }
+
+    result
+}
+
+#[test]
+fn test_hashed_static_files() {
+    assert_eq!(
+        hashed_static_file_name("style.css", b""),
+        "style.e3b0c44298.css"
+    );
+    assert_eq!(
+        hashed_static_file_name("LICENSE", b""),
+        "LICENSE.e3b0c44298"
+    );
+    assert!(is_hashable_static_file(
+        "Atkinson-Hyperlegible-Bold-102.woff2"
+    ));
+    assert!(!is_hashable_static_file("deploy.sh"));
+
+    let names = BTreeMap::from([("font.woff2", "font.0123456789.woff2".to_owned())]);
+    assert_eq!(
+        rewrite_css_urls(
+            "a { src: url(font.woff2); } b { src: url('font.woff2'); } c { src: url(other.woff2); }",
+            &names
+        ),
+        "a { src: url(font.0123456789.woff2); } b { src: url('font.0123456789.woff2'); } c { src: url(other.woff2); }"
+    );
+}
+
+/// days (in the `display_timezone`, if any) that threads must be in to be rendered, by the date
+/// of their newest post.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct DateRange {
+    pub since: Option<NaiveDate>,
+    pub until: Option<NaiveDate>,
+}
+
+impl DateRange {
+    fn is_unbounded(&self) -> bool {
+        self.since.is_none() && self.until.is_none()
+    }
+
+    /// returns true iff the thread’s newest post is in the range. if the range is bounded, threads
+    /// without any valid dates are never in the range.
+    fn contains(&self, thread: &Thread) -> bool {
+        if self.is_unbounded() {
+            return true;
+        }
+        let newest = thread
+            .posts
+            .iter()
+            .filter_map(|post| post.meta.published.as_deref())
+            .max_by_key(|published| parse_date(published));
+        let Some(day) = newest.and_then(display_day) else {
+            return false;
+        };
+        let Ok(day) = NaiveDate::parse_from_str(&day, "%Y-%m-%d") else {
+            return false;
+        };
+
+        self.since.map_or(true, |since| day >= since)
+            && self.until.map_or(true, |until| day <= until)
+    }
+}
+
+/// records that `post` will be rendered to `output_path`, or fails if another post already will.
+fn claim_output_path(
+    owners: &mut HashMap<SitePath, PostsPath>,
+    output_path: &SitePath,
+    post: &PostsPath,
+) -> eyre::Result<()> {
+    if let Some(other) = owners.insert(output_path.clone(), post.clone()) {
+        bail!(
+            "{other:?} and {post:?} would both be rendered to {output_path:?}; rename one of them"
+        );
+    }
+
+    Ok(())
+}
+
+/// writes `bytes` to `path` atomically, so a render that panics or gets killed never leaves a
+/// truncated file in the site, only the old file or the new one.
+fn write_atomic(path: impl AsRef<Path>, bytes: impl AsRef<[u8]>) -> eyre::Result<()> {
+    let mut file = AtomicFile::create(path)?;
+    file.write_all(bytes.as_ref())?;
+    file.commit()
+}
+
+/// a file that is written to a temporary file next to it, then renamed into place by
+/// [`AtomicFile::commit`], for output that is too big to build in memory for [`write_atomic`].
+/// if it’s dropped without being committed, the temporary file is deleted.
+///
+/// with `--diff`, the file is written to the [`OutputSink`] instead.
+struct AtomicFile {
+    path: PathBuf,
+    writer: Option<AtomicFileWriter>,
+}
+
+enum AtomicFileWriter {
+    File {
+        temp_path: PathBuf,
+        file: BufWriter<File>,
+    },
+    Memory(Vec<u8>),
+}
+
+impl AtomicFile {
+    fn create(path: impl AsRef<Path>) -> eyre::Result<Self> {
+        let path = path.as_ref().to_owned();
+        if output_in_memory() {
+            return Ok(Self {
+                path,
+                writer: Some(AtomicFileWriter::Memory(vec![])),
+            });
+        }
+        let filename = path
+            .file_name()
+            .ok_or_eyre("output path has no filename")?
+            .to_string_lossy();
+        // same directory, so the rename never crosses filesystems.
+        let temp_path = path.with_file_name(format!(".{filename}.tmp"));
+        let file =
+            File::create(&temp_path).wrap_err_with(|| format!("failed to create {temp_path:?}"))?;
+
+        Ok(Self {
+            path,
+            writer: Some(AtomicFileWriter::File {
+                temp_path,
+                file: BufWriter::new(file),
+            }),
+        })
+    }
+
+    fn commit(mut self) -> eyre::Result<()> {
+        match self.writer.take().expect("only taken here") {
+            AtomicFileWriter::File { temp_path, file } => {
+                file.into_inner().map_err(|error| error.into_error())?;
+                rename(&temp_path, &self.path)
+                    .wrap_err_with(|| format!("failed to rename {temp_path:?} to {:?}", self.path))
+            }
+            AtomicFileWriter::Memory(content) => {
+                if let OutputSink::Memory(files) = &mut *OUTPUT_SINK.lock().expect("poisoned") {
+                    files.insert(self.path.clone(), content);
+                }
+                Ok(())
+            }
+        }
+    }
+
+    fn writer(&mut self) -> &mut dyn Write {
+        match self.writer.as_mut().expect("only taken by commit") {
+            AtomicFileWriter::File { file, .. } => file,
+            AtomicFileWriter::Memory(content) => content,
+        }
+    }
+}
+
+impl Write for AtomicFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.writer().write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.writer().flush()
+    }
+}
+
+impl Drop for AtomicFile {
+    fn drop(&mut self) {
+        if let Some(AtomicFileWriter::File { temp_path, .. }) = self.writer.take() {
+            _ = remove_file(&temp_path);
+        }
+    }
+}
+
+/// where output files go. normally they are written to the site, but with `--diff`, they are
+/// kept in memory, so they can be compared with the files already there.
+enum OutputSink {
+    Disk,
+    /// the content of each output file, by its path.
+    Memory(BTreeMap<PathBuf, Vec<u8>>),
+}
+
+static OUTPUT_SINK: Mutex<OutputSink> = Mutex::new(OutputSink::Disk);
+
+fn output_in_memory() -> bool {
+    matches!(
+        *OUTPUT_SINK.lock().expect("poisoned"),
+        OutputSink::Memory(_)
+    )
+}
+
+/// if true, attachments are never downloaded while rendering, with `--offline`.
+static OFFLINE: AtomicBool = AtomicBool::new(false);
+
+/// returns the context for caching attachments while rendering, which is offline with `--offline`.
+fn attachments_context() -> RealAttachmentsContext {
+    RealAttachmentsContext {
+        offline: OFFLINE.load(Ordering::Relaxed),
+        ..Default::default()
+    }
+}
+
+/// reads an output file written by this render, which may only be in memory with `--diff`.
+fn read_output(path: impl AsRef<Path>) -> eyre::Result<Vec<u8>> {
+    let path = path.as_ref();
+    if let OutputSink::Memory(files) = &*OUTPUT_SINK.lock().expect("poisoned") {
+        if let Some(content) = files.get(path) {
+            return Ok(content.clone());
+        }
+    }
+
+    std::fs::read(path).wrap_err_with(|| format!("failed to read {path:?}"))
+}
+
+#[test]
+fn test_write_atomic() -> eyre::Result<()> {
+    let dir = std::env::temp_dir().join(format!("autost-test-write-atomic-{}", Uuid::new_v4()));
+    create_dir_all(&dir)?;
+    let path = dir.join("page.html");
+    write_atomic(&path, "old")?;
+    write_atomic(&path, "new")?;
+    assert_eq!(read_to_string(&path)?, "new");
+
+    // dropped without commit, so the old file is left alone.
+    let mut file = AtomicFile::create(&path)?;
+    file.write_all(b"half")?;
+    drop(file);
+    assert_eq!(read_to_string(&path)?, "new");
+    assert_eq!(read_dir(&dir)?.count(), 1);
+
+    std::fs::remove_dir_all(&dir)?;
+    Ok(())
+}
+
+fn parse_day(day: &str) -> Result<NaiveDate, String> {
+    NaiveDate::parse_from_str(day, "%Y-%m-%d")
+        .map_err(|error| format!("expected a day like 2024-12-31 ({error})"))
 }
 
 pub fn main(args: Render) -> eyre::Result<()> {
+    OFFLINE.store(args.offline, Ordering::Relaxed);
+    let dates = DateRange {
+        since: args.since,
+        until: args.until,
+    };
+    if let (Some(since), Some(until)) = (dates.since, dates.until) {
+        if since > until {
+            bail!("--since ({since}) must not be after --until ({until})");
+        }
+    }
+    if args.prune && !dates.is_unbounded() {
+        bail!("--prune can’t be used with --since or --until");
+    }
+    if let Some(archive_path) = &args.archive {
+        ArchiveFormat::from_path(archive_path)?;
+    }
+    let options = RenderOptions {
+        strict: args.strict,
+        slowest_posts: args.slowest_posts,
+        dates,
+        low_memory: args.low_memory,
+        all_in_one: args.all_in_one.clone(),
+        force: args.force,
+    };
+    if args.diff || args.diff_content {
+        if !args.specific_post_paths.is_empty() {
+            bail!("--diff can only be used when rendering all posts");
+        }
+        if !dates.is_unbounded() {
+            bail!("--diff can’t be used with --since or --until");
+        }
+        if args.prune || args.watch || args.archive.is_some() {
+            bail!("--diff can’t be used with --prune, --watch, or --archive");
+        }
+        let changed = render_diff(&args, &options)?;
+        if changed {
+            std::process::exit(1);
+        }
+        return Ok(());
+    }
     if !args.specific_post_paths.is_empty() {
+        if args.prune {
+            bail!("--prune can only be used when rendering all posts");
+        }
+        if args.watch {
+            bail!("--watch can only be used when rendering all posts");
+        }
+        if args.archive.is_some() {
+            bail!("--archive can only be used when rendering all posts");
+        }
         let specific_post_paths = args
             .specific_post_paths
             .into_iter()
             .map(|path| PostsPath::from_site_root_relative_path(&path))
             .collect::<eyre::Result<Vec<_>>>()?;
-        render(specific_post_paths)
+        render(specific_post_paths, &options)?;
     } else {
-        render_all()
+        let render = || -> eyre::Result<()> {
+            let output_paths = render_all(&options)?;
+            if args.prune {
+                prune_stale_output(&output_paths)?;
+            }
+            if let Some(archive_path) = &args.archive {
+                let mut entries = ArchiveEntries::default();
+                for path in output_paths.iter() {
+                    entries.insert(path);
+                }
+                entries.insert(&manifest_path()?);
+                entries.insert_dir(&SitePath::ATTACHMENTS)?;
+                write_archive(archive_path, &entries)?;
+            }
+            Ok(())
+        };
+        render()?;
+        if args.watch {
+            watch(render)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// renders all posts into memory, then lists the files in the site that would be added, changed,
+/// or removed (by `--prune`), for `--diff`. returns true iff there are any.
+fn render_diff(args: &Render, options: &RenderOptions) -> eyre::Result<bool> {
+    *OUTPUT_SINK.lock().expect("poisoned") = OutputSink::Memory(BTreeMap::default());
+    let output_paths = render_all(options);
+    let OutputSink::Memory(files) = std::mem::replace(
+        &mut *OUTPUT_SINK.lock().expect("poisoned"),
+        OutputSink::Disk,
+    ) else {
+        unreachable!("only set above");
+    };
+    let changes = diff_output(&files, &stale_output_paths(&output_paths?)?)?;
+
+    let mut counts = BTreeMap::<OutputChange, usize>::default();
+    for (path, change) in changes.iter() {
+        *counts.entry(*change).or_default() += 1;
+        println!("{change}: {}", path.display());
+        if args.diff_content && *change == OutputChange::Changed {
+            let old = std::fs::read(path)?;
+            match (std::str::from_utf8(&old), std::str::from_utf8(&files[path])) {
+                (Ok(old), Ok(new)) => {
+                    for line in line_diff(old, new) {
+                        println!("{line}");
+                    }
+                }
+                _ => println!("(binary file)"),
+            }
+        }
+    }
+    let count = |change| counts.get(&change).copied().unwrap_or_default();
+    info!(
+        "{} added, {} changed, {} removed, {} unchanged",
+        count(OutputChange::Added),
+        count(OutputChange::Changed),
+        count(OutputChange::Removed),
+        files.len() - count(OutputChange::Added) - count(OutputChange::Changed),
+    );
+
+    Ok(!changes.is_empty())
+}
+
+/// how a file in the site would change, for `--diff`.
+#[derive(Clone, Copy, Debug, Eq, Ord, PartialEq, PartialOrd)]
+enum OutputChange {
+    Added,
+    Changed,
+    Removed,
+}
+
+impl std::fmt::Display for OutputChange {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Self::Added => "added",
+            Self::Changed => "changed",
+            Self::Removed => "removed",
+        })
+    }
+}
+
+/// compares the output `files` of a render with the files in the site, returning the files that
+/// would be added or changed, and the `stale` files that `--prune` would remove, sorted by path.
+fn diff_output(
+    files: &BTreeMap<PathBuf, Vec<u8>>,
+    stale: &[SitePath],
+) -> eyre::Result<Vec<(PathBuf, OutputChange)>> {
+    let mut result = vec![];
+    for (path, content) in files {
+        match std::fs::read(path) {
+            Ok(old) if old == *content => {}
+            Ok(_) => result.push((path.clone(), OutputChange::Changed)),
+            Err(error) if error.kind() == io::ErrorKind::NotFound => {
+                result.push((path.clone(), OutputChange::Added))
+            }
+            Err(error) => Err(error).wrap_err_with(|| format!("failed to read {path:?}"))?,
+        }
+    }
+    for path in stale {
+        result.push((
+            AsRef::<Path>::as_ref(path).to_owned(),
+            OutputChange::Removed,
+        ));
+    }
+    result.sort();
+
+    Ok(result)
+}
+
+/// beyond this many lines before × lines after, [`line_diff`] gives up on finding the lines in
+/// common, so huge pages can’t take forever.
+const LINE_DIFF_MAX_CELLS: usize = 1_000_000;
+
+/// returns the lines that differ between `old` and `new`, prefixed with `-` or `+`, for
+/// `--diff-content`. each run of changes starts with a line like `@@ line 12 @@`.
+fn line_diff(old: &str, new: &str) -> Vec<String> {
+    let old = old.lines().collect::<Vec<_>>();
+    let new = new.lines().collect::<Vec<_>>();
+    let prefix = old.iter().zip(&new).take_while(|(o, n)| o == n).count();
+    let suffix = old[prefix..]
+        .iter()
+        .rev()
+        .zip(new[prefix..].iter().rev())
+        .take_while(|(o, n)| o == n)
+        .count();
+    let old = &old[prefix..old.len() - suffix];
+    let new = &new[prefix..new.len() - suffix];
+
+    // `common[i][j]` is the number of lines in common between `old[i..]` and `new[j..]`.
+    let mut common = vec![];
+    if old.len().saturating_mul(new.len()) <= LINE_DIFF_MAX_CELLS {
+        common = vec![vec![0usize; new.len() + 1]; old.len() + 1];
+        for i in (0..old.len()).rev() {
+            for j in (0..new.len()).rev() {
+                common[i][j] = if old[i] == new[j] {
+                    common[i + 1][j + 1] + 1
+                } else {
+                    common[i + 1][j].max(common[i][j + 1])
+                };
+            }
+        }
+    }
+
+    let mut result = vec![];
+    let (mut i, mut j) = (0, 0);
+    let mut in_change = false;
+    while i < old.len() || j < new.len() {
+        if i < old.len() && j < new.len() && old[i] == new[j] && !common.is_empty() {
+            i += 1;
+            j += 1;
+            in_change = false;
+            continue;
+        }
+        if !in_change {
+            result.push(format!("@@ line {} @@", prefix + i + 1));
+            in_change = true;
+        }
+        let remove = j == new.len()
+            || (i < old.len() && (common.is_empty() || common[i + 1][j] >= common[i][j + 1]));
+        if remove {
+            result.push(format!("-{}", old[i]));
+            i += 1;
+        } else {
+            result.push(format!("+{}", new[j]));
+            j += 1;
+        }
+    }
+
+    result
+}
+
+#[test]
+fn test_line_diff() {
+    assert!(line_diff("a\nb\n", "a\nb\n").is_empty());
+    assert_eq!(
+        line_diff("a\nb\nc\nd\ne\n", "a\nB\nc\nd\ne\nf\n"),
+        ["@@ line 2 @@", "-b", "+B", "@@ line 6 @@", "+f"]
+    );
+    assert_eq!(line_diff("a\nb\n", "b\n"), ["@@ line 1 @@", "-a"]);
+}
+
+#[test]
+fn test_diff_output() -> eyre::Result<()> {
+    let dir = std::env::temp_dir().join(format!("autost-test-diff-output-{}", Uuid::new_v4()));
+    create_dir_all(&dir)?;
+    std::fs::write(dir.join("same.html"), "same")?;
+    std::fs::write(dir.join("changed.html"), "old")?;
+    let files = BTreeMap::from([
+        (dir.join("same.html"), b"same".to_vec()),
+        (dir.join("changed.html"), b"new".to_vec()),
+        (dir.join("added.html"), b"added".to_vec()),
+    ]);
+    assert_eq!(
+        diff_output(&files, &[])?,
+        [
+            (dir.join("added.html"), OutputChange::Added),
+            (dir.join("changed.html"), OutputChange::Changed),
+        ]
+    );
+    // nothing was written.
+    assert!(!exists(dir.join("added.html"))?);
+
+    std::fs::remove_dir_all(&dir)?;
+    Ok(())
+}
+
+/// how many posts to load at a time, with `--low-memory`.
+const LOW_MEMORY_CHUNK_SIZE: usize = 256;
+
+/// how often to check for changes, with `--watch`.
+const WATCH_POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+/// how long changes must stop for before rendering, with `--watch`, so that a burst of edits
+/// only renders once.
+const WATCH_DEBOUNCE: Duration = Duration::from_millis(300);
+
+const SETTINGS_PATH: &str = "autost.toml";
+
+/// calls `render` whenever anything in `posts/` or `autost.toml` changes, forever. render errors
+/// are logged, not returned, so you can fix the problem and keep going.
+///
+/// settings can’t be reloaded in place, so if `autost.toml` changes, autost restarts itself.
+fn watch(render: impl Fn() -> eyre::Result<()>) -> eyre::Result<()> {
+    info!("watching posts/ and {SETTINGS_PATH} for changes");
+    let mut files = watched_files()?;
+    loop {
+        sleep(WATCH_POLL_INTERVAL);
+        let mut latest = watched_files()?;
+        if latest == files {
+            continue;
+        }
+        loop {
+            sleep(WATCH_DEBOUNCE);
+            let next = watched_files()?;
+            if next == latest {
+                break;
+            }
+            latest = next;
+        }
+
+        let changed = changed_files(&files, &latest);
+        for path in changed.iter() {
+            info!("changed: {path:?}");
+        }
+        if changed.contains(&Path::new(SETTINGS_PATH)) {
+            match Settings::load_default() {
+                Ok(_) => restart()?,
+                Err(error) => error!(?error, "failed to load settings; not restarting"),
+            }
+        } else if let Err(error) = render() {
+            error!(?error, "failed to render; waiting for more changes");
+        }
+        files = latest;
+    }
+}
+
+/// returns the modification times of the files that `--watch` watches.
+fn watched_files() -> eyre::Result<BTreeMap<PathBuf, SystemTime>> {
+    let mut result = BTreeMap::default();
+    if let Ok(metadata) = std::fs::metadata(SETTINGS_PATH) {
+        result.insert(SETTINGS_PATH.into(), metadata.modified()?);
+    }
+    let mut dirs = vec![AsRef::<Path>::as_ref(&*PostsPath::ROOT).to_owned()];
+    while let Some(dir) = dirs.pop() {
+        let Ok(entries) = read_dir(&dir) else {
+            continue;
+        };
+        for entry in entries {
+            // files can be deleted while we read the directory.
+            let Some((path, metadata)) = entry
+                .ok()
+                .and_then(|entry| Some((entry.path(), entry.metadata().ok()?)))
+            else {
+                continue;
+            };
+            if metadata.is_dir() {
+                dirs.push(path);
+            } else {
+                result.insert(path, metadata.modified()?);
+            }
+        }
+    }
+
+    Ok(result)
+}
+
+/// returns the files that were created, modified, or deleted.
+fn changed_files<'files>(
+    old: &'files BTreeMap<PathBuf, SystemTime>,
+    new: &'files BTreeMap<PathBuf, SystemTime>,
+) -> Vec<&'files Path> {
+    let paths = old.keys().chain(new.keys()).collect::<BTreeSet<_>>();
+
+    paths
+        .into_iter()
+        .filter(|path| old.get(*path) != new.get(*path))
+        .map(|path| &**path)
+        .collect()
+}
+
+/// replaces this process with a new autost process with the same arguments, so it loads the new
+/// settings.
+fn restart() -> eyre::Result<()> {
+    info!("settings changed; restarting");
+    let mut command = Command::new(std::env::current_exe()?);
+    command.args(std::env::args_os().skip(1));
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::process::CommandExt;
+        // only returns if there was an error.
+        Err(command.exec().into())
+    }
+    #[cfg(not(unix))]
+    {
+        std::process::exit(command.status()?.code().unwrap_or(1));
+    }
+}
+
+#[test]
+fn test_changed_files() {
+    let now = SystemTime::now();
+    let later = now + Duration::from_secs(1);
+    let old = BTreeMap::from([
+        (PathBuf::from("posts/1.md"), now),
+        (PathBuf::from("posts/2.md"), now),
+        (PathBuf::from("posts/3.md"), now),
+    ]);
+    let new = BTreeMap::from([
+        (PathBuf::from("posts/1.md"), now),
+        (PathBuf::from("posts/2.md"), later),
+        (PathBuf::from("posts/4.md"), now),
+    ]);
+    assert_eq!(
+        changed_files(&old, &new),
+        [
+            Path::new("posts/2.md"),
+            Path::new("posts/3.md"),
+            Path::new("posts/4.md")
+        ]
+    );
+    assert!(changed_files(&old, &old).is_empty());
+}
+
+/// options for [`render`] and [`render_all`], mostly from the `autost render` arguments.
+#[derive(Clone, Debug, Default)]
+pub struct RenderOptions {
+    /// if true, threads that reference a post that does not exist are an error.
+    pub strict: bool,
+    /// if debug logging is enabled, this many of the slowest posts are listed at the end.
+    pub slowest_posts: usize,
+    /// threads outside of these days are skipped, but the posts they reference are still loaded.
+    pub dates: DateRange,
+    /// if true, only a [`SpooledThread`] is kept for each thread after it is loaded, with its html
+    /// for listing pages in a temporary file, and threads are loaded again when their pages and
+    /// feed entries are written.
+    pub low_memory: bool,
+    /// if this is the name of a collection, every thread in that collection is also written to
+    /// `all-in-one.html`.
+    pub all_in_one: Option<String>,
+    /// if true, post pages are written even if nothing they depend on has changed since the last
+    /// render (see [`PageFingerprints`]).
+    pub force: bool,
+}
+
+/// renders all posts, returning the files that were written.
+pub fn render_all(options: &RenderOptions) -> eyre::Result<BTreeSet<SitePath>> {
+    let mut post_paths = vec![];
+
+    create_dir_all(&*PostsPath::ROOT)?;
+    for entry in read_dir(&*PostsPath::ROOT)? {
+        let entry = entry?;
+        let metadata = entry.metadata()?;
+        // cohost2autost creates directories for chost thread ancestors.
+        if metadata.is_dir() {
+            continue;
+        }
+
+        let path = PostsPath::ROOT.join_dir_entry(&entry)?;
+        if !path.is_post() {
+            continue;
+        }
+        post_paths.push(path);
+    }
+
+    render(post_paths, options)
+}
+
+/// renders the given posts, returning the files that were written.
+///
+/// post pages are only written if anything they depend on has changed since the last render (see
+/// [`PageFingerprints`]), unless [`RenderOptions::force`] is true. listing pages and feeds are
+/// always written.
+pub fn render<'posts>(
+    post_paths: Vec<PostsPath>,
+    options: &RenderOptions,
+) -> eyre::Result<BTreeSet<SitePath>> {
+    let RenderOptions {
+        strict,
+        slowest_posts,
+        dates,
+        low_memory,
+        force,
+        ..
+    } = *options;
+    let all_in_one = options.all_in_one.as_deref();
+    run_migrations()?;
+    if let Some(key) = all_in_one {
+        if !Collections::default()?.keys().any(|other| other == key) {
+            bail!("--all-in-one: unknown collection {key:?}");
+        }
+    }
+
+    let now = Utc::now().to_rfc3339_opts(SecondsFormat::Millis, true);
+    create_dir_all(&*SitePath::ROOT)?;
+    create_dir_all(&*SitePath::TAGGED)?;
+
+    let mut output_paths = write_static_files()?;
+    let deploy_path = SitePath::ROOT.join("deploy.sh")?;
+    #[cfg(unix)]
+    if output_paths.contains(&deploy_path) && !output_in_memory() {
+        use std::os::unix::fs::PermissionsExt;
+        let mut permissions = std::fs::metadata(&deploy_path)?.permissions();
+        let mode = permissions.mode();
+        permissions.set_mode(mode | 0o111);
+        std::fs::set_permissions(deploy_path, permissions)?;
+    }
+
+    // post pages link to adjacent threads, so we need to load every thread before we can write
+    // any post pages.
+    let mut threads_cache = ThreadStore::new(strict, low_memory)?;
+    // with `--low-memory`, posts are loaded in chunks, so that only one chunk of posts is ever
+    // in memory as a whole.
+    let chunk_size = match low_memory {
+        true => LOW_MEMORY_CHUNK_SIZE,
+        false => post_paths.len().max(1),
+    };
+    let progress = Progress::new("loading", post_paths.len());
+    let RenderResult {
+        mut tags,
+        mut collections,
+        mut interesting_output_paths,
+        mut threads_by_interesting_tag,
+        mut threads_by_series,
+        mut interesting_authors,
+        mut unlocalised_urls,
+    } = RenderResult::default()?;
+    let mut broken_references = vec![];
+    let mut load_timings = HashMap::new();
+    let mut skipped_by_date = 0;
+    let mut post_output_owners = HashMap::default();
+    let mut post_output_paths_by_thread = HashMap::<PostsPath, Vec<SitePath>>::default();
+    let mut page_redirects = vec![];
+    let mut cohost_post_pages = CohostPostPages::default();
+    for chunk in post_paths.chunks(chunk_size) {
+        let results = chunk
+            .par_iter()
+            .map(|path| {
+                let result = load_single_post(path.clone(), strict).and_then(|result| {
+                    if !dates.contains(&result.cached_thread.thread) {
+                        return Ok(None);
+                    }
+                    threads_cache.keep(result).map(Some)
+                });
+                progress.increment();
+                result
+            })
+            .collect::<Vec<_>>();
+
+        for result in results {
+            let Some(KeptThread {
+                path,
+                render_result: result,
+                thread: cached_thread,
+                timings,
+                cohost_post_id,
+            }) = result?
+            else {
+                skipped_by_date += 1;
+                continue;
+            };
+            for (tag, count) in result.tags {
+                *tags.entry(tag).or_insert(0) += count;
+            }
+            collections.merge(result.collections);
+            interesting_output_paths.extend(result.interesting_output_paths);
+            unlocalised_urls.extend(result.unlocalised_urls);
+            for (href, (author, count)) in result.interesting_authors {
+                interesting_authors.entry(href).or_insert((author, 0)).1 += count;
+            }
+            for (tag, threads) in result.threads_by_interesting_tag {
+                threads_by_interesting_tag
+                    .entry(tag)
+                    .or_default()
+                    .extend(threads);
+            }
+            for (series, threads) in result.threads_by_series {
+                threads_by_series.entry(series).or_default().extend(threads);
+            }
+            // two posts like `posts/1.md` and `posts/1.html` would clobber each other’s pages, and
+            // so would two posts with the same slug, or a slug and the old page of another post.
+            let slug = cached_thread.slug();
+            let mut post_output_paths = Vec::from_iter(path.rendered_path_with_slug(slug)?);
+            // old pages without the slug, or in the `.html` form if `clean_urls` is enabled.
+            let mut old_output_paths = vec![];
+            if let Some(new) = post_output_paths.first() {
+                let old_paths = [
+                    path.html_rendered_path_with_slug(None)?,
+                    path.html_rendered_path_with_slug(slug)?,
+                ];
+                for old in old_paths.into_iter().flatten() {
+                    if old != *new && !old_output_paths.iter().any(|(other, _)| *other == old) {
+                        old_output_paths.push((old, new.clone()));
+                    }
+                }
+            }
+            if let (Some(post_id), Some(page)) = (cohost_post_id, post_output_paths.first()) {
+                cohost_post_pages.insert(post_id, page);
+            }
+            if SETTINGS.embed_source == Some(EmbedSource::Download) {
+                post_output_paths.extend(path.rendered_source_path_with_slug(slug)?);
+            }
+            post_output_paths.extend(cached_thread.generated_og_image().cloned());
+            if SETTINGS.print_pages() {
+                let print_path = path.rendered_print_path_with_slug(slug)?;
+                if slug.is_some() {
+                    old_output_paths.extend(path.rendered_print_path()?.zip(print_path.clone()));
+                }
+                post_output_paths.extend(print_path);
+            }
+            for output_path in post_output_paths.iter() {
+                claim_output_path(&mut post_output_owners, output_path, &path)?;
+                output_paths.insert(output_path.clone());
+            }
+            post_output_paths_by_thread.insert(path.clone(), post_output_paths);
+            for (old, new) in old_output_paths {
+                claim_output_path(&mut post_output_owners, &old, &path)?;
+                page_redirects.push((old.rsync_deploy_line(), new.internal_url()));
+            }
+            for reference in cached_thread.broken_references() {
+                broken_references.push((path.clone(), reference.clone()));
+            }
+            load_timings.insert(path.clone(), timings);
+            threads_cache.insert(path, cached_thread);
+        }
+    }
+    // links to chosts can only be rewritten once we know where every chost’s page is.
+    threads_cache.cohost_post_pages = cohost_post_pages;
+    if !dates.is_unbounded() {
+        info!("skipped {skipped_by_date} threads outside of --since and --until");
+    }
+    // the site still renders, with “no posts yet” pages and empty feeds, but this is probably
+    // not what you want.
+    if post_paths.is_empty() {
+        warn!("no posts in posts/; your site will be empty until you write some");
+    } else if collections.len("index") == 0 {
+        warn!("none of the posts rendered are interesting, so your main page and feed will be empty; check interesting_tags, self_author, and other_self_authors");
+    }
+    for key in collections.keys() {
+        let threads_page_path = Collections::threads_page_path(key, &SitePath::ROOT)?;
+        for page in 1..=collections.page_count(key) {
+            let page_path = threads_page_path.listing_page(page)?;
+            if let Some(owner) = post_output_owners.get(&page_path) {
+                bail!("{owner:?} would be rendered to {page_path:?}, which is a page for collection {key:?}; rename the post");
+            }
+        }
+    }
+
+    let adjacent_threads = match SETTINGS.adjacent_threads_collection() {
+        Some(key) => collections.adjacent_threads(key)?,
+        None => HashMap::default(),
+    };
+    let series = threads_by_series
+        .into_iter()
+        .map(|(name, threads)| {
+            let threads = order_series(&name, threads);
+            (name, threads)
+        })
+        .collect::<BTreeMap<_, _>>();
+    let series_threads = series_threads(&series)?;
+    // with `title_only_posts = "inline"`, title-only threads on the main page have no page of
+    // their own, only a redirect to where they are on the main page, so their urls still work.
+    let mut inline_hrefs = HashMap::<PostsPath, String>::default();
+    if SETTINGS.title_only_posts() == TitleOnlyPosts::Inline {
+        let index_path = Collections::threads_page_path("index", &SitePath::ROOT)?;
+        // in the same order as the main page, so we know which page each thread is on.
+        for (i, path) in collections.paths("index").enumerate() {
+            let thread = threads_cache.get(path);
+            if !thread.is_title_only() {
+                continue;
+            }
+            let anchor_id = path
+                .anchor_id_with_slug(thread.slug())
+                .ok_or_eyre("thread is not a post")?;
+            let page_path = index_path.listing_page(i / SETTINGS.page_size() + 1)?;
+            inline_hrefs.insert(
+                path.clone(),
+                format!("{}#{anchor_id}", page_path.internal_url()),
+            );
+        }
+    }
+    // with `--diff`, nothing is on disk to compare with, so write every page. with
+    // `versioned_output`, the pages on disk are redirects to the last build, which we can’t copy
+    // into this build, so write every page there too.
+    let check_fingerprints = !force && !output_in_memory() && !SETTINGS.versioned_output();
+    let mut page_fingerprints = match output_in_memory() {
+        true => PageFingerprints::default(),
+        false => PageFingerprints::load()?,
+    };
+    let global_fingerprint = global_page_fingerprint(&threads_cache.cohost_post_pages)?;
+    let progress = Progress::new("rendering", threads_cache.len());
+    let results = threads_cache
+        .par_paths()
+        .map(|path| {
+            let adjacent = adjacent_threads.get(path).cloned().unwrap_or_default();
+            let series = series_threads.get(path);
+            let inline_href = inline_hrefs.get(path).map(|href| &**href);
+            let result = threads_cache.cached_thread(path).and_then(|cached_thread| {
+                let fingerprint = page_fingerprint(
+                    &global_fingerprint,
+                    path,
+                    &cached_thread,
+                    &adjacent,
+                    series,
+                    inline_href,
+                )?;
+                let up_to_date = check_fingerprints
+                    && page_fingerprints.get(path) == Some(&*fingerprint)
+                    && all_exist(&post_output_paths_by_thread[path])?;
+                let timings = match up_to_date {
+                    true => load_timings[path],
+                    false => write_single_post(
+                        path,
+                        &cached_thread,
+                        &adjacent,
+                        series,
+                        &threads_cache.cohost_post_pages,
+                        inline_href,
+                        load_timings[path],
+                    )?,
+                };
+                Ok((timings, fingerprint, up_to_date))
+            });
+            progress.increment();
+            let (timings, fingerprint, up_to_date) = result?;
+            Ok((timings, path.clone(), fingerprint, up_to_date))
+        })
+        .collect::<eyre::Result<Vec<_>>>()?;
+    let mut post_timings = vec![];
+    let mut up_to_date_count = 0;
+    for (timings, path, fingerprint, up_to_date) in results {
+        if up_to_date {
+            up_to_date_count += 1;
+        }
+        page_fingerprints.insert(&path, fingerprint);
+        post_timings.push((timings, path));
+    }
+    if up_to_date_count > 0 {
+        info!("skipped {up_to_date_count} post pages that have not changed since the last render (use --force to write them anyway)");
+    }
+    if !output_in_memory() {
+        page_fingerprints.save()?;
+    }
+
+    // author step: generate atom feeds, and json feeds next to them.
+    for key in collections.keys() {
+        for feed_path in collections.write_feed(key, &threads_cache)? {
+            if collections.is_interesting(key) {
+                interesting_output_paths.insert(feed_path.clone());
+            }
+            output_paths.insert(feed_path);
+        }
+    }
+    if let Some(days) = SETTINGS.updates_feed_days() {
+        let atom_feed_path = collections.write_updates_feed("index", days, &threads_cache)?;
+        interesting_output_paths.insert(atom_feed_path.clone());
+        output_paths.insert(atom_feed_path);
+    }
+    if let Some(favicon) = SETTINGS.favicon.as_ref() {
+        for path in write_favicon(favicon)? {
+            interesting_output_paths.insert(path.clone());
+            output_paths.insert(path);
+        }
+    }
+    if let Some(crawlers) = SETTINGS.crawlers.as_ref() {
+        for path in write_crawler_files(crawlers)? {
+            interesting_output_paths.insert(path.clone());
+            output_paths.insert(path);
+        }
+    }
+    if let Some(colophon) = SETTINGS.colophon.as_ref() {
+        for path in write_colophon_files(colophon, &now)? {
+            interesting_output_paths.insert(path.clone());
+            output_paths.insert(path);
+        }
+    }
+
+    // generate /tagged/<tag>.feed.xml, /tagged/<tag>.feed.json, and /tagged/<tag>.html.
+    let mut child_tags = BTreeMap::<&str, Vec<String>>::default();
+    for tag in threads_by_interesting_tag.keys() {
+        if let Some(parent) = tag_parent(tag) {
+            child_tags.entry(parent).or_default().push(tag.clone());
+        }
+    }
+    for children in child_tags.values_mut() {
+        children.sort();
+    }
+    let mut tag_page_owners = HashMap::<SitePath, &str>::default();
+    for (tag, threads) in threads_by_interesting_tag.iter() {
+        let (atom_feed_path, threads_page_path) =
+            match SitePath::tag_feed(tag).and_then(|feed| Ok((feed, SitePath::tag_page(tag)?))) {
+                Ok(paths) => paths,
+                Err(error) => {
+                    warn!("skipping tag page for {tag:?}: {error}");
+                    continue;
+                }
+            };
+        if let Some(parent) = atom_feed_path.parent() {
+            create_dir_all(parent)?;
+        }
+        let subtitle = match SETTINGS.tag_page(tag) {
+            Some(tag_page) => tag_page.description_text()?,
+            None => None,
+        };
+        let feed_title = SETTINGS.page_title(Some(tag));
+        let feed_threads = threads
+            .iter()
+            .take(SETTINGS.tag_feed_max_entries())
+            .map(|thread| &thread.path);
+        threads_cache.write_feed(
+            &atom_feed_path,
+            feed_threads.clone(),
+            &feed_title,
+            subtitle.as_deref(),
+        )?;
+        let json_feed_path = atom_feed_path.json_feed()?;
+        threads_cache.write_json_feed(
+            &json_feed_path,
+            feed_threads,
+            &feed_title,
+            subtitle.as_deref(),
+        )?;
+        output_paths.insert(atom_feed_path.clone());
+        interesting_output_paths.insert(atom_feed_path.clone());
+        output_paths.insert(json_feed_path.clone());
+        interesting_output_paths.insert(json_feed_path);
+        let pages = paginate(&threads_page_path, threads.iter().collect())?;
+        // a tag like `foo.2` would have the same page as page 2 of `foo`.
+        if let Some((page_path, other)) = pages
+            .iter()
+            .find_map(|(path, _, _)| Some((path, tag_page_owners.get(path)?)))
+        {
+            warn!("skipping tag page for {tag:?}: {page_path:?} is already a page for {other:?}");
+            continue;
+        }
+        for (page_path, threads, pagination) in pages {
+            threads_cache.write_listing_page(
+                &page_path,
+                threads.iter().copied(),
+                ListingPage {
+                    title: &SETTINGS.page_title(Some(&format!("#{tag}"))),
+                    feed_href: &Some(atom_feed_path.clone()),
+                    child_tags: child_tags.get(&**tag).map_or(&[], |children| &children[..]),
+                    tag_page: SETTINGS.tag_page(tag),
+                    authors: &[],
+                    layout: CollectionLayout::List,
+                    pagination: pagination.as_ref(),
+                },
+            )?;
+            tag_page_owners.insert(page_path.clone(), tag);
+            output_paths.insert(page_path.clone());
+            interesting_output_paths.insert(page_path);
+        }
+    }
+
+    // redirect the old pages of tags in `renamed_tags` to their new pages, including each page
+    // after the first, and copy their feeds, since feed readers won’t follow an html redirect.
+    for (alias, tag) in SETTINGS.tag_aliases() {
+        let paths = (|| -> eyre::Result<_> {
+            Ok((
+                SitePath::tag_page(&alias)?,
+                SitePath::tag_feed(&alias)?,
+                SitePath::tag_page(&tag)?,
+                SitePath::tag_feed(&tag)?,
+            ))
+        })();
+        let (alias_page_path, alias_feed_path, page_path, feed_path) = match paths {
+            Ok(paths) => paths,
+            Err(error) => {
+                warn!("skipping redirect for renamed tag {alias:?}: {error}");
+                continue;
+            }
+        };
+        if tag_page_owners.get(&page_path) != Some(&&*tag) {
+            // the tag has no page of its own, so there’s nowhere to redirect to.
+            continue;
+        }
+        let mut redirects = vec![(alias_page_path.clone(), page_path.clone())];
+        for page in 2.. {
+            let page_path = page_path.listing_page(page)?;
+            if tag_page_owners.get(&page_path) != Some(&&*tag) {
+                break;
+            }
+            redirects.push((alias_page_path.listing_page(page)?, page_path));
+        }
+        let alias_json_feed_path = alias_feed_path.json_feed()?;
+        if let Some(path) = [&alias_feed_path, &alias_json_feed_path]
+            .into_iter()
+            .chain(redirects.iter().map(|(alias_page_path, _)| alias_page_path))
+            .find(|path| output_paths.contains(*path))
+        {
+            warn!("skipping redirect for renamed tag {alias:?}: {path:?} is already an output");
+            continue;
+        }
+        if let Some(parent) = alias_feed_path.parent() {
+            create_dir_all(parent)?;
+        }
+        write_atomic(&alias_feed_path, read_output(&feed_path)?)?;
+        write_atomic(&alias_json_feed_path, read_output(feed_path.json_feed()?)?)?;
+        for path in [alias_feed_path, alias_json_feed_path] {
+            output_paths.insert(path.clone());
+            interesting_output_paths.insert(path);
+        }
+        for (alias_page_path, page_path) in redirects {
+            page_redirects.push((
+                alias_page_path.rsync_deploy_line(),
+                page_path.internal_url(),
+            ));
+        }
+    }
+
+    // generate /series/<name>.feed.xml and /series/<name>.html, if `series_pages` is enabled.
+    for (name, threads) in series.iter().filter(|_| SETTINGS.series_pages()) {
+        let (atom_feed_path, threads_page_path) = match SitePath::series_feed(name)
+            .and_then(|feed| Ok((feed, SitePath::series_page(name)?)))
+        {
+            Ok(paths) => paths,
+            Err(error) => {
+                warn!("skipping series page for {name:?}: {error}");
+                continue;
+            }
+        };
+        create_dir_all(&*SitePath::SERIES)?;
+        threads_cache.write_feed(
+            &atom_feed_path,
+            threads.iter().map(|thread| &thread.path),
+            &SETTINGS.page_title(Some(name)),
+            None,
+        )?;
+        output_paths.insert(atom_feed_path.clone());
+        interesting_output_paths.insert(atom_feed_path.clone());
+        threads_cache.write_listing_page(
+            &threads_page_path,
+            threads,
+            ListingPage {
+                title: &SETTINGS.page_title(Some(name)),
+                feed_href: &Some(atom_feed_path),
+                child_tags: &[],
+                tag_page: None,
+                authors: &[],
+                layout: CollectionLayout::List,
+                pagination: None,
+            },
+        )?;
+        output_paths.insert(threads_page_path.clone());
+        interesting_output_paths.insert(threads_page_path);
+    }
+
+    // generate /tagged/<tag>/by/<handle>.feed.xml for each of `tag_author_feeds`.
+    for feed in SETTINGS.tag_author_feeds.iter().flatten() {
+        if !SETTINGS.tag_is_interesting(&feed.tag) {
+            warn!(
+                "skipping tag author feed for {:?}: tag is not interesting",
+                feed.tag
+            );
+            continue;
+        }
+        let paths = threads_by_interesting_tag
+            .get(&feed.tag)
+            .into_iter()
+            .flatten()
+            .map(|thread| &thread.path)
+            .filter(|path| threads_cache.get(path).has_author(&feed.author))
+            .take(SETTINGS.tag_feed_max_entries())
+            .collect::<Vec<_>>();
+        let first_thread = paths
+            .first()
+            .map(|path| threads_cache.thread(path))
+            .transpose()?;
+        let Some(author) = first_thread.as_ref().and_then(|thread| {
+            thread
+                .meta
+                .authors()
+                .find(|author| author.href == feed.author)
+        }) else {
+            info!(
+                "skipping tag author feed for {:?} by {:?}: no threads",
+                feed.tag, feed.author
+            );
+            continue;
+        };
+        let atom_feed_path = SitePath::tag_author_feed(&feed.tag, &feed.handle)?;
+        if let Some(parent) = atom_feed_path.parent() {
+            create_dir_all(parent)?;
+        }
+        threads_cache.write_feed(
+            &atom_feed_path,
+            paths,
+            &SETTINGS.page_title(Some(&format!("{} by {}", feed.tag, author.display_name))),
+            None,
+        )?;
+        output_paths.insert(atom_feed_path.clone());
+        interesting_output_paths.insert(atom_feed_path);
+    }
+
+    let stats = Stats {
+        threads: threads_cache.len(),
+        interesting_threads: collections.len("index"),
+        collections: collections
+            .keys()
+            .map(|key| (key.to_owned(), collections.len(key)))
+            .collect(),
+        tags: tags.len(),
+        threads_by_tag: tags
+            .iter()
+            .map(|(tag, count)| (tag.clone(), *count))
+            .collect(),
+        attachments: threads_cache
+            .needs_attachments()
+            .collect::<BTreeSet<_>>()
+            .len(),
+    };
+    let stats_path = SitePath::ROOT.join("stats.json")?;
+    write_atomic(&stats_path, serde_json::to_vec_pretty(&stats)?)?;
+    output_paths.insert(stats_path);
+
+    let search_index_key = SETTINGS.search_index_collection();
+    let search_index_path =
+        collections.write_search_index(search_index_key, &threads_cache, &inline_hrefs)?;
+    if collections.is_interesting(search_index_key) {
+        interesting_output_paths.insert(search_index_path.clone());
+    }
+    output_paths.insert(search_index_path);
+
+    let mut tags = tags.into_iter().collect::<Vec<_>>();
+    tags.sort_by(|p, q| p.1.cmp(&q.1).reverse().then(p.0.cmp(&q.0)));
+    info!("all tags: {tags:?}");
+    info!(
+        "interesting tags: {:?}",
+        tags.iter()
+            .filter(|(tag, _)| SETTINGS.tag_is_interesting(tag))
+            .collect::<Vec<_>>()
+    );
+
+    // most prolific authors first.
+    let mut index_authors = interesting_authors.into_values().collect::<Vec<_>>();
+    index_authors.sort_by(|(p, p_count), (q, q_count)| {
+        q_count
+            .cmp(p_count)
+            .then(p.display_name.cmp(&q.display_name))
+    });
+
+    // reader step: generate posts pages.
+    for key in collections.keys() {
+        info!(
+            "writing threads page for collection {key:?} ({} threads)",
+            collections.len(key),
+        );
+        let authors = if key == "index" && SETTINGS.index_authors() {
+            &index_authors[..]
+        } else {
+            &[]
+        };
+        // TODO: write internal collections to another dir?
+        for threads_page_path in
+            collections.write_threads_page(key, &SitePath::ROOT, &threads_cache, authors)?
+        {
+            if collections.is_interesting(key) {
+                interesting_output_paths.insert(threads_page_path.clone());
+            }
+            output_paths.insert(threads_page_path);
+        }
+    }
+    if let Some(key) = all_in_one {
+        info!(
+            "writing all-in-one page for collection {key:?} ({} threads)",
+            collections.len(key),
+        );
+        let all_in_one_path = collections.write_all_in_one_page(key, &threads_cache)?;
+        if collections.is_interesting(key) {
+            interesting_output_paths.insert(all_in_one_path.clone());
+        }
+        output_paths.insert(all_in_one_path);
+    }
+
+    let redirect_paths = write_redirects(&page_redirects)?;
+    output_paths.extend(redirect_paths.iter().cloned());
+    interesting_output_paths.extend(redirect_paths);
+
+    if SETTINGS.versioned_output() {
+        let (versioned_paths, current_path) = write_versioned_output(&output_paths)?;
+        for (path, versioned_path) in versioned_paths {
+            if interesting_output_paths.contains(&path) {
+                interesting_output_paths.insert(versioned_path.clone());
+            }
+            output_paths.insert(versioned_path);
+        }
+        interesting_output_paths.insert(current_path.clone());
+        output_paths.insert(current_path);
+    }
+
+    // written last, so it can list everything else (but not itself).
+    write_manifest(&output_paths)?;
+
+    if !output_in_memory() {
+        let mut record = OutputRecord::load()?;
+        record.extend(&output_paths)?;
+        record.save()?;
+    }
+
+    if let Some(path) = &SETTINGS.interesting_output_filenames_list_path {
+        let interesting_output_paths = serialise_interesting_output_paths(
+            interesting_output_paths.iter(),
+            SETTINGS.interesting_output_filenames_format(),
+        )?;
+        write_atomic(path, interesting_output_paths)?;
+    }
+
+    if !broken_references.is_empty() {
+        warn!(
+            "skipped {} broken references to missing posts:",
+            broken_references.len()
+        );
+        for (path, reference) in broken_references {
+            warn!("- in {path:?}: {reference:?}");
+        }
+    }
+    if !unlocalised_urls.is_empty() {
+        warn!(
+            "found {} cohost urls that could not be replaced with cached attachments:",
+            unlocalised_urls.len()
+        );
+        for (path, url) in unlocalised_urls {
+            warn!("- in {path:?}: {url}");
+        }
+    }
+    report_offline_misses();
+    if slowest_posts > 0 && tracing::enabled!(Level::DEBUG) {
+        post_timings.sort_by(|(a, _), (b, _)| b.total().cmp(&a.total()));
+        debug!("slowest {} posts:", slowest_posts.min(post_timings.len()));
+        for (timings, path) in post_timings.iter().take(slowest_posts) {
+            debug!(
+                total = ?timings.total(),
+                load = ?timings.load,
+                template = ?timings.template,
+                write = ?timings.write,
+                "- {path:?}"
+            );
+        }
+    }
+
+    Ok(output_paths)
+}
+
+/// `site.webmanifest`, so browsers can use the `[favicon]` when installing the site.
+#[derive(Debug, Serialize)]
+struct WebAppManifest<'settings> {
+    name: &'settings str,
+    short_name: &'settings str,
+    start_url: &'settings str,
+    display: &'static str,
+    icons: Vec<WebAppManifestIcon<'settings>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    theme_color: Option<&'settings str>,
+}
+
+#[derive(Debug, Serialize)]
+struct WebAppManifestIcon<'settings> {
+    src: &'settings str,
+    r#type: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    sizes: Option<&'settings str>,
+}
+
+/// copies the `[favicon]` icons into the site, and writes `site.webmanifest`, returning the paths.
+fn write_favicon(favicon: &Favicon) -> eyre::Result<Vec<SitePath>> {
+    let mut result = vec![];
+    for icon in favicon.icons() {
+        let path = SitePath::ROOT.join(&icon.filename)?;
+        let content = std::fs::read(&icon.source)
+            .wrap_err_with(|| format!("failed to copy favicon: {:?}", icon.source))?;
+        write_atomic(&path, content)?;
+        result.push(path);
+    }
+
+    let manifest = WebAppManifest {
+        name: &SETTINGS.site_title,
+        short_name: &SETTINGS.site_title,
+        start_url: &SETTINGS.base_url,
+        display: "browser",
+        icons: favicon
+            .icons()
+            .iter()
+            .map(|icon| WebAppManifestIcon {
+                src: &icon.filename,
+                r#type: icon.media_type,
+                sizes: icon.sizes.as_deref(),
+            })
+            .collect(),
+        theme_color: favicon.theme_color.as_deref(),
+    };
+    let path = SitePath::ROOT.join("site.webmanifest")?;
+    write_atomic(&path, serde_json::to_vec_pretty(&manifest)?)?;
+    result.push(path);
+
+    Ok(result)
+}
+
+/// writes `robots.txt` and `llms.txt` for the `[crawlers]`, if they have anything to say,
+/// returning the paths.
+fn write_crawler_files(crawlers: &Crawlers) -> eyre::Result<Vec<SitePath>> {
+    let mut result = vec![];
+    for (filename, content) in [
+        ("robots.txt", robots_txt(crawlers)),
+        ("llms.txt", llms_txt(crawlers, &SETTINGS.external_base_url)?),
+    ] {
+        let Some(content) = content else { continue };
+        let path = SitePath::ROOT.join(filename)?;
+        write_atomic(&path, content)?;
+        result.push(path);
+    }
+
+    Ok(result)
+}
+
+/// one group per user agent, separated by blank lines, or None if there are no groups.
+fn robots_txt(crawlers: &Crawlers) -> Option<String> {
+    if crawlers.robots.is_empty() {
+        return None;
+    }
+
+    let groups = crawlers
+        .robots
+        .iter()
+        .map(|group| {
+            let mut result = format!("User-agent: {}\n", group.user_agent);
+            for path in &group.allow {
+                result += &format!("Allow: {path}\n");
+            }
+            for path in &group.disallow {
+                result += &format!("Disallow: {path}\n");
+            }
+            result
+        })
+        .collect::<Vec<_>>();
+
+    Some(groups.join("\n"))
+}
+
+/// markdown in the format of <https://llmstxt.org>, with links to the `[[nav]]` and the main atom
+/// feed, or None if there is no `llms_summary`.
+fn llms_txt(crawlers: &Crawlers, external_base_url: &str) -> eyre::Result<Option<String>> {
+    let Some(summary) = crawlers.llms_summary.as_deref() else {
+        return Ok(None);
+    };
+    let external_base_url = Url::parse(external_base_url)?;
+
+    let mut result = format!("# {}\n\n", SETTINGS.site_title);
+    for line in summary.trim().lines() {
+        result += &format!("> {line}\n");
+    }
+    result += "\n## links\n\n";
+    for link in &SETTINGS.nav {
+        result += &format!(
+            "- [{}]({})\n",
+            link.text,
+            external_base_url.join(&link.href)?
+        );
+    }
+    result += &format!(
+        "- [atom feed]({})\n",
+        external_base_url.join("index.feed.xml")?
+    );
+
+    Ok(Some(result))
+}
+
+#[test]
+fn test_crawler_files() -> eyre::Result<()> {
+    use crate::settings::RobotsGroup;
+
+    let mut crawlers = Crawlers::default();
+    assert_eq!(robots_txt(&crawlers), None);
+    assert_eq!(llms_txt(&crawlers, "https://example.com/blog/")?, None);
+
+    crawlers.robots = vec![
+        RobotsGroup {
+            user_agent: "GPTBot".to_owned(),
+            allow: vec![],
+            disallow: vec!["/".to_owned()],
+        },
+        RobotsGroup {
+            user_agent: "*".to_owned(),
+            allow: vec!["/blog/".to_owned()],
+            disallow: vec!["/blog/tagged/".to_owned(), "/drafts/".to_owned()],
+        },
+    ];
+    assert_eq!(
+        robots_txt(&crawlers).as_deref(),
+        Some(concat!(
+            "User-agent: GPTBot\n",
+            "Disallow: /\n",
+            "\n",
+            "User-agent: *\n",
+            "Allow: /blog/\n",
+            "Disallow: /blog/tagged/\n",
+            "Disallow: /drafts/\n",
+        ))
+    );
+
+    crawlers.llms_summary = Some("posts about birds\nand bugs\n".to_owned());
+    let llms = llms_txt(&crawlers, "https://example.com/blog/")?.expect("summary is set");
+    assert!(llms.starts_with(&format!("# {}\n\n", SETTINGS.site_title)));
+    assert!(llms.contains("> posts about birds\n> and bugs\n\n## links\n"));
+    assert!(llms.ends_with("- [atom feed](https://example.com/blog/index.feed.xml)\n"));
+
+    Ok(())
+}
+
+/// writes `humans.txt`, and `colophon.html` if enabled, for the `[colophon]`, returning the paths.
+fn write_colophon_files(colophon: &Colophon, now: &str) -> eyre::Result<Vec<SitePath>> {
+    let path = SitePath::ROOT.join("humans.txt")?;
+    write_atomic(&path, humans_txt(colophon, now))?;
+    let mut result = vec![path];
+    if colophon.page {
+        let path = SitePath::ROOT.join("colophon.html")?;
+        let content = ColophonTemplate::render(colophon, now)?;
+        let page =
+            ThreadsPageTemplate::render(&content, &SETTINGS.page_title(Some("colophon")), &None)?;
+        write_atomic(&path, page)?;
+        result.push(path);
+    }
+
+    Ok(result)
+}
+
+/// text in the format of <https://humanstxt.org>, with the owner, the tools used, and when the
+/// site was last rendered.
+fn humans_txt(colophon: &Colophon, now: &str) -> String {
+    let mut result = "/* TEAM */\n".to_owned();
+    if let Some(owner) = colophon.owner.as_deref() {
+        result += &format!("Owner: {owner}\n");
+    }
+    if let Some(contact) = colophon.contact.as_deref() {
+        result += &format!("Contact: {contact}\n");
+    }
+    result += "\n/* SITE */\n";
+    if let Some(now) = parse_date(now) {
+        result += &format!("Last update: {}\n", now.format("%Y/%m/%d"));
+    }
+    let software = [format!("autost {}", env!("CARGO_PKG_VERSION"))]
+        .into_iter()
+        .chain(colophon.tools.iter().cloned())
+        .collect::<Vec<_>>();
+    result += &format!("Software: {}\n", software.join(", "));
+
+    result
+}
+
+#[test]
+fn test_humans_txt() {
+    let mut colophon = Colophon::default();
+    let version = env!("CARGO_PKG_VERSION");
+    assert_eq!(
+        humans_txt(&colophon, "2024-10-01T04:30:00.000Z"),
+        format!("/* TEAM */\n\n/* SITE */\nLast update: 2024/10/01\nSoftware: autost {version}\n")
+    );
+
+    colophon.owner = Some("eggbug".to_owned());
+    colophon.contact = Some("eggbug@example.com".to_owned());
+    colophon.tools = vec!["neovim".to_owned()];
+    assert_eq!(
+        humans_txt(&colophon, "2024-10-01T04:30:00.000Z"),
+        format!(
+            concat!(
+                "/* TEAM */\n",
+                "Owner: eggbug\n",
+                "Contact: eggbug@example.com\n",
+                "\n",
+                "/* SITE */\n",
+                "Last update: 2024/10/01\n",
+                "Software: autost {}, neovim\n",
+            ),
+            version
+        )
+    );
+}
+
+/// a file written by the render, listed in `manifest.json` for deploy tools.
+#[derive(Debug, PartialEq, Serialize)]
+struct ManifestEntry {
+    /// relative to the site output directory, like `tagged/photography.html`.
+    path: String,
+    size: u64,
+    sha256: String,
+}
+
+impl ManifestEntry {
+    fn new(path: &SitePath) -> eyre::Result<Self> {
+        let content = read_output(path)?;
+        let sha256 = Sha256::digest(&content)
+            .map(|o| format!("{o:02x}"))
+            .join("");
+
+        Ok(Self {
+            path: path.rsync_deploy_line(),
+            size: content.len().try_into()?,
+            sha256,
+        })
+    }
+}
+
+/// writes `manifest.json`, listing the files written by this render, with their sizes and hashes.
+fn write_manifest(output_paths: &BTreeSet<SitePath>) -> eyre::Result<SitePath> {
+    let entries = manifest_entries(output_paths)?;
+    let manifest_path = manifest_path()?;
+    write_atomic(&manifest_path, serde_json::to_vec_pretty(&entries)?)?;
+
+    Ok(manifest_path)
+}
+
+fn manifest_path() -> eyre::Result<SitePath> {
+    SitePath::ROOT.join("manifest.json")
+}
+
+fn manifest_entries(output_paths: &BTreeSet<SitePath>) -> eyre::Result<Vec<ManifestEntry>> {
+    output_paths.par_iter().map(ManifestEntry::new).collect()
+}
+
+/// returns a short hash of the manifest entries, which changes whenever any file does.
+fn build_hash(entries: &[ManifestEntry]) -> eyre::Result<String> {
+    let sha256 = Sha256::digest(serde_json::to_vec(entries)?)
+        .map(|o| format!("{o:02x}"))
+        .join("");
+
+    Ok(sha256[..12].to_owned())
+}
+
+#[test]
+fn test_build_hash() -> eyre::Result<()> {
+    let entry = |sha256: &str| ManifestEntry {
+        path: "index.html".to_owned(),
+        size: 1,
+        sha256: sha256.to_owned(),
+    };
+    let hash = build_hash(&[entry("a")])?;
+    assert_eq!(hash.len(), 12);
+    assert_eq!(build_hash(&[entry("a")])?, hash);
+    assert_ne!(build_hash(&[entry("b")])?, hash);
+    assert_ne!(build_hash(&[])?, hash);
+
+    Ok(())
+}
+
+/// copies the files written by this render into `v/<build hash>/`, and replaces the pages at the
+/// top level with redirects there, so the build can be cached forever (`versioned_output`).
+/// other files, like atom feeds, stay at the top level too, so their urls still work.
+///
+/// returns the copies of each file, and `v/current`, which has the build hash.
+fn write_versioned_output(
+    output_paths: &BTreeSet<SitePath>,
+) -> eyre::Result<(BTreeMap<SitePath, SitePath>, SitePath)> {
+    let hash = build_hash(&manifest_entries(output_paths)?)?;
+    info!("writing build {hash} to v/{hash}/");
+    let mut result = BTreeMap::default();
+    for path in output_paths {
+        let versioned_path = path.versioned(&hash)?;
+        if let Some(parent) = versioned_path.parent() {
+            create_dir_all(parent)?;
+        }
+        // not hard links, so older builds stay intact even if something edits the site in place.
+        write_atomic(&versioned_path, read_output(path)?)?;
+        if path.filename().ends_with(".html") {
+            let href = versioned_path.internal_url();
+            let page = RedirectPageTemplate::render(&href, &path.external_url())?;
+            write_atomic(path, format!("{page}\n"))?;
+        }
+        result.insert(path.clone(), versioned_path);
+    }
+    let current_path = SitePath::VERSIONS.join("current")?;
+    write_atomic(&current_path, format!("{hash}\n"))?;
+
+    Ok((result, current_path))
+}
+
+/// deletes any pages and feeds that were written by an earlier render but not by this one, like
+/// pages for posts that were deleted or renamed. attachments, static files, and files you put in
+/// the site yourself are never deleted.
+fn prune_stale_output(output_paths: &BTreeSet<SitePath>) -> eyre::Result<()> {
+    let stale = stale_output_paths(output_paths)?;
+    for path in stale.iter() {
+        info!("pruning stale output file: {path:?}");
+        remove_file(path)?;
+    }
+    info!("pruned {} stale output files", stale.len());
+    let mut record = OutputRecord::load()?;
+    record.remove(&stale);
+    record.save()?;
+
+    Ok(())
+}
+
+/// returns the pages and feeds in the site that were written by an earlier render (see
+/// [`OutputRecord`]) but not by this render, for `--prune`.
+fn stale_output_paths(output_paths: &BTreeSet<SitePath>) -> eyre::Result<Vec<SitePath>> {
+    let result = OutputRecord::load()?
+        .existing_paths()?
+        .into_iter()
+        .filter(|path| {
+            let filename = path.filename();
+            let is_page_or_feed = filename.ends_with(".html")
+                || filename.ends_with(".feed.xml")
+                || filename.ends_with(".feed.json");
+            is_page_or_feed && !output_paths.contains(path)
+        })
+        .collect();
+
+    Ok(result)
+}
+
+/// the files in the site that were written by renders and not pruned since, so `--prune` only
+/// deletes files that autost wrote. kept in `render-cache/outputs.json`.
+#[derive(Debug, Default, Deserialize, Serialize)]
+struct OutputRecord {
+    /// paths relative to the site, like `tagged/foo.html`.
+    paths: BTreeSet<String>,
+}
+
+impl OutputRecord {
+    fn path() -> PathBuf {
+        Path::new(RENDER_CACHE_DIR).join("outputs.json")
+    }
+
+    /// loads the files written by earlier renders. if there are none, or we can’t read them,
+    /// nothing is pruned.
+    fn load() -> eyre::Result<Self> {
+        let path = Self::path();
+        if !exists(&path)? {
+            return Ok(Self::default());
+        }
+        match serde_json::from_reader(File::open(&path)?) {
+            Ok(result) => Ok(result),
+            Err(error) => {
+                warn!(
+                    ?path,
+                    "failed to read output record, pruning nothing: {error}"
+                );
+                Ok(Self::default())
+            }
+        }
+    }
+
+    fn save(&self) -> eyre::Result<()> {
+        save_render_cache_json(&Self::path(), self)
+    }
+
+    /// adds the files written by this render, and forgets any recorded files that are gone.
+    /// older builds of `versioned_output` and attachments are never recorded, so they are kept.
+    fn extend(&mut self, output_paths: &BTreeSet<SitePath>) -> eyre::Result<()> {
+        let paths = self.existing_paths()?.into_iter().chain(
+            output_paths
+                .iter()
+                .filter(|path| {
+                    !path.is_in(&SitePath::ATTACHMENTS) && !path.is_in(&SitePath::VERSIONS)
+                })
+                .cloned(),
+        );
+        self.paths = paths.map(|path| path.rsync_deploy_line()).collect();
+
+        Ok(())
+    }
+
+    fn remove(&mut self, paths: &[SitePath]) {
+        for path in paths {
+            self.paths.remove(&path.rsync_deploy_line());
+        }
+    }
+
+    /// returns the recorded files that are still in the site.
+    fn existing_paths(&self) -> eyre::Result<Vec<SitePath>> {
+        let mut result = vec![];
+        for path in self.paths.iter() {
+            match SitePath::ROOT.join(path) {
+                Ok(path) if exists(&path)? => result.push(path),
+                Ok(_) => {}
+                Err(error) => warn!("ignoring bad path in output record: {path:?}: {error}"),
+            }
+        }
+
+        Ok(result)
+    }
+}
+
+fn serialise_interesting_output_paths<'paths>(
+    paths: impl Iterator<Item = &'paths SitePath>,
+    format: OutputFilenamesFormat,
+) -> eyre::Result<String> {
+    let lines = paths.map(|path| path.rsync_deploy_line());
+
+    Ok(match format {
+        OutputFilenamesFormat::Lines => lines.map(|line| line + "\n").collect(),
+        OutputFilenamesFormat::Nul => lines.map(|line| line + "\0").collect(),
+        OutputFilenamesFormat::Json => serde_json::to_string(&lines.collect::<Vec<_>>())?,
+    })
+}
+
+#[test]
+fn test_serialise_interesting_output_paths() -> eyre::Result<()> {
+    let paths = [
+        SitePath::ROOT.join("a.html")?,
+        SitePath::ROOT.join("b c.html")?,
+    ];
+    assert_eq!(
+        serialise_interesting_output_paths(paths.iter(), OutputFilenamesFormat::Lines)?,
+        "a.html\nb c.html\n"
+    );
+    assert_eq!(
+        serialise_interesting_output_paths(paths.iter(), OutputFilenamesFormat::Nul)?,
+        "a.html\0b c.html\0"
+    );
+    assert_eq!(
+        serialise_interesting_output_paths(paths.iter(), OutputFilenamesFormat::Json)?,
+        r#"["a.html","b c.html"]"#
+    );
+
+    Ok(())
+}
+
+/// writes the `redirects` in each of the `redirect_formats`, and redirects from the old pages of
+/// posts with a `slug` or with `clean_urls` and of tags in `renamed_tags` to their new pages,
+/// returning the paths written.
+fn write_redirects(page_redirects: &[(String, String)]) -> eyre::Result<Vec<SitePath>> {
+    let status = SETTINGS.redirect_status();
+    let mut result = vec![];
+
+    // old paths and new paths are relative to `base_url`, like `nav` hrefs.
+    let mut redirects = SETTINGS
+        .redirects
+        .iter()
+        .flatten()
+        .map(|(from, to)| {
+            (
+                from.trim_start_matches('/'),
+                SETTINGS.base_url_relativise(to),
+            )
+        })
+        .collect::<Vec<_>>();
+    // `redirects` takes precedence over slugs, so you can send the old page somewhere else.
+    for (from, to) in page_redirects {
+        if !redirects.iter().any(|(other, _)| other == from) {
+            redirects.push((from, to.clone()));
+        }
+    }
+    let non_canonical_hosts = SETTINGS.non_canonical_hosts();
+    if redirects.is_empty() && non_canonical_hosts.is_empty() {
+        return Ok(vec![]);
+    }
+    if !non_canonical_hosts.is_empty()
+        && !SETTINGS
+            .redirect_formats()
+            .contains(&RedirectFormat::Netlify)
+    {
+        info!(
+            "canonical_host: configure your web server to redirect {} to {}",
+            non_canonical_hosts.join(" and "),
+            SETTINGS.external_base_url,
+        );
+    }
+    for (from, to) in redirects.iter() {
+        let Some(path) = to.strip_prefix(&SETTINGS.base_url) else {
+            // not on this site, so we can’t check it.
+            continue;
+        };
+        let path = path.split(['?', '#']).next().unwrap_or(path);
+        let path = urlencoding::decode(path)?;
+        let exists = if path.is_empty() || path.ends_with('/') {
+            SitePath::ROOT.join(&format!("{path}index.html"))
+        } else {
+            SitePath::ROOT.join(&path)
+        }
+        .map_or(Ok(false), exists)?;
+        if !exists {
+            warn!("redirect from {from:?} goes to missing page: {to:?}");
+        }
+    }
+
+    for format in SETTINGS.redirect_formats() {
+        match format {
+            RedirectFormat::Html => {
+                for (from, to) in redirects.iter() {
+                    let path = if from.is_empty() || from.ends_with('/') {
+                        SitePath::ROOT.join(&format!("{from}index.html"))?
+                    } else {
+                        SitePath::ROOT.join(from)?
+                    };
+                    if let Some(parent) = path.parent() {
+                        create_dir_all(parent)?;
+                    }
+                    let canonical_href = SETTINGS.external_base_url_absolutise(to);
+                    let page = RedirectPageTemplate::render(to, &canonical_href)?;
+                    write_atomic(&path, format!("{page}\n"))?;
+                    result.push(path);
+                }
+            }
+            RedirectFormat::Netlify => {
+                let path = SitePath::ROOT.join("_redirects")?;
+                let mut file = AtomicFile::create(&path)?;
+                // rules are matched in order, so send other hosts away before anything else.
+                let url = Url::parse(&SETTINGS.external_base_url)?;
+                let origin = url.origin().ascii_serialization();
+                for host in non_canonical_hosts.iter() {
+                    let scheme = url.scheme();
+                    writeln!(file, "{scheme}://{host}/* {origin}/:splat {status}!")?;
+                }
+                for (from, to) in redirects.iter() {
+                    writeln!(file, "{}{from} {to} {status}", SETTINGS.base_url)?;
+                }
+                file.commit()?;
+                result.push(path);
+            }
+            RedirectFormat::Json => {
+                let path = SitePath::ROOT.join("redirects.json")?;
+                let json = redirects
+                    .iter()
+                    .map(|(from, to)| {
+                        let from = format!("{}{from}", SETTINGS.base_url);
+                        (from, serde_json::json!({ "to": to, "status": status }))
+                    })
+                    .collect::<serde_json::Map<_, _>>();
+                write_atomic(&path, serde_json::to_vec_pretty(&json)?)?;
+                result.push(path);
+            }
+        }
+    }
+
+    Ok(result)
+}
+
+/// counts the thread’s tags, and adds it to the collections it belongs in.
+fn add_thread_to_collections(
+    result: &mut RenderResult,
+    path: &PostsPath,
+    rendered_path: &SitePath,
+    thread: &Thread,
+) {
+    let decision = decide_collections(thread);
+    match decision.route {
+        Route::Unlisted => {
+            // unlisted threads still need to be deployed, so their links work.
+            debug!("not adding unlisted thread to any collections: {path:?}");
+            result
+                .interesting_output_paths
+                .insert(rendered_path.clone());
+            return;
+        }
+        Route::Liked => {
+            // liked threads are only deployed if `publish_liked_posts`.
+            if SETTINGS.publish_liked_posts() {
+                result
+                    .interesting_output_paths
+                    .insert(rendered_path.clone());
+            }
+            result.collections.push("liked", path, thread);
+            return;
+        }
+        _ => {}
+    }
+    for tag in thread.meta.tags.iter() {
+        if !SETTINGS.tag_is_hidden(tag) {
+            *result.tags.entry(tag.clone()).or_insert(0usize) += 1;
+        }
+    }
+    result.collections.push("all", path, thread);
+    if let Some(published) = thread.meta.published.as_deref() {
+        if parse_date(published).is_none() {
+            warn!(
+                ?path,
+                "failed to parse published date {published:?}; sorting as {:?}",
+                SETTINGS.unparseable_dates_sort_as()
+            );
+        }
+    }
+    if decision.marked_interesting {
+        result.collections.push("marked_interesting", path, thread);
+    }
+    let was_interesting = decision.route == Route::Interesting;
+    if was_interesting {
+        result
+            .interesting_output_paths
+            .insert(rendered_path.clone());
+        result.collections.push("index", path, thread);
+        for author in thread.meta.authors() {
+            result
+                .interesting_authors
+                .entry(author.href.clone())
+                .or_insert((author.clone(), 0))
+                .1 += 1;
+        }
+        for tag in thread.meta.tags.iter() {
+            if SETTINGS.tag_has_page(tag) {
+                result
+                    .threads_by_interesting_tag
+                    .entry(tag.clone())
+                    .or_default()
+                    .insert(ThreadInCollection::new(path, thread));
+            }
+        }
+        if thread.meta.tags.is_empty() {
+            result
+                .collections
+                .push("untagged_interesting", path, thread);
+        }
+        if let Some(series) = thread.meta.series.as_ref() {
+            result
+                .threads_by_series
+                .entry(series.clone())
+                .or_default()
+                .push((
+                    thread.meta.series_index,
+                    ThreadInCollection::new(path, thread),
+                ));
+        }
+    }
+    match decision.route {
+        Route::Excluded if thread_has_own_input(thread) => {
+            result.collections.push("excluded", path, thread);
+            result.collections.push("skipped_own", path, thread);
+        }
+        Route::Excluded => {
+            result.collections.push("excluded", path, thread);
+            result.collections.push("skipped_other", path, thread);
+        }
+        Route::SkippedOwn => result.collections.push("skipped_own", path, thread),
+        Route::SkippedOther => result.collections.push("skipped_other", path, thread),
+        _ => {}
+    }
+    for collection in SETTINGS.collections.iter().flatten() {
+        if custom_collection_contains(collection, thread, was_interesting)
+            || decision.extra_collections.contains(&collection.name)
+        {
+            result.collections.push(&collection.name, path, thread);
+        }
+    }
+}
+
+/// returns true iff the thread matches all of the conditions of the custom collection.
+fn custom_collection_contains(
+    collection: &CustomCollection,
+    thread: &Thread,
+    was_interesting: bool,
+) -> bool {
+    if let Some(tags) = collection.tags.as_ref() {
+        if !thread.meta.tags.iter().any(|tag| tags.contains(tag)) {
+            return false;
+        }
+    }
+    if let Some(authors) = collection.authors.as_ref() {
+        if !thread
+            .meta
+            .authors()
+            .any(|author| authors.contains(&author.href))
+        {
+            return false;
+        }
+    }
+    if collection
+        .interesting
+        .is_some_and(|interesting| interesting != was_interesting)
+    {
+        return false;
+    }
+    let dates = DateRange {
+        since: collection.since(),
+        until: collection.until(),
+    };
+
+    dates.contains(thread)
+}
+
+#[test]
+fn test_add_thread_to_collections() -> eyre::Result<()> {
+    let mut result = RenderResult::default()?;
+    let path = PostsPath::generated_post_path(1);
+    let rendered_path = path
+        .rendered_path()?
+        .expect("guaranteed by generated_post_path");
+    let post = TemplatedPost::filter(
+        r#"<meta name="tags" content="photography"><meta name="tags" content="reading">"#,
+        Some(path.clone()),
+    )?;
+    let thread = Thread::try_from(post)?;
+    add_thread_to_collections(&mut result, &path, &rendered_path, &thread);
+
+    // the thread has two interesting tags, but it should only be in the index once.
+    assert_eq!(result.collections.len("index"), 1);
+    assert_eq!(result.collections.len("all"), 1);
+    assert_eq!(result.threads_by_interesting_tag["photography"].len(), 1);
+    assert_eq!(result.threads_by_interesting_tag["reading"].len(), 1);
+
+    // rendering the same thread again, like when merging results, should not duplicate it.
+    let mut other = RenderResult::default()?;
+    add_thread_to_collections(&mut other, &path, &rendered_path, &thread);
+    result.collections.merge(other.collections);
+    assert_eq!(result.collections.len("index"), 1);
+
+    // the same tag in different normalisation forms, like “café” with a combining accent, is
+    // grouped as one tag.
+    let mut result = RenderResult::default()?;
+    for (id, tag) in [(1, "caf\u{E9}"), (2, "cafe\u{301}")] {
+        let path = PostsPath::generated_post_path(id);
+        let rendered_path = path
+            .rendered_path()?
+            .expect("guaranteed by generated_post_path");
+        let post = TemplatedPost::filter(
+            &format!(r#"<meta name="tags" content="{tag}">"#),
+            Some(path.clone()),
+        )?;
+        let thread = Thread::try_from(post)?;
+        add_thread_to_collections(&mut result, &path, &rendered_path, &thread);
+    }
+    assert_eq!(result.tags, [("caf\u{E9}".to_owned(), 2)].into());
+
+    // unlisted threads are not in any collections, even if they are interesting.
+    let post = TemplatedPost::filter(
+        r#"<meta name="is_unlisted"><meta name="tags" content="photography">"#,
+        Some(path.clone()),
+    )?;
+    let thread = Thread::try_from(post)?;
+    let mut result = RenderResult::default()?;
+    add_thread_to_collections(&mut result, &path, &rendered_path, &thread);
+    assert!(result
+        .collections
+        .keys()
+        .all(|key| result.collections.len(key) == 0));
+    assert!(result.threads_by_interesting_tag.is_empty());
+    assert!(result.interesting_output_paths.contains(&rendered_path));
+
+    // liked threads are only in the liked collection, and not deployed by default.
+    let post = TemplatedPost::filter(
+        r#"<meta name="is_liked"><meta name="tags" content="photography">"#,
+        Some(path.clone()),
+    )?;
+    let thread = Thread::try_from(post)?;
+    let mut result = RenderResult::default()?;
+    add_thread_to_collections(&mut result, &path, &rendered_path, &thread);
+    assert!(result
+        .collections
+        .keys()
+        .all(|key| result.collections.len(key) == usize::from(key == "liked")));
+    assert!(result.threads_by_interesting_tag.is_empty());
+    assert!(result.tags.is_empty());
+    assert!(!result.interesting_output_paths.contains(&rendered_path));
+
+    Ok(())
+}
+
+#[test]
+fn test_cohost_shares_in_collections() -> eyre::Result<()> {
+    use std::mem::take;
+
+    use serde_json::json;
+
+    use crate::{cohost::Post, command::cohost2autost::write_chost};
+
+    let chost = |post_id: usize, handle: &str, headline: &str, markdown: &str| {
+        json!({
+            "postId": post_id,
+            "transparentShareOfPostId": null,
+            "shareOfPostId": null,
+            "filename": format!("{post_id}-slug"),
+            "publishedAt": "2024-01-01T00:00:00.000Z",
+            "headline": headline,
+            "tags": [],
+            "postingProject": {
+                "handle": handle,
+                "displayName": handle,
+                "privacy": "public",
+                "loggedOutPostVisibility": "public",
+            },
+            "shareTree": [],
+            "plainTextBody": markdown,
+            "blocks": if markdown.is_empty() {
+                json!([])
+            } else {
+                json!([{"type": "markdown", "markdown": {"content": markdown}}])
+            },
+            "astMap": {"spans": []},
+        })
+    };
+    // converts the chost and its share tree like cohost2autost, but without writing any files.
+    let convert = |chost: serde_json::Value| -> eyre::Result<Thread> {
+        let mut post: Post = serde_json::from_value(chost)?;
+        let convert_one = |post: Post, references, path: PostsPath| -> eyre::Result<_> {
+            let mut html = vec![];
+            let published = post.publishedAt.clone();
+            let context = attachments_context();
+            write_chost(
+                post, references, published, None, false, &mut html, &context,
+            )?;
+            TemplatedPost::filter(std::str::from_utf8(&html)?, Some(path))
+        };
+        let mut posts = vec![];
+        let mut references = vec![];
+        for shared_post in take(&mut post.shareTree) {
+            let path = PostsPath::references_post_path(post.postId, shared_post.postId);
+            posts.push(convert_one(shared_post, vec![], path.clone())?);
+            references.push(path);
+        }
+        let path = PostsPath::generated_post_path(post.postId);
+        posts.push(convert_one(post, references, path)?);
+        Thread::from_posts(posts, vec![])
+    };
+    let collections = |thread: &Thread| -> eyre::Result<Vec<String>> {
+        let mut result = RenderResult::default()?;
+        let path = thread.path.clone().expect("guaranteed by convert");
+        let rendered_path = path.rendered_path()?.expect("guaranteed by convert");
+        add_thread_to_collections(&mut result, &path, &rendered_path, thread);
+        Ok(result
+            .collections
+            .keys()
+            .filter(|key| result.collections.len(key) > 0)
+            .map(|key| key.to_owned())
+            .collect())
+    };
+
+    // in the example settings, staff is one of our own projects, and eggbug is someone else.
+    let original = chost(2, "eggbug", "hello", "original post");
+
+    // an original post by us is titled by its headline.
+    let thread = convert(chost(1, "staff", "own post", "hi"))?;
+    assert_eq!(thread.meta.title.as_deref(), Some("own post"));
+    assert!(!thread.is_transparent_share());
+    assert_eq!(collections(&thread)?, ["all", "skipped_own"]);
+
+    // a share with commentary is a post by us, even if it has no headline.
+    let mut reblog = chost(3, "staff", "", "nice");
+    reblog["shareOfPostId"] = json!(2);
+    reblog["shareTree"] = json!([original]);
+    let thread = convert(reblog)?;
+    assert_eq!(thread.posts.len(), 2);
+    assert_eq!(thread.meta.title.as_deref(), Some("nice"));
+    assert!(!thread.is_transparent_share());
+    assert_eq!(collections(&thread)?, ["all", "skipped_own"]);
+
+    // a transparent share has the title of the shared post, and is not ours unless we tagged it.
+    let mut share = chost(4, "staff", "", "");
+    share["shareOfPostId"] = json!(2);
+    share["transparentShareOfPostId"] = json!(2);
+    share["shareTree"] = json!([original]);
+    let thread = convert(share.clone())?;
+    assert_eq!(thread.posts.len(), 2);
+    assert_eq!(thread.meta.title.as_deref(), Some("hello"));
+    assert!(thread.is_transparent_share());
+    assert_eq!(collections(&thread)?, ["all", "skipped_other"]);
+    share["tags"] = json!(["birds"]);
+    let thread = convert(share)?;
+    assert_eq!(collections(&thread)?, ["all", "skipped_own"]);
+
+    Ok(())
+}
+
+#[test]
+fn test_custom_collection_contains() -> eyre::Result<()> {
+    let collection = |toml: &str| -> eyre::Result<CustomCollection> {
+        Ok(toml::from_str(&format!(
+            "name = \"custom\"\ntitle = \"custom\"\n{toml}"
+        ))?)
+    };
+    let thread = Thread::try_from(TemplatedPost::filter(
+        r#"<meta name="published" content="2024-06-01T00:00Z"><link rel="author" href="https://example.com"><meta name="tags" content="photography">"#,
+        None,
+    )?)?;
+
+    assert!(custom_collection_contains(&collection("")?, &thread, false));
+    assert!(custom_collection_contains(
+        &collection(r#"tags = ["reading", "photography"]"#)?,
+        &thread,
+        false
+    ));
+    assert!(!custom_collection_contains(
+        &collection(r#"tags = ["reading"]"#)?,
+        &thread,
+        false
+    ));
+    assert!(custom_collection_contains(
+        &collection(r#"authors = ["https://example.com"]"#)?,
+        &thread,
+        false
+    ));
+    assert!(!custom_collection_contains(
+        &collection(r#"authors = ["https://cohost.org/staff"]"#)?,
+        &thread,
+        false
+    ));
+    let in_2024 = collection("since = \"2024-01-01\"\nuntil = \"2024-12-31\"")?;
+    assert!(custom_collection_contains(&in_2024, &thread, false));
+    let in_2023 = collection("since = \"2023-01-01\"\nuntil = \"2023-12-31\"")?;
+    assert!(!custom_collection_contains(&in_2023, &thread, false));
+    let interesting = collection("interesting = true")?;
+    assert!(custom_collection_contains(&interesting, &thread, true));
+    assert!(!custom_collection_contains(&interesting, &thread, false));
+
+    // tags in other normalisation forms are the same tag, like “café” with a combining accent.
+    let decomposed = Thread::try_from(TemplatedPost::filter(
+        "<meta name=\"tags\" content=\"cafe\u{301}\">",
+        None,
+    )?)?;
+    assert_eq!(decomposed.meta.tags, ["caf\u{E9}"]);
+    assert!(custom_collection_contains(
+        &collection("tags = [\"caf\u{E9}\"]")?,
+        &decomposed,
+        false
+    ));
+
+    // threads are filed under each of their contributors too.
+    let collaboration = Thread::try_from(TemplatedPost::filter(
+        r#"<link rel="author" href="https://example.com"><link rel="contributor" href="https://cohost.org/staff">"#,
+        None,
+    )?)?;
+    assert!(custom_collection_contains(
+        &collection(r#"authors = ["https://cohost.org/staff"]"#)?,
+        &collaboration,
+        false
+    ));
+
+    Ok(())
+}
+
+#[test]
+fn test_date_range() -> eyre::Result<()> {
+    let thread = |meta: &str| -> eyre::Result<Thread> {
+        Thread::try_from(TemplatedPost::filter(meta, None)?)
+    };
+    let day = |day| parse_day(day).map_err(|error| eyre::eyre!(error));
+    let range = DateRange {
+        since: Some(day("2024-01-01")?),
+        until: Some(day("2024-12-31")?),
+    };
+    let dated =
+        |published: &str| thread(&format!(r#"<meta name="published" content="{published}">"#));
+
+    assert!(range.contains(&dated("2024-01-01T00:00Z")?));
+    assert!(range.contains(&dated("2024-12-31T23:59+08:00")?));
+    assert!(!range.contains(&dated("2023-12-31T23:59Z")?));
+    assert!(!range.contains(&dated("2025-01-01T00:00Z")?));
+    assert!(!range.contains(&thread("")?));
+    assert!(DateRange::default().contains(&thread("")?));
+    assert!(parse_day("2024-13-01").is_err());
+    assert!(parse_day("yesterday").is_err());
+
+    Ok(())
+}
+
+#[test]
+fn test_series_threads() -> eyre::Result<()> {
+    let paths = [1, 2, 3, 4].map(PostsPath::generated_post_path);
+    let mut threads = vec![];
+    for (path, (published, index)) in paths.iter().zip([
+        ("2024-01-01", Some(2)),
+        ("2024-01-02", None),
+        ("2024-01-03", Some(1)),
+        ("2024-01-04", Some(4)),
+    ]) {
+        let post = TemplatedPost::filter(
+            &format!(r#"<meta name="published" content="{published}T00:00Z">"#),
+            Some(path.clone()),
+        )?;
+        threads.push((
+            index,
+            ThreadInCollection::new(path, &Thread::try_from(post)?),
+        ));
+    }
+    let series = [("tutorial".to_owned(), order_series("tutorial", threads))]
+        .into_iter()
+        .collect();
+    let page = |i: usize| paths[i].rendered_path().map(Option::unwrap);
+
+    // by series_index: 3, 1, (gap), 4, then 2 without an index.
+    let series_threads = series_threads(&series)?;
+    assert_eq!(
+        series_threads[&paths[2]],
+        SeriesThreads {
+            name: "tutorial".to_owned(),
+            position: 1,
+            len: 4,
+            prev: None,
+            next: Some(page(0)?),
+            page: None,
+        }
+    );
+    assert_eq!(series_threads[&paths[3]].position, 3);
+    assert_eq!(series_threads[&paths[3]].prev, Some(page(0)?));
+    assert_eq!(series_threads[&paths[3]].next, Some(page(1)?));
+    assert_eq!(series_threads[&paths[1]].position, 4);
+    assert_eq!(series_threads[&paths[1]].next, None);
+
+    Ok(())
+}
+
+#[test]
+fn test_adjacent_threads() -> eyre::Result<()> {
+    let mut collections = Collections::default()?;
+    let paths = [1, 2, 3].map(PostsPath::generated_post_path);
+    for (path, published) in paths.iter().zip(["2024-01-01", "2024-01-03", "2024-01-02"]) {
+        let post = TemplatedPost::filter(
+            &format!(r#"<meta name="published" content="{published}T00:00Z">"#),
+            Some(path.clone()),
+        )?;
+        collections.push("all", path, &Thread::try_from(post)?);
+    }
+    let adjacent = collections.adjacent_threads("all")?;
+    let page = |i: usize| paths[i].rendered_path().map(Option::unwrap);
+
+    // chronologically: 1, 3, 2.
+    assert_eq!(
+        adjacent[&paths[0]],
+        AdjacentThreads {
+            prev: None,
+            next: Some(page(2)?),
+        }
+    );
+    assert_eq!(
+        adjacent[&paths[2]],
+        AdjacentThreads {
+            prev: Some(page(0)?),
+            next: Some(page(1)?),
+        }
+    );
+    assert_eq!(
+        adjacent[&paths[1]],
+        AdjacentThreads {
+            prev: Some(page(2)?),
+            next: None,
+        }
+    );
+    assert!(collections.adjacent_threads("nope").is_err());
+
+    Ok(())
+}
+
+#[test]
+fn test_claim_output_path() -> eyre::Result<()> {
+    let mut owners = HashMap::default();
+    let md = PostsPath::ROOT.join("1.md")?;
+    let html = PostsPath::ROOT.join("1.html")?;
+    let output_path = md.rendered_path()?.expect("guaranteed by argument");
+    assert_eq!(html.rendered_path()?.as_ref(), Some(&output_path));
+    claim_output_path(&mut owners, &output_path, &md)?;
+    assert!(claim_output_path(&mut owners, &output_path, &html).is_err());
+    claim_output_path(
+        &mut owners,
+        &PostsPath::ROOT
+            .join("2.md")?
+            .rendered_path()?
+            .expect("guaranteed by argument"),
+        &md,
+    )?;
+
+    Ok(())
+}
+
+#[test]
+fn test_recently_updated_threads() -> eyre::Result<()> {
+    for low_memory in [false, true] {
+        let mut collections = Collections::default()?;
+        let mut threads_cache = ThreadStore::new(false, low_memory)?;
+        let paths = [1, 2, 3, 4].map(PostsPath::generated_post_path);
+        let dates = [
+            ("2024-01-01", Some("2024-01-03")),
+            ("2024-01-02", Some("2024-01-02")),
+            ("2024-01-03", None),
+            ("2023-01-01", Some("2024-01-04")),
+        ];
+        for (path, (published, updated)) in paths.iter().zip(dates) {
+            let updated = updated
+                .map(|updated| format!(r#"<meta name="updated" content="{updated}T00:00Z">"#))
+                .unwrap_or_default();
+            let post = TemplatedPost::filter(
+                &format!(r#"<meta name="published" content="{published}T00:00Z">{updated}"#),
+                Some(path.clone()),
+            )?;
+            let thread = Thread::try_from(post)?;
+            collections.push("index", path, &thread);
+            let thread = threads_cache.store(CachedThread {
+                thread,
+                threads_content: String::new(),
+                listing_threads_content: None,
+            })?;
+            threads_cache.insert(path.clone(), thread);
+        }
+        let now = parse_date("2024-01-10T00:00Z").expect("guaranteed by argument");
+        let updated =
+            |days| collections.inner["index"].recently_updated_threads(days, now, &threads_cache);
+
+        assert_eq!(updated(30), [&paths[3], &paths[0]]);
+        assert_eq!(updated(6), [&paths[3]]);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_paginate() -> eyre::Result<()> {
+    let threads = (1..=45)
+        .map(|post_id| -> eyre::Result<_> {
+            let path = PostsPath::generated_post_path(post_id);
+            let thread = Thread::try_from(TemplatedPost::filter("", Some(path.clone()))?)?;
+            Ok(ThreadInCollection::new(&path, &thread))
+        })
+        .collect::<eyre::Result<Vec<_>>>()?;
+    let all = SitePath::ROOT.join("all.html")?;
+    let page = |n: usize| all.listing_page(n);
+
+    // `page_size` defaults to 20.
+    let pages = paginate(&all, threads.iter().collect())?;
+    assert_eq!(
+        pages
+            .iter()
+            .map(|(path, threads, _)| (path.clone(), threads.len()))
+            .collect::<Vec<_>>(),
+        [(page(1)?, 20), (page(2)?, 20), (page(3)?, 5)]
+    );
+    assert_eq!(
+        pages[0].2,
+        Some(Pagination {
+            page: 1,
+            pages: 3,
+            first: None,
+            prev: None,
+            next: Some(page(2)?),
+            last: Some(page(3)?),
+        })
+    );
+    assert_eq!(
+        pages[2].2,
+        Some(Pagination {
+            page: 3,
+            pages: 3,
+            first: Some(page(1)?),
+            prev: Some(page(2)?),
+            next: None,
+            last: None,
+        })
+    );
+
+    // one page has no pagination, and no threads is still one page.
+    let pages = paginate(&all, threads.iter().take(20).collect())?;
+    assert_eq!(pages.len(), 1);
+    assert_eq!(pages[0].2, None);
+    let pages = paginate(&all, vec![])?;
+    assert_eq!(pages.len(), 1);
+    assert_eq!((&pages[0].0, pages[0].1.len()), (&all, 0));
+
+    Ok(())
+}
+
+#[test]
+fn test_write_all_in_one_page() -> eyre::Result<()> {
+    let mut threads_cache = ThreadStore::new(false, false)?;
+    let mut collection = Collection::new("posts", true);
+    let paths = [1, 2].map(PostsPath::generated_post_path);
+    for (i, path) in paths.iter().enumerate() {
+        let html = format!(
+            r#"<meta name="title" content="post {0}"><meta name="published" content="2024-01-0{0}T00:00Z">"#,
+            i + 1
+        );
+        let thread = Thread::try_from(TemplatedPost::filter(&html, Some(path.clone()))?)?;
+        collection
+            .threads
+            .insert(ThreadInCollection::new(path, &thread));
+        let thread = threads_cache.store(CachedThread {
+            threads_content: format!(r#"<article class="thread">{}</article>"#, i + 1),
+            listing_threads_content: None,
+            thread,
+        })?;
+        threads_cache.insert(path.clone(), thread);
+    }
+
+    let path = std::env::temp_dir().join(format!("autost-{}.html", Uuid::new_v4()));
+    threads_cache.write_all_in_one_page(&path, collection.threads_ignoring_pins(), "title")?;
+    let html = std::fs::read_to_string(&path)?;
+    remove_file(&path)?;
+    // newest first, like the collection’s own page.
+    let toc = concat!(
+        r##"<li><a href="#thread-2">post 2</a> <time datetime="2024-01-02T00:00Z">2024-01-02</time>"##,
+        "\n",
+        r##"</li><li><a href="#thread-1">post 1</a> <time datetime="2024-01-01T00:00Z">2024-01-01</time>"##,
+    );
+    assert!(html.contains(toc), "{html}");
+    let sections = concat!(
+        r#"<section id="thread-2">"#,
+        "\n",
+        r#"<article class="thread">2</article>"#,
+        "\n",
+        r#"</section>"#,
+        "\n",
+        r#"<section id="thread-1">"#,
+    );
+    assert!(html.contains(sections), "{html}");
+
+    Ok(())
+}
+
+#[test]
+fn test_pinned_threads() -> eyre::Result<()> {
+    for low_memory in [false, true] {
+        let mut threads_cache = ThreadStore::new(false, low_memory)?;
+        let mut collection = Collection::new("posts", true);
+        let paths = [1, 2, 3].map(PostsPath::generated_post_path);
+        for (i, path) in paths.iter().enumerate() {
+            let html = format!(
+                r#"<meta name="published" content="2024-01-0{}T00:00Z">"#,
+                i + 1
+            );
+            let thread = Thread::try_from(TemplatedPost::filter(&html, Some(path.clone()))?)?;
+            let mut thread_in_collection = ThreadInCollection::new(path, &thread);
+            // pin the oldest thread.
+            thread_in_collection.pinned = (i == 0).then_some(0);
+            collection.threads.insert(thread_in_collection);
+            let thread = threads_cache.store(CachedThread {
+                threads_content: format!(r#"<article class="thread">{}</article>"#, i + 1),
+                listing_threads_content: None,
+                thread,
+            })?;
+            threads_cache.insert(path.clone(), thread);
+        }
+
+        let order = |threads: Vec<&ThreadInCollection>| {
+            threads
+                .into_iter()
+                .map(|thread| thread.path.clone())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            order(collection.threads.iter().collect()),
+            [&paths[0], &paths[2], &paths[1]].map(Clone::clone)
+        );
+        assert_eq!(
+            order(collection.threads_ignoring_pins()),
+            [&paths[2], &paths[1], &paths[0]].map(Clone::clone)
+        );
+
+        let path = std::env::temp_dir().join(format!("autost-{}.html", Uuid::new_v4()));
+        threads_cache.write_listing_page(
+            &path,
+            &collection.threads,
+            ListingPage {
+                title: "title",
+                feed_href: &None,
+                child_tags: &[],
+                tag_page: None,
+                authors: &[],
+                layout: CollectionLayout::List,
+                pagination: None,
+            },
+        )?;
+        let html = std::fs::read_to_string(&path)?;
+        remove_file(&path)?;
+        let expected = concat!(
+            r#"<article class="thread">"#,
+            "\n",
+            r#"<p class="pinned">pinned</p>1</article>"#,
+            r#"<article class="thread">3</article><article class="thread">2</article>"#,
+        );
+        assert!(html.contains(expected), "{html}");
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_spooled_listing_page() -> eyre::Result<()> {
+    let mut threads_cache = ThreadStore::new(false, true)?;
+    let mut threads = BTreeSet::default();
+    for (i, html) in [
+        "<p>first</p>",
+        r#"<p><a href="tagged/x.html">second</a></p>"#,
+    ]
+    .into_iter()
+    .enumerate()
+    {
+        let path = PostsPath::generated_post_path(i + 1);
+        let thread = Thread::try_from(TemplatedPost::filter(html, Some(path.clone()))?)?;
+        threads.insert(ThreadInCollection::new(&path, &thread));
+        let thread = threads_cache.store(CachedThread {
+            threads_content: format!("<article>{html}</article>"),
+            listing_threads_content: None,
+            thread,
+        })?;
+        threads_cache.insert(path, thread);
+    }
+
+    let path = std::env::temp_dir().join(format!("autost-{}.html", Uuid::new_v4()));
+    threads_cache.write_listing_page(
+        &path,
+        &threads,
+        ListingPage {
+            title: "title",
+            feed_href: &None,
+            child_tags: &[],
+            tag_page: None,
+            authors: &[],
+            layout: CollectionLayout::List,
+            pagination: None,
+        },
+    )?;
+    let html = std::fs::read_to_string(&path)?;
+    remove_file(&path)?;
+    let expected = format!(
+        r#"<article><p><a href="{}tagged/x.html">second</a></p></article><article><p>first</p></article>"#,
+        SETTINGS.base_url
+    );
+    assert!(html.contains(&expected), "{html}");
+    assert!(html.trim_end().ends_with("</html>"));
+
+    Ok(())
+}
+
+/// loads a post and its thread, and adds it to the collections it belongs in. the thread’s page is
+/// written later, by [`write_single_post`].
+fn load_single_post(path: PostsPath, strict: bool) -> eyre::Result<CacheableRenderResult> {
+    let mut result = RenderResult::default()?;
+    let start = Instant::now();
+
+    let thread = load_thread(&path, strict, &mut result)?;
+    let Some(rendered_path) = thread.rendered_path()? else {
+        bail!("post has no rendered path");
+    };
+    add_thread_to_collections(&mut result, &path, &rendered_path, &thread);
+    let load = start.elapsed();
+
+    if SETTINGS.print_pages() {
+        // deploy the print page wherever the normal page is deployed.
+        if let Some(print_path) = thread.rendered_print_path()? {
+            if result.interesting_output_paths.contains(&rendered_path) {
+                result.interesting_output_paths.insert(print_path);
+            }
+        }
+    }
+
+    let start = Instant::now();
+    let threads_content =
+        ThreadsContentTemplate::render_normal_without_fixing_relative_urls(&thread)?;
+    let listing_threads_content =
+        ThreadsContentTemplate::render_listing_without_fixing_relative_urls(&thread)?;
+    let template = start.elapsed();
+
+    let result = CacheableRenderResult {
+        render_result: result,
+        cached_thread: CachedThread {
+            thread,
+            threads_content,
+            listing_threads_content,
+        },
+        timings: PostTimings {
+            load,
+            template,
+            write: Duration::ZERO,
+        },
+    };
+
+    Ok(result)
+}
+
+/// loads a post and its thread, localising any cohost urls and caching its attachments.
+fn load_thread(path: &PostsPath, strict: bool, result: &mut RenderResult) -> eyre::Result<Thread> {
+    let post = TemplatedPost::load(path)?;
+    let mut thread = Thread::try_from(post)?;
+    if strict && !thread.broken_references.is_empty() {
+        bail!(
+            "{path:?}: thread references missing posts: {:?}",
+            thread.broken_references
+        );
+    }
+    if SETTINGS.localise_cohost_urls() {
+        for post in thread.posts.iter_mut() {
+            let localised = localise_cohost_urls(&post.safe_html, &attachments_context())?;
+            for url in localised.unlocalised_urls {
+                result.unlocalised_urls.insert((post.path.clone(), url));
+            }
+            if !localised.needs_attachments.is_empty() {
+                // the newly cached attachments may be large enough to scale, or small enough to
+                // inline.
+                let scaled = use_scaled_images(&localised.html)?;
+                post.safe_html = inline_small_images(&add_image_dimensions(&scaled.html)?)?;
+                for needs_attachments in [localised.needs_attachments, scaled.needs_attachments] {
+                    post.needs_attachments
+                        .extend(needs_attachments.iter().cloned());
+                    thread.needs_attachments.extend(needs_attachments);
+                }
+            }
+        }
+    }
+    if !output_in_memory() {
+        hard_link_attachments_into_site(thread.needs_attachments())?;
+    }
+
+    Ok(thread)
+}
+
+/// writes the page (and print page, if enabled) for a thread loaded by [`load_single_post`],
+/// returning the `timings` with the time spent here added.
+///
+/// if `inline_href` is given, the page is only a redirect to there, for `title_only_posts`.
+#[allow(clippy::too_many_arguments)]
+fn write_single_post(
+    path: &PostsPath,
+    cached_thread: &CachedThread,
+    adjacent: &AdjacentThreads,
+    series: Option<&SeriesThreads>,
+    cohost_post_pages: &CohostPostPages,
+    inline_href: Option<&str>,
+    timings: PostTimings,
+) -> eyre::Result<PostTimings> {
+    let CachedThread {
+        thread,
+        threads_content,
+        ..
+    } = cached_thread;
+    let threads_content = &*cohost_post_pages.localise_links(threads_content)?;
+    let Some(rendered_path) = thread.rendered_path()? else {
+        bail!("post has no rendered path");
+    };
+
+    let start = Instant::now();
+    let page_title = SETTINGS.page_title(thread.meta.title.as_deref());
+    let print_path = if SETTINGS.print_pages() {
+        thread.rendered_print_path()?
+    } else {
+        None
+    };
+    let comments = if SETTINGS.cohost_comments() {
+        load_comments(path)?
+    } else {
+        vec![]
+    };
+    let comments = match comments.is_empty() {
+        true => None,
+        false => Some(CommentsTemplate::render(&comments)?),
+    };
+    let source = match SETTINGS.embed_source {
+        Some(EmbedSource::Comment) => Some(EmbeddedSource::comment(&load_source(path)?)),
+        Some(EmbedSource::Script) => Some(EmbeddedSource::script(&load_source(path)?)),
+        Some(EmbedSource::Download) => {
+            let source_path = path
+                .rendered_source_path_with_slug(thread.meta.slug.as_deref())?
+                .ok_or_eyre("post has no rendered source path")?;
+            debug!("writing source: {source_path:?}");
+            write_atomic(&source_path, load_source(path)?)?;
+            Some(EmbeddedSource::Download(source_path))
+        }
+        None => None,
+    };
+    if let Some(og_image_path) = thread.generated_og_image.as_ref() {
+        debug!("writing og image: {og_image_path:?}");
+        if let Some(parent) = og_image_path.parent() {
+            create_dir_all(parent)?;
+        }
+        let title = thread.meta.title.as_deref().unwrap_or_default();
+        write_atomic(og_image_path, render_og_image(title)?)?;
+    }
+    let print_page = match print_path.as_ref() {
+        Some(_) => Some(ThreadPrintPageTemplate::render(
+            thread,
+            threads_content,
+            &page_title,
+            &rendered_path.external_url(),
+        )?),
+        None => None,
+    };
+    let threads_page = match inline_href {
+        Some(href) => {
+            RedirectPageTemplate::render(href, &SETTINGS.external_base_url_absolutise(href))?
+        }
+        None => ThreadsPageTemplate::render_single_thread(
+            thread,
+            threads_content,
+            &page_title,
+            &None,
+            ThreadPageParts {
+                print_href: &print_path,
+                adjacent,
+                series,
+                comments: comments.as_deref(),
+                source: source.as_ref(),
+            },
+        )?,
+    };
+    let template = timings.template + start.elapsed();
+
+    let start = Instant::now();
+    if let (Some(print_path), Some(print_page)) = (print_path, print_page) {
+        debug!("writing print page: {print_path:?}");
+        write_atomic(print_path, format!("{print_page}\n"))?;
+    }
+    debug!("writing post page: {rendered_path:?}");
+    if let Some(parent) = rendered_path.parent() {
+        create_dir_all(parent)?;
+    }
+    write_atomic(rendered_path, format!("{threads_page}\n"))?;
+    let write = start.elapsed();
+
+    let timings = PostTimings {
+        template,
+        write,
+        ..timings
+    };
+    trace!(
+        ?path,
+        load = ?timings.load,
+        template = ?timings.template,
+        write = ?timings.write,
+        "rendered post"
+    );
+
+    Ok(timings)
+}
+
+/// returns the source of the post, for `embed_source`: the markdown of a chost, if
+/// `autost cohost2autost` kept it, or else the post file itself.
+fn load_source(path: &PostsPath) -> eyre::Result<String> {
+    if let Some(source_path) = path.source_path()? {
+        if exists(&source_path)? {
+            return Ok(read_to_string(source_path)?);
+        }
+    }
+
+    Ok(read_to_string(path)?)
+}
+
+/// a hash of everything that goes into the post pages of each thread, as of the last render, so
+/// the next render can skip writing pages that would be the same. kept in `render-cache/pages.json`.
+#[derive(Debug, Default, Deserialize, Serialize)]
+struct PageFingerprints {
+    /// post path → [`page_fingerprint`] of its thread.
+    pages: BTreeMap<PathBuf, String>,
+}
+
+impl PageFingerprints {
+    fn path() -> PathBuf {
+        Path::new(RENDER_CACHE_DIR).join("pages.json")
+    }
+
+    /// loads the fingerprints from the last render. if there are none, or we can’t read them,
+    /// every page is written.
+    fn load() -> eyre::Result<Self> {
+        let path = Self::path();
+        if !exists(&path)? {
+            return Ok(Self::default());
+        }
+        match serde_json::from_reader(File::open(&path)?) {
+            Ok(result) => Ok(result),
+            Err(error) => {
+                warn!(
+                    ?path,
+                    "failed to read page fingerprints, writing every page: {error}"
+                );
+                Ok(Self::default())
+            }
+        }
+    }
+
+    fn save(&self) -> eyre::Result<()> {
+        save_render_cache_json(&Self::path(), self)
+    }
+
+    fn get(&self, path: &PostsPath) -> Option<&str> {
+        self.pages
+            .get(AsRef::<Path>::as_ref(path))
+            .map(|fingerprint| &**fingerprint)
+    }
+
+    fn insert(&mut self, path: &PostsPath, fingerprint: String) {
+        self.pages
+            .insert(AsRef::<Path>::as_ref(path).to_owned(), fingerprint);
+    }
+}
+
+/// writes `value` as json to a file in `render-cache/`, via a temporary file, so a render that was
+/// interrupted never leaves it half written.
+fn save_render_cache_json(path: &Path, value: &impl Serialize) -> eyre::Result<()> {
+    create_dir_all(RENDER_CACHE_DIR)?;
+    // not `write_atomic`, which would list it as an output of the render.
+    let temp_path = Path::new(RENDER_CACHE_DIR).join(format!(".{}.tmp", Uuid::new_v4()));
+    let mut writer = BufWriter::new(File::create(&temp_path)?);
+    serde_json::to_writer(&mut writer, value)?;
+    writer.flush()?;
+    drop(writer);
+    rename(temp_path, path)?;
+
+    Ok(())
+}
+
+/// returns a hash of what every post page depends on: the autost binary, the settings, the output
+/// filenames of static files, and where the pages of chosts are, for `cohost_post_links`.
+fn global_page_fingerprint(cohost_post_pages: &CohostPostPages) -> eyre::Result<String> {
+    let mut hash = Sha256::new();
+    hash.update(env!("CARGO_PKG_VERSION"));
+    // the templates are built into the binary, so a rebuild may change every page.
+    let exe = std::fs::metadata(std::env::current_exe()?)?;
+    hash.update(format!("\0{}\0{:?}", exe.len(), exe.modified()?));
+    hash.update(SETTINGS.source_hash());
+    for (filename, output_filename) in STATIC_FILE_NAMES.read().expect("poisoned").iter() {
+        hash.update(format!("\0{filename}\0{output_filename}"));
+    }
+    for urls in [
+        &cohost_post_pages.internal_urls,
+        &cohost_post_pages.external_urls,
+    ] {
+        for (post_id, url) in urls.iter().collect::<BTreeMap<_, _>>() {
+            hash.update(format!("\0{post_id}\0{url}"));
+        }
+    }
+
+    Ok(hash.finalize().map(|o| format!("{o:02x}")).join(""))
+}
+
+/// returns a hash of everything that goes into the post pages of a thread, which is the same as
+/// last time iff [`write_single_post`] would write the same pages.
+fn page_fingerprint(
+    global_fingerprint: &str,
+    path: &PostsPath,
+    cached_thread: &CachedThread,
+    adjacent: &AdjacentThreads,
+    series: Option<&SeriesThreads>,
+    inline_href: Option<&str>,
+) -> eyre::Result<String> {
+    let CachedThread {
+        thread,
+        threads_content,
+        ..
+    } = cached_thread;
+    let mut hash = Sha256::new();
+    hash.update(global_fingerprint);
+    hash.update(threads_content);
+    hash.update(format!(
+        "\0{thread:?}\0{adjacent:?}\0{series:?}\0{inline_href:?}"
+    ));
+    // the comments and source are read by `write_single_post`, not when the thread is loaded.
+    let extra_inputs = [path.comments_path()?, path.source_path()?];
+    let post_paths = thread.posts.iter().filter_map(|post| post.path.clone());
+    for input_path in extra_inputs.into_iter().flatten().chain(post_paths) {
+        if let Ok(metadata) = std::fs::metadata(&input_path) {
+            hash.update(format!(
+                "\0{input_path:?}\0{}\0{:?}",
+                metadata.len(),
+                metadata.modified()?
+            ));
+        }
+    }
+
+    Ok(hash.finalize().map(|o| format!("{o:02x}")).join(""))
+}
+
+/// returns true iff all of the given files exist in the site.
+fn all_exist(paths: &[SitePath]) -> eyre::Result<bool> {
+    for path in paths {
+        if !exists(path)? {
+            return Ok(false);
+        }
+    }
+
+    Ok(true)
+}
+
+struct CacheableRenderResult {
+    render_result: RenderResult,
+    cached_thread: CachedThread,
+    timings: PostTimings,
+}
+
+/// time spent rendering a post, for finding slow posts.
+#[derive(Clone, Copy, Debug)]
+struct PostTimings {
+    /// loading the post and its thread, and caching its attachments.
+    load: Duration,
+    /// rendering the templates.
+    template: Duration,
+    /// writing the pages.
+    write: Duration,
+}
+
+impl PostTimings {
+    fn total(&self) -> Duration {
+        self.load + self.template + self.write
+    }
+}
+
+struct RenderResult {
+    tags: HashMap<String, usize>,
+    collections: Collections,
+    interesting_output_paths: BTreeSet<SitePath>,
+    threads_by_interesting_tag: HashMap<String, BTreeSet<ThreadInCollection>>,
+    /// series name → (`series_index`, thread) for interesting threads in a `series`, in no order.
+    threads_by_series: HashMap<String, Vec<(Option<usize>, ThreadInCollection)>>,
+    /// `Author::href` → (author, number of interesting threads), like `tags`.
+    interesting_authors: BTreeMap<String, (Author, usize)>,
+    /// (post, url) for cohost urls that `localise_cohost_urls` could not replace.
+    unlocalised_urls: BTreeSet<(Option<PostsPath>, String)>,
+}
+
+/// counts from the render, written to `stats.json` for dashboards and other tools.
+#[derive(Serialize)]
+struct Stats {
+    threads: usize,
+    interesting_threads: usize,
+    collections: BTreeMap<String, usize>,
+    tags: usize,
+    threads_by_tag: BTreeMap<String, usize>,
+    attachments: usize,
+}
+
+/// one thread in `search-index.json`, for client-side search.
+#[derive(Serialize)]
+struct SearchIndexEntry {
+    href: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    title: Option<String>,
+    tags: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    published: Option<String>,
+    text: String,
+}
+
+#[derive(Clone)]
+struct CachedThread {
+    thread: Thread,
+    threads_content: String,
+    /// for listing pages, if any posts in the thread were truncated by `<!-- more -->` or
+    /// `read_more_after_chars`.
+    listing_threads_content: Option<String>,
+}
+
+/// everything on a listing page other than the threads.
+struct ListingPage<'page> {
+    title: &'page str,
+    feed_href: &'page Option<SitePath>,
+    child_tags: &'page [String],
+    tag_page: Option<&'page TagPage>,
+    authors: &'page [(Author, usize)],
+    layout: CollectionLayout,
+    pagination: Option<&'page Pagination>,
+}
+
+/// splits the threads of a listing page into pages of `page_size` threads, returning the path
+/// and threads of each page, and the [`Pagination`] for each page if there is more than one.
+/// there is always at least one page, even with no threads.
+#[allow(clippy::type_complexity)]
+fn paginate<'thread>(
+    path: &SitePath,
+    threads: Vec<&'thread ThreadInCollection>,
+) -> eyre::Result<
+    Vec<(
+        SitePath,
+        Vec<&'thread ThreadInCollection>,
+        Option<Pagination>,
+    )>,
+> {
+    let mut pages = threads
+        .chunks(SETTINGS.page_size())
+        .map(|threads| threads.to_vec())
+        .collect::<Vec<_>>();
+    if pages.is_empty() {
+        pages.push(vec![]);
+    }
+    let len = pages.len();
+    let page_path = |page: usize| path.listing_page(page);
+
+    pages
+        .into_iter()
+        .enumerate()
+        .map(|(i, threads)| {
+            let page = i + 1;
+            let pagination = match len {
+                1 => None,
+                _ => Some(Pagination {
+                    page,
+                    pages: len,
+                    first: (page > 2).then(|| page_path(1)).transpose()?,
+                    prev: (page > 1).then(|| page_path(page - 1)).transpose()?,
+                    next: (page < len).then(|| page_path(page + 1)).transpose()?,
+                    last: (page < len - 1).then(|| page_path(len)).transpose()?,
+                }),
+            };
+            Ok((page_path(page)?, threads, pagination))
+        })
+        .collect()
+}
+
+/// a thread loaded by [`load_single_post`], ready to be added to the [`ThreadStore`].
+struct KeptThread {
+    path: PostsPath,
+    render_result: RenderResult,
+    thread: StoredThread,
+    timings: PostTimings,
+    /// the post id of the chost that the thread is a page for, if any.
+    cohost_post_id: Option<usize>,
+}
+
+/// the pages of the chosts in the archive, by post id, for rewriting links to them
+/// (`cohost_post_links`).
+#[derive(Default)]
+struct CohostPostPages {
+    /// urls for html pages, without the scheme and host.
+    internal_urls: HashMap<usize, String>,
+    /// urls for atom feeds.
+    external_urls: HashMap<usize, String>,
+}
+
+/// the threads being rendered, either kept in memory, or (with `--low-memory`) kept as a summary
+/// and loaded again whenever they are needed.
+struct ThreadStore {
+    threads: HashMap<PostsPath, StoredThread>,
+    /// html of each thread for listing pages, if `--low-memory`.
+    spool: Option<Spool>,
+    strict: bool,
+    cohost_post_pages: CohostPostPages,
+}
+
+enum StoredThread {
+    Cached(CachedThread),
+    Spooled(SpooledThread),
+}
+
+/// what the rest of the render needs to know about a thread, without the thread itself.
+struct SpooledThread {
+    slug: Option<String>,
+    published: Option<String>,
+    updated: Option<String>,
+    author_hrefs: Vec<String>,
+    generated_og_image: Option<SitePath>,
+    is_title_only: bool,
+    needs_attachments: BTreeSet<SitePath>,
+    broken_references: Vec<PostsPath>,
+    /// where its html for listing pages is in the [`Spool`], with relative urls already fixed.
+    listing_html: Range<u64>,
+    inline_hashes: InlineHashes,
+}
+
+/// a temporary file with the html of each thread for listing pages, for `--low-memory`. the file
+/// is deleted when this is dropped.
+struct Spool {
+    path: PathBuf,
+    file: Mutex<File>,
+}
+
+impl ThreadStore {
+    fn new(strict: bool, low_memory: bool) -> eyre::Result<Self> {
+        Ok(Self {
+            threads: HashMap::default(),
+            spool: low_memory.then(Spool::new).transpose()?,
+            strict,
+            cohost_post_pages: CohostPostPages::default(),
+        })
+    }
+
+    /// prepares a thread loaded by [`load_single_post`] to be inserted.
+    fn keep(&self, result: CacheableRenderResult) -> eyre::Result<KeptThread> {
+        let CacheableRenderResult {
+            render_result,
+            cached_thread,
+            timings,
+        } = result;
+        let path = cached_thread
+            .thread
+            .path
+            .clone()
+            .ok_or_eyre("thread has no path")?;
+        let cohost_post_id = cached_thread
+            .thread
+            .main_post()?
+            .meta
+            .archived
+            .as_deref()
+            .and_then(|url| parse_chost_url(url).ok())
+            .map(|(_, post_id)| post_id);
+
+        Ok(KeptThread {
+            path,
+            render_result,
+            thread: self.store(cached_thread)?,
+            timings,
+            cohost_post_id,
+        })
+    }
+
+    /// returns the thread as is, or if `--low-memory`, spools its html and returns a summary.
+    fn store(&self, cached_thread: CachedThread) -> eyre::Result<StoredThread> {
+        let Some(spool) = self.spool.as_ref() else {
+            return Ok(StoredThread::Cached(cached_thread));
+        };
+        let CachedThread {
+            thread,
+            threads_content,
+            listing_threads_content,
+        } = cached_thread;
+        let listing_threads_content = listing_threads_content.unwrap_or(threads_content);
+        let (listing_html, inline_hashes) =
+            fix_relative_urls_in_threads_content(&listing_threads_content)?;
+
+        Ok(StoredThread::Spooled(SpooledThread {
+            slug: thread.meta.slug.clone(),
+            published: thread.meta.published.clone(),
+            updated: thread.meta.updated.clone(),
+            author_hrefs: thread
+                .meta
+                .authors()
+                .map(|author| author.href.clone())
+                .collect(),
+            is_title_only: thread.is_title_only(),
+            generated_og_image: thread.generated_og_image,
+            broken_references: thread.broken_references,
+            needs_attachments: thread.needs_attachments,
+            listing_html: spool.append(&listing_html)?,
+            inline_hashes,
+        }))
+    }
+
+    fn insert(&mut self, path: PostsPath, thread: StoredThread) {
+        debug_assert!(!self.threads.contains_key(&path));
+        self.threads.insert(path, thread);
+    }
+
+    fn len(&self) -> usize {
+        self.threads.len()
+    }
+
+    fn par_paths(&self) -> impl ParallelIterator<Item = &PostsPath> {
+        self.threads.par_iter().map(|(path, _)| path)
+    }
+
+    fn get(&self, path: &PostsPath) -> &StoredThread {
+        &self.threads[path]
+    }
+
+    /// returns the thread, loading it again if it was spooled.
+    fn thread(&self, path: &PostsPath) -> eyre::Result<Cow<'_, Thread>> {
+        Ok(match self.get(path) {
+            StoredThread::Cached(cached) => Cow::Borrowed(&cached.thread),
+            StoredThread::Spooled(_) => Cow::Owned(load_thread(
+                path,
+                self.strict,
+                &mut RenderResult::default()?,
+            )?),
+        })
+    }
+
+    /// writes an atom feed for the threads, one entry at a time, so the whole feed never needs to
+    /// be in memory.
+    fn write_feed<'path>(
+        &self,
+        path: &SitePath,
+        paths: impl IntoIterator<Item = &'path PostsPath>,
+        feed_title: &str,
+        subtitle: Option<&str>,
+    ) -> eyre::Result<()> {
+        let paths = paths.into_iter().collect::<Vec<_>>();
+        let updated = self.feed_updated(&paths);
+        let (before, after) =
+            AtomFeedTemplate::render_around_entries(path, feed_title, subtitle, &updated)?;
+        let mut file = AtomicFile::create(path)?;
+        file.write_all(before.as_bytes())?;
+        for path in paths {
+            let thread = self
+                .cohost_post_pages
+                .localise_links_in_thread(self.thread(path)?)?;
+            let entry = AtomFeedEntryTemplate::render(&thread)?;
+            file.write_all(entry.as_bytes())?;
+        }
+        file.write_all(after.as_bytes())?;
+        writeln!(file)?;
+        file.commit()?;
+
+        Ok(())
+    }
+
+    /// like [`Self::write_feed`], but writes a json feed.
+    fn write_json_feed<'path>(
+        &self,
+        path: &SitePath,
+        paths: impl IntoIterator<Item = &'path PostsPath>,
+        feed_title: &str,
+        subtitle: Option<&str>,
+    ) -> eyre::Result<()> {
+        let (before, after) = JsonFeed::render_around_items(path, feed_title, subtitle)?;
+        let mut file = AtomicFile::create(path)?;
+        file.write_all(before.as_bytes())?;
+        for (i, path) in paths.into_iter().enumerate() {
+            let thread = self
+                .cohost_post_pages
+                .localise_links_in_thread(self.thread(path)?)?;
+            if i > 0 {
+                file.write_all(b",")?;
+            }
+            file.write_all(JsonFeedItem::render(&thread)?.as_bytes())?;
+        }
+        file.write_all(after.as_bytes())?;
+        writeln!(file)?;
+        file.commit()?;
+
+        Ok(())
+    }
+
+    /// returns the `<updated>` of a feed with the given threads, which is when the newest of them
+    /// was updated or published, so rendering the same threads again makes the same feed. feeds
+    /// with no threads were last updated at the unix epoch.
+    fn feed_updated(&self, paths: &[&PostsPath]) -> String {
+        paths
+            .iter()
+            .filter_map(|path| {
+                let stored = self.get(path);
+                parse_date(stored.updated().or(stored.published())?)
+            })
+            .max()
+            .unwrap_or_default()
+            .to_rfc3339_opts(SecondsFormat::Millis, true)
+    }
+
+    /// returns the thread and its html for its own page, loading it again if it was spooled.
+    fn cached_thread(&self, path: &PostsPath) -> eyre::Result<Cow<'_, CachedThread>> {
+        Ok(match self.get(path) {
+            StoredThread::Cached(cached) => Cow::Borrowed(cached),
+            StoredThread::Spooled(_) => {
+                let thread = self.thread(path)?.into_owned();
+                let threads_content =
+                    ThreadsContentTemplate::render_normal_without_fixing_relative_urls(&thread)?;
+                Cow::Owned(CachedThread {
+                    thread,
+                    threads_content,
+                    listing_threads_content: None,
+                })
+            }
+        })
+    }
+
+    /// writes every thread to one page, with a table of contents. unlike listing pages, the whole
+    /// page is built in memory, even with `--low-memory`.
+    fn write_all_in_one_page<'thread>(
+        &self,
+        path: impl AsRef<Path>,
+        threads: impl IntoIterator<Item = &'thread ThreadInCollection>,
+        page_title: &str,
+    ) -> eyre::Result<()> {
+        let sections = threads
+            .into_iter()
+            .map(|thread| {
+                let cached = self.cached_thread(&thread.path)?;
+                let id = thread
+                    .path
+                    .anchor_id_with_slug(thread.slug.as_deref())
+                    .ok_or_eyre("thread is not a post")?;
+                let threads_content = self
+                    .cohost_post_pages
+                    .localise_links(&cached.threads_content)?;
+                Ok(AllInOneSection {
+                    id,
+                    title: cached
+                        .thread
+                        .meta
+                        .title
+                        .clone()
+                        .unwrap_or_else(|| "untitled".to_owned()),
+                    published: cached.thread.meta.published.clone(),
+                    threads_content: threads_content.into_owned(),
+                })
+            })
+            .collect::<eyre::Result<Vec<_>>>()?;
+        let page = AllInOnePageTemplate::render(page_title, &sections)?;
+        write_atomic(path, format!("{page}\n"))
+    }
+
+    fn needs_attachments(&self) -> impl Iterator<Item = &SitePath> {
+        self.threads.values().flat_map(|thread| match thread {
+            StoredThread::Cached(cached) => &cached.thread.needs_attachments,
+            StoredThread::Spooled(spooled) => &spooled.needs_attachments,
+        })
     }
-}
 
-pub fn render_all() -> eyre::Result<()> {
-    let mut post_paths = vec![];
+    /// writes a listing page for the threads, streaming their html from the spool if
+    /// `--low-memory`, so the whole page never needs to be in memory.
+    fn write_listing_page<'thread>(
+        &self,
+        path: impl AsRef<Path>,
+        threads: impl IntoIterator<Item = &'thread ThreadInCollection> + Clone,
+        page: ListingPage,
+    ) -> eyre::Result<()> {
+        let is_empty = threads.clone().into_iter().next().is_none();
+        let Some(spool) = self.spool.as_ref() else {
+            let threads_content = threads
+                .into_iter()
+                .map(|thread| match self.get(&thread.path) {
+                    StoredThread::Cached(cached) => {
+                        let content = cached
+                            .listing_threads_content
+                            .as_deref()
+                            .unwrap_or(&cached.threads_content);
+                        let content = self.cohost_post_pages.localise_links(content)?;
+                        Ok(match thread.pinned {
+                            Some(_) => Cow::from(mark_pinned(&content)),
+                            None => content,
+                        })
+                    }
+                    StoredThread::Spooled(_) => unreachable!("guaranteed by ThreadStore::store"),
+                })
+                .collect::<eyre::Result<Vec<_>>>()?
+                .join("");
+            let threads_content = match is_empty {
+                true => NO_THREADS_HTML,
+                false => &threads_content,
+            };
+            let page = ThreadsPageTemplate::render_collection(
+                threads_content,
+                page.title,
+                page.feed_href,
+                page.child_tags,
+                page.tag_page,
+                page.authors,
+                page.layout,
+                page.pagination,
+            )?;
+            write_atomic(path, format!("{page}\n"))?;
+            return Ok(());
+        };
 
-    create_dir_all(&*PostsPath::ROOT)?;
-    for entry in read_dir(&*PostsPath::ROOT)? {
-        let entry = entry?;
-        let metadata = entry.metadata()?;
-        // cohost2autost creates directories for chost thread ancestors.
-        if metadata.is_dir() {
-            continue;
+        let spooled = |thread: &ThreadInCollection| match self.get(&thread.path) {
+            StoredThread::Spooled(spooled) => spooled,
+            StoredThread::Cached(_) => unreachable!("guaranteed by ThreadStore::store"),
+        };
+        let mut inline_hashes = InlineHashes::default();
+        for thread in threads.clone() {
+            inline_hashes.extend(&spooled(thread).inline_hashes);
+        }
+        let (before, after) = ThreadsPageTemplate::render_collection_around_threads(
+            page.title,
+            page.feed_href,
+            page.child_tags,
+            page.tag_page,
+            page.authors,
+            page.layout,
+            page.pagination,
+            &inline_hashes,
+        )?;
+        let mut file = AtomicFile::create(path)?;
+        file.write_all(before.as_bytes())?;
+        if is_empty {
+            file.write_all(NO_THREADS_HTML.as_bytes())?;
+        }
+        for thread in threads {
+            let range = spooled(thread).listing_html.clone();
+            if thread.pinned.is_some() || self.cohost_post_pages.is_enabled() {
+                let mut content = vec![];
+                spool.copy_to(range, &mut content)?;
+                let content = String::from_utf8(content)?;
+                let content = self.cohost_post_pages.localise_links(&content)?;
+                match thread.pinned {
+                    Some(_) => file.write_all(mark_pinned(&content).as_bytes())?,
+                    None => file.write_all(content.as_bytes())?,
+                }
+            } else {
+                spool.copy_to(range, &mut file)?;
+            }
         }
+        file.write_all(after.as_bytes())?;
+        writeln!(file)?;
+        file.commit()?;
 
-        let path = PostsPath::ROOT.join_dir_entry(&entry)?;
-        post_paths.push(path);
+        Ok(())
     }
-
-    render(post_paths)
 }
 
-pub fn render<'posts>(post_paths: Vec<PostsPath>) -> eyre::Result<()> {
-    run_migrations()?;
+/// sorts the parts of a series by `series_index`, then oldest first, warning about parts without
+/// an index, parts with the same index, and gaps between indices.
+fn order_series(
+    name: &str,
+    mut threads: Vec<(Option<usize>, ThreadInCollection)>,
+) -> Vec<ThreadInCollection> {
+    // parts without an index come last.
+    threads.sort_by(|(p_index, p), (q_index, q)| {
+        (p_index.is_none(), p_index)
+            .cmp(&(q_index.is_none(), q_index))
+            .then_with(|| q.cmp_ignoring_pins(p))
+    });
+    let mut expected = 1;
+    for (index, thread) in threads.iter() {
+        let path = &thread.path;
+        match *index {
+            None => warn!(series = name, ?path, "part of series has no series_index"),
+            Some(index) if index < expected => {
+                warn!(
+                    series = name,
+                    ?path,
+                    index,
+                    "part of series has a duplicate series_index"
+                )
+            }
+            Some(index) => {
+                if index > expected {
+                    warn!(
+                        series = name,
+                        ?path,
+                        "series has no parts {expected} to {}",
+                        index - 1
+                    );
+                }
+                expected = index + 1;
+            }
+        }
+    }
 
-    let now = Utc::now().to_rfc3339_opts(SecondsFormat::Millis, true);
-    create_dir_all(&*SitePath::ROOT)?;
-    create_dir_all(&*SitePath::TAGGED)?;
+    threads.into_iter().map(|(_, thread)| thread).collect()
+}
 
-    fn copy_static(output_path: &SitePath, file: &StaticFile) -> eyre::Result<()> {
-        let StaticFile(filename, content) = file;
-        if let Some(static_path) = SETTINGS.path_to_static() {
-            std::fs::copy(static_path.join(filename), output_path.join(filename)?)?;
-        } else {
-            File::create(output_path.join(filename)?)?.write_all(content)?;
+/// returns the previous and next parts of each thread in each series, in the given order.
+fn series_threads(
+    series: &BTreeMap<String, Vec<ThreadInCollection>>,
+) -> eyre::Result<HashMap<PostsPath, SeriesThreads>> {
+    let rendered_path = |thread: Option<&ThreadInCollection>| match thread {
+        Some(thread) => thread.path.rendered_path_with_slug(thread.slug.as_deref()),
+        None => Ok(None),
+    };
+    let mut result = HashMap::default();
+    for (name, threads) in series.iter() {
+        let page = match SETTINGS.series_pages() {
+            true => SitePath::series_page(name).ok(),
+            false => None,
+        };
+        for (i, thread) in threads.iter().enumerate() {
+            let series_threads = SeriesThreads {
+                name: name.clone(),
+                position: i + 1,
+                len: threads.len(),
+                prev: rendered_path(i.checked_sub(1).and_then(|i| threads.get(i)))?,
+                next: rendered_path(threads.get(i + 1))?,
+                page: page.clone(),
+            };
+            result.insert(thread.path.clone(), series_threads);
         }
-        Ok(())
     }
-    struct StaticFile(&'static str, &'static [u8]);
-    let static_files = [
-        StaticFile("deploy.sh", include_bytes!("../../static/deploy.sh")),
-        StaticFile("style.css", include_bytes!("../../static/style.css")),
-        StaticFile("script.js", include_bytes!("../../static/script.js")),
-        StaticFile(
-            "Atkinson-Hyperlegible-Font-License-2020-1104.pdf",
-            include_bytes!("../../static/Atkinson-Hyperlegible-Font-License-2020-1104.pdf"),
-        ),
-        StaticFile(
-            "Atkinson-Hyperlegible-Regular-102.woff2",
-            include_bytes!("../../static/Atkinson-Hyperlegible-Regular-102.woff2"),
-        ),
-        StaticFile(
-            "Atkinson-Hyperlegible-Italic-102.woff2",
-            include_bytes!("../../static/Atkinson-Hyperlegible-Italic-102.woff2"),
-        ),
-        StaticFile(
-            "Atkinson-Hyperlegible-Bold-102.woff2",
-            include_bytes!("../../static/Atkinson-Hyperlegible-Bold-102.woff2"),
-        ),
-        StaticFile(
-            "Atkinson-Hyperlegible-BoldItalic-102.woff2",
-            include_bytes!("../../static/Atkinson-Hyperlegible-BoldItalic-102.woff2"),
-        ),
-    ];
-    for file in static_files.iter() {
-        copy_static(&*SitePath::ROOT, file)?;
+
+    Ok(result)
+}
+
+impl CohostPostPages {
+    fn insert(&mut self, post_id: usize, page: &SitePath) {
+        self.internal_urls.insert(post_id, page.internal_url());
+        self.external_urls.insert(post_id, page.external_url());
     }
-    #[cfg(unix)]
-    {
-        use std::os::unix::fs::PermissionsExt;
-        let deploy_path = SitePath::ROOT.join("deploy.sh")?;
-        let mut permissions = std::fs::metadata(&deploy_path)?.permissions();
-        let mode = permissions.mode();
-        permissions.set_mode(mode | 0o111);
-        std::fs::set_permissions(deploy_path, permissions)?;
+
+    fn is_enabled(&self) -> bool {
+        SETTINGS.cohost_post_links() != CohostPostLinks::Keep
     }
 
-    let results = post_paths
-        .into_par_iter()
-        .map(render_single_post)
-        .collect::<Vec<_>>();
+    fn note_missing(&self) -> bool {
+        SETTINGS.cohost_post_links() == CohostPostLinks::LocalWithNote
+    }
 
-    let RenderResult {
-        mut tags,
-        mut collections,
-        mut interesting_output_paths,
-        mut threads_by_interesting_tag,
-    } = RenderResult::default()?;
-    let mut threads_cache = HashMap::default();
-    for result in results {
-        let CacheableRenderResult {
-            render_result: result,
-            cached_thread,
-        } = result?;
-        for (tag, count) in result.tags {
-            *tags.entry(tag).or_insert(0) += count;
-        }
-        collections.merge(result.collections);
-        interesting_output_paths.extend(result.interesting_output_paths);
-        for (tag, threads) in result.threads_by_interesting_tag {
-            threads_by_interesting_tag
-                .entry(tag)
-                .or_default()
-                .extend(threads);
+    /// rewrites the links to chosts in html for a page, if `cohost_post_links` is enabled.
+    fn localise_links<'html>(&self, html: &'html str) -> eyre::Result<Cow<'html, str>> {
+        if !self.is_enabled() {
+            return Ok(Cow::Borrowed(html));
         }
-        let path = cached_thread
-            .thread
-            .path
-            .clone()
-            .ok_or_eyre("thread has no path")?;
-        debug_assert!(!threads_cache.contains_key(&path));
-        threads_cache.insert(path, cached_thread);
+
+        Ok(Cow::Owned(localise_cohost_post_links(
+            html,
+            &self.internal_urls,
+            self.note_missing(),
+        )?))
     }
 
-    // author step: generate atom feeds.
-    let atom_feed_path =
-        collections.write_atom_feed("index", &SitePath::ROOT, &now, &threads_cache)?;
-    interesting_output_paths.insert(atom_feed_path);
+    /// rewrites the links to chosts in each post of a thread for an atom feed, if
+    /// `cohost_post_links` is enabled.
+    fn localise_links_in_thread<'thread>(
+        &self,
+        thread: Cow<'thread, Thread>,
+    ) -> eyre::Result<Cow<'thread, Thread>> {
+        if !self.is_enabled() {
+            return Ok(thread);
+        }
+        let mut thread = thread.into_owned();
+        for post in thread.posts.iter_mut() {
+            post.safe_html = localise_cohost_post_links(
+                &post.safe_html,
+                &self.external_urls,
+                self.note_missing(),
+            )?;
+        }
 
-    // generate /tagged/<tag>.feed.xml and /tagged/<tag>.html.
-    for (tag, threads) in threads_by_interesting_tag {
-        let atom_feed_path = SitePath::TAGGED.join(&format!("{tag}.feed.xml"))?;
-        let thread_refs = threads
-            .iter()
-            .map(|thread| &threads_cache[&thread.path].thread)
-            .collect::<Vec<_>>();
-        let atom_feed = AtomFeedTemplate::render(
-            thread_refs,
-            &format!("{} — {tag}", SETTINGS.site_title),
-            &now,
-        )?;
-        writeln!(File::create(&atom_feed_path)?, "{}", atom_feed,)?;
-        interesting_output_paths.insert(atom_feed_path);
-        let threads_content = render_cached_threads_content(&threads_cache, &threads);
-        let threads_page = ThreadsPageTemplate::render(
-            &threads_content,
-            &format!("#{tag} — {}", SETTINGS.site_title),
-            &Some(SitePath::TAGGED.join(&format!("{tag}.feed.xml"))?),
-        )?;
-        // TODO: move this logic into path module and check for slashes
-        let threads_page_path = SitePath::TAGGED.join(&format!("{tag}.html"))?;
-        writeln!(File::create(&threads_page_path)?, "{}", threads_page)?;
-        interesting_output_paths.insert(threads_page_path);
+        Ok(Cow::Owned(thread))
     }
+}
 
-    let mut tags = tags.into_iter().collect::<Vec<_>>();
-    tags.sort_by(|p, q| p.1.cmp(&q.1).reverse().then(p.0.cmp(&q.0)));
-    info!("all tags: {tags:?}");
-    info!(
-        "interesting tags: {:?}",
-        tags.iter()
-            .filter(|(tag, _)| SETTINGS.tag_is_interesting(tag))
-            .collect::<Vec<_>>()
-    );
+/// the content of a listing page with no threads, like the main page of a new site.
+const NO_THREADS_HTML: &str = r#"<p class="no-threads">no posts yet.</p>"#;
 
-    // reader step: generate posts pages.
-    for key in collections.keys() {
-        info!(
-            "writing threads page for collection {key:?} ({} threads)",
-            collections.len(key),
-        );
-        // TODO: write internal collections to another dir?
-        let threads_page_path =
-            collections.write_threads_page(key, &SitePath::ROOT, &threads_cache)?;
-        if collections.is_interesting(key) {
-            interesting_output_paths.insert(threads_page_path);
-        }
+/// adds a label to the start of a pinned thread in the index, after its `<article>` tag.
+fn mark_pinned(threads_content: &str) -> String {
+    match threads_content.split_once(">") {
+        Some((start, rest)) => format!("{start}>\n<p class=\"pinned\">pinned</p>{rest}"),
+        None => threads_content.to_owned(),
     }
+}
 
-    let interesting_output_paths = interesting_output_paths
-        .into_iter()
-        .map(|path| format!("{}\n", path.rsync_deploy_line()))
-        .collect::<Vec<_>>()
-        .join("");
-    if let Some(path) = &SETTINGS.interesting_output_filenames_list_path {
-        File::create(path)?.write_all(interesting_output_paths.as_bytes())?;
+impl StoredThread {
+    fn slug(&self) -> Option<&str> {
+        match self {
+            Self::Cached(cached) => cached.thread.meta.slug.as_deref(),
+            Self::Spooled(spooled) => spooled.slug.as_deref(),
+        }
     }
 
-    Ok(())
-}
+    fn published(&self) -> Option<&str> {
+        match self {
+            Self::Cached(cached) => cached.thread.meta.published.as_deref(),
+            Self::Spooled(spooled) => spooled.published.as_deref(),
+        }
+    }
 
-fn render_single_post(path: PostsPath) -> eyre::Result<CacheableRenderResult> {
-    let mut result = RenderResult::default()?;
+    fn updated(&self) -> Option<&str> {
+        match self {
+            Self::Cached(cached) => cached.thread.meta.updated.as_deref(),
+            Self::Spooled(spooled) => spooled.updated.as_deref(),
+        }
+    }
 
-    let post = TemplatedPost::load(&path)?;
-    let Some(rendered_path) = path.rendered_path()? else {
-        bail!("post has no rendered path");
-    };
-    let thread = Thread::try_from(post)?;
-    hard_link_attachments_into_site(thread.needs_attachments())?;
-    for tag in thread.meta.tags.iter() {
-        *result.tags.entry(tag.clone()).or_insert(0usize) += 1;
-    }
-    result.collections.push("all", &path, &thread);
-    let mut was_interesting = false;
-    if thread.meta.is_main_self_author(&SETTINGS) {
-        was_interesting = true;
-    } else if SETTINGS.thread_is_on_excluded_archived_list(&thread) {
-        result.collections.push("excluded", &path, &thread);
-    } else if SETTINGS.thread_is_on_interesting_archived_list(&thread) {
-        result
-            .collections
-            .push("marked_interesting", &path, &thread);
-        was_interesting = true;
-    } else {
-        for tag in thread.meta.tags.iter() {
-            if SETTINGS.tag_is_interesting(tag) {
-                was_interesting = true;
-                break;
-            }
+    fn generated_og_image(&self) -> Option<&SitePath> {
+        match self {
+            Self::Cached(cached) => cached.thread.generated_og_image.as_ref(),
+            Self::Spooled(spooled) => spooled.generated_og_image.as_ref(),
         }
     }
-    if was_interesting {
-        result
-            .interesting_output_paths
-            .insert(rendered_path.clone());
-        result.collections.push("index", &path, &thread);
-        for tag in thread.meta.tags.iter() {
-            if SETTINGS.tag_is_interesting(tag) {
-                result
-                    .threads_by_interesting_tag
-                    .entry(tag.clone())
-                    .or_default()
-                    .insert(ThreadInCollection {
-                        published: thread.meta.published.clone(),
-                        path: path.clone(),
-                    });
-            }
+
+    fn is_title_only(&self) -> bool {
+        match self {
+            Self::Cached(cached) => cached.thread.is_title_only(),
+            Self::Spooled(spooled) => spooled.is_title_only,
         }
-        if thread.meta.tags.is_empty() {
-            result
-                .collections
-                .push("untagged_interesting", &path, &thread);
+    }
+
+    fn has_author(&self, href: &str) -> bool {
+        match self {
+            Self::Cached(cached) => cached.thread.meta.authors().any(|a| a.href == href),
+            Self::Spooled(spooled) => spooled.author_hrefs.iter().any(|h| h == href),
         }
-    } else {
-        // if the thread had some input from us at publish time, that is, if the last post was
-        // authored by us with content and/or tags...
-        if thread.posts.last().is_some_and(|post| {
-            (!post.meta.is_transparent_share || !post.meta.tags.is_empty())
-                && post
-                    .meta
-                    .author
-                    .as_ref()
-                    .is_some_and(|author| SETTINGS.other_self_authors.contains(&author.href))
-        }) {
-            result.collections.push("skipped_own", &path, &thread);
-        } else {
-            result.collections.push("skipped_other", &path, &thread);
+    }
+
+    fn broken_references(&self) -> &[PostsPath] {
+        match self {
+            Self::Cached(cached) => &cached.thread.broken_references,
+            Self::Spooled(spooled) => &spooled.broken_references,
         }
     }
+}
 
-    let threads_content =
-        ThreadsContentTemplate::render_normal_without_fixing_relative_urls(&thread)?;
+impl Spool {
+    fn new() -> eyre::Result<Self> {
+        let path = std::env::temp_dir().join(format!("autost-{}.spool", Uuid::new_v4()));
+        let file = File::options()
+            .read(true)
+            .write(true)
+            .create_new(true)
+            .open(&path)?;
 
-    debug!("writing post page: {rendered_path:?}");
-    let threads_page = ThreadsPageTemplate::render_single_thread(
-        &thread,
-        &threads_content,
-        &SETTINGS.page_title(thread.meta.title.as_deref()),
-        &None,
-    )?;
-    writeln!(File::create(rendered_path)?, "{}", threads_page)?;
+        Ok(Self {
+            path,
+            file: Mutex::new(file),
+        })
+    }
 
-    let result = CacheableRenderResult {
-        render_result: result,
-        cached_thread: CachedThread {
-            thread,
-            threads_content,
-        },
-    };
+    /// appends the html to the spool, returning where it is.
+    fn append(&self, html: &str) -> eyre::Result<Range<u64>> {
+        let mut file = self.file.lock().unwrap();
+        let start = file.seek(SeekFrom::End(0))?;
+        file.write_all(html.as_bytes())?;
 
-    Ok(result)
-}
+        Ok(start..start + u64::try_from(html.len())?)
+    }
 
-struct CacheableRenderResult {
-    render_result: RenderResult,
-    cached_thread: CachedThread,
-}
+    fn copy_to(&self, range: Range<u64>, writer: &mut impl Write) -> eyre::Result<()> {
+        let mut file = self.file.lock().unwrap();
+        file.seek(SeekFrom::Start(range.start))?;
+        std::io::copy(
+            &mut Read::by_ref(&mut *file).take(range.end - range.start),
+            writer,
+        )?;
 
-struct RenderResult {
-    tags: HashMap<String, usize>,
-    collections: Collections,
-    interesting_output_paths: BTreeSet<SitePath>,
-    threads_by_interesting_tag: HashMap<String, BTreeSet<ThreadInCollection>>,
+        Ok(())
+    }
 }
 
-struct CachedThread {
-    thread: Thread,
-    threads_content: String,
+impl Drop for Spool {
+    fn drop(&mut self) {
+        if let Err(error) = remove_file(&self.path) {
+            warn!(?error, "failed to remove spool file: {:?}", self.path);
+        }
+    }
 }
 
 struct Collections {
-    inner: BTreeMap<&'static str, Collection>,
+    inner: BTreeMap<String, Collection>,
 }
 
 struct Collection {
     feed_href: Option<SitePath>,
+    layout: CollectionLayout,
     title: String,
+    feed_title: String,
+    is_interesting: bool,
     threads: BTreeSet<ThreadInCollection>,
 }
 
 #[derive(Eq, PartialEq)]
 struct ThreadInCollection {
-    published: Option<String>,
+    /// the position of the thread in `pinned_posts`, only in the index collection.
+    pinned: Option<usize>,
+    published: SortDate,
+    post_id: Option<usize>,
     path: PostsPath,
+    slug: Option<String>,
 }
 
 impl RenderResult {
@@ -346,52 +4104,96 @@ impl RenderResult {
             collections: Collections::default()?,
             interesting_output_paths: Default::default(),
             threads_by_interesting_tag: Default::default(),
+            threads_by_series: Default::default(),
+            interesting_authors: Default::default(),
+            unlocalised_urls: Default::default(),
         })
     }
 }
 
 impl Collections {
     fn default() -> eyre::Result<Self> {
-        Ok(Self {
-            inner: [
-                (
-                    "index",
-                    Collection::new(Some(SitePath::ROOT.join("index.feed.xml")?), "posts"),
-                ),
-                ("all", Collection::new(None, "all posts")),
-                (
-                    "untagged_interesting",
-                    Collection::new(None, "untagged interesting posts"),
-                ),
-                (
-                    "excluded",
-                    Collection::new(None, "archived posts that were marked excluded"),
-                ),
-                (
-                    "marked_interesting",
-                    Collection::new(None, "archived posts that were marked interesting"),
-                ),
-                (
-                    "skipped_own",
-                    Collection::new(None, "own skipped archived posts"),
-                ),
-                (
-                    "skipped_other",
-                    Collection::new(None, "others’ skipped archived posts"),
-                ),
-            ]
-            .into(),
-        })
+        let mut inner: BTreeMap<String, Collection> = [
+            ("index", Collection::new("posts", true)),
+            ("all", Collection::new("all posts", false)),
+            (
+                "untagged_interesting",
+                Collection::new("untagged interesting posts", false),
+            ),
+            (
+                "excluded",
+                Collection::new("archived posts that were marked excluded", false),
+            ),
+            (
+                "marked_interesting",
+                Collection::new("archived posts that were marked interesting", false),
+            ),
+            (
+                "skipped_own",
+                Collection::new("own skipped archived posts", false),
+            ),
+            (
+                "skipped_other",
+                Collection::new("others’ skipped archived posts", false),
+            ),
+            (
+                "liked",
+                Collection::new("liked posts", SETTINGS.publish_liked_posts()),
+            ),
+        ]
+        .into_iter()
+        .map(|(key, collection)| (key.to_owned(), collection))
+        .collect();
+
+        // the index feed keeps its historical title, without the collection title.
+        let index = inner.get_mut("index").expect("guaranteed by argument");
+        index.feed_href = Some(SitePath::ROOT.join("index.feed.xml")?);
+        index.feed_title = SETTINGS.page_title(None);
+
+        // the all feed is for archival subscribers who want everything, not just interesting posts.
+        let all = inner.get_mut("all").expect("guaranteed by argument");
+        all.feed_href = Some(SitePath::ROOT.join("all.feed.xml")?);
+        let liked = inner.get_mut("liked").expect("guaranteed by argument");
+        liked.feed_href = Some(SitePath::ROOT.join("liked.feed.xml")?);
+
+        for custom in SETTINGS.collections.iter().flatten() {
+            if inner.contains_key(&custom.name) {
+                bail!("collections: collection already exists: {:?}", custom.name);
+            }
+            let mut collection = Collection::new(&custom.title, custom.is_interesting());
+            if let Some(filename) = custom.feed.as_deref() {
+                collection.feed_href = Some(SitePath::ROOT.join(filename)?);
+            }
+            // fail early if the name can’t be used as a filename.
+            Self::threads_page_path(&custom.name, &SitePath::ROOT)?;
+            inner.insert(custom.name.clone(), collection);
+        }
+        for (key, filename) in SETTINGS.collection_feeds.iter().flatten() {
+            let Some(collection) = inner.get_mut(&**key) else {
+                bail!("collection_feeds: unknown collection {key:?}");
+            };
+            collection.feed_href = Some(SitePath::ROOT.join(filename)?);
+        }
+        for (key, layout) in SETTINGS.collection_layouts.iter().flatten() {
+            let Some(collection) = inner.get_mut(&**key) else {
+                bail!("collection_layouts: unknown collection {key:?}");
+            };
+            collection.layout = *layout;
+        }
+
+        Ok(Self { inner })
     }
 
     fn merge(&mut self, other: Self) {
         assert!(self.inner.keys().eq(other.inner.keys()));
         for (key, collection) in other.inner {
-            assert_eq!(self.inner[key].feed_href, collection.feed_href);
-            assert_eq!(self.inner[key].title, collection.title);
+            assert_eq!(self.inner[&key].feed_href, collection.feed_href);
+            assert_eq!(self.inner[&key].layout, collection.layout);
+            assert_eq!(self.inner[&key].title, collection.title);
+            assert_eq!(self.inner[&key].is_interesting, collection.is_interesting);
             let threads = &mut self
                 .inner
-                .get_mut(key)
+                .get_mut(&key)
                 .expect("guaranteed by assert")
                 .threads;
             for thread in collection.threads {
@@ -401,131 +4203,345 @@ impl Collections {
     }
 
     fn keys(&self) -> impl Iterator<Item = &str> {
-        self.inner.keys().map(|key| *key)
+        self.inner.keys().map(|key| &**key)
     }
 
     fn len(&self, key: &str) -> usize {
         self.inner[key].threads.len()
     }
 
+    fn paths(&self, key: &str) -> impl Iterator<Item = &PostsPath> {
+        self.inner[key].threads.iter().map(|thread| &thread.path)
+    }
+
     fn push(&mut self, key: &str, path: &PostsPath, thread: &Thread) {
+        let mut thread = ThreadInCollection::new(path, thread);
+        if key == "index" {
+            thread.pinned = SETTINGS.pinned_post_position(path);
+        }
         self.inner
             .get_mut(key)
             .expect("BUG: unknown collection!")
             .threads
-            .insert(ThreadInCollection {
-                published: thread.meta.published.clone(),
-                path: path.clone(),
-            });
+            .insert(thread);
     }
 
     fn is_interesting(&self, key: &str) -> bool {
         self.inner[key].is_interesting()
     }
 
+    /// returns the chronologically previous and next threads of each thread in the collection.
+    fn adjacent_threads(&self, key: &str) -> eyre::Result<HashMap<PostsPath, AdjacentThreads>> {
+        let Some(collection) = self.inner.get(key) else {
+            bail!("adjacent_threads_collection: unknown collection {key:?}");
+        };
+        // threads are in reverse chronological order, so the next thread comes first.
+        let threads = collection.threads_ignoring_pins();
+        let mut result = HashMap::default();
+        for (i, thread) in threads.iter().enumerate() {
+            let rendered_path = |thread: Option<&&ThreadInCollection>| match thread {
+                Some(thread) => thread.path.rendered_path_with_slug(thread.slug.as_deref()),
+                None => Ok(None),
+            };
+            let next = rendered_path(i.checked_sub(1).and_then(|i| threads.get(i)))?;
+            let prev = rendered_path(threads.get(i + 1))?;
+            result.insert(thread.path.clone(), AdjacentThreads { prev, next });
+        }
+
+        Ok(result)
+    }
+
+    /// writes `search-index.json` for the threads in the collection, newest first.
+    fn write_search_index(
+        &self,
+        key: &str,
+        threads_cache: &ThreadStore,
+        inline_hrefs: &HashMap<PostsPath, String>,
+    ) -> eyre::Result<SitePath> {
+        let Some(collection) = self.inner.get(key) else {
+            bail!("search_index_collection: unknown collection {key:?}");
+        };
+        let entries = collection
+            .threads_ignoring_pins()
+            .par_iter()
+            .map(|thread| -> eyre::Result<Option<SearchIndexEntry>> {
+                let href = match inline_hrefs.get(&thread.path) {
+                    Some(href) => href.clone(),
+                    None => match thread
+                        .path
+                        .rendered_path_with_slug(thread.slug.as_deref())?
+                    {
+                        Some(rendered_path) => rendered_path.internal_url(),
+                        None => return Ok(None),
+                    },
+                };
+                let cached = threads_cache.cached_thread(&thread.path)?;
+                let meta = &cached.thread.meta;
+                Ok(Some(SearchIndexEntry {
+                    href,
+                    title: meta.title.clone().filter(|title| !title.is_empty()),
+                    tags: meta
+                        .tags
+                        .iter()
+                        .filter(|tag| !SETTINGS.tag_is_hidden(tag))
+                        .cloned()
+                        .collect(),
+                    published: meta
+                        .published
+                        .as_deref()
+                        .map(|date| match parse_date(date) {
+                            Some(date) => date.to_rfc3339_opts(SecondsFormat::Secs, true),
+                            None => date.to_owned(),
+                        }),
+                    text: threads_content_text(&cached.threads_content)?,
+                }))
+            })
+            .collect::<eyre::Result<Vec<_>>>()?;
+        let entries = entries.into_iter().flatten().collect::<Vec<_>>();
+        let path = SitePath::ROOT.join("search-index.json")?;
+        write_atomic(&path, serde_json::to_vec(&entries)?)?;
+
+        Ok(path)
+    }
+
+    /// writes the threads page for the collection, split by `page_size`, returning its pages.
     fn write_threads_page(
         &self,
         key: &str,
         output_dir: &SitePath,
-        threads_cache: &HashMap<PostsPath, CachedThread>,
+        threads_cache: &ThreadStore,
+        authors: &[(Author, usize)],
+    ) -> eyre::Result<Vec<SitePath>> {
+        let path = Self::threads_page_path(key, output_dir)?;
+        let collection = &self.inner[key];
+        // the main page is titled with the site title alone.
+        let title = (key != "index").then_some(collection.title.as_str());
+        collection.write_threads_page(&path, &SETTINGS.page_title(title), threads_cache, authors)
+    }
+
+    /// returns the number of pages that the threads page for the collection is split into.
+    fn page_count(&self, key: &str) -> usize {
+        self.len(key).div_ceil(SETTINGS.page_size()).max(1)
+    }
+
+    /// writes every thread in the collection to `all-in-one.html`, for `--all-in-one`.
+    fn write_all_in_one_page(
+        &self,
+        key: &str,
+        threads_cache: &ThreadStore,
     ) -> eyre::Result<SitePath> {
-        let path = output_dir.join(&format!("{key}.html"))?;
-        self.inner[key].write_threads_page(&path, threads_cache)?;
+        let path = SitePath::ROOT.join("all-in-one.html")?;
+        let collection = &self.inner[key];
+        let title = (key != "index").then_some(collection.title.as_str());
+        threads_cache.write_all_in_one_page(
+            &path,
+            collection.threads_ignoring_pins(),
+            &SETTINGS.page_title(title),
+        )?;
 
         Ok(path)
     }
 
-    fn write_atom_feed(
+    fn threads_page_path(key: &str, output_dir: &SitePath) -> eyre::Result<SitePath> {
+        output_dir.join(&format!("{key}.html"))
+    }
+
+    /// writes `updates.feed.xml`, for threads in the collection that were recently updated.
+    fn write_updates_feed(
         &self,
         key: &str,
-        output_dir: &SitePath,
-        now: &str,
-        threads_cache: &HashMap<PostsPath, CachedThread>,
+        days: u64,
+        threads_cache: &ThreadStore,
     ) -> eyre::Result<SitePath> {
-        let path = output_dir.join(&format!("{key}.feed.xml"))?;
-        self.inner[key].write_atom_feed(&path, now, threads_cache)?;
+        let path = SitePath::ROOT.join("updates.feed.xml")?;
+        let feed_title = SETTINGS.page_title(Some("recently updated"));
+        threads_cache.write_feed(
+            &path,
+            self.inner[key]
+                .recently_updated_threads(days, Utc::now(), threads_cache)
+                .into_iter()
+                .take(SETTINGS.feed_max_entries()),
+            &feed_title,
+            None,
+        )?;
 
         Ok(path)
     }
+
+    /// writes the atom feed for the collection, and the json feed next to it, if it has a
+    /// `feed_href`. returns the paths of the feeds.
+    fn write_feed(&self, key: &str, threads_cache: &ThreadStore) -> eyre::Result<Vec<SitePath>> {
+        let collection = &self.inner[key];
+        let Some(path) = collection.feed_href.clone() else {
+            return Ok(vec![]);
+        };
+        if let Some(parent) = path.parent() {
+            create_dir_all(parent)?;
+        }
+        collection.write_atom_feed(&path, threads_cache)?;
+        let json_feed_path = path.json_feed()?;
+        collection.write_json_feed(&json_feed_path, threads_cache)?;
+
+        Ok(vec![path, json_feed_path])
+    }
 }
 
 impl Collection {
-    fn new(feed_href: Option<SitePath>, title: &str) -> Self {
+    /// returns the threads that were updated in the last `days` days, newest update first,
+    /// excluding threads whose `updated` is the same as their `published`.
+    fn recently_updated_threads(
+        &self,
+        days: u64,
+        now: DateTime<Utc>,
+        threads_cache: &ThreadStore,
+    ) -> Vec<&PostsPath> {
+        let cutoff = now - chrono::Duration::days(days.try_into().unwrap_or(i64::MAX));
+        let mut result = self
+            .threads_ignoring_pins()
+            .into_iter()
+            .filter_map(|thread| {
+                let stored = threads_cache.get(&thread.path);
+                let updated = parse_date(stored.updated()?)?;
+                let published = stored.published().and_then(parse_date);
+                (published != Some(updated) && updated >= cutoff).then_some((updated, &thread.path))
+            })
+            .collect::<Vec<_>>();
+        // stable sort, so threads updated at the same time stay in collection order.
+        result.sort_by(|(p, _), (q, _)| q.cmp(p));
+
+        result.into_iter().map(|(_, path)| path).collect()
+    }
+
+    fn new(title: &str, is_interesting: bool) -> Self {
         Self {
-            feed_href,
+            feed_href: None,
+            layout: CollectionLayout::default(),
             title: title.to_owned(),
+            feed_title: SETTINGS.page_title(Some(title)),
+            is_interesting,
             threads: BTreeSet::default(),
         }
     }
 
     fn is_interesting(&self) -> bool {
-        // this definition may change in the future.
-        self.feed_href.is_some()
+        self.is_interesting
+    }
+
+    /// returns the threads in reverse chronological order, without moving pinned threads to the
+    /// start, for feeds and adjacent threads.
+    fn threads_ignoring_pins(&self) -> Vec<&ThreadInCollection> {
+        let mut result = self.threads.iter().collect::<Vec<_>>();
+        result.sort_by(|p, q| p.cmp_ignoring_pins(q));
+        result
     }
 
     fn write_threads_page(
         &self,
         posts_page_path: &SitePath,
-        threads_cache: &HashMap<PostsPath, CachedThread>,
-    ) -> eyre::Result<()> {
-        let threads_content = render_cached_threads_content(threads_cache, &self.threads);
-        writeln!(
-            File::create(posts_page_path)?,
-            "{}",
-            ThreadsPageTemplate::render(
-                &threads_content,
-                &format!("{} — {}", self.title, SETTINGS.site_title),
-                &self.feed_href,
-            )?
-        )?;
+        page_title: &str,
+        threads_cache: &ThreadStore,
+        authors: &[(Author, usize)],
+    ) -> eyre::Result<Vec<SitePath>> {
+        // threads are deduplicated by the BTreeSet, but only if they have the same `published`, so
+        // make sure we never push the same thread with different sort keys.
+        debug_assert_eq!(
+            self.threads
+                .iter()
+                .map(|thread| &thread.path)
+                .collect::<BTreeSet<_>>()
+                .len(),
+            self.threads.len(),
+            "BUG: duplicate threads in collection {posts_page_path:?}"
+        );
+        let mut result = vec![];
+        for (path, threads, pagination) in paginate(posts_page_path, self.threads.iter().collect())?
+        {
+            threads_cache.write_listing_page(
+                &path,
+                threads.iter().copied(),
+                ListingPage {
+                    title: page_title,
+                    feed_href: &self.feed_href,
+                    child_tags: &[],
+                    tag_page: None,
+                    authors,
+                    layout: self.layout,
+                    pagination: pagination.as_ref(),
+                },
+            )?;
+            result.push(path);
+        }
 
-        Ok(())
+        Ok(result)
     }
 
     fn write_atom_feed(
         &self,
         atom_feed_path: &SitePath,
-        now: &str,
-        threads_cache: &HashMap<PostsPath, CachedThread>,
+        threads_cache: &ThreadStore,
     ) -> eyre::Result<()> {
-        let thread_refs = self
-            .threads
-            .iter()
-            .map(|thread| &threads_cache[&thread.path].thread)
-            .collect::<Vec<_>>();
-        writeln!(
-            File::create(atom_feed_path)?,
-            "{}",
-            AtomFeedTemplate::render(thread_refs, &SETTINGS.site_title, now)?
-        )?;
+        threads_cache.write_feed(
+            atom_feed_path,
+            self.threads_ignoring_pins()
+                .into_iter()
+                .take(SETTINGS.feed_max_entries())
+                .map(|thread| &thread.path),
+            &self.feed_title,
+            None,
+        )
+    }
 
-        Ok(())
+    fn write_json_feed(
+        &self,
+        json_feed_path: &SitePath,
+        threads_cache: &ThreadStore,
+    ) -> eyre::Result<()> {
+        threads_cache.write_json_feed(
+            json_feed_path,
+            self.threads_ignoring_pins()
+                .into_iter()
+                .take(SETTINGS.feed_max_entries())
+                .map(|thread| &thread.path),
+            &self.feed_title,
+            None,
+        )
     }
 }
 
-impl Ord for ThreadInCollection {
-    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
-        // reverse chronological
+impl ThreadInCollection {
+    fn new(path: &PostsPath, thread: &Thread) -> Self {
+        Self {
+            pinned: None,
+            published: SortDate::new(thread.meta.published.as_deref()),
+            post_id: path.post_id(),
+            path: path.clone(),
+            slug: thread.meta.slug.clone(),
+        }
+    }
+
+    fn cmp_ignoring_pins(&self, other: &Self) -> std::cmp::Ordering {
+        // reverse chronological, with the same tiebreakers as Thread::reverse_chronological
         self.published
             .cmp(&other.published)
             .reverse()
+            .then_with(|| self.post_id.cmp(&other.post_id).reverse())
             .then_with(|| self.path.cmp(&other.path))
     }
 }
+
+impl Ord for ThreadInCollection {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        // pinned threads first, in the order of `pinned_posts`, then reverse chronological.
+        // the slug is compared last so that this agrees with the derived PartialEq.
+        let pinned = |thread: &Self| (thread.pinned.is_none(), thread.pinned);
+        pinned(self)
+            .cmp(&pinned(other))
+            .then_with(|| self.cmp_ignoring_pins(other))
+            .then_with(|| self.slug.cmp(&other.slug))
+    }
+}
 impl PartialOrd for ThreadInCollection {
     fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
         Some(self.cmp(other))
     }
 }
-
-fn render_cached_threads_content(
-    cache: &HashMap<PostsPath, CachedThread>,
-    threads: &BTreeSet<ThreadInCollection>,
-) -> String {
-    let threads_contents = threads
-        .iter()
-        .map(|thread| &*cache[&thread.path].threads_content)
-        .collect::<Vec<_>>();
-
-    threads_contents.join("")
-}
@@ -27,7 +27,7 @@ use crate::{
     render_markdown, PostMeta, TemplatedPost, Thread,
 };
 
-use crate::command::render::render_all;
+use crate::command::render::{render_all, static_file_name, RenderOptions};
 
 #[derive(clap::Args, Debug)]
 pub struct Server {
@@ -38,7 +38,7 @@ pub struct Server {
 static HTML: &'static str = "text/html; charset=utf-8";
 
 pub async fn main(args: Server) -> eyre::Result<()> {
-    render_all()?;
+    render_all(&RenderOptions::default())?;
 
     let compose_route = warp::path!("compose")
         .and(warp::filters::method::get())
@@ -60,11 +60,26 @@ pub async fn main(args: Server) -> eyre::Result<()> {
             let meta = PostMeta {
                 archived: None,
                 references,
+                remote_references: vec![],
                 title: Some("headline".to_owned()),
                 published: Some(now),
+                updated: None,
                 author: SETTINGS.self_author.clone(),
+                contributors: vec![],
                 tags: vec![],
                 is_transparent_share: false,
+                content_warning: None,
+                is_redacted: false,
+                slug: None,
+                lang: None,
+                series: None,
+                series_index: None,
+                is_unlisted: false,
+                is_liked: false,
+                extra: Default::default(),
+                engagement: Default::default(),
+                edits: vec![],
+                fediverse_url: None,
             };
             let meta = meta
                 .render()
@@ -131,11 +146,11 @@ pub async fn main(args: Server) -> eyre::Result<()> {
                 file.write_all(unsafe_source.as_bytes())
                     .wrap_err("failed to write post file")
                     .map_err(InternalError)?;
-                render_all().map_err(InternalError)?;
+                render_all(&RenderOptions::default()).map_err(InternalError)?;
 
                 let post = TemplatedPost::load(&path).map_err(InternalError)?;
-                let _thread = Thread::try_from(post).map_err(InternalError)?;
-                let url = path.rendered_path()
+                let thread = Thread::try_from(post).map_err(InternalError)?;
+                let url = thread.rendered_path()
                     .map_err(InternalError)?
                     .ok_or_eyre("path has no rendered path")
                     .map_err(InternalError)?
@@ -301,6 +316,14 @@ struct ComposeTemplate {
     source: String,
 }
 
+impl ComposeTemplate {
+    /// the compose page is not rendered by `autost render`, so it refers to static files by their
+    /// output filenames itself.
+    fn static_file(&self, filename: &str) -> String {
+        static_file_name(filename).unwrap_or_else(|| filename.to_owned())
+    }
+}
+
 async fn recover(error: Rejection) -> Result<impl Reply, std::convert::Infallible> {
     Ok(if let Some(error) = error.find::<BadRequest>() {
         error!(
@@ -0,0 +1,282 @@
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap},
+    fs::{metadata, read_dir},
+};
+
+use jane_eyre::eyre::{self, Context};
+use rayon::iter::{IntoParallelIterator, IntoParallelRefIterator, ParallelIterator};
+use scraper::Html;
+use serde::Serialize;
+
+use crate::{
+    date::display_day,
+    path::{AttachmentsPath, PostsPath},
+    TemplatedPost, SETTINGS,
+};
+
+#[derive(clap::Args, Debug)]
+pub struct Stats {
+    #[arg(
+        long,
+        value_enum,
+        default_value_t = StatsFormat::Text,
+        help = "print the report as text, or as json for other tools"
+    )]
+    format: StatsFormat,
+
+    #[arg(long, default_value_t = 10, help = "how many tags and authors to list")]
+    top: usize,
+}
+
+#[derive(Clone, Copy, Debug, clap::ValueEnum)]
+enum StatsFormat {
+    Text,
+    Json,
+}
+
+/// a breakdown of the posts directly in `posts/`, like `posts/1.html`, which are the last post in
+/// each thread. the posts they share only count towards shared authors and attachments.
+#[derive(Debug, Default, PartialEq, Serialize)]
+struct ArchiveStats {
+    posts: usize,
+    /// month (like `2024-10`, in the `display_timezone` if any) → number of posts.
+    posts_per_month: BTreeMap<String, usize>,
+    posts_without_date: usize,
+    /// the most used tags, after renaming and implying tags (`[tag_definitions]`), most used first.
+    top_tags: Vec<(String, usize)>,
+    /// the authors of the most shared posts, by `display_handle`, most shared first.
+    top_shared_authors: Vec<(String, usize)>,
+    /// mean length of the text of each post, in characters, ignoring html.
+    average_post_chars: usize,
+    /// attachments needed by the posts and the posts they share, and their total size.
+    attachments: usize,
+    attachments_bytes: u64,
+}
+
+pub fn main(args: Stats) -> eyre::Result<()> {
+    let mut paths = vec![];
+    for entry in read_dir(&*PostsPath::ROOT)? {
+        let entry = entry?;
+        if entry.metadata()?.is_dir() {
+            continue;
+        }
+        let path = PostsPath::ROOT.join_dir_entry(&entry)?;
+        if path.is_post() {
+            paths.push(path);
+        }
+    }
+    let load = |path: &PostsPath| TemplatedPost::load(path).wrap_err_with(|| format!("{path:?}"));
+    let posts = paths
+        .par_iter()
+        .map(load)
+        .collect::<eyre::Result<Vec<_>>>()?;
+
+    // load each shared post once, even if it is shared by many posts.
+    let shared_paths = posts
+        .iter()
+        .flat_map(|post| &post.meta.references)
+        .collect::<BTreeSet<_>>();
+    let shared_posts = shared_paths
+        .into_par_iter()
+        .map(|path| Ok((path.clone(), load(path)?)))
+        .collect::<eyre::Result<HashMap<_, _>>>()?;
+
+    let stats = ArchiveStats::new(&posts, &shared_posts, args.top)?;
+    match args.format {
+        StatsFormat::Text => print!("{}", stats.text()),
+        StatsFormat::Json => println!("{}", serde_json::to_string_pretty(&stats)?),
+    }
+
+    Ok(())
+}
+
+impl ArchiveStats {
+    fn new(
+        posts: &[TemplatedPost],
+        shared_posts: &HashMap<PostsPath, TemplatedPost>,
+        top: usize,
+    ) -> eyre::Result<Self> {
+        let mut result = Self {
+            posts: posts.len(),
+            ..Default::default()
+        };
+        let mut tags = BTreeMap::<String, usize>::default();
+        let mut shared_authors = BTreeMap::<&str, usize>::default();
+        let mut attachments = BTreeSet::<AttachmentsPath>::default();
+        let mut total_chars = 0;
+
+        for post in posts {
+            match post.meta.published.as_deref().and_then(display_day) {
+                Some(day) => {
+                    let month = day.get(..7).unwrap_or(&day).to_owned();
+                    *result.posts_per_month.entry(month).or_default() += 1;
+                }
+                None => result.posts_without_date += 1,
+            }
+
+            let post_tags = SETTINGS.extra_archived_thread_tags(post).to_vec();
+            let post_tags = post_tags
+                .into_iter()
+                .chain(post.meta.tags.clone())
+                .collect();
+            for tag in SETTINGS.resolve_tags(post_tags) {
+                *tags.entry(tag).or_default() += 1;
+            }
+
+            total_chars += Html::parse_fragment(&post.safe_html)
+                .root_element()
+                .text()
+                .map(|text| text.chars().count())
+                .sum::<usize>();
+
+            let shared = post
+                .meta
+                .references
+                .iter()
+                .filter_map(|path| shared_posts.get(path));
+            for shared_post in shared.clone() {
+                if let Some(author) = shared_post.meta.author.as_ref() {
+                    *shared_authors.entry(&author.display_handle).or_default() += 1;
+                }
+            }
+            for site_path in [post]
+                .into_iter()
+                .chain(shared)
+                .flat_map(|post| &post.needs_attachments)
+            {
+                if let Some(attachments_path) = site_path.attachments_path()? {
+                    attachments.insert(attachments_path);
+                }
+            }
+        }
+
+        result.top_tags = top_counts(tags, top);
+        result.top_shared_authors = top_counts(
+            shared_authors
+                .into_iter()
+                .map(|(author, count)| (author.to_owned(), count)),
+            top,
+        );
+        result.average_post_chars = total_chars.checked_div(posts.len()).unwrap_or(0);
+        result.attachments = attachments.len();
+        // attachments that have not been downloaded yet count as zero bytes.
+        result.attachments_bytes = attachments
+            .iter()
+            .filter_map(|path| metadata(path).ok())
+            .map(|metadata| metadata.len())
+            .sum();
+
+        Ok(result)
+    }
+
+    fn text(&self) -> String {
+        let mut result = format!("posts: {}", self.posts);
+        if self.posts_without_date > 0 {
+            result += &format!(" ({} without a date)", self.posts_without_date);
+        }
+        result += &format!("\naverage post length: {} chars\n", self.average_post_chars);
+        result += &format!(
+            "attachments: {} ({})\n",
+            self.attachments,
+            human_bytes(self.attachments_bytes)
+        );
+
+        if !self.posts_per_month.is_empty() {
+            result += "\nposts per month:\n";
+            let max = self.posts_per_month.values().copied().max().unwrap_or(0);
+            for (month, &count) in self.posts_per_month.iter() {
+                // at most 40 columns, but at least one for any month with posts.
+                let bar = "#".repeat((count * 40).div_ceil(max.max(1)));
+                result += &format!("  {month} {count:>6} {bar}\n");
+            }
+        }
+        for (heading, counts) in [
+            ("most used tags", &self.top_tags),
+            ("most shared authors", &self.top_shared_authors),
+        ] {
+            if !counts.is_empty() {
+                result += &format!("\n{heading}:\n");
+                for (name, count) in counts {
+                    result += &format!("  {count:>6} {name}\n");
+                }
+            }
+        }
+
+        result
+    }
+}
+
+/// returns the `top` names with the highest counts, highest first, then by name.
+fn top_counts(
+    counts: impl IntoIterator<Item = (String, usize)>,
+    top: usize,
+) -> Vec<(String, usize)> {
+    let mut result = counts.into_iter().collect::<Vec<_>>();
+    result.sort_by(|(a_name, a), (b_name, b)| b.cmp(a).then(a_name.cmp(b_name)));
+    result.truncate(top);
+    result
+}
+
+fn human_bytes(bytes: u64) -> String {
+    let mut value = bytes as f64;
+    for unit in ["bytes", "KiB", "MiB", "GiB"] {
+        if value < 1024.0 || unit == "GiB" {
+            return if unit == "bytes" {
+                format!("{bytes} bytes")
+            } else {
+                format!("{value:.1} {unit}")
+            };
+        }
+        value /= 1024.0;
+    }
+    unreachable!()
+}
+
+#[test]
+fn test_archive_stats() -> eyre::Result<()> {
+    let post = |filename: &str, html: &str| {
+        TemplatedPost::filter(html, Some(PostsPath::ROOT.join(filename)?))
+    };
+    let shared = post(
+        "1/2.html",
+        r#"<link rel="author" href="https://cohost.org/staff" name="staff"><meta name="author_display_handle" content="@staff"><p>shared</p>"#,
+    )?;
+    let posts = [
+        post(
+            "1.html",
+            r#"<meta name="published" content="2024-10-01T00:00:00Z"><meta name="tags" content="b"><meta name="tags" content="a"><link rel="references" href="1/2.html"><p>hello</p>"#,
+        )?,
+        post(
+            "3.html",
+            r#"<meta name="published" content="2024-10-20T00:00:00Z"><meta name="tags" content="b"><p>hi <b>there</b></p>"#,
+        )?,
+        post("4.html", "<p>no date</p>")?,
+    ];
+    let shared_posts = [(PostsPath::ROOT.join("1/2.html")?, shared)]
+        .into_iter()
+        .collect();
+
+    let stats = ArchiveStats::new(&posts, &shared_posts, 10)?;
+    assert_eq!(
+        stats,
+        ArchiveStats {
+            posts: 3,
+            posts_per_month: [("2024-10".to_owned(), 2)].into_iter().collect(),
+            posts_without_date: 1,
+            top_tags: vec![("b".to_owned(), 2), ("a".to_owned(), 1)],
+            top_shared_authors: vec![("@staff".to_owned(), 1)],
+            // “hello”, “hi there”, “no date”
+            average_post_chars: 20 / 3,
+            attachments: 0,
+            attachments_bytes: 0,
+        }
+    );
+    assert!(stats
+        .text()
+        .contains("  2024-10      2 ########################################\n"));
+
+    assert_eq!(human_bytes(512), "512 bytes");
+    assert_eq!(human_bytes(3 * 1024 * 1024 / 2), "1.5 MiB");
+
+    Ok(())
+}
@@ -0,0 +1,191 @@
+use std::{
+    collections::BTreeSet,
+    fs::{exists, read_dir},
+    path::Path,
+};
+
+use jane_eyre::eyre::{self, bail};
+use url::Url;
+
+use crate::{
+    command::{doctor::Report, render::STATIC_FILES},
+    path::PostsPath,
+    settings::Settings,
+    TemplatedPost,
+};
+
+#[derive(clap::Args, Debug)]
+pub struct ValidateConfig {}
+
+pub fn main(_args: ValidateConfig) -> eyre::Result<()> {
+    let mut report = Report::default();
+
+    // not `SETTINGS`, so we can report errors instead of panicking.
+    match Settings::load_default() {
+        Ok(settings) => {
+            check_settings(&mut report, &settings);
+            check_paths(&mut report, &settings)?;
+            check_interesting_tags(&mut report, &settings, &tags_used_by_posts(&settings)?);
+        }
+        Err(error) => report.error("settings", format!("failed to load autost.toml: {error:#}")),
+    }
+
+    report.print();
+    let error_count = report.error_count();
+    if error_count > 0 {
+        bail!("found {error_count} errors");
+    }
+
+    Ok(())
+}
+
+/// checks that the url settings parse, and are consistent with each other.
+pub(crate) fn check_settings(report: &mut Report, settings: &Settings) {
+    report.ok("settings");
+    match Url::parse(&settings.external_base_url) {
+        Ok(url) if !matches!(url.scheme(), "http" | "https") => report.error(
+            "settings",
+            format!("external_base_url is not http or https: {url}"),
+        ),
+        Ok(url) if url.path() != settings.base_url => report.warning(
+            "settings",
+            format!(
+                "external_base_url path ({}) is different from base_url ({})",
+                url.path(),
+                settings.base_url
+            ),
+        ),
+        Ok(_) => {}
+        Err(error) => report.error(
+            "settings",
+            format!("external_base_url is not an absolute url: {error}"),
+        ),
+    }
+    if let Some(attachments_base_url) = settings.attachments_base_url() {
+        if let Err(error) = Url::parse(attachments_base_url) {
+            report.error(
+                "settings",
+                format!("attachments_base_url is not an absolute url: {error}"),
+            );
+        }
+    }
+    let author_hrefs = settings
+        .self_author
+        .iter()
+        .map(|author| ("[self_author] href", &author.href))
+        .chain(
+            settings
+                .other_self_authors
+                .iter()
+                .map(|href| ("other_self_authors", href)),
+        );
+    for (setting, href) in author_hrefs {
+        if let Err(error) = Url::parse(href) {
+            report.warning(
+                "settings",
+                format!("{setting} is not an absolute url ({error}): {href:?}"),
+            );
+        }
+    }
+    if settings.self_author.is_none() {
+        report.warning(
+            "settings",
+            "no [self_author], so new posts will have no author".to_owned(),
+        );
+    }
+}
+
+/// checks that the files and directories in the settings exist.
+fn check_paths(report: &mut Report, settings: &Settings) -> eyre::Result<()> {
+    report.ok("paths");
+    if let Some(path) = settings.interesting_output_filenames_list_path.as_deref() {
+        let parent = Path::new(path)
+            .parent()
+            .filter(|parent| parent != &Path::new(""));
+        if let Some(parent) = parent.filter(|parent| !parent.is_dir()) {
+            report.warning(
+                "paths",
+                format!("interesting_output_filenames_list_path is in a directory that does not exist: {parent:?}"),
+            );
+        }
+    }
+    if let Some(static_path) = settings.path_to_static() {
+        if !static_path.is_dir() {
+            report.error(
+                "paths",
+                format!("path_to_static is not a directory: {static_path:?}"),
+            );
+        } else {
+            for file in STATIC_FILES.iter() {
+                let path = static_path.join(file.0);
+                if !exists(&path)? {
+                    report.warning(
+                        "paths",
+                        format!("path_to_static is missing a static file: {path:?}"),
+                    );
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// warns about interesting tags that are not used by any post, in `tags_seen`.
+pub(crate) fn check_interesting_tags(
+    report: &mut Report,
+    settings: &Settings,
+    tags_seen: &BTreeSet<String>,
+) {
+    report.ok("tags");
+    for tag in settings.interesting_tags_iter() {
+        if !tags_seen.contains(tag) {
+            report.warning(
+                "tags",
+                format!("interesting tag is not used by any post: {tag:?}"),
+            );
+        }
+    }
+}
+
+/// returns the tags used by posts, after `archived_thread_tags`, `renamed_tags`, and
+/// `implied_tags`. posts that fail to load are skipped, since that’s not a settings problem.
+fn tags_used_by_posts(settings: &Settings) -> eyre::Result<BTreeSet<String>> {
+    let mut result = BTreeSet::default();
+    let Ok(entries) = read_dir(&*PostsPath::ROOT) else {
+        return Ok(result);
+    };
+    for entry in entries {
+        let entry = entry?;
+        if entry.metadata()?.is_dir() {
+            continue;
+        }
+        let path = PostsPath::ROOT.join_dir_entry(&entry)?;
+        if !path.is_post() {
+            continue;
+        }
+        let Ok(post) = TemplatedPost::load(&path) else {
+            continue;
+        };
+        let tags = settings.extra_archived_thread_tags(&post).to_vec();
+        let tags = tags.into_iter().chain(post.meta.tags).collect();
+        result.extend(settings.resolve_tags(tags));
+    }
+
+    Ok(result)
+}
+
+#[test]
+fn test_check_settings() -> eyre::Result<()> {
+    let mut settings = Settings::load_example()?;
+    let mut report = Report::default();
+    check_settings(&mut report, &settings);
+    assert_eq!(report.error_count(), 0);
+
+    settings.external_base_url = "example.com/".to_owned();
+    let mut report = Report::default();
+    check_settings(&mut report, &settings);
+    assert_eq!(report.error_count(), 1);
+
+    Ok(())
+}
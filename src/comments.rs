@@ -0,0 +1,126 @@
+//! comments archived from cohost, shown beneath post pages if `cohost_comments` is enabled.
+//!
+//! comments are kept next to the post they are on, like `posts/10000000.comments.json`, rather
+//! than in the post itself, so they can be left out of the site without editing any posts.
+
+use std::{
+    fs::{exists, File},
+    io::Write,
+};
+
+use jane_eyre::eyre;
+use serde::{Deserialize, Serialize};
+
+use crate::{cohost::CommentWrapper, path::PostsPath, render_markdown, Author};
+
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct ArchivedComment {
+    /// None if the commenter’s page was deleted, or if either of you blocked the other.
+    pub author: Option<Author>,
+    pub published: String,
+    /// unsanitised html, or None if the comment was deleted or hidden.
+    pub body: Option<String>,
+    /// replies to the comment, oldest first.
+    #[serde(default)]
+    pub replies: Vec<ArchivedComment>,
+}
+
+impl ArchivedComment {
+    /// converts cohost comments, skipping deleted or hidden comments unless they have replies.
+    pub fn from_cohost(comments: &[CommentWrapper]) -> Vec<Self> {
+        comments
+            .iter()
+            .map(|wrapper| {
+                let comment = &wrapper.comment;
+                let body =
+                    (!comment.deleted && !comment.hidden).then(|| render_markdown(&comment.body));
+                Self {
+                    author: wrapper.poster.as_ref().map(Author::from),
+                    published: comment.postedAtISO.clone(),
+                    body,
+                    replies: Self::from_cohost(&comment.children),
+                }
+            })
+            .filter(|comment| comment.body.is_some() || !comment.replies.is_empty())
+            .collect()
+    }
+}
+
+/// loads the archived comments on the post, if any.
+pub fn load_comments(post: &PostsPath) -> eyre::Result<Vec<ArchivedComment>> {
+    let Some(path) = post.comments_path()? else {
+        return Ok(vec![]);
+    };
+    if !exists(&path)? {
+        return Ok(vec![]);
+    }
+
+    Ok(serde_json::from_reader(File::open(&path)?)?)
+}
+
+/// writes the archived comments on the post, if there are any.
+pub fn write_comments(post: &PostsPath, comments: &[ArchivedComment]) -> eyre::Result<()> {
+    let Some(path) = post.comments_path()? else {
+        return Ok(());
+    };
+    if comments.is_empty() {
+        return Ok(());
+    }
+    let mut file = File::create(&path)?;
+    serde_json::to_writer_pretty(&mut file, comments)?;
+    writeln!(file)?;
+
+    Ok(())
+}
+
+/// returns the number of comments, including replies.
+pub fn count_comments(comments: &[ArchivedComment]) -> usize {
+    comments
+        .iter()
+        .map(|comment| 1 + count_comments(&comment.replies))
+        .sum()
+}
+
+#[test]
+fn test_from_cohost() -> eyre::Result<()> {
+    let comments: Vec<CommentWrapper> = serde_json::from_value(serde_json::json!([
+        {
+            "comment": { "postedAtISO": "2024-01-01T00:00:00.000Z", "body": "*hi*", "children": [
+                { "comment": { "postedAtISO": "2024-01-02T00:00:00.000Z", "body": "hello" } },
+            ] },
+            "poster": { "handle": "staff", "displayName": "cohost dot org" },
+        },
+        {
+            "comment": { "postedAtISO": "2024-01-03T00:00:00.000Z", "deleted": true, "body": "", "children": [
+                { "comment": { "postedAtISO": "2024-01-04T00:00:00.000Z", "body": "reply" }, "poster": null },
+            ] },
+        },
+        { "comment": { "postedAtISO": "2024-01-05T00:00:00.000Z", "hidden": true, "body": "spam" } },
+    ]))?;
+    let archived = ArchivedComment::from_cohost(&comments);
+    assert_eq!(
+        archived.len(),
+        2,
+        "hidden comments without replies are skipped"
+    );
+    assert_eq!(count_comments(&archived), 4);
+    assert_eq!(archived[0].body.as_deref(), Some("<p><em>hi</em></p>\n"));
+    assert_eq!(
+        archived[0]
+            .author
+            .as_ref()
+            .map(|author| &*author.display_handle),
+        Some("@staff")
+    );
+    assert_eq!(archived[0].replies[0].author, None);
+    assert_eq!(
+        archived[1].body, None,
+        "deleted comments keep their replies"
+    );
+    assert_eq!(
+        archived[1].replies[0].body.as_deref(),
+        Some("<p>reply</p>\n")
+    );
+
+    Ok(())
+}
@@ -29,6 +29,32 @@ pub fn serialise_inline_style(tokens: &[InlineStyleToken]) -> String {
         .join("")
 }
 
+/// wraps the stylesheet of a `<style>` element in `@scope`, so it only applies inside the parent
+/// of the `<style>` element. returns None if the stylesheet has an unbalanced `}`, which would end
+/// the `@scope` rule early.
+pub fn scope_stylesheet(css: &str) -> Option<String> {
+    let mut input = ParserInput::new(css);
+    let mut parser = Parser::new(&mut input);
+    // blocks are skipped by the next call to `next_*()`, so we only see top-level tokens.
+    loop {
+        match parser.next_including_whitespace_and_comments() {
+            Ok(Token::CloseCurlyBracket) => return None,
+            Ok(_) => {}
+            Err(BasicParseError {
+                kind: BasicParseErrorKind::UnexpectedToken(Token::CloseCurlyBracket),
+                ..
+            }) => return None,
+            Err(BasicParseError {
+                kind: BasicParseErrorKind::EndOfInput,
+                ..
+            }) => break,
+            Err(_) => {}
+        }
+    }
+
+    Some(format!("@scope {{\n{css}\n}}"))
+}
+
 fn parse<'i>(parser: &'i mut Parser) -> Vec<InlineStyleToken> {
     let mut result = vec![];
     loop {
@@ -121,6 +147,23 @@ fn test_round_trip_inline_style() {
     assert_eq!(serialise_inline_style(&tokens), expected);
 }
 
+#[test]
+fn test_scope_stylesheet() {
+    assert_eq!(
+        scope_stylesheet("p { color: red; }").as_deref(),
+        Some("@scope {\np { color: red; }\n}")
+    );
+    assert_eq!(
+        scope_stylesheet("@media print { p { color: red; } }").as_deref(),
+        Some("@scope {\n@media print { p { color: red; } }\n}")
+    );
+    // no escaping the @scope rule.
+    assert_eq!(scope_stylesheet("} body { color: red; }"), None);
+    assert_eq!(scope_stylesheet("p { color: red; } } body { }"), None);
+    // braces in strings, comments, and other blocks are fine.
+    assert!(scope_stylesheet("p::after { content: '}'; } /* } */ p { x: [ } ] }").is_some());
+}
+
 #[test]
 fn test_serialise_string_value() {
     assert_eq!(serialise_string_value(r#"http://test"#), r#"'http://test'"#);
@@ -0,0 +1,247 @@
+use std::fmt::{Display, Write};
+
+use chrono::{DateTime, SecondsFormat, TimeZone, Utc};
+use chrono_tz::Tz;
+
+use crate::{settings::UnparseableDates, SETTINGS};
+
+/// `date_format = "long"`, like “1 October 2024, 04:30 AWST”.
+pub const DATE_FORMAT_LONG: &str = "%-d %B %Y, %H:%M %Z";
+/// `date_format = "short"`, like “2024-10-01 04:30”.
+pub const DATE_FORMAT_SHORT: &str = "%Y-%m-%d %H:%M";
+
+/// key for sorting posts by date, where posts without a valid date sort as the oldest or newest
+/// (`unparseable_dates_sort_as`), rather than wherever their text happens to sort.
+#[derive(Clone, Debug, Eq, Ord, PartialEq, PartialOrd)]
+pub enum SortDate {
+    Oldest,
+    Date(DateTime<Utc>),
+    Newest,
+}
+
+impl SortDate {
+    pub fn new(published: Option<&str>) -> Self {
+        Self::new_with(published, SETTINGS.unparseable_dates_sort_as())
+    }
+
+    fn new_with(published: Option<&str>, unparseable: UnparseableDates) -> Self {
+        match (published.and_then(parse_date), unparseable) {
+            (Some(published), _) => Self::Date(published),
+            (None, UnparseableDates::Oldest) => Self::Oldest,
+            (None, UnparseableDates::Newest) => Self::Newest,
+        }
+    }
+}
+
+/// parses a post timestamp as rfc 3339, or rfc 3339 without seconds (like `2024-10-01T04:30Z`).
+pub fn parse_date(published: &str) -> Option<DateTime<Utc>> {
+    if let Ok(result) = DateTime::parse_from_rfc3339(published) {
+        return Some(result.to_utc());
+    }
+    let (date_hour_minute, offset) = (published.get(..16)?, published.get(16..)?);
+    if !offset.starts_with(['Z', 'z', '+', '-']) {
+        return None;
+    }
+    let result = DateTime::parse_from_rfc3339(&format!("{date_hour_minute}:00{offset}")).ok()?;
+
+    Some(result.to_utc())
+}
+
+#[test]
+fn test_sort_date() {
+    let date = |published| parse_date(published).map(SortDate::Date);
+    let oldest = UnparseableDates::Oldest;
+    let newest = UnparseableDates::Newest;
+    assert_eq!(
+        Some(SortDate::new_with(Some("2024-10-01T12:00:00Z"), oldest)),
+        date("2024-10-01T12:00:00Z")
+    );
+    assert_eq!(
+        Some(SortDate::new_with(Some("2024-10-01T04:30+08:00"), oldest)),
+        date("2024-09-30T20:30:00Z")
+    );
+    assert_eq!(SortDate::new_with(Some(""), oldest), SortDate::Oldest);
+    assert_eq!(SortDate::new_with(Some(""), newest), SortDate::Newest);
+    assert_eq!(
+        SortDate::new_with(Some("yesterday"), oldest),
+        SortDate::Oldest
+    );
+    assert_eq!(
+        SortDate::new_with(Some("yesterday"), newest),
+        SortDate::Newest
+    );
+    assert_eq!(SortDate::new_with(None, oldest), SortDate::Oldest);
+
+    // offsets are taken into account, unlike when comparing the text.
+    assert!(
+        SortDate::new_with(Some("2024-10-01T12:00:00+08:00"), oldest)
+            < SortDate::new_with(Some("2024-10-01T11:00:00Z"), oldest)
+    );
+    assert!(SortDate::Oldest < SortDate::new_with(Some("1970-01-01T00:00:00Z"), oldest));
+    assert!(SortDate::Newest > SortDate::new_with(Some("9999-01-01T00:00:00Z"), oldest));
+}
+
+/// returns the day of a post timestamp, like `2024-10-01`, in the `display_timezone` if any, or
+/// None if the timestamp can’t be parsed.
+pub fn display_day(published: &str) -> Option<String> {
+    let date = parse_date(published)?;
+    match SETTINGS.display_timezone() {
+        Some(timezone) => Some(date.with_timezone(&timezone).format("%Y-%m-%d").to_string()),
+        // the day as written, in the post’s own offset.
+        None => published.get(..10).map(ToOwned::to_owned),
+    }
+}
+
+/// formats a post timestamp for display in html output, relative to now (like “3 days ago”) if
+/// `relative_dates` is enabled, otherwise the same as [`display_date`].
+pub fn display_date_or_relative(published: &str) -> String {
+    if SETTINGS.relative_dates() {
+        if let Some(result) = relative_date(published, Utc::now()) {
+            return result;
+        }
+    }
+
+    display_date(published)
+}
+
+/// formats a post timestamp for display in html output, in the `display_timezone` if any, and
+/// with the `date_format` if any.
+///
+/// timestamps that can’t be parsed as rfc 3339 are returned unchanged.
+pub fn display_date(published: &str) -> String {
+    display_date_with(
+        published,
+        SETTINGS.display_timezone(),
+        SETTINGS.date_format(),
+    )
+}
+
+fn display_date_with(published: &str, timezone: Option<Tz>, format: Option<&str>) -> String {
+    let Ok(date) = DateTime::parse_from_rfc3339(published) else {
+        return published.to_owned();
+    };
+    let result = match (timezone, format) {
+        (None, None) => return published.to_owned(),
+        (Some(timezone), None) => {
+            return date
+                .with_timezone(&timezone)
+                .to_rfc3339_opts(SecondsFormat::AutoSi, false)
+        }
+        (Some(timezone), Some(format)) => format_date(&date.with_timezone(&timezone), format),
+        // in the post’s own offset.
+        (None, Some(format)) => format_date(&date, format),
+    };
+
+    result.unwrap_or_else(|| published.to_owned())
+}
+
+/// formats a date with a chrono format string, or returns None if the format is invalid, rather
+/// than panicking like `to_string` would.
+fn format_date<Tz: TimeZone>(date: &DateTime<Tz>, format: &str) -> Option<String>
+where
+    Tz::Offset: Display,
+{
+    let mut result = String::default();
+    write!(result, "{}", date.format(format)).ok()?;
+
+    Some(result)
+}
+
+#[test]
+fn test_display_date_with() {
+    let new_york = "America/New_York".parse().ok();
+    assert_eq!(
+        display_date_with("2024-01-01T12:00:00Z", None, None),
+        "2024-01-01T12:00:00Z"
+    );
+    assert_eq!(
+        display_date_with("2024-01-01T12:00:00Z", new_york, None),
+        "2024-01-01T07:00:00-05:00"
+    );
+    // daylight saving time
+    assert_eq!(
+        display_date_with("2024-07-01T12:00:00.123Z", new_york, None),
+        "2024-07-01T08:00:00.123-04:00"
+    );
+    assert_eq!(
+        display_date_with("2024-10-01T04:30Z", new_york, None),
+        "2024-10-01T04:30Z"
+    );
+
+    let long = Some(DATE_FORMAT_LONG);
+    assert_eq!(
+        display_date_with("2024-07-01T12:00:00Z", new_york, long),
+        "1 July 2024, 08:00 EDT"
+    );
+    assert_eq!(
+        display_date_with("2024-07-01T12:00:00+08:00", None, Some(DATE_FORMAT_SHORT)),
+        "2024-07-01 12:00"
+    );
+    assert_eq!(
+        display_date_with("2024-07-01T12:00:00Z", None, Some("%Q")),
+        "2024-07-01T12:00:00Z"
+    );
+}
+
+/// formats a timestamp relative to `now`, like “3 days ago” or “in 2 hours”.
+///
+/// returns None if the timestamp can’t be parsed as rfc 3339.
+pub fn relative_date(published: &str, now: DateTime<Utc>) -> Option<String> {
+    let published = DateTime::parse_from_rfc3339(published).ok()?;
+    let seconds = now.signed_duration_since(published).num_seconds();
+    let (is_future, seconds) = (seconds < 0, seconds.unsigned_abs());
+
+    let (count, unit) = match seconds {
+        0..60 => (seconds, "second"),
+        60..3600 => (seconds / 60, "minute"),
+        3600..86400 => (seconds / 3600, "hour"),
+        _ => match seconds / 86400 {
+            days @ 0..30 => (days, "day"),
+            days @ 30..365 => (days / 30, "month"),
+            days => (days / 365, "year"),
+        },
+    };
+    let plural = if count == 1 { "" } else { "s" };
+
+    Some(if is_future {
+        format!("in {count} {unit}{plural}")
+    } else {
+        format!("{count} {unit}{plural} ago")
+    })
+}
+
+#[test]
+fn test_relative_date() {
+    let now = DateTime::parse_from_rfc3339("2024-10-01T12:00:00Z")
+        .unwrap()
+        .to_utc();
+    assert_eq!(
+        relative_date("2024-10-01T12:00:00Z", now).as_deref(),
+        Some("0 seconds ago")
+    );
+    assert_eq!(
+        relative_date("2024-10-01T11:59:00Z", now).as_deref(),
+        Some("1 minute ago")
+    );
+    assert_eq!(
+        relative_date("2024-10-01T07:00:00-02:00", now).as_deref(),
+        Some("3 hours ago")
+    );
+    assert_eq!(
+        relative_date("2024-09-28T12:00:00Z", now).as_deref(),
+        Some("3 days ago")
+    );
+    assert_eq!(
+        relative_date("2024-06-01T12:00:00Z", now).as_deref(),
+        Some("4 months ago")
+    );
+    assert_eq!(
+        relative_date("2022-01-01T12:00:00Z", now).as_deref(),
+        Some("2 years ago")
+    );
+    assert_eq!(
+        relative_date("2024-10-03T12:00:00Z", now).as_deref(),
+        Some("in 2 days")
+    );
+    assert_eq!(relative_date("2024-10-01T04:30Z", now), None);
+}
@@ -367,6 +367,85 @@ pub fn parse_xml(mut input: &[u8]) -> eyre::Result<RcDom> {
     Ok(dom)
 }
 
+/// svg elements that can run scripts or embed other documents.
+const UNSAFE_SVG_ELEMENTS: &[&str] = &["script", "foreignObject", "iframe", "embed", "object"];
+
+/// removes scripts, event handlers, and references to external resources from an svg image, so
+/// it can be inlined safely. returns the sanitised svg, and descriptions of what was removed.
+pub fn sanitise_svg(input: &[u8]) -> eyre::Result<(Vec<u8>, Vec<String>)> {
+    let dom = parse_xml(input)?;
+    let has_svg_root =
+        dom.document.children.borrow().iter().any(
+            |kid| matches!(&kid.data, NodeData::Element { name, .. } if &*name.local == "svg"),
+        );
+    if !has_svg_root {
+        bail!("not an svg image");
+    }
+
+    let mut removed = vec![];
+    let mut transform = Transform::new(dom.document.clone());
+    while transform.next(|kids, new_kids| {
+        for kid in kids {
+            if let NodeData::Element { name, attrs, .. } = &kid.data {
+                let element = &*name.local;
+                if UNSAFE_SVG_ELEMENTS.contains(&element) {
+                    removed.push(format!("<{element}>"));
+                    continue;
+                }
+                if element == "style" && css_has_external_reference(&text_content(kid.clone())?) {
+                    removed.push("<style> with external references".to_owned());
+                    continue;
+                }
+                attrs.borrow_mut().retain(|attr| {
+                    let attribute = &*attr.name.local;
+                    let value = attr.value.to_str().trim();
+                    let unsafe_reason = if attribute.to_ascii_lowercase().starts_with("on") {
+                        Some("event handler")
+                    } else if match attribute {
+                        "href" | "src" => !is_internal_svg_reference(value),
+                        "style" => css_has_external_reference(value),
+                        _ => false,
+                    } {
+                        Some("external reference")
+                    } else {
+                        None
+                    };
+                    if let Some(reason) = unsafe_reason {
+                        removed.push(format!("{attribute} attribute on <{element}> ({reason})"));
+                    }
+                    unsafe_reason.is_none()
+                });
+            }
+            new_kids.push(kid.clone());
+        }
+        Ok(())
+    })? {}
+
+    let mut result = Vec::default();
+    let document: SerializableHandle = dom.document.clone().into();
+    xml5ever::serialize::serialize(&mut result, &document, Default::default())?;
+
+    Ok((result, removed))
+}
+
+/// returns true iff the url is a fragment in the same document, or a raster image `data:` url.
+fn is_internal_svg_reference(url: &str) -> bool {
+    url.starts_with('#') || (url.starts_with("data:image/") && !url.starts_with("data:image/svg"))
+}
+
+/// returns true iff the css imports other stylesheets or has `url()`s outside the document.
+fn css_has_external_reference(css: &str) -> bool {
+    let css = css.to_ascii_lowercase();
+
+    css.contains("@import")
+        || css.split("url(").skip(1).any(|rest| {
+            !rest
+                .trim_start()
+                .trim_start_matches(['"', '\''])
+                .starts_with('#')
+        })
+}
+
 pub fn serialize_html_document(dom: RcDom) -> eyre::Result<String> {
     serialize_node_contents(dom.document.clone())
 }
@@ -441,10 +520,13 @@ pub fn create_element(dom: &mut RcDom, html_local_name: &str) -> Handle {
 }
 
 pub fn rename_idl_to_content_attribute(tag_name: &str, attribute_name: &str) -> QualName {
+    let dataset_or_aria_name = dataset_or_aria_content_attribute(attribute_name);
     let result = RENAME_IDL_TO_CONTENT_ATTRIBUTE
         .get_key_value(&(Some(tag_name), attribute_name))
         .or_else(|| RENAME_IDL_TO_CONTENT_ATTRIBUTE.get_key_value(&(None, attribute_name)))
-        .map_or(attribute_name, |(_, name)| name);
+        .map(|(_, name)| *name)
+        .or(dataset_or_aria_name.as_deref())
+        .unwrap_or(attribute_name);
 
     // to be extra cautious about converting attributes correctly, warn if we see attributes not on
     // our known-good list.
@@ -452,7 +534,11 @@ pub fn rename_idl_to_content_attribute(tag_name: &str, attribute_name: &str) ->
         .lock()
         .unwrap()
         .insert((tag_name.to_owned(), result.to_owned()));
-    if !KNOWN_GOOD_ATTRIBUTES.contains(&(None, result))
+    // `data-*` and `aria-*` attributes are only used by css and scripts on the page, so they
+    // are always safe to pass through.
+    let is_data_or_aria = result.starts_with("data-") || result.starts_with("aria-");
+    if !is_data_or_aria
+        && !KNOWN_GOOD_ATTRIBUTES.contains(&(None, result))
         && !KNOWN_GOOD_ATTRIBUTES.contains(&(Some(tag_name), result))
     {
         warn!("saw attribute not on known-good-attributes list! check if output is correct for: <{tag_name} {result}>");
@@ -465,6 +551,31 @@ pub fn rename_idl_to_content_attribute(tag_name: &str, attribute_name: &str) ->
     QualName::attribute(result)
 }
 
+/// converts hast property names like `dataFooBar` and `ariaDescribedBy` to content attribute
+/// names like `data-foo-bar` and `aria-describedby`.
+fn dataset_or_aria_content_attribute(attribute_name: &str) -> Option<String> {
+    if let Some(rest) = attribute_name.strip_prefix("data") {
+        if !rest.starts_with(|c: char| c.is_ascii_uppercase()) {
+            return None;
+        }
+        let mut result = "data".to_owned();
+        for c in rest.chars() {
+            if c.is_ascii_uppercase() {
+                result.push('-');
+            }
+            result.push(c.to_ascii_lowercase());
+        }
+        Some(result)
+    } else if let Some(rest) = attribute_name.strip_prefix("aria") {
+        if !rest.starts_with(|c: char| c.is_ascii_uppercase()) {
+            return None;
+        }
+        Some(format!("aria-{}", rest.to_ascii_lowercase()))
+    } else {
+        None
+    }
+}
+
 #[test]
 
 fn test_rename_idl_to_content_attribute() {
@@ -472,6 +583,22 @@ fn test_rename_idl_to_content_attribute() {
         rename_idl_to_content_attribute("div", "tabIndex"),
         QualName::attribute("tabindex"),
     );
+    assert_eq!(
+        rename_idl_to_content_attribute("div", "dataFooBar"),
+        QualName::attribute("data-foo-bar"),
+    );
+    assert_eq!(
+        rename_idl_to_content_attribute("div", "data-foo"),
+        QualName::attribute("data-foo"),
+    );
+    assert_eq!(
+        rename_idl_to_content_attribute("div", "ariaDescribedBy"),
+        QualName::attribute("aria-describedby"),
+    );
+    assert_eq!(
+        rename_idl_to_content_attribute("div", "database"),
+        QualName::attribute("database"),
+    );
 }
 
 pub fn convert_idl_to_content_attribute(
@@ -482,6 +609,11 @@ pub fn convert_idl_to_content_attribute(
     if value == Value::Bool(false) {
         return None;
     }
+    // event handlers like `onClick` would run scripts on the page.
+    if attribute_name.to_ascii_lowercase().starts_with("on") {
+        warn!("removing event handler attribute: <{tag_name} {attribute_name}>");
+        return None;
+    }
 
     Some(Attribute {
         name: rename_idl_to_content_attribute(tag_name, attribute_name),
@@ -546,6 +678,21 @@ fn test_convert_idl_to_content_attribute() {
             value: "foo bar".into(),
         }),
     );
+    assert_eq!(
+        convert_idl_to_content_attribute("div", "dataFoo", Value::String("bar".to_owned())),
+        Some(Attribute {
+            name: QualName::attribute("data-foo"),
+            value: "bar".into(),
+        }),
+    );
+    assert_eq!(
+        convert_idl_to_content_attribute("div", "onClick", Value::String("alert(1)".to_owned())),
+        None,
+    );
+    assert_eq!(
+        convert_idl_to_content_attribute("div", "onclick", Value::String("alert(1)".to_owned())),
+        None,
+    );
 }
 
 pub fn text_content(node: Handle) -> eyre::Result<String> {
@@ -597,3 +744,29 @@ pub fn html_attributes_with_non_embedding_urls() -> &'static BTreeMap<QualName,
 {
     &HTML_ATTRIBUTES_WITH_NON_EMBEDDING_URLS
 }
+
+#[test]
+fn test_sanitise_svg() -> eyre::Result<()> {
+    let (svg, removed) = sanitise_svg(
+        br##"<svg xmlns="http://www.w3.org/2000/svg" xmlns:xlink="http://www.w3.org/1999/xlink" onload="alert(1)"><script>alert(2)</script><rect id="r" width="1" height="1" onclick="alert(3)" style="fill: url(#g)"/><use xlink:href="#r"/><use href="https://example.com/a.svg#r"/><image href="data:image/png;base64,AAAA"/><style>@import url(https://example.com/a.css);</style></svg>"##,
+    )?;
+    let svg = String::from_utf8(svg)?;
+    assert!(!svg.contains("alert"));
+    assert!(!svg.contains("example.com"));
+    assert!(svg.contains(r##"style="fill: url(#g)""##));
+    assert!(svg.contains(r##"href="#r""##));
+    assert!(svg.contains("data:image/png"));
+    assert_eq!(
+        removed,
+        [
+            "onload attribute on <svg> (event handler)",
+            "<script>",
+            "onclick attribute on <rect> (event handler)",
+            "href attribute on <use> (external reference)",
+            "<style> with external references",
+        ]
+    );
+    assert!(sanitise_svg(b"<html><script>alert(1)</script></html>").is_err());
+
+    Ok(())
+}
@@ -0,0 +1,1467 @@
+//! downscaling of large image attachments, for `max_image_dimension`.
+//!
+//! there are no image crates here, so this has a small png decoder and encoder, a baseline jpeg
+//! decoder and encoder, and a box filter, which are all we need to make photos smaller. anything
+//! else, like progressive jpegs or interlaced pngs, is left at its original size.
+
+use std::{
+    f32::consts::PI,
+    io::{Read, Write},
+    sync::LazyLock,
+};
+
+use flate2::{read::ZlibDecoder, write::ZlibEncoder, Compression};
+use jane_eyre::eyre::{self, bail, OptionExt};
+
+use crate::attachments::{exif_orientation, orientation_segment};
+
+/// the quality of downscaled jpegs, from 1 to 100, as in libjpeg.
+const JPEG_QUALITY: u32 = 90;
+
+/// the most pixels we will decode, so a tiny file claiming to be a huge image can’t use up all
+/// of our memory.
+const MAX_PIXELS: usize = 200_000_000;
+
+/// decoded pixels, with 8 bits per channel: gray, gray and alpha, rgb, or rgba.
+#[derive(Debug, PartialEq)]
+struct Pixels {
+    width: usize,
+    height: usize,
+    channels: usize,
+    data: Vec<u8>,
+}
+
+impl Pixels {
+    fn has_alpha(&self) -> bool {
+        self.channels == 2 || self.channels == 4
+    }
+}
+
+/// returns the png or jpeg image downscaled so its longest side is `max_dimension`, in the same
+/// format. returns None if the image is no larger than that already, or if it is not a png or
+/// jpeg we can decode, like a progressive jpeg or an interlaced png.
+pub fn downscale_image(input: &[u8], max_dimension: usize) -> eyre::Result<Option<Vec<u8>>> {
+    if max_dimension == 0 {
+        bail!("max dimension must be at least 1");
+    }
+    if input.starts_with(PNG_SIGNATURE) {
+        let Some(pixels) = decode_png(input)? else {
+            return Ok(None);
+        };
+        let Some((width, height)) = scaled_size(&pixels, max_dimension) else {
+            return Ok(None);
+        };
+        return Ok(Some(encode_png(&resize(&pixels, width, height))?));
+    }
+    if input.starts_with(&[0xFF, 0xD8]) {
+        let Some((pixels, orientation)) = decode_jpeg(input)? else {
+            return Ok(None);
+        };
+        let Some((width, height)) = scaled_size(&pixels, max_dimension) else {
+            return Ok(None);
+        };
+        return Ok(Some(encode_jpeg(
+            &resize(&pixels, width, height),
+            orientation,
+        )?));
+    }
+
+    Ok(None)
+}
+
+/// returns the jpeg with its pixels rotated or flipped by the given exif orientation, re-encoded
+/// without an orientation, so it is the right way up without one. returns None if it is not a
+/// jpeg we can decode, like a progressive jpeg, or if the orientation is not one of the eight
+/// that exif defines.
+pub fn apply_jpeg_orientation(input: &[u8], orientation: u16) -> eyre::Result<Option<Vec<u8>>> {
+    let Some((pixels, _)) = decode_jpeg(input)? else {
+        return Ok(None);
+    };
+    let Some(pixels) = orient(&pixels, orientation) else {
+        return Ok(None);
+    };
+
+    Ok(Some(encode_jpeg(&pixels, None)?))
+}
+
+/// returns the image as it would be shown with the given exif orientation, or None if the
+/// orientation is not one of the eight that exif defines.
+fn orient(pixels: &Pixels, orientation: u16) -> Option<Pixels> {
+    let (w, h) = (pixels.width, pixels.height);
+    // the source pixel for each pixel (x, y) of the result.
+    let source: fn(usize, usize, usize, usize) -> (usize, usize) = match orientation {
+        1 => |x, y, _, _| (x, y),
+        2 => |x, y, w, _| (w - 1 - x, y),
+        3 => |x, y, w, h| (w - 1 - x, h - 1 - y),
+        4 => |x, y, _, h| (x, h - 1 - y),
+        5 => |x, y, _, _| (y, x),
+        6 => |x, y, _, h| (y, h - 1 - x),
+        7 => |x, y, w, h| (w - 1 - y, h - 1 - x),
+        8 => |x, y, w, _| (w - 1 - y, x),
+        _ => return None,
+    };
+    let (width, height) = if orientation >= 5 { (h, w) } else { (w, h) };
+
+    let channels = pixels.channels;
+    let mut data = Vec::with_capacity(pixels.data.len());
+    for y in 0..height {
+        for x in 0..width {
+            let (source_x, source_y) = source(x, y, w, h);
+            data.extend_from_slice(
+                &pixels.data[(source_y * w + source_x) * channels..][..channels],
+            );
+        }
+    }
+
+    Some(Pixels {
+        width,
+        height,
+        channels,
+        data,
+    })
+}
+
+/// returns the size of the image with its longest side at `max_dimension`, keeping the aspect
+/// ratio, or None if it already fits.
+fn scaled_size(pixels: &Pixels, max_dimension: usize) -> Option<(usize, usize)> {
+    let longest = pixels.width.max(pixels.height);
+    if longest <= max_dimension {
+        return None;
+    }
+    let scale = |side: usize| (side * max_dimension + longest / 2) / longest;
+
+    Some((scale(pixels.width).max(1), scale(pixels.height).max(1)))
+}
+
+/// resizes the image to a smaller size, averaging the pixels that each new pixel covers (a box
+/// filter). colors are weighted by their alpha, so transparent pixels don’t darken the edges.
+fn resize(pixels: &Pixels, width: usize, height: usize) -> Pixels {
+    let channels = pixels.channels;
+    let alpha = pixels.has_alpha().then_some(channels - 1);
+    let columns = box_weights(pixels.width, width);
+    let rows = box_weights(pixels.height, height);
+
+    // sums each source row horizontally into `width` pixels, premultiplying by alpha.
+    let resize_row = |y: usize, result: &mut [f32]| {
+        let row = &pixels.data[y * pixels.width * channels..][..pixels.width * channels];
+        for (x, weights) in columns.iter().enumerate() {
+            let out = &mut result[x * channels..][..channels];
+            out.fill(0.0);
+            for &(source_x, weight) in weights {
+                let pixel = &row[source_x * channels..][..channels];
+                let a = alpha.map_or(1.0, |alpha| f32::from(pixel[alpha]) / 255.0);
+                for (c, value) in pixel.iter().enumerate() {
+                    let premultiplied = if Some(c) == alpha { 1.0 } else { a };
+                    out[c] += f32::from(*value) * premultiplied * weight;
+                }
+            }
+        }
+    };
+
+    let mut data = Vec::with_capacity(width * height * channels);
+    let mut row = vec![0.0; width * channels];
+    let mut sum = vec![0.0; width * channels];
+    for weights in rows.iter() {
+        sum.fill(0.0);
+        for &(source_y, weight) in weights {
+            resize_row(source_y, &mut row);
+            for (sum, value) in sum.iter_mut().zip(row.iter()) {
+                *sum += value * weight;
+            }
+        }
+        for pixel in sum.chunks(channels) {
+            let a = alpha.map_or(1.0, |alpha| pixel[alpha] / 255.0);
+            for (c, value) in pixel.iter().enumerate() {
+                let value = match alpha {
+                    Some(alpha) if c != alpha && a > 0.0 => value / a,
+                    Some(alpha) if c != alpha => 0.0,
+                    _ => *value,
+                };
+                data.push(value.round().clamp(0.0, 255.0) as u8);
+            }
+        }
+    }
+
+    Pixels {
+        width,
+        height,
+        channels,
+        data,
+    }
+}
+
+/// for each of the `to` pixels, returns the `from` pixels it covers and how much of each, which
+/// add up to 1.
+fn box_weights(from: usize, to: usize) -> Vec<Vec<(usize, f32)>> {
+    let scale = from as f64 / to as f64;
+    (0..to)
+        .map(|i| {
+            let start = i as f64 * scale;
+            let end = ((i + 1) as f64 * scale).min(from as f64);
+            let first = start.floor() as usize;
+            let last = (end.ceil() as usize).min(from);
+            (first..last)
+                .map(|j| {
+                    let covered = end.min((j + 1) as f64) - start.max(j as f64);
+                    (j, (covered / scale) as f32)
+                })
+                .filter(|&(_, weight)| weight > 0.0)
+                .collect()
+        })
+        .collect()
+}
+
+const PNG_SIGNATURE: &[u8] = b"\x89PNG\r\n\x1A\n";
+
+/// decodes a non-interlaced png, or returns None for an interlaced png, or one with a
+/// transparent color key in `tRNS` (which only palette images are likely to have).
+fn decode_png(input: &[u8]) -> eyre::Result<Option<Pixels>> {
+    let mut rest = input
+        .strip_prefix(PNG_SIGNATURE)
+        .ok_or_eyre("bad png: no signature")?;
+    let mut header = None;
+    let mut palette = vec![];
+    let mut transparency = None;
+    let mut compressed = vec![];
+    while !rest.is_empty() {
+        let (len, after) = rest
+            .split_first_chunk::<4>()
+            .ok_or_eyre("bad png: truncated chunk")?;
+        let len = usize::try_from(u32::from_be_bytes(*len))?;
+        let (kind, after) = after
+            .split_first_chunk::<4>()
+            .ok_or_eyre("bad png: truncated chunk")?;
+        let data = after.get(..len).ok_or_eyre("bad png: truncated chunk")?;
+        // skip the crc too.
+        rest = after
+            .get(len + 4..)
+            .ok_or_eyre("bad png: truncated chunk")?;
+        match kind {
+            b"IHDR" => header = Some(PngHeader::parse(data)?),
+            b"PLTE" => palette = data.to_vec(),
+            b"tRNS" => transparency = Some(data.to_vec()),
+            b"IDAT" => compressed.extend_from_slice(data),
+            b"IEND" => break,
+            _ => {}
+        }
+    }
+    let header = header.ok_or_eyre("bad png: no IHDR")?;
+    if header.interlaced || (transparency.is_some() && header.color_type != 3) {
+        return Ok(None);
+    }
+
+    let channels = match header.color_type {
+        0 | 3 => 1,
+        2 => 3,
+        4 => 2,
+        6 => 4,
+        other => bail!("bad png: color type {other}"),
+    };
+    let bits_per_pixel = channels * usize::from(header.bit_depth);
+    let stride = (header.width * bits_per_pixel).div_ceil(8);
+    let bytes_per_pixel = bits_per_pixel.div_ceil(8);
+    let mut filtered = Vec::with_capacity((stride + 1) * header.height);
+    ZlibDecoder::new(&compressed[..])
+        .take(u64::try_from((stride + 1) * header.height)?)
+        .read_to_end(&mut filtered)?;
+    if filtered.len() != (stride + 1) * header.height {
+        bail!("bad png: wrong amount of image data");
+    }
+
+    let mut samples = vec![0u8; stride * header.height];
+    for y in 0..header.height {
+        let (filter, line) = filtered[y * (stride + 1)..][..stride + 1]
+            .split_first()
+            .expect("guaranteed by length");
+        let (before, after) = samples.split_at_mut(y * stride);
+        let previous = before
+            .get(before.len().saturating_sub(stride)..)
+            .filter(|_| y > 0);
+        unfilter_png_row(
+            *filter,
+            line,
+            previous,
+            &mut after[..stride],
+            bytes_per_pixel,
+        )?;
+    }
+
+    // convert to 8 bits per channel, looking up palette colors.
+    let mut data = Vec::with_capacity(header.width * header.height * channels);
+    for row in samples.chunks(stride) {
+        for x in 0..header.width * channels {
+            data.push(match header.bit_depth {
+                8 => row[x],
+                // keep the most significant byte.
+                16 => row[x * 2],
+                depth => {
+                    let depth = usize::from(depth);
+                    let bit = x * depth;
+                    let value = (row[bit / 8] >> (8 - depth - bit % 8)) & ((1 << depth) - 1);
+                    if header.color_type == 3 {
+                        value
+                    } else {
+                        // scale gray up to 8 bits, like 0b11 to 0xFF.
+                        (u16::from(value) * 255 / ((1 << depth) - 1)) as u8
+                    }
+                }
+            });
+        }
+    }
+    if header.color_type == 3 {
+        let alpha = transparency.unwrap_or_default();
+        let channels = if alpha.is_empty() { 3 } else { 4 };
+        let mut result = Vec::with_capacity(data.len() * channels);
+        for index in data {
+            let index = usize::from(index);
+            let rgb = palette
+                .get(index * 3..index * 3 + 3)
+                .ok_or_eyre("bad png: color not in palette")?;
+            result.extend_from_slice(rgb);
+            if channels == 4 {
+                result.push(alpha.get(index).copied().unwrap_or(255));
+            }
+        }
+        return Ok(Some(Pixels {
+            width: header.width,
+            height: header.height,
+            channels,
+            data: result,
+        }));
+    }
+
+    Ok(Some(Pixels {
+        width: header.width,
+        height: header.height,
+        channels,
+        data,
+    }))
+}
+
+struct PngHeader {
+    width: usize,
+    height: usize,
+    bit_depth: u8,
+    color_type: u8,
+    interlaced: bool,
+}
+
+impl PngHeader {
+    fn parse(data: &[u8]) -> eyre::Result<Self> {
+        let Some((size, &[bit_depth, color_type, _compression, _filter, interlace])) =
+            data.split_first_chunk::<8>()
+        else {
+            bail!("bad png: wrong IHDR length");
+        };
+        let width = usize::try_from(u32::from_be_bytes(size[..4].try_into()?))?;
+        let height = usize::try_from(u32::from_be_bytes(size[4..].try_into()?))?;
+        let valid_depths: &[u8] = match color_type {
+            0 => &[1, 2, 4, 8, 16],
+            3 => &[1, 2, 4, 8],
+            2 | 4 | 6 => &[8, 16],
+            _ => &[],
+        };
+        if !valid_depths.contains(&bit_depth) {
+            bail!("bad png: bit depth {bit_depth} for color type {color_type}");
+        }
+        if width == 0 || height == 0 || width.saturating_mul(height) > MAX_PIXELS {
+            bail!("bad png: unsupported size {width}x{height}");
+        }
+
+        Ok(Self {
+            width,
+            height,
+            bit_depth,
+            color_type,
+            interlaced: interlace != 0,
+        })
+    }
+}
+
+fn unfilter_png_row(
+    filter: u8,
+    line: &[u8],
+    previous: Option<&[u8]>,
+    result: &mut [u8],
+    bytes_per_pixel: usize,
+) -> eyre::Result<()> {
+    for i in 0..line.len() {
+        let left = i
+            .checked_sub(bytes_per_pixel)
+            .map_or(0, |left| result[left]);
+        let up = previous.map_or(0, |previous| previous[i]);
+        let up_left = match (previous, i.checked_sub(bytes_per_pixel)) {
+            (Some(previous), Some(left)) => previous[left],
+            _ => 0,
+        };
+        let predictor = match filter {
+            0 => 0,
+            1 => left,
+            2 => up,
+            3 => ((u16::from(left) + u16::from(up)) / 2) as u8,
+            4 => paeth(left, up, up_left),
+            other => bail!("bad png: filter type {other}"),
+        };
+        result[i] = line[i].wrapping_add(predictor);
+    }
+
+    Ok(())
+}
+
+fn paeth(left: u8, up: u8, up_left: u8) -> u8 {
+    let estimate = i16::from(left) + i16::from(up) - i16::from(up_left);
+    let distance = |value: u8| (estimate - i16::from(value)).abs();
+    if distance(left) <= distance(up) && distance(left) <= distance(up_left) {
+        left
+    } else if distance(up) <= distance(up_left) {
+        up
+    } else {
+        up_left
+    }
+}
+
+/// encodes the pixels as a png, choosing a filter for each row by the usual heuristic (the
+/// smallest sum of absolute differences).
+fn encode_png(pixels: &Pixels) -> eyre::Result<Vec<u8>> {
+    let color_type = match pixels.channels {
+        1 => 0,
+        2 => 4,
+        3 => 2,
+        4 => 6,
+        other => bail!("can’t encode {other} channels as png"),
+    };
+    let mut result = PNG_SIGNATURE.to_vec();
+    let mut chunk = |kind: &[u8; 4], data: &[u8]| -> eyre::Result<()> {
+        result.extend(u32::try_from(data.len())?.to_be_bytes());
+        let start = result.len();
+        result.extend(kind);
+        result.extend(data);
+        let crc = crc32fast::hash(&result[start..]);
+        result.extend(crc.to_be_bytes());
+        Ok(())
+    };
+
+    let mut header = vec![];
+    header.extend(u32::try_from(pixels.width)?.to_be_bytes());
+    header.extend(u32::try_from(pixels.height)?.to_be_bytes());
+    // 8 bits per channel, deflate, adaptive filtering, no interlacing.
+    header.extend([8, color_type, 0, 0, 0]);
+    chunk(b"IHDR", &header)?;
+
+    let stride = pixels.width * pixels.channels;
+    let mut encoder = ZlibEncoder::new(vec![], Compression::default());
+    let mut filtered = vec![0; stride];
+    let mut best = vec![0; stride];
+    for (y, row) in pixels.data.chunks(stride).enumerate() {
+        let previous = y
+            .checked_sub(1)
+            .map(|y| &pixels.data[y * stride..][..stride]);
+        let mut best_filter = 0;
+        let mut best_cost = usize::MAX;
+        for filter in 0..=4 {
+            for i in 0..stride {
+                let left = i.checked_sub(pixels.channels).map_or(0, |left| row[left]);
+                let up = previous.map_or(0, |previous| previous[i]);
+                let up_left = match (previous, i.checked_sub(pixels.channels)) {
+                    (Some(previous), Some(left)) => previous[left],
+                    _ => 0,
+                };
+                let predictor = match filter {
+                    0 => 0,
+                    1 => left,
+                    2 => up,
+                    3 => ((u16::from(left) + u16::from(up)) / 2) as u8,
+                    _ => paeth(left, up, up_left),
+                };
+                filtered[i] = row[i].wrapping_sub(predictor);
+            }
+            let cost = filtered
+                .iter()
+                .map(|&value| usize::from((value as i8).unsigned_abs()))
+                .sum();
+            if cost < best_cost {
+                best_cost = cost;
+                best_filter = filter;
+                best.copy_from_slice(&filtered);
+            }
+        }
+        encoder.write_all(&[best_filter])?;
+        encoder.write_all(&best)?;
+    }
+    chunk(b"IDAT", &encoder.finish()?)?;
+    chunk(b"IEND", &[])?;
+
+    Ok(result)
+}
+
+/// the natural (row-major) index of each coefficient in a jpeg block, in zigzag order.
+#[rustfmt::skip]
+const ZIGZAG: [usize; 64] = [
+    0, 1, 8, 16, 9, 2, 3, 10,
+    17, 24, 32, 25, 18, 11, 4, 5,
+    12, 19, 26, 33, 40, 48, 41, 34,
+    27, 20, 13, 6, 7, 14, 21, 28,
+    35, 42, 49, 56, 57, 50, 43, 36,
+    29, 22, 15, 23, 30, 37, 44, 51,
+    58, 59, 52, 45, 38, 31, 39, 46,
+    53, 60, 61, 54, 47, 55, 62, 63,
+];
+
+/// `DCT[x][u]` is the dct basis function u at sample x, with the scaling for u = 0.
+static DCT: LazyLock<[[f32; 8]; 8]> = LazyLock::new(|| {
+    let mut result = [[0.0; 8]; 8];
+    for (x, row) in result.iter_mut().enumerate() {
+        for (u, value) in row.iter_mut().enumerate() {
+            let scale = if u == 0 { 0.5f32.sqrt() } else { 1.0 };
+            *value = scale * ((2 * x + 1) as f32 * u as f32 * PI / 16.0).cos() / 2.0;
+        }
+    }
+    result
+});
+
+#[derive(Clone, Copy)]
+struct JpegComponent {
+    id: u8,
+    h: usize,
+    v: usize,
+    quantization: usize,
+    dc_table: usize,
+    ac_table: usize,
+}
+
+#[derive(Clone, Default)]
+struct HuffmanTable {
+    /// for each code length from 1 to 16, the largest code of that length (or -1 if none), and
+    /// the index into `values` of the first code of that length, minus that code.
+    max_code: [i32; 17],
+    offset: [i32; 17],
+    values: Vec<u8>,
+}
+
+impl HuffmanTable {
+    fn new(counts: &[u8; 16], values: &[u8]) -> eyre::Result<Self> {
+        let mut result = Self {
+            max_code: [-1; 17],
+            offset: [0; 17],
+            values: values.to_vec(),
+        };
+        let mut code = 0i32;
+        let mut index = 0i32;
+        for (len, &count) in (1..=16).zip(counts.iter()) {
+            let count = i32::from(count);
+            result.offset[len] = index - code;
+            if count > 0 {
+                result.max_code[len] = code + count - 1;
+            }
+            code = (code + count) << 1;
+            index += count;
+        }
+        if usize::try_from(index)? != values.len() {
+            bail!("bad jpeg: huffman table has the wrong number of values");
+        }
+
+        Ok(result)
+    }
+
+    fn decode(&self, reader: &mut BitReader) -> eyre::Result<u8> {
+        let mut code = 0;
+        for len in 1..=16 {
+            code = (code << 1) | reader.bit();
+            if code <= self.max_code[len] {
+                let index = usize::try_from(self.offset[len] + code)?;
+                return self
+                    .values
+                    .get(index)
+                    .copied()
+                    .ok_or_eyre("bad jpeg: bad huffman code");
+            }
+        }
+
+        bail!("bad jpeg: bad huffman code")
+    }
+}
+
+/// reads the entropy-coded data of a scan, removing the zero bytes stuffed after 0xFF, and
+/// stopping at the next marker.
+struct BitReader<'input> {
+    data: &'input [u8],
+    position: usize,
+    bits: u32,
+    count: u32,
+}
+
+impl BitReader<'_> {
+    fn bit(&mut self) -> i32 {
+        if self.count == 0 {
+            self.bits = u32::from(self.next_byte());
+            self.count = 8;
+        }
+        self.count -= 1;
+        ((self.bits >> self.count) & 1) as i32
+    }
+
+    fn next_byte(&mut self) -> u8 {
+        match self.data.get(self.position..) {
+            Some([0xFF, 0x00, ..]) => {
+                self.position += 2;
+                0xFF
+            }
+            // a marker, or the end of the data, so pretend the rest is zeros.
+            Some([0xFF, ..]) | None | Some([]) => 0,
+            Some([byte, ..]) => {
+                self.position += 1;
+                *byte
+            }
+        }
+    }
+
+    fn receive(&mut self, len: u8) -> i32 {
+        (0..len).fold(0, |value, _| (value << 1) | self.bit())
+    }
+
+    /// reads `len` bits as a signed value, where values with a leading zero are negative.
+    fn receive_extend(&mut self, len: u8) -> eyre::Result<i32> {
+        // sizes come from huffman tables in the file, so they could be anything.
+        if len > 16 {
+            bail!("bad jpeg: coefficient size {len}");
+        }
+        let value = self.receive(len);
+
+        Ok(if len > 0 && value < 1 << (len - 1) {
+            value - (1 << len) + 1
+        } else {
+            value
+        })
+    }
+
+    /// skips to the byte after the next restart marker.
+    fn restart(&mut self) -> eyre::Result<()> {
+        self.count = 0;
+        while let Some(&byte) = self.data.get(self.position) {
+            self.position += 1;
+            if byte == 0xFF {
+                match self.data.get(self.position) {
+                    Some(0xD0..=0xD7) => {
+                        self.position += 1;
+                        return Ok(());
+                    }
+                    Some(0xFF) | Some(0x00) => {}
+                    _ => bail!("bad jpeg: expected restart marker"),
+                }
+            }
+        }
+
+        bail!("bad jpeg: expected restart marker")
+    }
+}
+
+/// decodes a baseline jpeg with one (gray) or three (ycbcr) components, returning its pixels and
+/// exif orientation. returns None for other jpegs, like progressive or arithmetic-coded jpegs,
+/// or cmyk jpegs.
+fn decode_jpeg(input: &[u8]) -> eyre::Result<Option<(Pixels, Option<u16>)>> {
+    let mut quantization = [[0u16; 64]; 4];
+    let mut dc_tables = vec![HuffmanTable::default(); 4];
+    let mut ac_tables = vec![HuffmanTable::default(); 4];
+    let mut components = vec![];
+    let (mut width, mut height) = (0, 0);
+    let mut restart_interval = 0;
+    let mut orientation = None;
+    let mut adobe_transform = None;
+    let mut rest = input.get(2..).ok_or_eyre("bad jpeg: truncated")?;
+    let scan = loop {
+        let Some((&[0xFF, marker], after)) = rest.split_first_chunk::<2>() else {
+            bail!("bad jpeg: expected marker");
+        };
+        // fill bytes before a marker.
+        if marker == 0xFF {
+            rest = &rest[1..];
+            continue;
+        }
+        if marker == 0xD9 {
+            bail!("bad jpeg: no scan");
+        }
+        let Some((&len, _)) = after.split_first_chunk::<2>() else {
+            bail!("bad jpeg: truncated segment");
+        };
+        let len = usize::from(u16::from_be_bytes(len));
+        let Some(payload) = after.get(2..len).filter(|_| len >= 2) else {
+            bail!("bad jpeg: truncated segment");
+        };
+        rest = &after[len..];
+        match marker {
+            // baseline and extended sequential dct, with huffman coding.
+            0xC0 | 0xC1 => {
+                let [precision, h1, h0, w1, w0, count, ref specs @ ..] = *payload else {
+                    bail!("bad jpeg: truncated frame header");
+                };
+                height = usize::from(u16::from_be_bytes([h1, h0]));
+                width = usize::from(u16::from_be_bytes([w1, w0]));
+                // a height of zero means the height comes later, in a DNL marker.
+                if precision != 8 || height == 0 || !matches!(count, 1 | 3) {
+                    return Ok(None);
+                }
+                if width == 0 || width * height > MAX_PIXELS {
+                    bail!("bad jpeg: unsupported size {width}x{height}");
+                }
+                for spec in specs.chunks(3).take(count.into()) {
+                    let &[id, sampling, quantization] = spec else {
+                        bail!("bad jpeg: truncated frame header");
+                    };
+                    let (h, v) = (usize::from(sampling >> 4), usize::from(sampling & 15));
+                    if !(1..=4).contains(&h) || !(1..=4).contains(&v) || quantization > 3 {
+                        bail!("bad jpeg: bad component");
+                    }
+                    components.push(JpegComponent {
+                        id,
+                        h,
+                        v,
+                        quantization: quantization.into(),
+                        dc_table: 0,
+                        ac_table: 0,
+                    });
+                }
+                if components.len() != usize::from(count) {
+                    bail!("bad jpeg: truncated frame header");
+                }
+            }
+            // progressive, lossless, hierarchical, or arithmetic-coded.
+            0xC2 | 0xC3 | 0xC5..=0xC7 | 0xC9..=0xCB | 0xCD..=0xCF => return Ok(None),
+            0xC4 => {
+                let mut tables = payload;
+                while let Some((&class_and_id, after)) = tables.split_first() {
+                    let (counts, after) = after
+                        .split_first_chunk::<16>()
+                        .ok_or_eyre("bad jpeg: truncated huffman table")?;
+                    let total = counts.iter().map(|&count| usize::from(count)).sum();
+                    let values = after
+                        .get(..total)
+                        .ok_or_eyre("bad jpeg: truncated huffman table")?;
+                    let table = HuffmanTable::new(counts, values)?;
+                    let id = usize::from(class_and_id & 15);
+                    match class_and_id >> 4 {
+                        0 if id < 4 => dc_tables[id] = table,
+                        1 if id < 4 => ac_tables[id] = table,
+                        _ => bail!("bad jpeg: bad huffman table"),
+                    }
+                    tables = &after[total..];
+                }
+            }
+            0xDB => {
+                let mut tables = payload;
+                while let Some((&precision_and_id, after)) = tables.split_first() {
+                    let id = usize::from(precision_and_id & 15);
+                    let table = quantization
+                        .get_mut(id)
+                        .ok_or_eyre("bad jpeg: bad quantization table")?;
+                    let size = if precision_and_id >> 4 == 0 { 1 } else { 2 };
+                    let values = after
+                        .get(..64 * size)
+                        .ok_or_eyre("bad jpeg: truncated quantization table")?;
+                    for (value, bytes) in table.iter_mut().zip(values.chunks(size)) {
+                        *value = bytes.iter().fold(0, |a, &b| a << 8 | u16::from(b));
+                    }
+                    tables = &after[64 * size..];
+                }
+            }
+            0xDD => {
+                let &[i1, i0] = payload else {
+                    bail!("bad jpeg: bad restart interval");
+                };
+                restart_interval = usize::from(u16::from_be_bytes([i1, i0]));
+            }
+            0xE1 if payload.starts_with(b"Exif\0\0") => {
+                orientation = orientation.or(exif_orientation(&payload[6..]));
+            }
+            0xEE if payload.starts_with(b"Adobe") => {
+                adobe_transform = payload.get(11).copied();
+            }
+            0xDA => break payload,
+            _ => {}
+        }
+    };
+    if components.is_empty() {
+        bail!("bad jpeg: no frame header");
+    }
+
+    // only single scans with every component (which is all that baseline encoders make).
+    let Some((&count, specs)) = scan.split_first() else {
+        bail!("bad jpeg: truncated scan header");
+    };
+    if usize::from(count) != components.len() {
+        return Ok(None);
+    }
+    for spec in specs.chunks(2).take(count.into()) {
+        let &[id, tables] = spec else {
+            bail!("bad jpeg: truncated scan header");
+        };
+        let component = components
+            .iter_mut()
+            .find(|component| component.id == id)
+            .ok_or_eyre("bad jpeg: scan has unknown component")?;
+        component.dc_table = usize::from(tables >> 4).min(3);
+        component.ac_table = usize::from(tables & 15).min(3);
+    }
+
+    let max_h = components.iter().map(|c| c.h).max().expect("not empty");
+    let max_v = components.iter().map(|c| c.v).max().expect("not empty");
+    // with one component, each mcu is one block, whatever the sampling factors say.
+    let (max_h, max_v) = if components.len() == 1 {
+        components[0].h = 1;
+        components[0].v = 1;
+        (1, 1)
+    } else {
+        (max_h, max_v)
+    };
+    let mcus_x = width.div_ceil(8 * max_h);
+    let mcus_y = height.div_ceil(8 * max_v);
+    let mut planes = components
+        .iter()
+        .map(|c| vec![0u8; mcus_x * c.h * 8 * mcus_y * c.v * 8])
+        .collect::<Vec<_>>();
+
+    let mut reader = BitReader {
+        data: rest,
+        position: 0,
+        bits: 0,
+        count: 0,
+    };
+    let mut predictions = vec![0i32; components.len()];
+    let mut block = [0f32; 64];
+    for mcu in 0..mcus_x * mcus_y {
+        if restart_interval > 0 && mcu > 0 && mcu % restart_interval == 0 {
+            reader.restart()?;
+            predictions.fill(0);
+        }
+        let (mcu_x, mcu_y) = (mcu % mcus_x, mcu / mcus_x);
+        for (i, component) in components.iter().enumerate() {
+            let table = &quantization[component.quantization];
+            let stride = mcus_x * component.h * 8;
+            for block_y in 0..component.v {
+                for block_x in 0..component.h {
+                    block.fill(0.0);
+                    // dc differences have at most 11 bits in 8-bit jpegs.
+                    let size = dc_tables[component.dc_table].decode(&mut reader)?;
+                    if size > 11 {
+                        bail!("bad jpeg: dc difference size {size}");
+                    }
+                    predictions[i] = predictions[i]
+                        .checked_add(reader.receive_extend(size)?)
+                        .ok_or_eyre("bad jpeg: dc coefficient out of range")?;
+                    block[0] = predictions[i]
+                        .checked_mul(i32::from(table[0]))
+                        .ok_or_eyre("bad jpeg: dc coefficient out of range")?
+                        as f32;
+                    let mut k = 1;
+                    while k < 64 {
+                        let symbol = ac_tables[component.ac_table].decode(&mut reader)?;
+                        // ac coefficients have at most 10 bits in 8-bit jpegs.
+                        let (run, size) = (usize::from(symbol >> 4), symbol & 15);
+                        if size > 10 {
+                            bail!("bad jpeg: ac coefficient size {size}");
+                        }
+                        if size == 0 {
+                            if run != 15 {
+                                break;
+                            }
+                            k += 16;
+                            continue;
+                        }
+                        k += run;
+                        if k > 63 {
+                            bail!("bad jpeg: too many coefficients");
+                        }
+                        let value = reader
+                            .receive_extend(size)?
+                            .checked_mul(i32::from(table[k]))
+                            .ok_or_eyre("bad jpeg: ac coefficient out of range")?;
+                        block[ZIGZAG[k]] = value as f32;
+                        k += 1;
+                    }
+                    let x = (mcu_x * component.h + block_x) * 8;
+                    let y = (mcu_y * component.v + block_y) * 8;
+                    let samples = inverse_dct(&block);
+                    for (row, samples) in samples.chunks(8).enumerate() {
+                        planes[i][(y + row) * stride + x..][..8].copy_from_slice(samples);
+                    }
+                }
+            }
+        }
+    }
+
+    // upsample any subsampled components, and convert to rgb.
+    let channels = components.len();
+    let mut data = Vec::with_capacity(width * height * channels);
+    for y in 0..height {
+        for x in 0..width {
+            let mut sample = [0f32; 3];
+            for (i, component) in components.iter().enumerate() {
+                let stride = mcus_x * component.h * 8;
+                let (cx, cy) = (x * component.h / max_h, y * component.v / max_v);
+                sample[i] = f32::from(planes[i][cy * stride + cx]);
+            }
+            if channels == 1 {
+                data.push(sample[0] as u8);
+            } else if adobe_transform == Some(0) {
+                data.extend(sample.map(|value| value as u8));
+            } else {
+                let [luma, cb, cr] = sample;
+                let (cb, cr) = (cb - 128.0, cr - 128.0);
+                data.extend(
+                    [
+                        luma + 1.402 * cr,
+                        luma - 0.344_136 * cb - 0.714_136 * cr,
+                        luma + 1.772 * cb,
+                    ]
+                    .map(|value| value.round().clamp(0.0, 255.0) as u8),
+                );
+            }
+        }
+    }
+
+    Ok(Some((
+        Pixels {
+            width,
+            height,
+            channels,
+            data,
+        },
+        orientation,
+    )))
+}
+
+/// returns the samples for the dequantized coefficients of a block, in natural order.
+fn inverse_dct(block: &[f32; 64]) -> [u8; 64] {
+    let mut result = [0u8; 64];
+    // blocks with only a dc coefficient are common, and are all one value.
+    if block[1..].iter().all(|&value| value == 0.0) {
+        let value = (block[0] / 8.0 + 128.0).round().clamp(0.0, 255.0) as u8;
+        result.fill(value);
+        return result;
+    }
+    let mut rows = [0f32; 64];
+    for v in 0..8 {
+        for x in 0..8 {
+            rows[v * 8 + x] = (0..8).map(|u| DCT[x][u] * block[v * 8 + u]).sum();
+        }
+    }
+    for y in 0..8 {
+        for x in 0..8 {
+            let value: f32 = (0..8).map(|v| DCT[y][v] * rows[v * 8 + x]).sum();
+            result[y * 8 + x] = (value + 128.0).round().clamp(0.0, 255.0) as u8;
+        }
+    }
+
+    result
+}
+
+/// returns the coefficients for the samples of a block, in natural order.
+fn forward_dct(samples: &[f32; 64]) -> [f32; 64] {
+    let mut rows = [0f32; 64];
+    for y in 0..8 {
+        for u in 0..8 {
+            rows[y * 8 + u] = (0..8).map(|x| DCT[x][u] * samples[y * 8 + x]).sum();
+        }
+    }
+    let mut result = [0f32; 64];
+    for v in 0..8 {
+        for u in 0..8 {
+            result[v * 8 + u] = (0..8).map(|y| DCT[y][v] * rows[y * 8 + u]).sum();
+        }
+    }
+
+    result
+}
+
+/// the example tables from annex k of the jpeg spec, in natural order.
+#[rustfmt::skip]
+const LUMA_QUANTIZATION: [u16; 64] = [
+    16, 11, 10, 16, 24, 40, 51, 61,
+    12, 12, 14, 19, 26, 58, 60, 55,
+    14, 13, 16, 24, 40, 57, 69, 56,
+    14, 17, 22, 29, 51, 87, 80, 62,
+    18, 22, 37, 56, 68, 109, 103, 77,
+    24, 35, 55, 64, 81, 104, 113, 92,
+    49, 64, 78, 87, 103, 121, 120, 101,
+    72, 92, 95, 98, 112, 100, 103, 99,
+];
+#[rustfmt::skip]
+const CHROMA_QUANTIZATION: [u16; 64] = [
+    17, 18, 24, 47, 99, 99, 99, 99,
+    18, 21, 26, 66, 99, 99, 99, 99,
+    24, 26, 56, 99, 99, 99, 99, 99,
+    47, 66, 99, 99, 99, 99, 99, 99,
+    99, 99, 99, 99, 99, 99, 99, 99,
+    99, 99, 99, 99, 99, 99, 99, 99,
+    99, 99, 99, 99, 99, 99, 99, 99,
+    99, 99, 99, 99, 99, 99, 99, 99,
+];
+const LUMA_DC_COUNTS: [u8; 16] = [0, 1, 5, 1, 1, 1, 1, 1, 1, 0, 0, 0, 0, 0, 0, 0];
+const CHROMA_DC_COUNTS: [u8; 16] = [0, 3, 1, 1, 1, 1, 1, 1, 1, 1, 1, 0, 0, 0, 0, 0];
+const DC_VALUES: [u8; 12] = [0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11];
+const LUMA_AC_COUNTS: [u8; 16] = [0, 2, 1, 3, 3, 2, 4, 3, 5, 5, 4, 4, 0, 0, 1, 0x7D];
+#[rustfmt::skip]
+const LUMA_AC_VALUES: [u8; 162] = [
+    0x01, 0x02, 0x03, 0x00, 0x04, 0x11, 0x05, 0x12, 0x21, 0x31, 0x41, 0x06, 0x13, 0x51, 0x61, 0x07,
+    0x22, 0x71, 0x14, 0x32, 0x81, 0x91, 0xA1, 0x08, 0x23, 0x42, 0xB1, 0xC1, 0x15, 0x52, 0xD1, 0xF0,
+    0x24, 0x33, 0x62, 0x72, 0x82, 0x09, 0x0A, 0x16, 0x17, 0x18, 0x19, 0x1A, 0x25, 0x26, 0x27, 0x28,
+    0x29, 0x2A, 0x34, 0x35, 0x36, 0x37, 0x38, 0x39, 0x3A, 0x43, 0x44, 0x45, 0x46, 0x47, 0x48, 0x49,
+    0x4A, 0x53, 0x54, 0x55, 0x56, 0x57, 0x58, 0x59, 0x5A, 0x63, 0x64, 0x65, 0x66, 0x67, 0x68, 0x69,
+    0x6A, 0x73, 0x74, 0x75, 0x76, 0x77, 0x78, 0x79, 0x7A, 0x83, 0x84, 0x85, 0x86, 0x87, 0x88, 0x89,
+    0x8A, 0x92, 0x93, 0x94, 0x95, 0x96, 0x97, 0x98, 0x99, 0x9A, 0xA2, 0xA3, 0xA4, 0xA5, 0xA6, 0xA7,
+    0xA8, 0xA9, 0xAA, 0xB2, 0xB3, 0xB4, 0xB5, 0xB6, 0xB7, 0xB8, 0xB9, 0xBA, 0xC2, 0xC3, 0xC4, 0xC5,
+    0xC6, 0xC7, 0xC8, 0xC9, 0xCA, 0xD2, 0xD3, 0xD4, 0xD5, 0xD6, 0xD7, 0xD8, 0xD9, 0xDA, 0xE1, 0xE2,
+    0xE3, 0xE4, 0xE5, 0xE6, 0xE7, 0xE8, 0xE9, 0xEA, 0xF1, 0xF2, 0xF3, 0xF4, 0xF5, 0xF6, 0xF7, 0xF8,
+    0xF9, 0xFA,
+];
+const CHROMA_AC_COUNTS: [u8; 16] = [0, 2, 1, 2, 4, 4, 3, 4, 7, 5, 4, 4, 0, 1, 2, 0x77];
+#[rustfmt::skip]
+const CHROMA_AC_VALUES: [u8; 162] = [
+    0x00, 0x01, 0x02, 0x03, 0x11, 0x04, 0x05, 0x21, 0x31, 0x06, 0x12, 0x41, 0x51, 0x07, 0x61, 0x71,
+    0x13, 0x22, 0x32, 0x81, 0x08, 0x14, 0x42, 0x91, 0xA1, 0xB1, 0xC1, 0x09, 0x23, 0x33, 0x52, 0xF0,
+    0x15, 0x62, 0x72, 0xD1, 0x0A, 0x16, 0x24, 0x34, 0xE1, 0x25, 0xF1, 0x17, 0x18, 0x19, 0x1A, 0x26,
+    0x27, 0x28, 0x29, 0x2A, 0x35, 0x36, 0x37, 0x38, 0x39, 0x3A, 0x43, 0x44, 0x45, 0x46, 0x47, 0x48,
+    0x49, 0x4A, 0x53, 0x54, 0x55, 0x56, 0x57, 0x58, 0x59, 0x5A, 0x63, 0x64, 0x65, 0x66, 0x67, 0x68,
+    0x69, 0x6A, 0x73, 0x74, 0x75, 0x76, 0x77, 0x78, 0x79, 0x7A, 0x82, 0x83, 0x84, 0x85, 0x86, 0x87,
+    0x88, 0x89, 0x8A, 0x92, 0x93, 0x94, 0x95, 0x96, 0x97, 0x98, 0x99, 0x9A, 0xA2, 0xA3, 0xA4, 0xA5,
+    0xA6, 0xA7, 0xA8, 0xA9, 0xAA, 0xB2, 0xB3, 0xB4, 0xB5, 0xB6, 0xB7, 0xB8, 0xB9, 0xBA, 0xC2, 0xC3,
+    0xC4, 0xC5, 0xC6, 0xC7, 0xC8, 0xC9, 0xCA, 0xD2, 0xD3, 0xD4, 0xD5, 0xD6, 0xD7, 0xD8, 0xD9, 0xDA,
+    0xE2, 0xE3, 0xE4, 0xE5, 0xE6, 0xE7, 0xE8, 0xE9, 0xEA, 0xF2, 0xF3, 0xF4, 0xF5, 0xF6, 0xF7, 0xF8,
+    0xF9, 0xFA,
+];
+
+/// returns the code and length for each value in a huffman table.
+fn huffman_codes(counts: &[u8; 16], values: &[u8]) -> [(u16, u8); 256] {
+    let mut result = [(0, 0); 256];
+    let mut code = 0u16;
+    let mut values = values.iter();
+    for (len, &count) in (1..=16).zip(counts.iter()) {
+        for value in values.by_ref().take(count.into()) {
+            result[usize::from(*value)] = (code, len);
+            code += 1;
+        }
+        code <<= 1;
+    }
+    result
+}
+
+/// writes entropy-coded data, stuffing a zero byte after each 0xFF.
+struct BitWriter {
+    data: Vec<u8>,
+    bits: u32,
+    count: u32,
+}
+
+impl BitWriter {
+    fn write(&mut self, value: u16, len: u8) {
+        for i in (0..len).rev() {
+            self.bits = (self.bits << 1) | u32::from((value >> i) & 1);
+            self.count += 1;
+            if self.count == 8 {
+                let byte = self.bits as u8;
+                self.data.push(byte);
+                if byte == 0xFF {
+                    self.data.push(0);
+                }
+                self.bits = 0;
+                self.count = 0;
+            }
+        }
+    }
+
+    /// pads the last byte with ones.
+    fn finish(mut self) -> Vec<u8> {
+        while self.count != 0 {
+            self.write(1, 1);
+        }
+        self.data
+    }
+}
+
+/// encodes a gray or rgb image as a baseline jpeg, with 4:2:0 chroma subsampling, and an exif
+/// orientation if it had one.
+fn encode_jpeg(pixels: &Pixels, orientation: Option<u16>) -> eyre::Result<Vec<u8>> {
+    if !matches!(pixels.channels, 1 | 3) {
+        bail!("can’t encode {} channels as jpeg", pixels.channels);
+    }
+    let scale = if JPEG_QUALITY < 50 {
+        5000 / JPEG_QUALITY
+    } else {
+        200 - JPEG_QUALITY * 2
+    };
+    let quantize = |table: &[u16; 64]| {
+        table.map(|value| ((u32::from(value) * scale + 50) / 100).clamp(1, 255) as u16)
+    };
+    let tables = [quantize(&LUMA_QUANTIZATION), quantize(&CHROMA_QUANTIZATION)];
+
+    let mut result = vec![0xFF, 0xD8];
+    let mut segment = |marker: u8, payload: &[u8]| -> eyre::Result<()> {
+        result.extend([0xFF, marker]);
+        result.extend(u16::try_from(payload.len() + 2)?.to_be_bytes());
+        result.extend(payload);
+        Ok(())
+    };
+    // jfif 1.1, no density, no thumbnail.
+    segment(0xE0, b"JFIF\0\x01\x01\0\0\x01\0\x01\0\0")?;
+    if let Some(orientation) = orientation.filter(|&orientation| orientation != 1) {
+        // the segment we need is already made for `strip_exif`, marker and all.
+        segment(0xE1, &orientation_segment(orientation)[4..])?;
+    }
+    let color = pixels.channels == 3;
+    let mut dqt = vec![];
+    for (id, table) in tables.iter().enumerate().take(if color { 2 } else { 1 }) {
+        dqt.push(u8::try_from(id)?);
+        dqt.extend(ZIGZAG.map(|i| table[i] as u8));
+    }
+    segment(0xDB, &dqt)?;
+    let mut sof = vec![8];
+    sof.extend(u16::try_from(pixels.height)?.to_be_bytes());
+    sof.extend(u16::try_from(pixels.width)?.to_be_bytes());
+    if color {
+        sof.extend([3, 1, 0x22, 0, 2, 0x11, 1, 3, 0x11, 1]);
+    } else {
+        sof.extend([1, 1, 0x11, 0]);
+    }
+    segment(0xC0, &sof)?;
+    let mut dht = vec![];
+    let mut table = |class_and_id: u8, counts: &[u8; 16], values: &[u8]| {
+        dht.push(class_and_id);
+        dht.extend(counts);
+        dht.extend(values);
+    };
+    table(0x00, &LUMA_DC_COUNTS, &DC_VALUES);
+    table(0x10, &LUMA_AC_COUNTS, &LUMA_AC_VALUES);
+    if color {
+        table(0x01, &CHROMA_DC_COUNTS, &DC_VALUES);
+        table(0x11, &CHROMA_AC_COUNTS, &CHROMA_AC_VALUES);
+    }
+    segment(0xC4, &dht)?;
+    if color {
+        segment(0xDA, &[3, 1, 0x00, 2, 0x11, 3, 0x11, 0, 63, 0])?;
+    } else {
+        segment(0xDA, &[1, 1, 0x00, 0, 63, 0])?;
+    }
+
+    let luma_codes = (
+        huffman_codes(&LUMA_DC_COUNTS, &DC_VALUES),
+        huffman_codes(&LUMA_AC_COUNTS, &LUMA_AC_VALUES),
+    );
+    let chroma_codes = (
+        huffman_codes(&CHROMA_DC_COUNTS, &DC_VALUES),
+        huffman_codes(&CHROMA_AC_COUNTS, &CHROMA_AC_VALUES),
+    );
+    let mut writer = BitWriter {
+        data: vec![],
+        bits: 0,
+        count: 0,
+    };
+    let mut predictions = [0i32; 3];
+    let mut encode_block =
+        |samples: &[f32; 64], component: usize, writer: &mut BitWriter| -> eyre::Result<()> {
+            let (dc_codes, ac_codes) = if component == 0 {
+                &luma_codes
+            } else {
+                &chroma_codes
+            };
+            let table = &tables[component.min(1)];
+            let coefficients = forward_dct(samples);
+            let quantized = ZIGZAG.map(|i| (coefficients[i] / f32::from(table[i])).round() as i32);
+            let write_value =
+                |writer: &mut BitWriter, codes: &[(u16, u8); 256], symbol_high: u8, value: i32| {
+                    let size = (32 - value.unsigned_abs().leading_zeros()) as u8;
+                    let (code, len) = codes[usize::from(symbol_high | size)];
+                    writer.write(code, len);
+                    // negative values are written as their ones’ complement.
+                    let bits = if value < 0 { value - 1 } else { value };
+                    writer.write((bits & ((1 << size) - 1)) as u16, size);
+                };
+            let difference = quantized[0] - predictions[component];
+            predictions[component] = quantized[0];
+            write_value(writer, dc_codes, 0, difference);
+            let mut run = 0;
+            for &value in &quantized[1..] {
+                if value == 0 {
+                    run += 1;
+                    continue;
+                }
+                while run >= 16 {
+                    let (code, len) = ac_codes[0xF0];
+                    writer.write(code, len);
+                    run -= 16;
+                }
+                write_value(writer, ac_codes, run << 4, value);
+                run = 0;
+            }
+            if run > 0 {
+                let (code, len) = ac_codes[0x00];
+                writer.write(code, len);
+            }
+            Ok(())
+        };
+
+    // samples outside the image repeat the last row or column.
+    let sample = |x: usize, y: usize| -> &[u8] {
+        let (x, y) = (x.min(pixels.width - 1), y.min(pixels.height - 1));
+        &pixels.data[(y * pixels.width + x) * pixels.channels..][..pixels.channels]
+    };
+    let ycbcr = |pixel: &[u8]| -> [f32; 3] {
+        if let &[gray] = pixel {
+            return [f32::from(gray), 128.0, 128.0];
+        }
+        let [r, g, b] = [0, 1, 2].map(|i| f32::from(pixel[i]));
+        [
+            0.299 * r + 0.587 * g + 0.114 * b,
+            -0.168_736 * r - 0.331_264 * g + 0.5 * b + 128.0,
+            0.5 * r - 0.418_688 * g - 0.081_312 * b + 128.0,
+        ]
+    };
+    let mcu_size = if color { 16 } else { 8 };
+    let mut block = [0f32; 64];
+    for mcu_y in (0..pixels.height).step_by(mcu_size) {
+        for mcu_x in (0..pixels.width).step_by(mcu_size) {
+            for (block_x, block_y) in
+                [(0, 0), (8, 0), (0, 8), (8, 8)]
+                    .into_iter()
+                    .take(if color { 4 } else { 1 })
+            {
+                for (i, value) in block.iter_mut().enumerate() {
+                    let pixel = sample(mcu_x + block_x + i % 8, mcu_y + block_y + i / 8);
+                    *value = ycbcr(pixel)[0] - 128.0;
+                }
+                encode_block(&block, 0, &mut writer)?;
+            }
+            if color {
+                for component in 1..=2 {
+                    // average each 2×2 square of chroma samples.
+                    for (i, value) in block.iter_mut().enumerate() {
+                        let (x, y) = (mcu_x + i % 8 * 2, mcu_y + i / 8 * 2);
+                        let sum: f32 = [(0, 0), (1, 0), (0, 1), (1, 1)]
+                            .map(|(dx, dy)| ycbcr(sample(x + dx, y + dy))[component])
+                            .iter()
+                            .sum();
+                        *value = sum / 4.0 - 128.0;
+                    }
+                    encode_block(&block, component, &mut writer)?;
+                }
+            }
+        }
+    }
+    result.extend(writer.finish());
+    result.extend([0xFF, 0xD9]);
+
+    Ok(result)
+}
+
+#[cfg(test)]
+mod test {
+    use jane_eyre::eyre;
+
+    use crate::downscale::{
+        apply_jpeg_orientation, box_weights, decode_jpeg, decode_png, downscale_image, encode_jpeg,
+        encode_png, orient, resize, HuffmanTable, Pixels, CHROMA_AC_COUNTS, CHROMA_AC_VALUES,
+        LUMA_AC_COUNTS, LUMA_AC_VALUES,
+    };
+
+    fn gradient(width: usize, height: usize, channels: usize) -> Pixels {
+        let mut data = vec![];
+        for y in 0..height {
+            for x in 0..width {
+                let pixel = [x * 255 / width, y * 255 / height, 128, 255];
+                data.extend(
+                    pixel[4 - channels.max(1)..]
+                        .iter()
+                        .map(|&value| value as u8),
+                );
+            }
+        }
+        Pixels {
+            width,
+            height,
+            channels,
+            data,
+        }
+    }
+
+    fn max_difference(a: &Pixels, b: &Pixels) -> u8 {
+        assert_eq!(
+            (a.width, a.height, a.channels),
+            (b.width, b.height, b.channels)
+        );
+        a.data
+            .iter()
+            .zip(b.data.iter())
+            .map(|(a, b)| a.abs_diff(*b))
+            .max()
+            .unwrap_or(0)
+    }
+
+    #[test]
+    fn test_box_weights() {
+        assert_eq!(
+            box_weights(4, 2),
+            [vec![(0, 0.5), (1, 0.5)], vec![(2, 0.5), (3, 0.5)]]
+        );
+        assert_eq!(
+            box_weights(3, 2),
+            [
+                vec![(0, 2.0 / 3.0), (1, 1.0 / 3.0)],
+                vec![(1, 1.0 / 3.0), (2, 2.0 / 3.0)]
+            ]
+        );
+    }
+
+    #[test]
+    fn test_resize() {
+        let pixels = Pixels {
+            width: 2,
+            height: 2,
+            channels: 4,
+            data: vec![255, 0, 0, 255, 0, 0, 255, 0, 255, 0, 0, 255, 0, 0, 0, 0],
+        };
+        // transparent pixels don’t count towards the color.
+        assert_eq!(
+            resize(&pixels, 1, 1),
+            Pixels {
+                width: 1,
+                height: 1,
+                channels: 4,
+                data: vec![255, 0, 0, 128],
+            }
+        );
+    }
+
+    #[test]
+    fn test_png_round_trip() -> eyre::Result<()> {
+        for channels in 1..=4 {
+            let pixels = gradient(37, 21, channels);
+            assert_eq!(decode_png(&encode_png(&pixels)?)?, Some(pixels));
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_jpeg_round_trip() -> eyre::Result<()> {
+        for channels in [1, 3] {
+            let pixels = gradient(37, 21, channels);
+            let (decoded, orientation) =
+                decode_jpeg(&encode_jpeg(&pixels, Some(6))?)?.expect("baseline jpeg");
+            assert_eq!(orientation, Some(6));
+            assert!(max_difference(&pixels, &decoded) < 12);
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_orient() -> eyre::Result<()> {
+        // 1 2 3
+        // 4 5 6
+        let pixels = Pixels {
+            width: 3,
+            height: 2,
+            channels: 1,
+            data: vec![1, 2, 3, 4, 5, 6],
+        };
+        for (orientation, width, data) in [
+            (1, 3, [1, 2, 3, 4, 5, 6]),
+            (2, 3, [3, 2, 1, 6, 5, 4]),
+            (3, 3, [6, 5, 4, 3, 2, 1]),
+            (4, 3, [4, 5, 6, 1, 2, 3]),
+            (5, 2, [1, 4, 2, 5, 3, 6]),
+            (6, 2, [4, 1, 5, 2, 6, 3]),
+            (7, 2, [6, 3, 5, 2, 4, 1]),
+            (8, 2, [3, 6, 2, 5, 1, 4]),
+        ] {
+            let oriented = orient(&pixels, orientation).expect("valid orientation");
+            assert_eq!((oriented.width, oriented.height), (width, 6 / width));
+            assert_eq!(oriented.data, data);
+        }
+        assert_eq!(orient(&pixels, 9), None);
+
+        let jpeg = encode_jpeg(&gradient(37, 21, 3), Some(6))?;
+        let (rotated, orientation) =
+            decode_jpeg(&apply_jpeg_orientation(&jpeg, 6)?.expect("baseline jpeg"))?
+                .expect("baseline jpeg");
+        assert_eq!((rotated.width, rotated.height), (21, 37));
+        assert_eq!(orientation, None);
+        let expected = orient(&gradient(37, 21, 3), 6).expect("valid orientation");
+        assert!(max_difference(&expected, &rotated) < 12);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_huffman_tables() -> eyre::Result<()> {
+        for (counts, values) in [
+            (LUMA_AC_COUNTS, LUMA_AC_VALUES),
+            (CHROMA_AC_COUNTS, CHROMA_AC_VALUES),
+        ] {
+            let mut expected = vec![0x00, 0xF0];
+            expected.extend((0..16).flat_map(|run| (1..=10).map(move |size| run << 4 | size)));
+            let mut actual = values.to_vec();
+            expected.sort();
+            actual.sort();
+            assert_eq!(actual, expected);
+            HuffmanTable::new(&counts, &values)?;
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_downscale_image() -> eyre::Result<()> {
+        let png = encode_png(&gradient(300, 200, 4))?;
+        assert_eq!(downscale_image(&png, 300)?, None);
+        let scaled = decode_png(&downscale_image(&png, 150)?.expect("too big"))?.expect("png");
+        assert_eq!((scaled.width, scaled.height), (150, 100));
+        assert!(max_difference(&scaled, &resize(&gradient(300, 200, 4), 150, 100)) == 0);
+
+        let jpeg = encode_jpeg(&gradient(200, 301, 3), None)?;
+        let scaled = downscale_image(&jpeg, 100)?.expect("too big");
+        let (scaled, _) = decode_jpeg(&scaled)?.expect("baseline jpeg");
+        assert_eq!((scaled.width, scaled.height), (66, 100));
+        assert!(max_difference(&scaled, &gradient(66, 100, 3)) < 16);
+
+        // not an image we can scale.
+        assert_eq!(downscale_image(b"GIF89a", 100)?, None);
+        assert!(downscale_image(&png[..50], 100).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_decode_malformed() -> eyre::Result<()> {
+        // a dc huffman table whose symbols are all too big for a dc difference.
+        let mut jpeg = encode_jpeg(&gradient(16, 16, 3), None)?;
+        let dht = jpeg
+            .windows(5)
+            .position(|window| window[..2] == [0xFF, 0xC4] && window[4] == 0x00)
+            .expect("has a dc table");
+        let counts = &jpeg[dht + 5..][..16];
+        let total = counts
+            .iter()
+            .map(|&count| usize::from(count))
+            .sum::<usize>();
+        jpeg[dht + 21..][..total].fill(0x20);
+        assert!(decode_jpeg(&jpeg).is_err());
+
+        // bytes changed or cut off anywhere are errors, or decode to something, but never panic.
+        let inputs = [
+            encode_jpeg(&gradient(24, 16, 3), Some(6))?,
+            encode_jpeg(&gradient(9, 9, 1), None)?,
+            encode_png(&gradient(12, 8, 4))?,
+        ];
+        for input in inputs {
+            for i in (0..input.len()).step_by(5) {
+                for value in [0x00, 0x7F, 0xFF] {
+                    let mut changed = input.clone();
+                    changed[i] = value;
+                    _ = downscale_image(&changed, 4);
+                }
+                _ = downscale_image(&input[..i], 4);
+            }
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,101 @@
+//! custom emoji in post titles and tags, like `:eggbug:`, from the `emoji` setting.
+
+use askama::{Html, MarkupDisplay};
+
+use crate::SETTINGS;
+
+/// where the text is going, which decides whether emoji can be images.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum EmojiContext {
+    /// html content, where emoji become `<img>` elements.
+    Html,
+    /// plain text, like `<title>`, `<meta content>`, or atom `<title>` and `<category>`, where
+    /// emoji stay as their shortcodes.
+    Text,
+}
+
+/// renders the emoji shortcodes in the text for the given context. for [`EmojiContext::Html`]
+/// the result is escaped html, so use it with `|safe`. for [`EmojiContext::Text`] the result is
+/// still plain text, so let the template escape it as usual.
+pub fn render_emoji(text: &str, context: EmojiContext) -> String {
+    render_emoji_with(text, context, |shortcode| SETTINGS.emoji_url(shortcode))
+}
+
+fn render_emoji_with<'url>(
+    text: &str,
+    context: EmojiContext,
+    emoji_url: impl Fn(&str) -> Option<&'url str>,
+) -> String {
+    if context == EmojiContext::Text {
+        return text.to_owned();
+    }
+
+    let mut result = String::default();
+    let mut rest = text;
+    while let Some(start) = rest.find(':') {
+        let (before, after) = rest.split_at(start);
+        result += &escape(before);
+        let after = &after[1..];
+        let shortcode = after
+            .find(':')
+            .map(|end| &after[..end])
+            .filter(|shortcode| is_valid_shortcode(shortcode));
+        match shortcode.and_then(|shortcode| Some((shortcode, emoji_url(shortcode)?))) {
+            Some((shortcode, url)) => {
+                rest = &after[shortcode.len() + 1..];
+                let shortcode = escape(&format!(":{shortcode}:"));
+                result += &format!(
+                    r#"<img class="emoji" src="{}" alt="{shortcode}" title="{shortcode}">"#,
+                    escape(url),
+                );
+            }
+            None => {
+                // the closing colon might start another shortcode.
+                result += ":";
+                rest = after;
+            }
+        }
+    }
+    result += &escape(rest);
+
+    result
+}
+
+/// shortcodes can have letters, digits, `_`, `-`, and `+`, like `:eggbug:` or `:+1:`.
+pub fn is_valid_shortcode(shortcode: &str) -> bool {
+    !shortcode.is_empty()
+        && shortcode
+            .chars()
+            .all(|c| c.is_alphanumeric() || matches!(c, '_' | '-' | '+'))
+}
+
+fn escape(text: &str) -> String {
+    MarkupDisplay::new_unsafe(text, Html).to_string()
+}
+
+#[test]
+fn test_render_emoji() {
+    let emoji_url = |shortcode: &str| match shortcode {
+        "eggbug" => Some("attachments/emoji/eggbug.png"),
+        "+1" => Some("attachments/emoji/plus1.png"),
+        _ => None,
+    };
+    let title = "hello :eggbug: & 10:30 :nope: :+1::eggbug:";
+    assert_eq!(
+        render_emoji_with(title, EmojiContext::Html, emoji_url),
+        concat!(
+            r#"hello <img class="emoji" src="attachments/emoji/eggbug.png" alt=":eggbug:" title=":eggbug:">"#,
+            r#" &amp; 10:30 :nope: "#,
+            r#"<img class="emoji" src="attachments/emoji/plus1.png" alt=":+1:" title=":+1:">"#,
+            r#"<img class="emoji" src="attachments/emoji/eggbug.png" alt=":eggbug:" title=":eggbug:">"#,
+        )
+    );
+    assert_eq!(
+        render_emoji_with(title, EmojiContext::Text, emoji_url),
+        title
+    );
+    assert_eq!(
+        render_emoji_with("<b>:eggbug", EmojiContext::Html, emoji_url),
+        "&lt;b&gt;:eggbug"
+    );
+}
@@ -0,0 +1,69 @@
+//! typed errors for library users who want to handle some failures differently, like retrying
+//! only on network errors. so far these are only used by [`crate::attachments`]; everything else
+//! returns [`eyre::Result`].
+//!
+//! [`Error`] converts to and from [`eyre::Report`], so `?` works both ways, and the cli prints it
+//! the same as any other error.
+
+use jane_eyre::eyre;
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    /// reading or writing a file failed.
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+
+    /// a request failed, like a timeout, a connection error, or an error status. these are
+    /// usually worth retrying later.
+    #[error(transparent)]
+    Network(#[from] reqwest::Error),
+
+    /// some input was malformed, like a redirect target or a filename that isn’t unicode.
+    #[error("{0}")]
+    Parse(String),
+
+    /// something we needed wasn’t there, like a file in a cached attachment directory, or a
+    /// redirect from the cohost attachment endpoint.
+    #[error("{0}")]
+    MissingReference(String),
+
+    /// an attachment was not downloaded, because its host is not allowed by
+    /// `[imported_attachment_hosts]`.
+    #[error("host not allowed by imported_attachment_hosts: {0}")]
+    BlockedHost(String),
+
+    /// an attachment was not cached, and was not downloaded, because we are in offline mode.
+    #[error("not cached, and not downloading in offline mode: {0}")]
+    Offline(String),
+
+    /// any other error.
+    #[error(transparent)]
+    Other(#[from] eyre::Report),
+}
+
+impl Error {
+    /// returns true iff the error is a [`Error::Network`] error.
+    pub fn is_network(&self) -> bool {
+        matches!(self, Self::Network(_))
+    }
+
+    /// returns true iff the error is a [`Error::Offline`] error.
+    pub fn is_offline(&self) -> bool {
+        matches!(self, Self::Offline(_))
+    }
+}
+
+#[test]
+fn test_error() {
+    let error = Error::from(std::io::Error::new(std::io::ErrorKind::NotFound, "oh no"));
+    assert!(matches!(error, Error::Io(_)));
+    assert!(!error.is_network());
+
+    // `?` into eyre keeps the message, so the cli prints the same thing.
+    let report = eyre::Report::from(Error::MissingReference("directory is empty".to_owned()));
+    assert_eq!(report.to_string(), "directory is empty");
+    let error = Error::from(eyre::eyre!("something else"));
+    assert_eq!(error.to_string(), "something else");
+}
@@ -1,16 +1,30 @@
-use std::{cmp::Ordering, collections::BTreeSet, fs::File, io::Read, sync::LazyLock};
+use std::{
+    cmp::Ordering,
+    collections::{BTreeMap, BTreeSet, HashMap},
+    fs::{exists, metadata, File},
+    io::Read,
+    sync::{LazyLock, Mutex},
+    time::SystemTime,
+};
 
 use askama::Template;
 use jane_eyre::eyre::{self, Context, OptionExt};
 use markup5ever_rcdom::RcDom;
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
+use tracing::warn;
 use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt, EnvFilter};
 
 use crate::{
+    attachments::media_type,
+    command::import::cache_remote_reference,
+    date::{display_day, SortDate},
     dom::serialize_html_fragment,
-    meta::extract_metadata,
+    meta::{
+        add_image_dimensions, extract_metadata, inline_small_images, scope_style_elements,
+        use_scaled_images,
+    },
     path::{PostsPath, SitePath},
-    settings::Settings,
+    settings::{PostStyles, Settings, ThreadPostOrder},
 };
 
 pub mod command {
@@ -18,21 +32,39 @@ pub mod command {
     pub mod cohost2autost;
     pub mod cohost2json;
     pub mod cohost_archive;
+    pub mod doctor;
+    pub mod export;
+    pub mod gc;
     pub mod import;
+    pub mod import_post;
     pub mod new;
+    pub mod prefetch;
     pub mod render;
     pub mod server;
+    pub mod stats;
+    pub mod validate_config;
 }
 
+pub mod archive;
 pub mod attachments;
 pub mod cohost;
+pub mod comments;
 pub mod css;
+pub mod date;
 pub mod dom;
+pub mod downscale;
+pub mod emoji;
+pub mod error;
 pub mod meta;
 pub mod migrations;
+pub mod og_image;
 pub mod output;
 pub mod path;
+pub mod progress;
+pub mod render_cache;
+pub mod routing;
 pub mod settings;
+pub mod transform;
 
 pub static SETTINGS: LazyLock<Settings> = LazyLock::new(|| {
     #[cfg(test)]
@@ -49,14 +81,108 @@ pub static SETTINGS: LazyLock<Settings> = LazyLock::new(|| {
 pub struct PostMeta {
     pub archived: Option<String>,
     pub references: Vec<PostsPath>,
+    /// `<link rel=references>` to posts on other sites, by url.
+    pub remote_references: Vec<String>,
     pub title: Option<String>,
     pub published: Option<String>,
+    pub updated: Option<String>,
     pub author: Option<Author>,
+    /// other authors of the post, for collaborations, like `<link rel="contributor">`.
+    pub contributors: Vec<Author>,
     pub tags: Vec<String>,
     pub is_transparent_share: bool,
+    pub content_warning: Option<String>,
+    /// the post content was removed when archiving, like logged-in-only chosts.
+    pub is_redacted: bool,
+    /// the filename of the post’s page without `.html`, like `my-post`, instead of the filename of
+    /// the post. the page at the old filename redirects here.
+    pub slug: Option<String>,
+    /// bcp 47 language tag for the post content, like `en` or `ja-JP`.
+    pub lang: Option<String>,
+    /// the name of the series the post is a part of, like `<meta name="series" content="my
+    /// tutorial">`. threads in a series link to the previous and next parts, in the order of
+    /// `series_index`.
+    pub series: Option<String>,
+    /// the position of the post in its `series`, starting at 1.
+    pub series_index: Option<usize>,
+    /// the post has a page, but is not in any collection, tag page, or feed.
+    pub is_unlisted: bool,
+    /// the post is one you liked on cohost, not one you wrote or shared (`cohost2autost --liked`).
+    /// liked threads are only in the `liked` collection, and only if `publish_liked_posts`.
+    pub is_liked: bool,
+    /// metadata that autost doesn’t model, like `<meta name="extra:location">`, by key. shown in
+    /// the post footer if the key is in `footer_extra_meta`.
+    pub extra: BTreeMap<String, String>,
+    /// historical engagement counts from the original platform, like `<meta name="likes">`.
+    pub engagement: Engagement,
+    /// notes about edits to the post, like `<meta name="edit" date="2024-12-31T12:00Z"
+    /// content="fixed a typo">`, shown at the bottom of the post in the order they were written.
+    /// the newest note also counts as when the post was `updated`.
+    pub edits: Vec<EditNote>,
+    /// where the post was cross-posted to on the fediverse, like
+    /// `<meta name="fediverse_url" content="https://example.social/@me/1">`, so replies and
+    /// boosts can happen there.
+    pub fediverse_url: Option<String>,
+}
+
+/// a dated note about an edit to a post, for the edit log at the bottom of the post.
+#[derive(Clone, Debug, PartialEq)]
+pub struct EditNote {
+    /// when the edit was made, in the same format as `published`.
+    pub date: String,
+    /// markdown or html, which is sanitised, like `"fixed a *typo*"`.
+    pub note: String,
+}
+
+impl EditNote {
+    /// returns the `note` rendered as markdown and sanitised, without the `<p>` around it if it
+    /// is only one paragraph.
+    pub fn note_html(&self) -> String {
+        let html = ammonia::clean(&render_markdown(&self.note));
+        let html = html.trim();
+        match html
+            .strip_prefix("<p>")
+            .and_then(|html| html.strip_suffix("</p>"))
+        {
+            Some(inner) if !inner.contains("<p>") => inner.to_owned(),
+            _ => html.to_owned(),
+        }
+    }
+
+    /// returns the day of the edit, like `2024-12-31`.
+    pub fn display_day(&self) -> String {
+        display_day(&self.date).unwrap_or_else(|| self.date.clone())
+    }
+}
+
+/// engagement counts from an archive, shown in post footers if `show_engagement_counts` is set.
+/// these are a record of the past, so they never change, and missing counts are not zero.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Engagement {
+    pub likes: Option<usize>,
+    pub comments: Option<usize>,
 }
 
-#[derive(Clone, Debug, Deserialize, PartialEq)]
+impl Engagement {
+    /// returns the counts as text, like “3 likes, 1 comment”, or None if there are no counts.
+    pub fn summary(&self) -> Option<String> {
+        let count = |count: Option<usize>, singular: &str, plural: &str| {
+            count.map(|count| match count {
+                1 => format!("1 {singular}"),
+                count => format!("{count} {plural}"),
+            })
+        };
+        let counts = [
+            count(self.likes, "like", "likes"),
+            count(self.comments, "comment", "comments"),
+        ];
+        let counts = counts.into_iter().flatten().collect::<Vec<_>>();
+
+        Some(counts.join(", ")).filter(|summary| !summary.is_empty())
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
 pub struct Author {
     pub href: String,
     pub name: String,
@@ -70,6 +196,16 @@ pub struct ExtractedPost {
     pub needs_attachments: BTreeSet<SitePath>,
     pub og_image: Option<String>,
     pub og_description: String,
+    /// audio and video attachments, in order.
+    pub media: Vec<SitePath>,
+}
+
+/// an audio or video attachment, for `<link rel="enclosure">` in atom feeds.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Enclosure {
+    pub href: String,
+    pub mime_type: &'static str,
+    pub length: u64,
 }
 
 #[derive(Clone, Debug)]
@@ -79,7 +215,11 @@ pub struct Thread {
     pub meta: PostMeta,
     pub needs_attachments: BTreeSet<SitePath>,
     pub og_image: Option<String>,
+    /// the card to generate for `og_image`, for `[og_images]`, if the thread had no image.
+    pub generated_og_image: Option<SitePath>,
     pub og_description: Option<String>,
+    /// `references` that were skipped because the referenced post does not exist.
+    pub broken_references: Vec<PostsPath>,
 }
 
 #[derive(Clone, Debug)]
@@ -91,9 +231,20 @@ pub struct TemplatedPost {
     pub needs_attachments: BTreeSet<SitePath>,
     pub og_image: Option<String>,
     pub og_description: String,
+    /// audio and video attachments, in order.
+    pub media: Vec<SitePath>,
 }
 
 impl PostMeta {
+    pub fn has_references(&self) -> bool {
+        !self.references.is_empty() || !self.remote_references.is_empty()
+    }
+
+    /// the author (if any), then the contributors.
+    pub fn authors(&self) -> impl Iterator<Item = &Author> {
+        self.author.iter().chain(self.contributors.iter())
+    }
+
     pub fn is_main_self_author(&self, settings: &Settings) -> bool {
         self.author
             .as_ref()
@@ -141,8 +292,20 @@ fn test_is_main_self_author() -> eyre::Result<()> {
 }
 
 impl Thread {
+    /// newest first, then threads published at the same time by post id (highest first), then
+    /// by href. each key is totally ordered, so this is a total order, and the order of threads
+    /// never depends on the order they were loaded in.
     pub fn reverse_chronological(p: &Thread, q: &Thread) -> Ordering {
-        p.meta.published.cmp(&q.meta.published).reverse()
+        let post_id = |thread: &Thread| thread.path.as_ref().and_then(|path| path.post_id());
+        let href = |thread: &Thread| thread.path.as_ref().map(|path| path.references_url());
+        let p_date = SortDate::new(p.meta.published.as_deref());
+        let q_date = SortDate::new(q.meta.published.as_deref());
+
+        p_date
+            .cmp(&q_date)
+            .reverse()
+            .then_with(|| post_id(p).cmp(&post_id(q)).reverse())
+            .then_with(|| href(p).cmp(&href(q)))
     }
 
     pub fn url_for_original_path(&self) -> eyre::Result<Option<String>> {
@@ -151,35 +314,49 @@ impl Thread {
         Ok(result)
     }
 
-    pub fn url_for_html_permalink(&self) -> eyre::Result<Option<String>> {
+    /// returns the path of the thread’s page, which respects the `slug` of the main post, if any.
+    pub fn rendered_path(&self) -> eyre::Result<Option<SitePath>> {
         let result = self
             .path
             .as_ref()
-            .map(|path| path.rendered_path())
+            .map(|path| path.rendered_path_with_slug(self.meta.slug.as_deref()))
             .transpose()?
-            .flatten()
-            .map(|path| path.internal_url());
+            .flatten();
 
         Ok(result)
     }
 
-    pub fn url_for_atom_permalink(&self) -> eyre::Result<Option<String>> {
+    /// returns the path of the thread’s print page, which respects the `slug` of the main post.
+    pub fn rendered_print_path(&self) -> eyre::Result<Option<SitePath>> {
         let result = self
             .path
             .as_ref()
-            .map(|path| path.rendered_path())
+            .map(|path| path.rendered_print_path_with_slug(self.meta.slug.as_deref()))
             .transpose()?
-            .flatten()
-            .map(|path| path.external_url());
+            .flatten();
+
+        Ok(result)
+    }
+
+    pub fn url_for_html_permalink(&self) -> eyre::Result<Option<String>> {
+        let result = self.rendered_path()?.map(|path| path.internal_url());
+
+        Ok(result)
+    }
+
+    pub fn url_for_atom_permalink(&self) -> eyre::Result<Option<String>> {
+        let result = self.rendered_path()?.map(|path| path.external_url());
 
         Ok(result)
     }
 
     pub fn atom_feed_entry_id(&self) -> eyre::Result<Option<String>> {
+        // not `rendered_path`, so that adding a slug or enabling `clean_urls` doesn’t make the
+        // post look new to readers.
         let result = self
             .path
             .as_ref()
-            .map(|path| path.rendered_path())
+            .map(|path| path.html_rendered_path_with_slug(None))
             .transpose()?
             .flatten()
             .map(|path| path.atom_feed_entry_id());
@@ -191,10 +368,14 @@ impl Thread {
         self.needs_attachments.iter()
     }
 
-    pub fn posts_in_thread(&self) -> impl Iterator<Item = PostInThread> + '_ {
+    /// returns the posts in the order they are shown. [`Thread::posts`] is always oldest first,
+    /// with the main post last, but the posts are shown in reverse with
+    /// [`ThreadPostOrder::NewestFirst`]. transparent shares stay next to the posts they share,
+    /// just on the other side.
+    pub fn posts_in_thread(&self, order: ThreadPostOrder) -> Vec<PostInThread> {
         let len = self.posts.len();
-
-        self.posts
+        let mut result = self
+            .posts
             .iter()
             .cloned()
             .enumerate()
@@ -211,11 +392,115 @@ impl Thread {
                     }
                 }
             })
+            .collect::<Vec<_>>();
+        if order == ThreadPostOrder::NewestFirst {
+            result.reverse();
+        }
+
+        result
+    }
+
+    /// returns how many of the oldest posts to collapse, such that only the `expanded` newest
+    /// posts with content before the main post (and any transparent shares after them) are left
+    /// expanded. the main post is never collapsed.
+    pub fn collapsed_ancestors(&self, expanded: usize) -> usize {
+        let ancestors = &self.posts[..self.posts.len().saturating_sub(1)];
+        let mut remaining = expanded;
+        for (i, post) in ancestors.iter().enumerate().rev() {
+            if !post.meta.is_transparent_share {
+                if remaining == 0 {
+                    return i + 1;
+                }
+                remaining -= 1;
+            }
+        }
+
+        0
+    }
+
+    /// returns the audio and video attachments in the last post that is not a transparent share,
+    /// skipping any attachments that are not cached.
+    pub fn enclosures(&self) -> eyre::Result<Vec<Enclosure>> {
+        let Some(post) = self
+            .posts
+            .iter()
+            .rev()
+            .find(|post| !post.meta.is_transparent_share)
+        else {
+            return Ok(vec![]);
+        };
+
+        let mut result = vec![];
+        for site_path in post.media.iter() {
+            let Some(mime_type) = media_type(site_path.filename()) else {
+                continue;
+            };
+            let Some(attachments_path) = site_path.attachments_path()? else {
+                continue;
+            };
+            let Ok(metadata) = metadata(&attachments_path) else {
+                warn!(
+                    ?attachments_path,
+                    "skipping enclosure for missing attachment"
+                );
+                continue;
+            };
+            result.push(Enclosure {
+                href: SETTINGS.external_base_url_absolutise(&site_path.base_relative_url()),
+                mime_type,
+                length: metadata.len(),
+            });
+        }
+
+        Ok(result)
+    }
+
+    /// the authors to attribute the thread to in feeds. for transparent shares, these are the
+    /// authors of the shared post, since the sharer didn’t write anything.
+    pub fn feed_authors(&self) -> Vec<&Author> {
+        let result = self
+            .posts
+            .iter()
+            .rev()
+            .find(|post| !post.meta.is_transparent_share)
+            .map(|post| post.meta.authors().collect::<Vec<_>>())
+            .unwrap_or_default();
+        if result.is_empty() {
+            self.meta.authors().collect()
+        } else {
+            result
+        }
+    }
+
+    /// returns true iff the thread is a transparent share, that is, the last post is a
+    /// transparent share, so we added nothing but maybe tags.
+    pub fn is_transparent_share(&self) -> bool {
+        self.posts
+            .last()
+            .is_some_and(|post| post.meta.is_transparent_share)
     }
 
     pub fn main_post(&self) -> eyre::Result<&TemplatedPost> {
         self.posts.last().ok_or_eyre("thread has no posts")
     }
+
+    /// returns true iff the thread is one post with a title, but no text or media in its body,
+    /// for `title_only_posts`.
+    pub fn is_title_only(&self) -> bool {
+        let [post] = &self.posts[..] else {
+            return false;
+        };
+        !post.meta.is_transparent_share
+            && !post.meta.is_redacted
+            && post
+                .meta
+                .title
+                .as_ref()
+                .is_some_and(|t| !t.trim().is_empty())
+            && post.og_description.trim().is_empty()
+            && post.og_image.is_none()
+            && post.media.is_empty()
+    }
 }
 
 pub struct PostInThread {
@@ -240,16 +525,43 @@ impl TryFrom<TemplatedPost> for Thread {
             .collect();
         let resolved_tags = SETTINGS.resolve_tags(combined_tags);
         post.meta.tags = resolved_tags;
-        let mut meta = post.meta.clone();
 
-        let mut posts = post
-            .meta
-            .references
-            .iter()
-            .map(|path| TemplatedPost::load(path))
-            .collect::<Result<Vec<_>, _>>()?;
+        // skip references to posts that don’t exist, rather than failing the whole thread.
+        // `autost render --strict` turns these back into errors.
+        let mut posts = vec![];
+        let mut broken_references = vec![];
+        for url in post.meta.remote_references.iter() {
+            posts.push(TemplatedPost::load_remote_reference(url)?);
+        }
+        for reference in post.meta.references.iter() {
+            if !exists(reference)? {
+                warn!(
+                    ?path,
+                    ?reference,
+                    "skipping broken reference to missing post"
+                );
+                broken_references.push(reference.clone());
+                continue;
+            }
+            posts.push(TemplatedPost::load(reference)?);
+        }
         posts.push(post);
 
+        Thread::from_posts(posts, broken_references)
+    }
+}
+
+impl Thread {
+    /// creates a thread from the posts it references (if any) and the main post, in that order,
+    /// without loading anything.
+    pub fn from_posts(
+        posts: Vec<TemplatedPost>,
+        broken_references: Vec<PostsPath>,
+    ) -> eyre::Result<Self> {
+        let main_post = posts.last().ok_or_eyre("thread has no posts")?;
+        let path = main_post.path.clone();
+        let mut meta = main_post.meta.clone();
+
         // TODO: skip threads with other authors?
         // TODO: skip threads with private or logged-in-only authors?
         // TODO: gate sensitive posts behind an interaction?
@@ -263,15 +575,20 @@ impl TryFrom<TemplatedPost> for Thread {
         meta.title = last_non_transparent_share_post.map(|post| {
             if let Some(title) = post.meta.title.clone().filter(|t| !t.is_empty()) {
                 title
-            } else if let Some(author) = post.meta.author.as_ref() {
-                format!("untitled post by {}", author.display_handle)
             } else {
-                "untitled post".to_owned()
+                post.untitled_title()
             }
         });
         let og_image = last_non_transparent_share_post
             .and_then(|post| post.og_image.as_deref())
-            .map(|og_image| SETTINGS.base_url_relativise(og_image));
+            .map(|og_image| SETTINGS.external_base_url_absolutise(og_image));
+        let generated_og_image = match (&og_image, &path, &SETTINGS.og_images) {
+            (None, Some(path), Some(_)) => {
+                path.rendered_og_image_path_with_slug(meta.slug.as_deref())?
+            }
+            _ => None,
+        };
+        let og_image = og_image.or_else(|| generated_og_image.as_ref().map(|p| p.external_url()));
         let og_description =
             last_non_transparent_share_post.map(|post| post.og_description.to_owned());
 
@@ -287,13 +604,60 @@ impl TryFrom<TemplatedPost> for Thread {
             meta,
             needs_attachments,
             og_image,
+            generated_og_image,
             og_description,
+            broken_references,
         })
     }
 }
 
 impl TemplatedPost {
+    /// loads and filters the post, or returns a copy of the post from a previous call, if the file
+    /// has not been modified since then. posts are often loaded many times in one render, because
+    /// they are loaded again for each thread that references them.
     pub fn load(path: &PostsPath) -> eyre::Result<Self> {
+        static CACHE: LazyLock<Mutex<HashMap<PostsPath, (SystemTime, TemplatedPost)>>> =
+            LazyLock::new(Mutex::default);
+
+        let modified = metadata(path)?.modified()?;
+        if let Some((cached_modified, post)) = CACHE.lock().unwrap().get(path) {
+            if *cached_modified == modified {
+                return Ok(post.clone());
+            }
+        }
+
+        // don’t hold the lock while parsing, so other threads can load other posts.
+        let post = Self::load_uncached(path)?;
+        CACHE
+            .lock()
+            .unwrap()
+            .insert(path.to_owned(), (modified, post.clone()));
+
+        Ok(post)
+    }
+
+    /// loads the cached copy of a remote post, fetching it if needed, or if it can’t be fetched,
+    /// returns a post that only links to it.
+    fn load_remote_reference(url: &str) -> eyre::Result<Self> {
+        match cache_remote_reference(url).and_then(|path| Self::load(&path)) {
+            Ok(post) => Ok(post),
+            Err(error) => {
+                warn!(
+                    url,
+                    ?error,
+                    "failed to fetch remote reference, linking to it instead"
+                );
+                let href = url.replace('&', "&amp;");
+                let mut post =
+                    Self::filter(&format!(r#"<p><a href="{href}">{href}</a></p>"#), None)?;
+                post.meta.archived = Some(url.to_owned());
+
+                Ok(post)
+            }
+        }
+    }
+
+    fn load_uncached(path: &PostsPath) -> eyre::Result<Self> {
         let mut file = File::open(path)?;
         let mut unsafe_source = String::default();
         file.read_to_string(&mut unsafe_source)?;
@@ -308,35 +672,84 @@ impl TemplatedPost {
         Self::filter(&unsafe_html, Some(path.to_owned()))
     }
 
+    /// returns a title for a post without one, from the start of its text content if any, or its
+    /// author and date otherwise. posts with content warnings never use their text content.
+    fn untitled_title(&self) -> String {
+        const MAX_CHARS: usize = 60;
+        let summary = self.og_description.trim();
+        if !summary.is_empty() && SETTINGS.content_warnings(&self.meta).is_empty() {
+            if summary.chars().count() <= MAX_CHARS {
+                return summary.to_owned();
+            }
+            let mut result = String::default();
+            for word in summary.split(' ') {
+                if result.chars().count() + word.chars().count() + 1 > MAX_CHARS {
+                    break;
+                }
+                if !result.is_empty() {
+                    result.push(' ');
+                }
+                result.push_str(word);
+            }
+            if result.is_empty() {
+                result = summary.chars().take(MAX_CHARS).collect();
+            }
+            return format!("{result}…");
+        }
+
+        let author = self
+            .meta
+            .author
+            .as_ref()
+            .map(|author| &author.display_handle);
+        let day = self.meta.published.as_deref().and_then(display_day);
+        match (author, day) {
+            (Some(author), Some(day)) => format!("untitled post by {author} on {day}"),
+            (Some(author), None) => format!("untitled post by {author}"),
+            (None, Some(day)) => format!("untitled post on {day}"),
+            (None, None) => "untitled post".to_owned(),
+        }
+    }
+
     pub fn filter(unsafe_html: &str, path: Option<PostsPath>) -> eyre::Result<Self> {
         // reader step: extract metadata.
         let post = extract_metadata(unsafe_html)?;
 
         // reader step: filter html.
         let extracted_html = serialize_html_fragment(post.dom)?;
-        let safe_html = ammonia::Builder::default()
+        let mut builder = ammonia::Builder::default();
+        builder
             .add_generic_attributes(["style", "id"])
             .add_generic_attributes(["data-cohost-href", "data-cohost-src"]) // cohost2autost
             .add_generic_attributes(["data-import-src"]) // autost import
+            .add_generic_attribute_prefixes(["data-", "aria-"])
             .add_tag_attributes("a", ["target"])
             .add_tag_attributes("audio", ["controls", "src"])
             .add_tag_attributes("details", ["open", "name"]) // <details name> for cohost compatibility
             .add_tag_attributes("img", ["loading"])
-            .add_tag_attributes("video", ["controls", "src"])
+            .add_tag_attributes("video", ["controls", "src", "width", "height"])
             .add_tags(["audio", "meta", "video"])
             .add_tag_attributes("meta", ["name", "content"])
-            .id_prefix(Some("user-content-")) // cohost compatibility
-            .clean(&extracted_html)
-            .to_string();
+            .id_prefix(Some("user-content-")); // cohost compatibility
+        if SETTINGS.post_styles() == PostStyles::Scoped {
+            builder.add_tags(["style"]).rm_clean_content_tags(["style"]);
+        }
+        let safe_html = builder.clean(&extracted_html).to_string();
+        let safe_html = scope_style_elements(&safe_html)?;
+        let scaled = use_scaled_images(&safe_html)?;
+        let safe_html = inline_small_images(&add_image_dimensions(&scaled.html)?)?;
+        let mut needs_attachments = post.needs_attachments;
+        needs_attachments.extend(scaled.needs_attachments);
 
         Ok(TemplatedPost {
             path,
             meta: post.meta,
             original_html: unsafe_html.to_owned(),
             safe_html,
-            needs_attachments: post.needs_attachments,
+            needs_attachments,
             og_image: post.og_image,
             og_description: post.og_description,
+            media: post.media,
         })
     }
 }
@@ -376,6 +789,152 @@ pub fn render_markdown(markdown: &str) -> String {
     unsafe_html
 }
 
+#[test]
+fn test_thread_title() -> eyre::Result<()> {
+    let title = |html: &str| -> eyre::Result<Option<String>> {
+        Ok(Thread::try_from(TemplatedPost::filter(html, None)?)?
+            .meta
+            .title)
+    };
+    assert_eq!(
+        title(r#"<meta name="title" content="hello">body"#)?.as_deref(),
+        Some("hello")
+    );
+    assert_eq!(
+        title(r#"<meta name="title" content=""><p>first words</p><p>of the body</p>"#)?.as_deref(),
+        Some("first words of the body")
+    );
+    let long = "word ".repeat(20);
+    assert_eq!(
+        title(&long)?.as_deref(),
+        Some("word word word word word word word word word word word word…")
+    );
+    assert_eq!(
+        title(r#"<meta name="content_warning" content="spoilers">the butler did it"#)?.as_deref(),
+        Some("untitled post")
+    );
+    assert_eq!(
+        title(r#"<meta name="published" content="2024-10-01T04:30Z">"#)?.as_deref(),
+        Some("untitled post on 2024-10-01")
+    );
+    assert_eq!(title("")?.as_deref(), Some("untitled post"));
+
+    Ok(())
+}
+
+#[test]
+fn test_is_title_only() -> eyre::Result<()> {
+    let thread = |html: &str| Thread::try_from(TemplatedPost::filter(html, None)?);
+    assert!(thread(r#"<meta name="title" content="hello">"#)?.is_title_only());
+    assert!(!thread(r#"<meta name="title" content="hello">body"#)?.is_title_only());
+    assert!(!thread(r#"<meta name="title" content="hello"><img src="a.png">"#)?.is_title_only());
+    assert!(!thread("")?.is_title_only());
+    let share = r#"<meta name="title" content="hello"><meta name="is_transparent_share">"#;
+    assert!(!thread(share)?.is_title_only());
+
+    Ok(())
+}
+
+#[test]
+fn test_engagement_summary() {
+    assert_eq!(Engagement::default().summary(), None);
+    let engagement = Engagement {
+        likes: Some(3),
+        comments: Some(1),
+    };
+    assert_eq!(engagement.summary().as_deref(), Some("3 likes, 1 comment"));
+    let engagement = Engagement {
+        likes: Some(0),
+        comments: None,
+    };
+    assert_eq!(engagement.summary().as_deref(), Some("0 likes"));
+}
+
+#[test]
+fn test_collapsed_ancestors() -> eyre::Result<()> {
+    let post = |html: &str| TemplatedPost::filter(html, None);
+    let share = r#"<meta name="is_transparent_share">"#;
+    // oldest first: a share, a post with content, another share, another post, then the main post.
+    let thread = Thread::from_posts(
+        vec![
+            post(share)?,
+            post("a")?,
+            post(share)?,
+            post("b")?,
+            post("main")?,
+        ],
+        vec![],
+    )?;
+    assert_eq!(thread.collapsed_ancestors(0), 4);
+    assert_eq!(thread.collapsed_ancestors(1), 2);
+    assert_eq!(thread.collapsed_ancestors(2), 0);
+    assert_eq!(thread.collapsed_ancestors(3), 0);
+
+    let thread = Thread::from_posts(vec![post("main")?], vec![])?;
+    assert_eq!(thread.collapsed_ancestors(0), 0);
+
+    Ok(())
+}
+
+#[test]
+fn test_reverse_chronological() -> eyre::Result<()> {
+    let thread = |published: &str, path: &str| -> eyre::Result<Thread> {
+        let html = format!(r#"<meta name="published" content="{published}">"#);
+        let path = PostsPath::from_site_root_relative_path(path)?;
+        Thread::try_from(TemplatedPost::filter(&html, Some(path))?)
+    };
+    let same = "2024-10-01T00:00:00Z";
+    let threads = [
+        thread("2024-10-02T00:00:00Z", "posts/1.html")?,
+        thread(same, "posts/10.html")?,
+        thread(same, "posts/9.html")?,
+        thread(same, "posts/b.html")?,
+        thread(same, "posts/a.html")?,
+        thread("2024-09-30T00:00:00Z", "posts/100.html")?,
+    ];
+    let expected = ["1", "10", "9", "a", "b", "100"].map(|id| format!("{id}.html"));
+
+    // every rotation and its reverse sorts the same way.
+    for i in 0..threads.len() {
+        let mut rotated = threads.to_vec();
+        rotated.rotate_left(i);
+        for mut input in [rotated.clone(), rotated.into_iter().rev().collect()] {
+            input.sort_by(Thread::reverse_chronological);
+            let actual = input
+                .iter()
+                .map(|thread| thread.path.as_ref().map(|path| path.filename().to_owned()))
+                .collect::<Option<Vec<_>>>()
+                .ok_or_eyre("thread has no path")?;
+            assert_eq!(actual, expected);
+        }
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_filter() -> eyre::Result<()> {
+    let post = TemplatedPost::filter(
+        r#"<div data-foo="bar" aria-label="baz" onclick="alert(1)">hello</div>"#,
+        None,
+    )?;
+    assert_eq!(
+        post.safe_html,
+        r#"<div data-foo="bar" aria-label="baz">hello</div>"#
+    );
+
+    let post = TemplatedPost::filter(
+        r#"<style media="print">p > a { color: red; }</style><style>} body { color: red; }</style><p>hello</p>"#,
+        None,
+    )?;
+    assert_eq!(
+        post.safe_html,
+        "<style>@scope {\np > a { color: red; }\n}</style><p>hello</p>"
+    );
+
+    Ok(())
+}
+
 #[test]
 fn test_render_markdown() {
     assert_eq!(
@@ -6,10 +6,17 @@ use autost::{
         cohost2autost::Cohost2autost,
         cohost2json::Cohost2json,
         cohost_archive::CohostArchive,
+        doctor::Doctor,
+        export::Export,
+        gc::Gc,
         import::{Import, Reimport},
+        import_post::ImportPost,
         new::New,
+        prefetch::Prefetch,
         render::Render,
         server::Server,
+        stats::Stats,
+        validate_config::ValidateConfig,
     },
     SETTINGS,
 };
@@ -17,32 +24,60 @@ use clap::Parser;
 use jane_eyre::eyre;
 
 #[derive(clap::Parser, Debug)]
+struct Args {
+    #[arg(
+        long = "set",
+        global = true,
+        value_name = "KEY=VALUE",
+        help = "override a setting in autost.toml, like `--set site_title=ao!!` (takes precedence over AUTOST_* environment variables)"
+    )]
+    settings: Vec<String>,
+
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(clap::Subcommand, Debug)]
 enum Command {
     Attach(Attach),
     Cohost2autost(Cohost2autost),
     Cohost2json(Cohost2json),
     CohostArchive(CohostArchive),
+    Doctor(Doctor),
+    Export(Export),
+    Gc(Gc),
     Import(Import),
+    ImportPost(ImportPost),
     New(New),
+    Prefetch(Prefetch),
     Reimport(Reimport),
     Render(Render),
     Server(Server),
+    Stats(Stats),
+    ValidateConfig(ValidateConfig),
 }
 
 #[tokio::main]
 async fn main() -> eyre::Result<()> {
     cli_init()?;
 
-    let command = Command::parse();
+    let Args { settings, command } = Args::parse();
+    autost::settings::set_cli_overrides(&settings)?;
 
     if matches!(
         command,
         Command::Attach { .. }
             | Command::Cohost2autost { .. }
+            | Command::Doctor { .. }
+            | Command::Export { .. }
+            | Command::Gc { .. }
             | Command::Import { .. }
+            | Command::ImportPost { .. }
+            | Command::Prefetch { .. }
             | Command::Reimport { .. }
             | Command::Render { .. }
             | Command::Server { .. }
+            | Command::Stats { .. }
     ) {
         // fail fast if there are any settings errors.
         let _ = &*SETTINGS;
@@ -53,10 +88,17 @@ async fn main() -> eyre::Result<()> {
         Command::Cohost2autost(args) => command::cohost2autost::main(args),
         Command::Cohost2json(args) => command::cohost2json::main(args).await,
         Command::CohostArchive(args) => command::cohost_archive::main(args).await,
+        Command::Doctor(args) => command::doctor::main(args),
+        Command::Export(args) => command::export::main(args),
+        Command::Gc(args) => command::gc::main(args),
         Command::Import(args) => command::import::main(args).await,
+        Command::ImportPost(args) => command::import_post::main(args).await,
         Command::New(args) => command::new::main(args),
+        Command::Prefetch(args) => command::prefetch::main(args),
         Command::Reimport(args) => command::import::reimport(args).await,
         Command::Render(args) => command::render::main(args),
         Command::Server(args) => command::server::main(args).await,
+        Command::Stats(args) => command::stats::main(args),
+        Command::ValidateConfig(args) => command::validate_config::main(args),
     }
 }
@@ -1,18 +1,30 @@
-use std::{collections::BTreeSet, fs::create_dir_all};
+use std::{
+    cell::RefCell,
+    collections::{BTreeMap, BTreeSet, HashMap},
+    fs::{create_dir_all, metadata, read},
+    rc::Rc,
+};
 
-use html5ever::QualName;
+use base64::{prelude::BASE64_STANDARD, Engine};
+use html5ever::{Attribute, QualName};
 use jane_eyre::eyre::{self, bail, OptionExt};
-use markup5ever_rcdom::NodeData;
-use tracing::trace;
+use markup5ever_rcdom::{Handle, Node, NodeData};
+use tracing::{trace, warn};
+use url::Url;
 
 use crate::{
-    css::{parse_inline_style, InlineStyleToken},
+    attachments::{scaled_copy, AttachmentsContext, Dimensions},
+    cohost::{parse_chost_url, Cacheable},
+    css::{parse_inline_style, scope_stylesheet, serialise_inline_style, InlineStyleToken},
+    date::parse_date,
     dom::{
-        html_attributes_with_urls, parse_html_fragment, text_content_for_summaries, AttrsRefExt,
-        QualNameExt, TendrilExt, Transform,
+        create_element, html_attributes_with_urls, parse_html_fragment, serialize_html_fragment,
+        text_content, text_content_for_summaries, AttrsMutExt, AttrsRefExt, DepthTraverse,
+        HandleExt, QualNameExt, TendrilExt, Transform,
     },
     path::{hard_link_if_not_exists, PostsPath, SitePath},
-    Author, ExtractedPost, PostMeta,
+    settings::normalise_tag,
+    Author, EditNote, ExtractedPost, PostMeta, SETTINGS,
 };
 
 pub fn extract_metadata(unsafe_html: &str) -> eyre::Result<ExtractedPost> {
@@ -21,6 +33,7 @@ pub fn extract_metadata(unsafe_html: &str) -> eyre::Result<ExtractedPost> {
     let mut meta = PostMeta::default();
     let mut needs_attachments = BTreeSet::default();
     let mut og_image = None;
+    let mut media = vec![];
     let og_description = text_content_for_summaries(dom.document.clone())?;
     let mut author_href = None;
     let mut author_name = None;
@@ -29,6 +42,21 @@ pub fn extract_metadata(unsafe_html: &str) -> eyre::Result<ExtractedPost> {
     let mut transform = Transform::new(dom.document.clone());
     while transform.next(|kids, new_kids| {
         for kid in kids {
+            // `<!-- more -->` would be stripped with the other comments, so keep it as an element.
+            if let NodeData::Comment { contents } = &kid.data {
+                if contents.trim() == "more" {
+                    new_kids.push(Node::new(NodeData::Element {
+                        name: QualName::html("span"),
+                        attrs: RefCell::new(vec![Attribute {
+                            name: QualName::attribute(READ_MORE_MARKER_ATTRIBUTE),
+                            value: "".into(),
+                        }]),
+                        template_contents: RefCell::new(None),
+                        mathml_annotation_xml_integration_point: false,
+                    }));
+                    continue;
+                }
+            }
             if let NodeData::Element { name, attrs, .. } = &kid.data {
                 let attrs = attrs.borrow();
                 if name == &QualName::html("meta") {
@@ -40,6 +68,9 @@ pub fn extract_metadata(unsafe_html: &str) -> eyre::Result<ExtractedPost> {
                         Some("published") => {
                             meta.published = content;
                         }
+                        Some("updated") => {
+                            meta.updated = content;
+                        }
                         Some("author_display_name") => {
                             author_display_name = content;
                         }
@@ -48,12 +79,78 @@ pub fn extract_metadata(unsafe_html: &str) -> eyre::Result<ExtractedPost> {
                         }
                         Some("tags") => {
                             if let Some(tag) = content {
-                                meta.tags.push(tag);
+                                meta.tags.push(normalise_tag(&tag));
                             }
                         }
                         Some("is_transparent_share") => {
                             meta.is_transparent_share = true;
                         }
+                        Some("is_redacted") => {
+                            meta.is_redacted = true;
+                        }
+                        Some("is_unlisted") => {
+                            meta.is_unlisted = true;
+                        }
+                        Some("is_liked") => {
+                            meta.is_liked = true;
+                        }
+                        Some("content_warning") => {
+                            meta.content_warning = content;
+                        }
+                        Some(name @ ("likes" | "comments")) => {
+                            let count = match content.as_deref().map(str::parse) {
+                                Some(Ok(count)) => Some(count),
+                                count => {
+                                    warn!(name, ?count, "ignoring invalid engagement count");
+                                    None
+                                }
+                            };
+                            match name {
+                                "likes" => meta.engagement.likes = count,
+                                _ => meta.engagement.comments = count,
+                            }
+                        }
+                        Some("slug") => match content {
+                            Some(slug) if is_valid_slug(&slug) => meta.slug = Some(slug),
+                            slug => warn!(?slug, "ignoring invalid slug"),
+                        },
+                        Some("series") => match content {
+                            Some(series) if !series.trim().is_empty() => {
+                                meta.series = Some(series)
+                            }
+                            series => warn!(?series, "ignoring empty series"),
+                        },
+                        Some("series_index") => match content.as_deref().map(str::parse) {
+                            Some(Ok(index)) if index > 0 => meta.series_index = Some(index),
+                            index => warn!(?index, "ignoring invalid series index"),
+                        },
+                        Some("edit") => match (attrs.attr_str("date")?, content) {
+                            (Some(date), Some(note))
+                                if parse_date(date).is_some() && !note.trim().is_empty() =>
+                            {
+                                meta.edits.push(EditNote {
+                                    date: date.to_owned(),
+                                    note,
+                                })
+                            }
+                            (date, note) => warn!(?date, ?note, "ignoring invalid edit note"),
+                        },
+                        Some("fediverse_url") => match content.as_deref().map(Url::parse) {
+                            Some(Ok(url)) if matches!(url.scheme(), "http" | "https") => {
+                                meta.fediverse_url = Some(url.to_string())
+                            }
+                            url => warn!(?url, "ignoring invalid fediverse url"),
+                        },
+                        Some("lang") => match content {
+                            Some(lang) if is_valid_language_tag(&lang) => meta.lang = Some(lang),
+                            lang => warn!(?lang, "ignoring invalid language tag"),
+                        },
+                        Some(name) => {
+                            if let (Some(key), Some(content)) = (name.strip_prefix("extra:"), content)
+                            {
+                                meta.extra.insert(key.to_owned(), content);
+                            }
+                        }
                         _ => {}
                     }
                     continue;
@@ -66,13 +163,33 @@ pub fn extract_metadata(unsafe_html: &str) -> eyre::Result<ExtractedPost> {
                         }
                         Some("references") => {
                             if let Some(href) = href {
-                                meta.references.push(PostsPath::from_references_url(&href)?);
+                                match Url::parse(&href) {
+                                    // posts on other sites, by absolute url.
+                                    Ok(url) if matches!(url.scheme(), "http" | "https") => {
+                                        meta.remote_references.push(url.to_string());
+                                    }
+                                    _ => {
+                                        meta.references
+                                            .push(PostsPath::from_references_url(&href)?);
+                                    }
+                                }
                             }
                         }
                         Some("author") => {
                             author_href = href;
                             author_name = name;
                         }
+                        Some("contributor") => {
+                            let attr = |name| -> eyre::Result<String> {
+                                Ok(attrs.attr_str(name)?.unwrap_or("").to_owned())
+                            };
+                            meta.contributors.push(Author {
+                                href: href.unwrap_or_default(),
+                                name: name.unwrap_or_default(),
+                                display_name: attr("display_name")?,
+                                display_handle: attr("display_handle")?,
+                            });
+                        }
                         _ => {}
                     }
                     continue;
@@ -99,6 +216,22 @@ pub fn extract_metadata(unsafe_html: &str) -> eyre::Result<ExtractedPost> {
                             }
                         }
                     }
+                    // audio and video attachments become enclosures in atom feeds.
+                    if [
+                        QualName::html("audio"),
+                        QualName::html("video"),
+                        QualName::html("source"),
+                    ]
+                    .contains(name)
+                    {
+                        if let Some(src) = attrs.attr_str("src")? {
+                            if let Ok(url) = SitePath::from_rendered_attachment_url(src) {
+                                if !media.contains(&url) {
+                                    media.push(url);
+                                }
+                            }
+                        }
+                    }
                     // use the first <img src>, if any, as the <meta> og:image.
                     if og_image.is_none() && name == &QualName::html("img") {
                         if let Some(src) = attrs.attr_str("src")?.map(|t| t.to_owned()) {
@@ -112,6 +245,13 @@ pub fn extract_metadata(unsafe_html: &str) -> eyre::Result<ExtractedPost> {
         Ok(())
     })? {}
 
+    // the newest edit note is when the post was last updated, unless `updated` is even newer.
+    if let Some(latest) = meta.edits.iter().max_by_key(|edit| parse_date(&edit.date)) {
+        if meta.updated.as_deref().and_then(parse_date) < parse_date(&latest.date) {
+            meta.updated = Some(latest.date.clone());
+        }
+    }
+
     if author_href.is_some()
         || author_name.is_some()
         || author_display_name.is_some()
@@ -131,6 +271,7 @@ pub fn extract_metadata(unsafe_html: &str) -> eyre::Result<ExtractedPost> {
         needs_attachments,
         og_image,
         og_description,
+        media,
     })
 }
 
@@ -153,12 +294,971 @@ pub fn hard_link_attachments_into_site<'paths>(
     Ok(())
 }
 
+/// html with any remaining cohost resource urls replaced by cached attachments.
+pub struct LocalisedHtml {
+    pub html: String,
+    pub needs_attachments: BTreeSet<SitePath>,
+    /// cohost urls that could not be replaced, because they are not a known kind of resource, or
+    /// they could not be cached.
+    pub unlocalised_urls: Vec<String>,
+}
+
+/// replaces any cohost resource urls left in a post (attachments, emoji and other static assets,
+/// avatars, and headers) with cached attachments, caching them if needed. `autost cohost2autost`
+/// already does this for chosts, so this is a safety net for posts written in other ways.
+///
+/// links to cohost pages (`<a href>`) are not reported, since they are not resources.
+pub fn localise_cohost_urls(
+    html: &str,
+    context: &dyn AttachmentsContext,
+) -> eyre::Result<LocalisedHtml> {
+    // find the resources first, so they can be cached all at once, which may be concurrent.
+    let mut urls = BTreeSet::default();
+    let mut unlocalised_urls = vec![];
+    let unchanged = rewrite_resource_urls(html, |url| {
+        if Cacheable::from_url(url).is_some() {
+            urls.insert(url.to_owned());
+        } else if is_cohost_url(url) {
+            unlocalised_urls.push(url.to_owned());
+        }
+        Ok(None)
+    })?;
+    if urls.is_empty() {
+        return Ok(LocalisedHtml {
+            html: unchanged,
+            needs_attachments: BTreeSet::default(),
+            unlocalised_urls,
+        });
+    }
+
+    let cacheables = urls
+        .iter()
+        .filter_map(|url| Cacheable::from_url(url))
+        .collect::<Vec<_>>();
+    let cached = urls
+        .iter()
+        .map(|url| &**url)
+        .zip(context.cache_many_cohost_resources(&cacheables))
+        .collect::<BTreeMap<_, _>>();
+    let mut needs_attachments = BTreeSet::default();
+    // find the unlocalised urls again, to report them in order.
+    unlocalised_urls.clear();
+    let html = rewrite_resource_urls(html, |url| {
+        let Some(result) = cached.get(url) else {
+            if is_cohost_url(url) {
+                unlocalised_urls.push(url.to_owned());
+            }
+            return Ok(None);
+        };
+        match result {
+            Ok(path) => {
+                trace!(url, ?path, "localising cohost resource url");
+                let site_path = path.site_path()?;
+                let result = site_path.base_relative_url();
+                needs_attachments.insert(site_path);
+                Ok(Some(result))
+            }
+            Err(error) => {
+                warn!(url, ?error, "failed to cache cohost resource");
+                unlocalised_urls.push(url.to_owned());
+                Ok(None)
+            }
+        }
+    })?;
+
+    Ok(LocalisedHtml {
+        html,
+        needs_attachments,
+        unlocalised_urls,
+    })
+}
+
+/// calls `rewrite` for each resource url in the html, in attributes and inline styles, replacing
+/// the url if it returns Some. links (`<a href>`) are not resources, so they are left alone.
+fn rewrite_resource_urls(
+    html: &str,
+    mut rewrite: impl FnMut(&str) -> eyre::Result<Option<String>>,
+) -> eyre::Result<String> {
+    let dom = parse_html_fragment(html.as_bytes())?;
+    let mut transform = Transform::new(dom.document.clone());
+    while transform.next(|kids, new_kids| {
+        for kid in kids {
+            if let NodeData::Element { name, attrs, .. } = &kid.data {
+                let mut attrs = attrs.borrow_mut();
+                if let Some(attr_names) = html_attributes_with_urls().get(name) {
+                    for attr in attrs.iter_mut() {
+                        let is_link = name == &QualName::html("a")
+                            && attr.name == QualName::attribute("href");
+                        if attr_names.contains(&attr.name) && !is_link {
+                            if let Some(url) = rewrite(attr.value.to_str())? {
+                                attr.value = url.into();
+                            }
+                        }
+                    }
+                }
+                if let Some(style) = attrs.attr_mut("style") {
+                    let mut changed = false;
+                    let mut tokens = vec![];
+                    for token in parse_inline_style(style.value.to_str()) {
+                        tokens.push(match token {
+                            InlineStyleToken::Url(url) => match rewrite(&url)? {
+                                Some(url) => {
+                                    changed = true;
+                                    InlineStyleToken::Url(url)
+                                }
+                                None => InlineStyleToken::Url(url),
+                            },
+                            other => other,
+                        });
+                    }
+                    if changed {
+                        style.value = serialise_inline_style(&tokens).into();
+                    }
+                }
+            }
+            new_kids.push(kid.clone());
+        }
+        Ok(())
+    })? {}
+
+    Ok(serialize_html_fragment(dom)?)
+}
+
+/// replaces links (`<a href>`) to chosts that have pages in the archive with links to those pages,
+/// where `pages` maps the post id of each chost to the url of its page. if `note_missing`, links
+/// to any other chosts are followed by a note that cohost is gone.
+pub fn localise_cohost_post_links(
+    html: &str,
+    pages: &HashMap<usize, String>,
+    note_missing: bool,
+) -> eyre::Result<String> {
+    let dom = parse_html_fragment(html.as_bytes())?;
+    let mut transform = Transform::new(dom.document.clone());
+    while transform.next(|kids, new_kids| {
+        for kid in kids {
+            new_kids.push(kid.clone());
+            let NodeData::Element { name, attrs, .. } = &kid.data else {
+                continue;
+            };
+            if name != &QualName::html("a") {
+                continue;
+            }
+            let mut attrs = attrs.borrow_mut();
+            let Some(href) = attrs.attr_mut("href") else {
+                continue;
+            };
+            let Ok((_, post_id)) = parse_chost_url(href.value.to_str()) else {
+                continue;
+            };
+            if let Some(page) = pages.get(&post_id) {
+                trace!(
+                    url = href.value.to_str(),
+                    page,
+                    "localising cohost post link"
+                );
+                href.value = page.as_str().into();
+            } else if note_missing {
+                new_kids.push(Node::new(NodeData::Text {
+                    contents: RefCell::new(" ".into()),
+                }));
+                let note = Node::new(NodeData::Element {
+                    name: QualName::html("small"),
+                    attrs: RefCell::new(vec![Attribute {
+                        name: QualName::attribute("class"),
+                        value: "cohost-gone".into(),
+                    }]),
+                    template_contents: RefCell::new(None),
+                    mathml_annotation_xml_integration_point: false,
+                });
+                note.children.replace(vec![Node::new(NodeData::Text {
+                    contents: RefCell::new("(cohost is gone)".into()),
+                })]);
+                new_kids.push(note);
+            }
+        }
+        Ok(())
+    })? {}
+
+    serialize_html_fragment(dom)
+}
+
+fn is_cohost_url(url: &str) -> bool {
+    Url::parse(url)
+        .ok()
+        .and_then(|url| url.host_str().map(|host| host.to_owned()))
+        .is_some_and(|host| {
+            host == "cohost.org"
+                || host.ends_with(".cohost.org")
+                || host == "cohostcdn.org"
+                || host.ends_with(".cohostcdn.org")
+        })
+}
+
+/// scopes `<style>` elements to their parent element with `@scope`, so styles in one post can’t
+/// affect other posts on the same page. `<style>` elements that would escape the scope are removed.
+pub fn scope_style_elements(html: &str) -> eyre::Result<String> {
+    if !html.contains("<style") {
+        return Ok(html.to_owned());
+    }
+    let dom = parse_html_fragment(html.as_bytes())?;
+
+    let mut transform = Transform::new(dom.document.clone());
+    while transform.next(|kids, new_kids| {
+        for kid in kids {
+            if let NodeData::Element { name, .. } = &kid.data {
+                if name == &QualName::html("style") {
+                    let css = text_content(kid.clone())?;
+                    let Some(scoped) = scope_stylesheet(&css) else {
+                        warn!("removing <style> with unbalanced braces: {css:?}");
+                        continue;
+                    };
+                    kid.children.replace(vec![Node::new(NodeData::Text {
+                        contents: RefCell::new(scoped.into()),
+                    })]);
+                }
+            }
+            new_kids.push(kid.clone());
+        }
+        Ok(())
+    })? {}
+
+    serialize_html_fragment(dom)
+}
+
+/// marks where `<!-- more -->` was in a post, after filtering.
+const READ_MORE_MARKER_ATTRIBUTE: &str = "data-autost-more";
+
+/// returns the html up to the `<!-- more -->` marker, if any, or truncated to about `max_chars`
+/// characters of text, if any. returns None if the html would not be truncated.
+///
+/// the html is truncated between nodes or between words, and any open elements are closed.
+pub fn read_more_excerpt(html: &str, max_chars: Option<usize>) -> eyre::Result<Option<String>> {
+    let has_marker = html.contains(READ_MORE_MARKER_ATTRIBUTE);
+    if !has_marker && max_chars.is_none() {
+        return Ok(None);
+    }
+    let dom = parse_html_fragment(html.as_bytes())?;
+
+    // the marker always wins over `max_chars`, so you can choose where to cut long posts.
+    let mut budget = if has_marker { None } else { max_chars };
+    if !truncate_node_contents(&dom.document, &mut budget)? {
+        return Ok(None);
+    }
+
+    Ok(Some(serialize_html_fragment(dom)?))
+}
+
+/// returns the text of the posts in the html for a thread, without any html, for search indexes.
+/// only the text in `.e-content` is included, so author names, tags, and dates are left out.
+pub fn threads_content_text(threads_content: &str) -> eyre::Result<String> {
+    let dom = parse_html_fragment(threads_content.as_bytes())?;
+    let mut words = vec![];
+    collect_content_words(&dom.document, false, &mut words)?;
+
+    Ok(words.join(" "))
+}
+
+fn collect_content_words(
+    node: &Handle,
+    mut in_content: bool,
+    words: &mut Vec<String>,
+) -> eyre::Result<()> {
+    match &node.data {
+        // stylesheets and scripts are not text you can read.
+        NodeData::Element { name, .. }
+            if [QualName::html("style"), QualName::html("script")].contains(name) =>
+        {
+            return Ok(());
+        }
+        NodeData::Element { attrs, .. } => {
+            if let Some(class) = attrs.borrow().attr_str("class")? {
+                let classes = class.split_ascii_whitespace().collect::<Vec<_>>();
+                if classes.contains(&"title-only") {
+                    return Ok(());
+                }
+                in_content |= classes.contains(&"e-content");
+            }
+        }
+        NodeData::Text { contents } if in_content => {
+            for word in contents.borrow().to_str().split_ascii_whitespace() {
+                words.push(word.to_owned());
+            }
+        }
+        _ => {}
+    }
+    for kid in node.children.borrow().iter() {
+        collect_content_words(kid, in_content, words)?;
+    }
+
+    Ok(())
+}
+
+/// returns true iff the contents of the node were truncated.
+fn truncate_node_contents(node: &Handle, budget: &mut Option<usize>) -> eyre::Result<bool> {
+    let kids = node.children.borrow().clone();
+    for (i, kid) in kids.iter().enumerate() {
+        let keep = match &kid.data {
+            NodeData::Element { attrs, .. }
+                if attrs
+                    .borrow()
+                    .attr_str(READ_MORE_MARKER_ATTRIBUTE)?
+                    .is_some() =>
+            {
+                Some(i)
+            }
+            // stylesheets and scripts are not text you can read.
+            NodeData::Element { name, .. }
+                if [QualName::html("style"), QualName::html("script")].contains(name) =>
+            {
+                None
+            }
+            NodeData::Element { .. } if truncate_node_contents(kid, budget)? => {
+                // drop elements that would be left empty.
+                Some(if kid.children.borrow().is_empty() {
+                    i
+                } else {
+                    i + 1
+                })
+            }
+            NodeData::Element { .. } => None,
+            NodeData::Text { contents } => {
+                let mut contents = contents.borrow_mut();
+                let len = contents.trim().chars().count();
+                match budget {
+                    Some(budget) if len > *budget => {
+                        let text = contents.to_str();
+                        let leading = text.chars().take_while(|c| c.is_whitespace()).count();
+                        let end = text
+                            .char_indices()
+                            .nth(leading + *budget)
+                            .map_or(text.len(), |(end, _)| end);
+                        // cut at the last word boundary, unless that would leave no words.
+                        let end = text[..end]
+                            .trim_end()
+                            .rfind(char::is_whitespace)
+                            .filter(|&end| !text[..end].trim().is_empty())
+                            .unwrap_or(end);
+                        let text = text[..end].trim_end().to_owned();
+                        if text.trim().is_empty() {
+                            Some(i)
+                        } else {
+                            *contents = format!("{text}…").into();
+                            Some(i + 1)
+                        }
+                    }
+                    Some(budget) => {
+                        *budget -= len;
+                        None
+                    }
+                    None => None,
+                }
+            }
+            _ => None,
+        };
+        if let Some(keep) = keep {
+            node.children.borrow_mut().truncate(keep);
+            return Ok(true);
+        }
+    }
+
+    Ok(false)
+}
+
+/// replaces `<img src>` attachments no bigger than `inline_images_max_bytes` with `data:` urls,
+/// so the html no longer depends on the attachments directory.
+pub fn inline_small_images(html: &str) -> eyre::Result<String> {
+    let Some(max_bytes) = SETTINGS.inline_images_max_bytes else {
+        return Ok(html.to_owned());
+    };
+    let dom = parse_html_fragment(html.as_bytes())?;
+
+    let mut transform = Transform::new(dom.document.clone());
+    while transform.next(|kids, new_kids| {
+        for kid in kids {
+            if let NodeData::Element { name, attrs, .. } = &kid.data {
+                if name == &QualName::html("img") {
+                    for attr in attrs.borrow_mut().iter_mut() {
+                        if attr.name == QualName::attribute("src") {
+                            if let Some(url) = inline_image_url(attr.value.to_str(), max_bytes)? {
+                                attr.value = url.into();
+                            }
+                        }
+                    }
+                }
+            }
+            new_kids.push(kid.clone());
+        }
+        Ok(())
+    })? {}
+
+    serialize_html_fragment(dom)
+}
+
+/// adds `width` and `height` to `<img>` elements with attachment urls that have neither, using
+/// the saved [`Dimensions`] of the attachment, so the page doesn’t shift around as images load.
+pub fn add_image_dimensions(html: &str) -> eyre::Result<String> {
+    let dom = parse_html_fragment(html.as_bytes())?;
+
+    let mut transform = Transform::new(dom.document.clone());
+    while transform.next(|kids, new_kids| {
+        for kid in kids {
+            if let NodeData::Element { name, attrs, .. } = &kid.data {
+                if name == &QualName::html("img") {
+                    let mut attrs = attrs.borrow_mut();
+                    if attrs.attr_str("width")?.is_none() && attrs.attr_str("height")?.is_none() {
+                        let dimensions = match attrs.attr_str("src")? {
+                            Some(url) => image_dimensions(url)?,
+                            None => None,
+                        };
+                        if let Some(Dimensions { width, height }) = dimensions {
+                            attrs.push(Attribute {
+                                name: QualName::attribute("width"),
+                                value: width.to_string().into(),
+                            });
+                            attrs.push(Attribute {
+                                name: QualName::attribute("height"),
+                                value: height.to_string().into(),
+                            });
+                        }
+                    }
+                }
+            }
+            new_kids.push(kid.clone());
+        }
+        Ok(())
+    })? {}
+
+    serialize_html_fragment(dom)
+}
+
+fn image_dimensions(url: &str) -> eyre::Result<Option<Dimensions>> {
+    let Ok(site_path) = SitePath::from_rendered_attachment_url(url) else {
+        return Ok(None);
+    };
+    let Some(path) = site_path.attachments_path()? else {
+        return Ok(None);
+    };
+    if !metadata(&path).is_ok_and(|metadata| metadata.is_file()) {
+        return Ok(None);
+    }
+
+    Dimensions::load_or_probe(&path)
+}
+
+/// html with large image attachments replaced by their scaled copies.
+pub struct ScaledHtml {
+    pub html: String,
+    pub needs_attachments: BTreeSet<SitePath>,
+}
+
+/// replaces each `<img>` attachment larger than `max_image_dimension` with its [`scaled_copy`],
+/// keeping the original size in `data-original-width` and `data-original-height`, and links it
+/// to the original (unless it’s already in a link), so the full size image is still available.
+pub fn use_scaled_images(html: &str) -> eyre::Result<ScaledHtml> {
+    let mut dom = parse_html_fragment(html.as_bytes())?;
+    let mut needs_attachments = BTreeSet::default();
+    let is_element = |node: &Handle, html_local_name: &str| matches!(&node.data, NodeData::Element { name, .. } if name == &QualName::html(html_local_name));
+
+    let imgs = DepthTraverse::elements(dom.document.clone())
+        .filter(|node| is_element(node, "img"))
+        .collect::<Vec<_>>();
+    for img in imgs {
+        let mut attrs = img.attrs().expect("guaranteed by is_element");
+        let Some(url) = attrs.attr_str("src")?.map(ToOwned::to_owned) else {
+            continue;
+        };
+        let Ok(site_path) = SitePath::from_rendered_attachment_url(&url) else {
+            continue;
+        };
+        let Some(path) = site_path.attachments_path()? else {
+            continue;
+        };
+        if !metadata(&path).is_ok_and(|metadata| metadata.is_file()) {
+            continue;
+        }
+        let Some(scaled_path) = scaled_copy(&path)? else {
+            continue;
+        };
+        let (Some(original), Some(scaled)) = (
+            Dimensions::load_or_probe(&path)?,
+            Dimensions::load_or_probe(&scaled_path)?,
+        ) else {
+            continue;
+        };
+        let scaled_site_path = scaled_path.site_path()?;
+        if let Some(src) = attrs.attr_mut("src") {
+            src.value = scaled_site_path.base_relative_url().into();
+        }
+        // any width or height in the post is how big the author wants the image to look.
+        let has_size = attrs.attr_str("width")?.is_some() || attrs.attr_str("height")?.is_some();
+        let mut push = |name: &str, value: usize| {
+            if attrs.attr_str(name).is_ok_and(|value| value.is_none()) {
+                attrs.push(Attribute {
+                    name: QualName::attribute(name),
+                    value: value.to_string().into(),
+                });
+            }
+        };
+        if !has_size {
+            push("width", scaled.width);
+            push("height", scaled.height);
+        }
+        push("data-original-width", original.width);
+        push("data-original-height", original.height);
+        drop(attrs);
+        needs_attachments.insert(scaled_site_path);
+
+        let Some(parent) = img.parent.take().and_then(|parent| parent.upgrade()) else {
+            continue;
+        };
+        if is_element(&parent, "a") {
+            img.parent.set(Some(Rc::downgrade(&parent)));
+            continue;
+        }
+        let link = create_element(&mut dom, "a");
+        link.attrs().expect("guaranteed by create_element").extend([
+            Attribute {
+                name: QualName::attribute("target"),
+                value: "_blank".into(),
+            },
+            Attribute {
+                name: QualName::attribute("href"),
+                value: url.into(),
+            },
+        ]);
+        for kid in parent.children.borrow_mut().iter_mut() {
+            if Rc::ptr_eq(kid, &img) {
+                *kid = link.clone();
+            }
+        }
+        link.parent.set(Some(Rc::downgrade(&parent)));
+        img.parent.set(Some(Rc::downgrade(&link)));
+        link.children.borrow_mut().push(img);
+    }
+
+    Ok(ScaledHtml {
+        html: serialize_html_fragment(dom)?,
+        needs_attachments,
+    })
+}
+
+fn inline_image_url(url: &str, max_bytes: u64) -> eyre::Result<Option<String>> {
+    let Ok(site_path) = SitePath::from_rendered_attachment_url(url) else {
+        return Ok(None);
+    };
+    let Some(path) = site_path.attachments_path()? else {
+        return Ok(None);
+    };
+    match metadata(&path) {
+        Ok(metadata) if metadata.is_file() && metadata.len() <= max_bytes => {}
+        _ => return Ok(None),
+    }
+    let result = data_url(path.filename(), &read(&path)?);
+    if result.is_some() {
+        trace!(?path, "inlining small image");
+    }
+
+    Ok(result)
+}
+
+/// returns a `data:` url for the given image, or None if it’s not a known image type. svg images
+/// are inlined as text, and other images as base64.
+fn data_url(filename: &str, content: &[u8]) -> Option<String> {
+    let (_, extension) = filename.rsplit_once(".")?;
+    let mime = match &*extension.to_ascii_lowercase() {
+        "svg" => {
+            let svg = std::str::from_utf8(content).ok()?;
+            return Some(format!("data:image/svg+xml,{}", urlencoding::encode(svg)));
+        }
+        "avif" => "image/avif",
+        "gif" => "image/gif",
+        "jpg" | "jpeg" => "image/jpeg",
+        "png" => "image/png",
+        "webp" => "image/webp",
+        _ => return None,
+    };
+
+    Some(format!(
+        "data:{mime};base64,{}",
+        BASE64_STANDARD.encode(content)
+    ))
+}
+
+/// returns true iff `tag` is well-formed enough to be a bcp 47 language tag, like `en`, `ja-JP`,
+/// or `zh-Hant-TW`. this is only a syntax check, not a check against the subtag registry.
+pub fn is_valid_language_tag(tag: &str) -> bool {
+    let mut subtags = tag.split('-');
+    let Some(language) = subtags.next() else {
+        return false;
+    };
+    let language_ok = (2..=8).contains(&language.len())
+        && language.bytes().all(|b| b.is_ascii_alphabetic())
+        || language.eq_ignore_ascii_case("x")
+        || language.eq_ignore_ascii_case("i");
+
+    language_ok
+        && subtags.all(|subtag| {
+            (1..=8).contains(&subtag.len()) && subtag.bytes().all(|b| b.is_ascii_alphanumeric())
+        })
+}
+
+/// returns true iff `slug` is safe to use as a filename and url path segment, like `my-post`. slugs
+/// can have letters, digits, `-`, and `_`, but no dots or slashes, so they can’t escape the site
+/// or look like another kind of file.
+pub fn is_valid_slug(slug: &str) -> bool {
+    !slug.is_empty()
+        && slug.len() <= 200
+        && slug
+            .chars()
+            .all(|c| c.is_alphanumeric() || c == '-' || c == '_')
+}
+
+#[test]
+fn test_is_valid_slug() {
+    assert!(is_valid_slug("my-post"));
+    assert!(is_valid_slug("2024_year_in_review"));
+    assert!(is_valid_slug("日記"));
+    assert!(!is_valid_slug(""));
+    assert!(!is_valid_slug("../index"));
+    assert!(!is_valid_slug("a/b"));
+    assert!(!is_valid_slug("post.print"));
+    assert!(!is_valid_slug("my post"));
+    assert!(!is_valid_slug(&"a".repeat(201)));
+}
+
+#[test]
+fn test_is_valid_language_tag() {
+    assert!(is_valid_language_tag("en"));
+    assert!(is_valid_language_tag("ja-JP"));
+    assert!(is_valid_language_tag("zh-Hant-TW"));
+    assert!(is_valid_language_tag("x-klingon"));
+    assert!(!is_valid_language_tag(""));
+    assert!(!is_valid_language_tag("e"));
+    assert!(!is_valid_language_tag("en_AU"));
+    assert!(!is_valid_language_tag("en-"));
+    assert!(!is_valid_language_tag("en-verylongsubtag"));
+    assert!(!is_valid_language_tag("\"><script>"));
+}
+
+#[test]
+fn test_localise_cohost_urls() -> eyre::Result<()> {
+    use crate::path::AttachmentsPath;
+    use std::path::Path;
+    struct TestAttachmentsContext {}
+    impl AttachmentsContext for TestAttachmentsContext {
+        fn store(&self, _input_path: &Path) -> crate::error::Result<AttachmentsPath> {
+            unreachable!()
+        }
+        fn cache_imported(
+            &self,
+            _url: &str,
+            _post_basename: &str,
+        ) -> crate::error::Result<AttachmentsPath> {
+            unreachable!()
+        }
+        fn cache_cohost_resource(
+            &self,
+            cacheable: &Cacheable,
+        ) -> crate::error::Result<AttachmentsPath> {
+            match cacheable {
+                Cacheable::Attachment { id } => {
+                    Ok(AttachmentsPath::ROOT.join(id)?.join("a.png")?)
+                }
+                Cacheable::Static { filename, .. } => {
+                    Ok(AttachmentsPath::COHOST_STATIC.join(filename)?)
+                }
+                _ => Err(eyre::eyre!("offline").into()),
+            }
+        }
+        fn cache_cohost_thumb(&self, _id: &str) -> crate::error::Result<AttachmentsPath> {
+            unreachable!()
+        }
+    }
+
+    let result = localise_cohost_urls(
+        r#"<img src="https://cohost.org/rc/attachment-redirect/44444444-4444-4444-4444-444444444444"><div style="background: url(https://cohost.org/static/a.png)"></div><img src="https://staging.cohostcdn.org/avatar/b.png"><img src="https://cohost.org/rc/unknown"><a href="https://cohost.org/staff">@staff</a>"#,
+        &TestAttachmentsContext {},
+    )?;
+    assert_eq!(
+        result.html,
+        r#"<img src="attachments/44444444-4444-4444-4444-444444444444/a.png"><div style="background: url('attachments/cohost-static/a.png')"></div><img src="https://staging.cohostcdn.org/avatar/b.png"><img src="https://cohost.org/rc/unknown"><a href="https://cohost.org/staff">@staff</a>"#
+    );
+    assert_eq!(result.needs_attachments.len(), 2);
+    assert_eq!(
+        result.unlocalised_urls,
+        [
+            "https://staging.cohostcdn.org/avatar/b.png",
+            "https://cohost.org/rc/unknown"
+        ]
+    );
+
+    Ok(())
+}
+
+#[test]
+fn test_data_url() {
+    assert_eq!(
+        data_url("file.PNG", b"png").as_deref(),
+        Some("data:image/png;base64,cG5n")
+    );
+    assert_eq!(
+        data_url("file.svg", b"<svg/>").as_deref(),
+        Some("data:image/svg+xml,%3Csvg%2F%3E")
+    );
+    assert_eq!(data_url("file.mp4", b"mp4"), None);
+    assert_eq!(data_url("file", b"?"), None);
+}
+
 #[test]
 fn test_extract_metadata() -> eyre::Result<()> {
     use crate::dom::serialize_html_fragment;
+    use askama::Template;
     let post = extract_metadata(r#"<meta name="title" content="foo">bar"#)?;
     assert_eq!(serialize_html_fragment(post.dom)?, "bar");
     assert_eq!(post.meta.title.as_deref(), Some("foo"));
 
+    let post = extract_metadata(r#"<meta name="lang" content="ja-JP">"#)?;
+    assert_eq!(post.meta.lang.as_deref(), Some("ja-JP"));
+    let post = extract_metadata(r#"<meta name="lang" content="ja_JP">"#)?;
+    assert_eq!(post.meta.lang, None);
+
+    let post = extract_metadata(r#"<meta name="slug" content="my-post">"#)?;
+    assert_eq!(post.meta.slug.as_deref(), Some("my-post"));
+    assert_eq!(
+        extract_metadata(&post.meta.render()?)?.meta,
+        post.meta,
+        "slug should round trip"
+    );
+    let post = extract_metadata(r#"<meta name="slug" content="../index">"#)?;
+    assert_eq!(post.meta.slug, None);
+
+    let post = extract_metadata(
+        r#"<meta name="updated" content="2024-12-02T00:00Z"><meta name="edit" date="2024-12-03T00:00Z" content="fixed a *typo*"><meta name="edit" date="2024-12-01T00:00Z" content="added a photo"><meta name="edit" content="no date">"#,
+    )?;
+    assert_eq!(
+        post.meta.edits,
+        [
+            EditNote {
+                date: "2024-12-03T00:00Z".to_owned(),
+                note: "fixed a *typo*".to_owned(),
+            },
+            EditNote {
+                date: "2024-12-01T00:00Z".to_owned(),
+                note: "added a photo".to_owned(),
+            },
+        ]
+    );
+    assert_eq!(post.meta.edits[0].note_html(), "fixed a <em>typo</em>");
+    assert_eq!(
+        post.meta.updated.as_deref(),
+        Some("2024-12-03T00:00Z"),
+        "the newest edit note should bump updated"
+    );
+    assert_eq!(
+        extract_metadata(&post.meta.render()?)?.meta,
+        post.meta,
+        "edits should round trip"
+    );
+
+    let post = extract_metadata(
+        r#"<meta name="series" content="my tutorial"><meta name="series_index" content="2">"#,
+    )?;
+    assert_eq!(post.meta.series.as_deref(), Some("my tutorial"));
+    assert_eq!(post.meta.series_index, Some(2));
+    assert_eq!(
+        extract_metadata(&post.meta.render()?)?.meta,
+        post.meta,
+        "series should round trip"
+    );
+    let post = extract_metadata(r#"<meta name="series_index" content="0">"#)?;
+    assert_eq!(post.meta.series_index, None);
+
+    let post =
+        extract_metadata(r#"<meta name="fediverse_url" content="https://example.social/@me/1">"#)?;
+    assert_eq!(
+        post.meta.fediverse_url.as_deref(),
+        Some("https://example.social/@me/1")
+    );
+    assert_eq!(
+        extract_metadata(&post.meta.render()?)?.meta,
+        post.meta,
+        "fediverse_url should round trip"
+    );
+    let post = extract_metadata(r#"<meta name="fediverse_url" content="javascript:alert(1)">"#)?;
+    assert_eq!(post.meta.fediverse_url, None);
+
+    let post = extract_metadata(
+        r#"<link rel="references" href="1.html"><link rel="references" href="https://example.com/2.html">"#,
+    )?;
+    assert_eq!(post.meta.references, [PostsPath::generated_post_path(1)]);
+    assert_eq!(post.meta.remote_references, ["https://example.com/2.html"]);
+    assert_eq!(
+        extract_metadata(&post.meta.render()?)?.meta,
+        post.meta,
+        "references should round trip"
+    );
+
+    let post = extract_metadata(
+        r#"<link rel="author" href="https://example.com/a" name="a"><link rel="contributor" href="https://example.com/b" name="b" display_name="B" display_handle="b.example.com">"#,
+    )?;
+    assert_eq!(
+        post.meta
+            .authors()
+            .map(|author| &*author.href)
+            .collect::<Vec<_>>(),
+        ["https://example.com/a", "https://example.com/b"]
+    );
+    assert_eq!(post.meta.contributors[0].display_name, "B");
+    assert_eq!(
+        extract_metadata(&post.meta.render()?)?.meta,
+        post.meta,
+        "contributors should round trip"
+    );
+
+    let post = extract_metadata(
+        r#"<meta name="extra:location" content="Perth"><meta name="extra:numLikes" content="3"><meta name="extra:">"#,
+    )?;
+    assert_eq!(
+        post.meta.extra,
+        std::collections::BTreeMap::from([
+            ("location".to_owned(), "Perth".to_owned()),
+            ("numLikes".to_owned(), "3".to_owned()),
+        ])
+    );
+    assert_eq!(
+        extract_metadata(&post.meta.render()?)?.meta,
+        post.meta,
+        "extra metadata should round trip"
+    );
+
+    let post = extract_metadata(
+        r#"<meta name="likes" content="3"><meta name="comments" content="many">"#,
+    )?;
+    assert_eq!(
+        post.meta.engagement,
+        crate::Engagement {
+            likes: Some(3),
+            comments: None,
+        }
+    );
+    assert_eq!(
+        extract_metadata(&post.meta.render()?)?.meta,
+        post.meta,
+        "engagement counts should round trip"
+    );
+
+    let post = extract_metadata(
+        r#"<audio src="attachments/a/1.mp3"></audio><video><source src="attachments/b/2.mp4"></video><audio src="https://example.com/3.mp3"></audio><audio src="attachments/a/1.mp3"></audio>"#,
+    )?;
+    assert_eq!(
+        post.media,
+        [
+            SitePath::from_rendered_attachment_url("attachments/a/1.mp3")?,
+            SitePath::from_rendered_attachment_url("attachments/b/2.mp4")?,
+        ]
+    );
+
+    Ok(())
+}
+
+#[test]
+fn test_add_image_dimensions() -> eyre::Result<()> {
+    // images that aren’t attachments, or already have dimensions, are left alone.
+    for html in [
+        r#"<img src="https://example.com/image.png">"#,
+        r#"<img src="attachments/00000000-0000-0000-0000-000000000000/missing.png">"#,
+        r#"<img src="attachments/x/image.png" width="1">"#,
+        r#"<img alt="no src">"#,
+    ] {
+        assert_eq!(add_image_dimensions(html)?, html);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_use_scaled_images() -> eyre::Result<()> {
+    // images that aren’t attachments, or aren’t there, are left alone.
+    for html in [
+        r#"<img src="https://example.com/image.png">"#,
+        r#"<img src="attachments/00000000-0000-0000-0000-000000000000/missing.png">"#,
+        r#"<img alt="no src">"#,
+    ] {
+        let result = use_scaled_images(html)?;
+        assert_eq!(result.html, html);
+        assert!(result.needs_attachments.is_empty());
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_read_more_excerpt() -> eyre::Result<()> {
+    let excerpt = |html: &str, max_chars| -> eyre::Result<Option<String>> {
+        let post = extract_metadata(html)?;
+        read_more_excerpt(&serialize_html_fragment(post.dom)?, max_chars)
+    };
+    assert_eq!(excerpt("<p>hello world</p>", None)?, None);
+    assert_eq!(excerpt("<p>hello world</p>", Some(11))?, None);
+    assert_eq!(
+        excerpt("<p>hello world</p><p>more</p>", Some(8))?.as_deref(),
+        Some("<p>hello…</p>")
+    );
+    assert_eq!(
+        excerpt("<p>hello world</p><p>more</p>", Some(11))?.as_deref(),
+        Some("<p>hello world</p>")
+    );
+    assert_eq!(
+        excerpt("<p><b>hello</b> world</p><p>more</p>", Some(3))?.as_deref(),
+        Some("<p><b>hel…</b></p>")
+    );
+    assert_eq!(
+        excerpt("<style>p { color: red; }</style><p>hello</p>", Some(5))?,
+        None
+    );
+
+    // the marker wins over `max_chars`, and survives filtering.
+    assert_eq!(
+        excerpt("<p>hello world</p>\n<!-- more -->\n<p>more</p>", Some(3))?.as_deref(),
+        Some("<p>hello world</p>\n")
+    );
+    let post = crate::TemplatedPost::filter("<p>hello</p><!-- more --><p>world</p>", None)?;
+    assert_eq!(
+        read_more_excerpt(&post.safe_html, None)?.as_deref(),
+        Some("<p>hello</p>")
+    );
+
+    Ok(())
+}
+
+#[test]
+fn test_threads_content_text() -> eyre::Result<()> {
+    assert_eq!(
+        threads_content_text(
+            r#"<article class="thread"><article class="post"><header><a class="p-author">eggbug</a></header>
+<div class="content e-content"><style>p { color: red; }</style><p>hello
+  <b>world</b></p></div><footer><a class="p-category">#tag</a></footer></article>
+<article class="post"><div class="content e-content"><p>second&amp;post</p><p class="title-only">(no text)</p></div></article></article>"#
+        )?,
+        "hello world second&post"
+    );
+    assert_eq!(threads_content_text("<p>not in a post</p>")?, "");
+
+    Ok(())
+}
+
+#[test]
+fn test_localise_cohost_post_links() -> eyre::Result<()> {
+    let pages = HashMap::from([(123456, "/123456.html".to_owned())]);
+    let html = r#"<p><a href="https://cohost.org/staff/post/123456-slug">here</a> and <a href="https://cohost.org/staff/post/7-other">there</a> and <a href="https://cohost.org/staff">staff</a></p>"#;
+    assert_eq!(
+        localise_cohost_post_links(html, &pages, false)?,
+        r#"<p><a href="/123456.html">here</a> and <a href="https://cohost.org/staff/post/7-other">there</a> and <a href="https://cohost.org/staff">staff</a></p>"#
+    );
+    assert_eq!(
+        localise_cohost_post_links(html, &pages, true)?,
+        r#"<p><a href="/123456.html">here</a> and <a href="https://cohost.org/staff/post/7-other">there</a> <small class="cohost-gone">(cohost is gone)</small> and <a href="https://cohost.org/staff">staff</a></p>"#
+    );
+
     Ok(())
 }
@@ -0,0 +1,739 @@
+//! generated social-card images for `og:image`, for threads without an image of their own, for
+//! `[og_images]`.
+//!
+//! each card is the title of the thread and your `site_title`, in the font you choose, on a plain
+//! background. there are no image or font crates here, so this has a small truetype parser, a
+//! scanline rasteriser, and a png encoder, which are all we need for text on a solid color.
+
+use std::{
+    collections::BTreeMap,
+    fs::read,
+    io::Write,
+    sync::{Arc, Mutex},
+};
+
+use flate2::{write::ZlibEncoder, Compression};
+use jane_eyre::eyre::{self, bail, OptionExt, WrapErr};
+
+use crate::SETTINGS;
+
+pub const WIDTH: usize = 1200;
+pub const HEIGHT: usize = 630;
+const PADDING: f32 = 80.0;
+const TITLE_SIZE: f32 = 64.0;
+const TITLE_MAX_LINES: usize = 4;
+const SITE_TITLE_SIZE: f32 = 36.0;
+/// how many times each row of pixels is sampled, for antialiasing.
+const SUBSAMPLES: usize = 5;
+/// how many line segments each quadratic curve in a glyph becomes.
+const CURVE_SEGMENTS: usize = 8;
+
+/// returns a card for the thread with the given title, as a png, using `[og_images]`.
+pub fn render_og_image(title: &str) -> eyre::Result<Vec<u8>> {
+    let settings = SETTINGS
+        .og_images
+        .as_ref()
+        .ok_or_eyre("og_images setting is not set")?;
+    let font = load_font(&settings.font)?;
+    let background = parse_hex_color(settings.background())
+        .ok_or_eyre("og_images: background is not a color")?;
+    let foreground = parse_hex_color(settings.foreground())
+        .ok_or_eyre("og_images: foreground is not a color")?;
+
+    let mut coverage = Coverage::new(WIDTH, HEIGHT);
+    let max_width = WIDTH as f32 - 2.0 * PADDING;
+    let lines = wrap_lines(title, max_width, TITLE_MAX_LINES, |text| {
+        font.text_width(text, TITLE_SIZE)
+    });
+    let mut baseline = PADDING + font.ascender(TITLE_SIZE);
+    for line in lines {
+        coverage.draw_text(&font, &line, TITLE_SIZE, PADDING, baseline);
+        baseline += font.line_height(TITLE_SIZE);
+    }
+    let site_title = wrap_lines(&SETTINGS.site_title, max_width, 1, |text| {
+        font.text_width(text, SITE_TITLE_SIZE)
+    });
+    for line in site_title {
+        coverage.draw_text(
+            &font,
+            &line,
+            SITE_TITLE_SIZE,
+            PADDING,
+            HEIGHT as f32 - PADDING,
+        );
+    }
+
+    encode_png(WIDTH, HEIGHT, &coverage.composite(background, foreground))
+}
+
+/// returns the font at the given path, parsing it only once per render.
+fn load_font(path: &str) -> eyre::Result<Arc<Font>> {
+    static CACHE: Mutex<BTreeMap<String, Arc<Font>>> = Mutex::new(BTreeMap::new());
+
+    let mut cache = CACHE.lock().unwrap();
+    if let Some(font) = cache.get(path) {
+        return Ok(font.clone());
+    }
+    let data = read(path).wrap_err_with(|| format!("failed to read font: {path:?}"))?;
+    let font = Arc::new(Font::parse(data).wrap_err_with(|| format!("bad font: {path:?}"))?);
+    cache.insert(path.to_owned(), font.clone());
+
+    Ok(font)
+}
+
+/// parses a css color like `#83254f` or `#fff` into rgb.
+pub fn parse_hex_color(color: &str) -> Option<[u8; 3]> {
+    let hex = color.strip_prefix('#')?;
+    if !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+        return None;
+    }
+    let digit = |i: usize| u8::from_str_radix(&hex[i..i + 1], 16).ok();
+    match hex.len() {
+        3 => Some([digit(0)? * 17, digit(1)? * 17, digit(2)? * 17]),
+        6 => {
+            let byte = |i: usize| u8::from_str_radix(&hex[i..i + 2], 16).ok();
+            Some([byte(0)?, byte(2)?, byte(4)?])
+        }
+        _ => None,
+    }
+}
+
+/// breaks the text into at most `max_lines` lines no wider than `max_width`, at spaces where
+/// possible, ending the last line with an ellipsis if the text didn’t fit.
+fn wrap_lines(
+    text: &str,
+    max_width: f32,
+    max_lines: usize,
+    width: impl Fn(&str) -> f32,
+) -> Vec<String> {
+    let mut lines: Vec<String> = vec![];
+    let mut line = String::new();
+    for word in text.split_whitespace() {
+        let candidate = match line.is_empty() {
+            true => word.to_owned(),
+            false => format!("{line} {word}"),
+        };
+        if width(&candidate) <= max_width {
+            line = candidate;
+            continue;
+        }
+        if !line.is_empty() {
+            lines.push(std::mem::take(&mut line));
+        }
+        // words too long for a line of their own are broken anywhere.
+        for c in word.chars() {
+            line.push(c);
+            if width(&line) > max_width && line.chars().count() > 1 {
+                line.pop();
+                lines.push(std::mem::replace(&mut line, c.to_string()));
+            }
+        }
+    }
+    if !line.is_empty() {
+        lines.push(line);
+    }
+
+    if lines.len() > max_lines {
+        lines.truncate(max_lines);
+        let last = lines.last_mut().expect("guaranteed by max_lines > 0");
+        loop {
+            let candidate = format!("{}…", last.trim_end());
+            if width(&candidate) <= max_width || last.is_empty() {
+                *last = candidate;
+                break;
+            }
+            last.pop();
+        }
+    }
+
+    lines
+}
+
+/// the parts of a truetype font needed to draw its glyphs.
+struct Font {
+    data: Vec<u8>,
+    units_per_em: f32,
+    ascender: f32,
+    descender: f32,
+    line_gap: f32,
+    long_loca: bool,
+    num_glyphs: u16,
+    num_h_metrics: u16,
+    cmap: usize,
+    hmtx: usize,
+    loca: usize,
+    glyf: usize,
+}
+
+impl Font {
+    fn parse(data: Vec<u8>) -> eyre::Result<Self> {
+        let num_tables = read_u16(&data, 4)?;
+        let mut tables = BTreeMap::new();
+        for i in 0..usize::from(num_tables) {
+            let record = 12 + 16 * i;
+            let tag = data.get(record..record + 4).ok_or_eyre("truncated font")?;
+            tables.insert(tag.to_owned(), read_u32(&data, record + 8)? as usize);
+        }
+        let table = |tag: &[u8; 4]| {
+            tables
+                .get(&tag[..])
+                .copied()
+                .ok_or_else(|| eyre::eyre!("font has no {:?} table", String::from_utf8_lossy(tag)))
+        };
+        let head = table(b"head")?;
+        let hhea = table(b"hhea")?;
+        let maxp = table(b"maxp")?;
+
+        let mut result = Self {
+            units_per_em: read_u16(&data, head + 18)?.into(),
+            long_loca: read_i16(&data, head + 50)? != 0,
+            ascender: read_i16(&data, hhea + 4)?.into(),
+            descender: read_i16(&data, hhea + 6)?.into(),
+            line_gap: read_i16(&data, hhea + 8)?.into(),
+            num_h_metrics: read_u16(&data, hhea + 34)?,
+            num_glyphs: read_u16(&data, maxp + 4)?,
+            cmap: 0,
+            hmtx: table(b"hmtx")?,
+            loca: table(b"loca")?,
+            glyf: table(b"glyf")?,
+            data,
+        };
+        result.cmap = result.unicode_cmap_subtable(table(b"cmap")?)?;
+        if result.units_per_em == 0.0 || result.num_h_metrics == 0 {
+            bail!("font has bad metrics");
+        }
+
+        Ok(result)
+    }
+
+    /// finds a format 4 or format 12 subtable for unicode.
+    fn unicode_cmap_subtable(&self, cmap: usize) -> eyre::Result<usize> {
+        let mut best = None;
+        for i in 0..usize::from(read_u16(&self.data, cmap + 2)?) {
+            let record = cmap + 4 + 8 * i;
+            let platform = read_u16(&self.data, record)?;
+            let encoding = read_u16(&self.data, record + 2)?;
+            let subtable = cmap + read_u32(&self.data, record + 4)? as usize;
+            let format = read_u16(&self.data, subtable)?;
+            let unicode = platform == 0 || (platform == 3 && (encoding == 1 || encoding == 10));
+            match format {
+                12 if unicode => return Ok(subtable),
+                4 if unicode => best = Some(subtable),
+                _ => {}
+            }
+        }
+
+        best.ok_or_eyre("font has no unicode cmap")
+    }
+
+    fn glyph_index(&self, c: char) -> u16 {
+        self.lookup_glyph_index(u32::from(c)).unwrap_or(0)
+    }
+
+    fn lookup_glyph_index(&self, c: u32) -> eyre::Result<u16> {
+        let data = &self.data;
+        let subtable = self.cmap;
+        if read_u16(data, subtable)? == 12 {
+            for i in 0..read_u32(data, subtable + 12)? as usize {
+                let group = subtable + 16 + 12 * i;
+                let start = read_u32(data, group)?;
+                let end = read_u32(data, group + 4)?;
+                if (start..=end).contains(&c) {
+                    let glyph = read_u32(data, group + 8)? + (c - start);
+                    return Ok(u16::try_from(glyph).unwrap_or(0));
+                }
+            }
+            return Ok(0);
+        }
+
+        let Ok(c) = u16::try_from(c) else {
+            return Ok(0);
+        };
+        let segments = usize::from(read_u16(data, subtable + 6)? / 2);
+        let end_codes = subtable + 14;
+        let start_codes = end_codes + 2 * segments + 2;
+        let deltas = start_codes + 2 * segments;
+        let range_offsets = deltas + 2 * segments;
+        for i in 0..segments {
+            if c > read_u16(data, end_codes + 2 * i)? {
+                continue;
+            }
+            let start = read_u16(data, start_codes + 2 * i)?;
+            if c < start {
+                return Ok(0);
+            }
+            let delta = read_u16(data, deltas + 2 * i)?;
+            let range_offset = read_u16(data, range_offsets + 2 * i)?;
+            if range_offset == 0 {
+                return Ok(c.wrapping_add(delta));
+            }
+            let address =
+                range_offsets + 2 * i + usize::from(range_offset) + 2 * usize::from(c - start);
+            return match read_u16(data, address)? {
+                0 => Ok(0),
+                glyph => Ok(glyph.wrapping_add(delta)),
+            };
+        }
+
+        Ok(0)
+    }
+
+    fn advance_width(&self, glyph: u16) -> f32 {
+        let index = glyph.min(self.num_h_metrics - 1);
+        read_u16(&self.data, self.hmtx + 4 * usize::from(index)).map_or(0.0, f32::from)
+    }
+
+    fn scale(&self, size: f32) -> f32 {
+        size / self.units_per_em
+    }
+
+    fn ascender(&self, size: f32) -> f32 {
+        self.ascender * self.scale(size)
+    }
+
+    fn line_height(&self, size: f32) -> f32 {
+        (self.ascender - self.descender + self.line_gap) * self.scale(size)
+    }
+
+    fn text_width(&self, text: &str, size: f32) -> f32 {
+        text.chars()
+            .map(|c| self.advance_width(self.glyph_index(c)))
+            .sum::<f32>()
+            * self.scale(size)
+    }
+
+    /// returns the contours of the glyph, in font units, as closed polylines.
+    fn outline(&self, glyph: u16) -> Vec<Vec<(f32, f32)>> {
+        let mut contours = vec![];
+        // glyphs with bad data are drawn as blank, rather than failing the whole render.
+        let _ = self.append_outline(glyph, [1.0, 0.0, 0.0, 1.0, 0.0, 0.0], 0, &mut contours);
+        contours
+    }
+
+    fn glyph_range(&self, glyph: u16) -> eyre::Result<(usize, usize)> {
+        if glyph >= self.num_glyphs {
+            bail!("glyph out of range");
+        }
+        let i = usize::from(glyph);
+        let (start, end) = match self.long_loca {
+            true => (
+                read_u32(&self.data, self.loca + 4 * i)? as usize,
+                read_u32(&self.data, self.loca + 4 * i + 4)? as usize,
+            ),
+            false => (
+                2 * usize::from(read_u16(&self.data, self.loca + 2 * i)?),
+                2 * usize::from(read_u16(&self.data, self.loca + 2 * i + 2)?),
+            ),
+        };
+
+        Ok((self.glyf + start, self.glyf + end))
+    }
+
+    /// appends the contours of the glyph, transformed by the affine `[a, b, c, d, e, f]`, where
+    /// `x′ = ax + cy + e` and `y′ = bx + dy + f`.
+    fn append_outline(
+        &self,
+        glyph: u16,
+        transform: [f32; 6],
+        depth: usize,
+        contours: &mut Vec<Vec<(f32, f32)>>,
+    ) -> eyre::Result<()> {
+        let data = &self.data;
+        let (start, end) = self.glyph_range(glyph)?;
+        if start == end {
+            // no outline, like a space.
+            return Ok(());
+        }
+        let [a, b, c, d, e, f] = transform;
+        let apply = |(x, y): (f32, f32)| (a * x + c * y + e, b * x + d * y + f);
+
+        let num_contours = read_i16(data, start)?;
+        if num_contours < 0 {
+            if depth > 8 {
+                bail!("composite glyph nested too deeply");
+            }
+            let mut offset = start + 10;
+            loop {
+                let flags = read_u16(data, offset)?;
+                let component = read_u16(data, offset + 2)?;
+                offset += 4;
+                let (dx, dy) = if flags & 0x0001 != 0 {
+                    offset += 4;
+                    (read_i16(data, offset - 4)?, read_i16(data, offset - 2)?)
+                } else {
+                    offset += 2;
+                    let byte = |i: usize| data.get(i).map(|&b| i16::from(b as i8));
+                    (
+                        byte(offset - 2).ok_or_eyre("truncated glyph")?,
+                        byte(offset - 1).ok_or_eyre("truncated glyph")?,
+                    )
+                };
+                // components positioned by matching points are rare, and are drawn unmoved.
+                let (dx, dy) = match flags & 0x0002 != 0 {
+                    true => (f32::from(dx), f32::from(dy)),
+                    false => (0.0, 0.0),
+                };
+                let f2dot14 = |i: usize| read_i16(data, i).map(|v| f32::from(v) / 16384.0);
+                let [ca, cb, cc, cd] = if flags & 0x0008 != 0 {
+                    offset += 2;
+                    let scale = f2dot14(offset - 2)?;
+                    [scale, 0.0, 0.0, scale]
+                } else if flags & 0x0040 != 0 {
+                    offset += 4;
+                    [f2dot14(offset - 4)?, 0.0, 0.0, f2dot14(offset - 2)?]
+                } else if flags & 0x0080 != 0 {
+                    offset += 8;
+                    [
+                        f2dot14(offset - 8)?,
+                        f2dot14(offset - 6)?,
+                        f2dot14(offset - 4)?,
+                        f2dot14(offset - 2)?,
+                    ]
+                } else {
+                    [1.0, 0.0, 0.0, 1.0]
+                };
+                let (e2, f2) = apply((dx, dy));
+                let combined = [
+                    a * ca + c * cb,
+                    b * ca + d * cb,
+                    a * cc + c * cd,
+                    b * cc + d * cd,
+                    e2,
+                    f2,
+                ];
+                self.append_outline(component, combined, depth + 1, contours)?;
+                if flags & 0x0020 == 0 {
+                    return Ok(());
+                }
+            }
+        }
+
+        let num_contours = num_contours as usize;
+        let mut end_points = vec![];
+        for i in 0..num_contours {
+            end_points.push(usize::from(read_u16(data, start + 10 + 2 * i)?));
+        }
+        let num_points = end_points.last().map_or(0, |last| last + 1);
+        let instructions = start + 10 + 2 * num_contours;
+        let mut offset = instructions + 2 + usize::from(read_u16(data, instructions)?);
+
+        let mut flags = Vec::with_capacity(num_points);
+        while flags.len() < num_points {
+            let flag = *data.get(offset).ok_or_eyre("truncated glyph")?;
+            offset += 1;
+            flags.push(flag);
+            if flag & 0x08 != 0 {
+                let repeat = *data.get(offset).ok_or_eyre("truncated glyph")?;
+                offset += 1;
+                for _ in 0..repeat {
+                    flags.push(flag);
+                }
+            }
+        }
+        flags.truncate(num_points);
+
+        let mut read_coordinates = |short: u8, same_or_positive: u8| -> eyre::Result<Vec<f32>> {
+            let mut value = 0i32;
+            let mut result = Vec::with_capacity(num_points);
+            for &flag in flags.iter() {
+                if flag & short != 0 {
+                    let delta = i32::from(*data.get(offset).ok_or_eyre("truncated glyph")?);
+                    offset += 1;
+                    value += if flag & same_or_positive != 0 {
+                        delta
+                    } else {
+                        -delta
+                    };
+                } else if flag & same_or_positive == 0 {
+                    value += i32::from(read_i16(data, offset)?);
+                    offset += 2;
+                }
+                result.push(value as f32);
+            }
+            Ok(result)
+        };
+        let xs = read_coordinates(0x02, 0x10)?;
+        let ys = read_coordinates(0x04, 0x20)?;
+
+        let mut contour_start = 0;
+        for &contour_end in end_points.iter() {
+            if contour_end < contour_start || contour_end >= num_points {
+                bail!("bad contour in glyph");
+            }
+            let points = (contour_start..=contour_end)
+                .map(|i| (apply((xs[i], ys[i])), flags[i] & 0x01 != 0))
+                .collect::<Vec<_>>();
+            contours.push(flatten_contour(&points));
+            contour_start = contour_end + 1;
+        }
+
+        Ok(())
+    }
+}
+
+/// turns a truetype contour, made of on-curve points and quadratic control points, into a closed
+/// polyline.
+fn flatten_contour(points: &[((f32, f32), bool)]) -> Vec<(f32, f32)> {
+    let midpoint = |(x0, y0): (f32, f32), (x1, y1): (f32, f32)| ((x0 + x1) / 2.0, (y0 + y1) / 2.0);
+    let n = points.len();
+    if n == 0 {
+        return vec![];
+    }
+    // start at an on-curve point, or if there are none, between the last and first points.
+    let (first, order) = match points.iter().position(|&(_, on_curve)| on_curve) {
+        Some(i) => (
+            points[i].0,
+            (1..=n).map(|k| (i + k) % n).collect::<Vec<_>>(),
+        ),
+        None => (midpoint(points[n - 1].0, points[0].0), (0..n).collect()),
+    };
+
+    let mut result = vec![first];
+    let mut control = None;
+    let quad = |result: &mut Vec<(f32, f32)>, (cx, cy): (f32, f32), (x1, y1): (f32, f32)| {
+        let (x0, y0) = *result.last().expect("guaranteed by first");
+        for step in 1..=CURVE_SEGMENTS {
+            let t = step as f32 / CURVE_SEGMENTS as f32;
+            let u = 1.0 - t;
+            result.push((
+                u * u * x0 + 2.0 * u * t * cx + t * t * x1,
+                u * u * y0 + 2.0 * u * t * cy + t * t * y1,
+            ));
+        }
+    };
+    for i in order {
+        let (point, on_curve) = points[i];
+        match (on_curve, control.take()) {
+            (true, Some(c)) => quad(&mut result, c, point),
+            (true, None) => result.push(point),
+            (false, Some(c)) => {
+                quad(&mut result, c, midpoint(c, point));
+                control = Some(point);
+            }
+            (false, None) => control = Some(point),
+        }
+    }
+    if let Some(c) = control {
+        quad(&mut result, c, first);
+    }
+
+    result
+}
+
+/// how much of each pixel is covered by text, from 0 to 1 (or more, where glyphs overlap).
+struct Coverage {
+    width: usize,
+    height: usize,
+    alpha: Vec<f32>,
+}
+
+impl Coverage {
+    fn new(width: usize, height: usize) -> Self {
+        Self {
+            width,
+            height,
+            alpha: vec![0.0; width * height],
+        }
+    }
+
+    fn draw_text(&mut self, font: &Font, text: &str, size: f32, x: f32, baseline: f32) {
+        let scale = font.scale(size);
+        let mut pen = x;
+        for c in text.chars() {
+            let glyph = font.glyph_index(c);
+            let contours = font
+                .outline(glyph)
+                .into_iter()
+                .map(|contour| {
+                    contour
+                        .into_iter()
+                        .map(|(gx, gy)| (pen + gx * scale, baseline - gy * scale))
+                        .collect::<Vec<_>>()
+                })
+                .collect::<Vec<_>>();
+            self.fill(&contours);
+            pen += font.advance_width(glyph) * scale;
+        }
+    }
+
+    /// fills the closed polylines, in pixels, with the nonzero winding rule.
+    fn fill(&mut self, contours: &[Vec<(f32, f32)>]) {
+        let mut edges = vec![];
+        for contour in contours {
+            for (i, &(x0, y0)) in contour.iter().enumerate() {
+                let (x1, y1) = contour[(i + 1) % contour.len()];
+                match y0.partial_cmp(&y1) {
+                    Some(std::cmp::Ordering::Less) => edges.push((x0, y0, x1, y1, 1)),
+                    Some(std::cmp::Ordering::Greater) => edges.push((x1, y1, x0, y0, -1)),
+                    _ => {}
+                }
+            }
+        }
+        let Some(top) = edges.iter().map(|e| e.1).reduce(f32::min) else {
+            return;
+        };
+        let bottom = edges.iter().map(|e| e.3).fold(top, f32::max);
+        let top = top.floor().max(0.0) as usize;
+        let bottom = (bottom.ceil().max(0.0) as usize).min(self.height);
+
+        let mut crossings = vec![];
+        for row in top..bottom {
+            for sample in 0..SUBSAMPLES {
+                let y = row as f32 + (sample as f32 + 0.5) / SUBSAMPLES as f32;
+                crossings.clear();
+                for &(x0, y0, x1, y1, direction) in edges.iter() {
+                    if y0 <= y && y < y1 {
+                        crossings.push((x0 + (y - y0) / (y1 - y0) * (x1 - x0), direction));
+                    }
+                }
+                crossings.sort_by(|a, b| a.0.total_cmp(&b.0));
+                let mut winding = 0;
+                let mut span_start = 0.0;
+                for &(x, direction) in crossings.iter() {
+                    if winding == 0 {
+                        span_start = x;
+                    }
+                    winding += direction;
+                    if winding == 0 {
+                        self.add_span(row, span_start, x, 1.0 / SUBSAMPLES as f32);
+                    }
+                }
+            }
+        }
+    }
+
+    fn add_span(&mut self, row: usize, x0: f32, x1: f32, amount: f32) {
+        let x0 = x0.clamp(0.0, self.width as f32);
+        let x1 = x1.clamp(0.0, self.width as f32);
+        if x1 <= x0 {
+            return;
+        }
+        let pixels = &mut self.alpha[row * self.width..][..self.width];
+        let first = x0 as usize;
+        let last = (x1 as usize).min(self.width - 1);
+        if first == last {
+            pixels[first] += (x1 - x0) * amount;
+            return;
+        }
+        pixels[first] += (first as f32 + 1.0 - x0) * amount;
+        for pixel in &mut pixels[first + 1..last] {
+            *pixel += amount;
+        }
+        pixels[last] += (x1 - last as f32).min(1.0) * amount;
+    }
+
+    /// blends the foreground onto the background by the coverage, returning rgb pixels.
+    fn composite(&self, background: [u8; 3], foreground: [u8; 3]) -> Vec<u8> {
+        let mut result = Vec::with_capacity(self.alpha.len() * 3);
+        for &alpha in self.alpha.iter() {
+            let alpha = alpha.clamp(0.0, 1.0);
+            for (bg, fg) in background.into_iter().zip(foreground) {
+                let value = f32::from(bg) * (1.0 - alpha) + f32::from(fg) * alpha;
+                result.push(value.round() as u8);
+            }
+        }
+        result
+    }
+}
+
+/// encodes rgb pixels as a png.
+fn encode_png(width: usize, height: usize, rgb: &[u8]) -> eyre::Result<Vec<u8>> {
+    if rgb.len() != width * height * 3 {
+        bail!("wrong number of pixels for {width}x{height}");
+    }
+    let mut result = b"\x89PNG\r\n\x1A\n".to_vec();
+    let mut chunk = |kind: &[u8; 4], data: &[u8]| -> eyre::Result<()> {
+        result.extend(u32::try_from(data.len())?.to_be_bytes());
+        let start = result.len();
+        result.extend(kind);
+        result.extend(data);
+        let crc = crc32fast::hash(&result[start..]);
+        result.extend(crc.to_be_bytes());
+        Ok(())
+    };
+
+    let mut header = vec![];
+    header.extend(u32::try_from(width)?.to_be_bytes());
+    header.extend(u32::try_from(height)?.to_be_bytes());
+    // 8 bits per channel, rgb, deflate, no filtering, no interlacing.
+    header.extend([8, 2, 0, 0, 0]);
+    chunk(b"IHDR", &header)?;
+
+    let mut encoder = ZlibEncoder::new(vec![], Compression::default());
+    for row in rgb.chunks(width * 3) {
+        // filter type 0, none.
+        encoder.write_all(&[0])?;
+        encoder.write_all(row)?;
+    }
+    chunk(b"IDAT", &encoder.finish()?)?;
+    chunk(b"IEND", &[])?;
+
+    Ok(result)
+}
+
+fn read_u16(data: &[u8], offset: usize) -> eyre::Result<u16> {
+    let bytes = data.get(offset..offset + 2).ok_or_eyre("truncated font")?;
+    Ok(u16::from_be_bytes([bytes[0], bytes[1]]))
+}
+
+fn read_i16(data: &[u8], offset: usize) -> eyre::Result<i16> {
+    read_u16(data, offset).map(|value| value as i16)
+}
+
+fn read_u32(data: &[u8], offset: usize) -> eyre::Result<u32> {
+    let bytes = data.get(offset..offset + 4).ok_or_eyre("truncated font")?;
+    Ok(u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+}
+
+#[test]
+fn test_wrap_lines() {
+    // one unit per character.
+    let width = |text: &str| text.chars().count() as f32;
+    assert_eq!(wrap_lines("hello world", 11.0, 4, width), ["hello world"]);
+    assert_eq!(
+        wrap_lines("hello world", 10.0, 4, width),
+        ["hello", "world"]
+    );
+    assert_eq!(wrap_lines("  ", 10.0, 4, width), Vec::<String>::new());
+    assert_eq!(wrap_lines("abcdefgh", 3.0, 4, width), ["abc", "def", "gh"]);
+    assert_eq!(
+        wrap_lines("one two three four", 5.0, 2, width),
+        ["one", "two…"]
+    );
+    assert_eq!(wrap_lines("three four", 5.0, 1, width), ["thre…"]);
+}
+
+#[test]
+fn test_encode_png() -> eyre::Result<()> {
+    use flate2::read::ZlibDecoder;
+    use std::io::Read;
+
+    let mut coverage = Coverage::new(4, 2);
+    coverage.fill(&[vec![(0.0, 0.0), (2.0, 0.0), (2.0, 2.0), (0.0, 2.0)]]);
+    let rgb = coverage.composite([0, 0, 0], [255, 255, 255]);
+    assert_eq!(rgb[0..12], [255, 255, 255, 255, 255, 255, 0, 0, 0, 0, 0, 0]);
+
+    let png = encode_png(4, 2, &rgb)?;
+    assert_eq!(png[..8], *b"\x89PNG\r\n\x1A\n");
+    assert_eq!(png[12..16], *b"IHDR");
+    assert_eq!(png[16..24], [0, 0, 0, 4, 0, 0, 0, 2]);
+    let ihdr_crc = crc32fast::hash(&png[12..29]);
+    assert_eq!(png[29..33], ihdr_crc.to_be_bytes());
+    assert_eq!(png[37..41], *b"IDAT");
+    let idat_len = u32::from_be_bytes(png[33..37].try_into()?) as usize;
+    let mut pixels = vec![];
+    ZlibDecoder::new(&png[41..41 + idat_len]).read_to_end(&mut pixels)?;
+    assert_eq!(pixels.len(), 2 * (1 + 4 * 3));
+    assert_eq!(
+        pixels[13..26],
+        [0, 255, 255, 255, 255, 255, 255, 0, 0, 0, 0, 0, 0]
+    );
+    assert_eq!(png[png.len() - 8..png.len() - 4], *b"IEND");
+
+    assert_eq!(parse_hex_color("#83254f"), Some([0x83, 0x25, 0x4f]));
+    assert_eq!(parse_hex_color("#fff"), Some([255, 255, 255]));
+    assert_eq!(parse_hex_color("red"), None);
+    assert_eq!(parse_hex_color("#12345"), None);
+
+    Ok(())
+}
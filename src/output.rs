@@ -1,28 +1,203 @@
 //! output templates. these templates are wrapped in a safe interface that
 //! guarantees that path-relative urls are made path-absolute.
 
+use std::{
+    collections::BTreeSet,
+    fs::read_to_string,
+    ops::{Deref, Range},
+};
+
 use askama::Template;
-use jane_eyre::eyre;
-use markup5ever_rcdom::{NodeData, RcDom};
+use base64::{prelude::BASE64_STANDARD, Engine};
+use chrono::SecondsFormat;
+use html5ever::{Attribute, QualName};
+use jane_eyre::eyre::{self, OptionExt};
+use markup5ever_rcdom::{Handle, NodeData, RcDom};
+use serde::Serialize;
+use sha2::{Digest, Sha256};
 
 use crate::{
+    command::render::static_file_name,
+    comments::{count_comments, ArchivedComment},
+    date::parse_date,
     dom::{
-        html_attributes_with_urls, parse_html_document, parse_html_fragment,
-        serialize_html_document, serialize_html_fragment, TendrilExt, Transform,
+        create_element, html_attributes_with_urls, parse_html_document, parse_html_fragment,
+        serialize_html_document, serialize_html_fragment, text_content, AttrsRefExt,
+        BreadthTraverse, QualNameExt, TendrilExt, Transform,
     },
-    path::{parse_path_relative_scheme_less_url_string, SitePath},
-    Author, PostMeta, Thread, SETTINGS,
+    emoji::{render_emoji, EmojiContext},
+    meta::read_more_excerpt,
+    path::SitePath,
+    settings::{CollectionLayout, Colophon, TagPage, ThreadPostOrder, TitleOnlyPosts},
+    transform::apply_html_transforms,
+    Author, PostInThread, PostMeta, TemplatedPost, Thread, SETTINGS,
 };
 
 #[derive(Clone, Debug, Template)]
 #[template(path = "threads.html")]
 pub struct ThreadsPageTemplate<'template> {
+    lang: Option<&'template str>,
     thread_page_meta: Option<&'template str>,
     /// not `threads: Vec<Thread>`, to encourage us to cache ThreadsContentTemplate output between
     /// individual thread pages and combined collection pages.
     threads_content: &'template str,
     page_title: &'template str,
     feed_href: &'template Option<SitePath>,
+    /// for thread pages, if `print_pages` is enabled, the print-friendly version of the page.
+    print_href: &'template Option<SitePath>,
+    /// for thread pages, if `adjacent_threads_collection` is set, the previous and next threads.
+    adjacent: &'template AdjacentThreads,
+    /// for thread pages in a `series`, the previous and next parts.
+    series: Option<&'template SeriesThreads>,
+    /// for tag pages, the tags namespaced under this tag, like `project/autost` for `project`.
+    child_tags: &'template [String],
+    /// for tag pages, the description and banner image from `[tag_pages]`, if any.
+    tag_page: Option<&'template TagPage>,
+    /// for the main page, if `index_authors` is enabled, the authors and their thread counts.
+    authors: &'template [(Author, usize)],
+    /// for listing pages split into more than one page, the other pages.
+    pagination: Option<&'template Pagination>,
+    /// for thread pages, the `fediverse_url` of the thread, if any.
+    fediverse_url: Option<&'template str>,
+    /// for thread pages, if `cohost_comments` is enabled, the rendered [`CommentsTemplate`].
+    comments: Option<&'template str>,
+    /// for thread pages, if `embed_source` is set, the source of the thread’s main post.
+    source: Option<&'template EmbeddedSource>,
+}
+
+/// same as [`ThreadsPageTemplate`], but with the threads in a grid (`collection_layouts`).
+#[derive(Clone, Debug, Template)]
+#[template(path = "threads-grid.html")]
+pub struct ThreadsGridPageTemplate<'template> {
+    page: ThreadsPageTemplate<'template>,
+}
+
+/// lets the parent template, `threads.html`, see the fields of the page.
+impl<'template> Deref for ThreadsGridPageTemplate<'template> {
+    type Target = ThreadsPageTemplate<'template>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.page
+    }
+}
+
+/// the chronologically previous (older) and next (newer) thread pages of a thread, in the
+/// collection chosen by `adjacent_threads_collection`.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct AdjacentThreads {
+    pub prev: Option<SitePath>,
+    pub next: Option<SitePath>,
+}
+
+/// where a listing page is among the pages it was split into, for `page_size`. pages are newest
+/// first, so `prev` is newer and `next` is older.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Pagination {
+    /// starting from 1.
+    pub page: usize,
+    pub pages: usize,
+    pub first: Option<SitePath>,
+    pub prev: Option<SitePath>,
+    pub next: Option<SitePath>,
+    pub last: Option<SitePath>,
+}
+
+/// the previous and next parts of a thread in its `series`, in the order of `series_index`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SeriesThreads {
+    pub name: String,
+    /// the position of the thread in the series, starting at 1, which may not be its
+    /// `series_index` if there are gaps.
+    pub position: usize,
+    pub len: usize,
+    pub prev: Option<SitePath>,
+    pub next: Option<SitePath>,
+    /// the index page of the series, if `series_pages` is enabled.
+    pub page: Option<SitePath>,
+}
+
+/// the source of a thread’s main post, for thread pages with `embed_source`.
+#[derive(Clone, Debug, PartialEq)]
+pub enum EmbeddedSource {
+    /// the source, with any `--` written as `- -`, since comments can’t contain `-->`.
+    Comment(String),
+    /// the source, with any `</script` written as `<\/script`, so it can’t end the script early.
+    Script(String),
+    /// where the source was written.
+    Download(SitePath),
+}
+
+impl EmbeddedSource {
+    pub fn comment(source: &str) -> Self {
+        Self::Comment(source.replace("--", "- -"))
+    }
+
+    pub fn script(source: &str) -> Self {
+        let mut result = String::with_capacity(source.len());
+        let mut rest = source;
+        while let Some(index) = rest.find("</") {
+            let (before, after) = rest.split_at(index);
+            let is_script_end_tag = after
+                .get(2..8)
+                .is_some_and(|name| name.eq_ignore_ascii_case("script"));
+            result.push_str(before);
+            result.push_str(if is_script_end_tag { "<\\/" } else { "</" });
+            rest = &after[2..];
+        }
+        result.push_str(rest);
+
+        Self::Script(result)
+    }
+}
+
+/// the parts of a thread page that listing pages don’t have, for
+/// [`ThreadsPageTemplate::render_single_thread`].
+#[derive(Clone, Copy, Debug)]
+pub struct ThreadPageParts<'template> {
+    /// if `print_pages` is enabled, the print-friendly version of the page.
+    pub print_href: &'template Option<SitePath>,
+    /// if `adjacent_threads_collection` is set, the previous and next threads.
+    pub adjacent: &'template AdjacentThreads,
+    /// if the thread is in a `series`, the previous and next parts.
+    pub series: Option<&'template SeriesThreads>,
+    /// if `cohost_comments` is enabled, the rendered [`CommentsTemplate`].
+    pub comments: Option<&'template str>,
+    /// if `embed_source` is set, the source of the thread’s main post.
+    pub source: Option<&'template EmbeddedSource>,
+}
+
+/// print-friendly version of a thread page, without the site nav or scripts, and with all
+/// `<details>` (like content warnings) expanded.
+#[derive(Clone, Debug, Template)]
+#[template(path = "thread-print.html")]
+pub struct ThreadPrintPageTemplate<'template> {
+    lang: Option<&'template str>,
+    threads_content: &'template str,
+    page_title: &'template str,
+    /// the normal version of the page.
+    canonical_href: &'template str,
+}
+
+/// every thread in a collection on one page, with a table of contents, for reading offline
+/// (`--all-in-one`). the stylesheet is inlined, so the page works on its own.
+#[derive(Clone, Debug, Template)]
+#[template(path = "all-in-one.html")]
+pub struct AllInOnePageTemplate<'template> {
+    lang: Option<&'template str>,
+    page_title: &'template str,
+    /// the contents of `style.css`, if it was written by this render.
+    stylesheet: Option<String>,
+    sections: &'template [AllInOneSection],
+}
+
+/// one thread in an [`AllInOnePageTemplate`], with its entry in the table of contents.
+#[derive(Clone, Debug)]
+pub struct AllInOneSection {
+    /// the fragment id of the section, like `thread-10000000`.
+    pub id: String,
+    pub title: String,
+    pub published: Option<String>,
+    pub threads_content: String,
 }
 
 #[derive(Clone, Debug, Template)]
@@ -30,6 +205,9 @@ pub struct ThreadsPageTemplate<'template> {
 pub struct ThreadsContentTemplate<'template> {
     thread: &'template Thread,
     simple_mode: bool,
+    /// for listing pages, truncate long posts with a link to the thread page.
+    read_more: bool,
+    post_order: ThreadPostOrder,
 }
 
 #[derive(Clone, Debug, Template)]
@@ -44,59 +222,441 @@ pub struct ThreadOrPostHeaderTemplate<'template> {
 #[template(path = "thread-or-post-author.html")]
 pub struct ThreadOrPostAuthorTemplate<'template> {
     author: &'template Author,
+    /// for your own projects, the avatar from `[[self_projects]]`, if any.
+    avatar: Option<&'template str>,
+}
+
+/// the archived comments beneath a thread page, for `cohost_comments`.
+#[derive(Clone, Debug, Template)]
+#[template(path = "comments.html")]
+pub struct CommentsTemplate<'template> {
+    comments: &'template [ArchivedComment],
+    /// for the top level, the number of comments including replies, for the heading.
+    count: Option<usize>,
+}
+
+/// the content of `colophon.html`, for `[colophon]`.
+#[derive(Clone, Debug, Template)]
+#[template(path = "colophon.html")]
+pub struct ColophonTemplate<'template> {
+    colophon: &'template Colophon,
+    version: &'template str,
+    /// when the site was rendered.
+    now: &'template str,
 }
 
 #[derive(Clone, Debug, Template)]
 #[template(path = "thread-or-post-meta.html")]
 pub struct ThreadOrPostMetaTemplate<'template> {
     thread: &'template Thread,
+    json_ld: Option<String>,
+}
+
+/// schema.org metadata for a thread page, serialised as json-ld.
+///
+/// <https://schema.org/BlogPosting>
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct BlogPosting<'thread> {
+    #[serde(rename = "@context")]
+    context: &'static str,
+    #[serde(rename = "@type")]
+    r#type: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    url: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    headline: Option<&'thread str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    date_published: Option<&'thread str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    date_modified: Option<&'thread str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    author: Option<OneOrMany<Person<'thread>>>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    keywords: Vec<&'thread str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    image: Option<&'thread str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    in_language: Option<&'thread str>,
+}
+
+#[derive(Debug, Serialize)]
+struct Person<'thread> {
+    #[serde(rename = "@type")]
+    r#type: &'static str,
+    name: &'thread str,
+    url: &'thread str,
+}
+
+/// a single value, or an array if there are several, like `author` for collaborations.
+#[derive(Debug, Serialize)]
+#[serde(untagged)]
+enum OneOrMany<T> {
+    One(T),
+    Many(Vec<T>),
+}
+
+impl<T> OneOrMany<T> {
+    fn new(mut values: Vec<T>) -> Option<Self> {
+        match values.len() {
+            0 => None,
+            1 => values.pop().map(Self::One),
+            _ => Some(Self::Many(values)),
+        }
+    }
+}
+
+impl<'thread> BlogPosting<'thread> {
+    fn new(thread: &'thread Thread) -> eyre::Result<Self> {
+        let url = thread.rendered_path()?.map(|path| path.external_url());
+
+        Ok(Self {
+            context: "https://schema.org",
+            r#type: "BlogPosting",
+            url,
+            headline: thread
+                .meta
+                .title
+                .as_deref()
+                .filter(|title| !title.is_empty()),
+            date_published: thread.meta.published.as_deref(),
+            date_modified: thread.meta.updated.as_deref(),
+            author: OneOrMany::new(
+                thread
+                    .meta
+                    .authors()
+                    .map(|author| Person {
+                        r#type: "Person",
+                        name: &author.display_name,
+                        url: &author.href,
+                    })
+                    .collect(),
+            ),
+            keywords: thread
+                .meta
+                .tags
+                .iter()
+                .filter(|tag| !SETTINGS.tag_is_hidden(tag))
+                .map(|tag| &**tag)
+                .collect(),
+            image: thread.og_image.as_deref(),
+            in_language: SETTINGS.lang(Some(&thread.meta)),
+        })
+    }
+
+    /// serialises to json that is safe to put in a `<script>` element, by escaping `<`, so the
+    /// content can never contain `</script>` or `<!--`.
+    fn to_script_content(&self) -> eyre::Result<String> {
+        Ok(serde_json::to_string(self)?.replace('<', "\\u003c"))
+    }
 }
 
 #[derive(Clone, Debug, Template)]
 #[template(path = "feed.xml")]
 pub struct AtomFeedTemplate<'template> {
-    thread_refs: Vec<&'template Thread>,
+    /// from [`AtomFeedEntryTemplate`].
+    entries: &'template str,
+    /// the feed itself, for its `<id>` and `<link rel="self">`.
+    feed_href: &'template SitePath,
     feed_title: &'template str,
+    /// for tag feeds, the description from `[tag_pages]`, if any.
+    subtitle: Option<&'template str>,
     updated: &'template str,
 }
 
+#[derive(Clone, Debug, Template)]
+#[template(path = "feed-entry.xml")]
+pub struct AtomFeedEntryTemplate<'template> {
+    thread: &'template Thread,
+}
+
+/// a [json feed](https://www.jsonfeed.org/version/1.1/), written next to the atom feeds for
+/// collections and tags. the items go last, so [`JsonFeed::render_around_items`] can split them
+/// out.
+#[derive(Debug, Serialize)]
+pub struct JsonFeed<'feed> {
+    version: &'static str,
+    title: &'feed str,
+    home_page_url: &'feed str,
+    feed_url: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    description: Option<&'feed str>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    authors: Vec<JsonFeedAuthor<'feed>>,
+    items: [(); 0],
+}
+
+#[derive(Debug, Serialize)]
+pub struct JsonFeedItem<'feed> {
+    id: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    url: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    title: Option<String>,
+    content_html: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    date_published: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    date_modified: Option<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    authors: Vec<JsonFeedAuthor<'feed>>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    tags: Vec<String>,
+}
+
+#[derive(Debug, Serialize)]
+struct JsonFeedAuthor<'feed> {
+    name: &'feed str,
+    url: &'feed str,
+}
+
+/// not wrapped in `fix_relative_urls`, because `<meta http-equiv="refresh">` is not an attribute
+/// with a url, so the caller needs to make `href` path-absolute (or absolute) anyway.
+#[derive(Clone, Debug, Template)]
+#[template(path = "redirect.html")]
+pub struct RedirectPageTemplate<'template> {
+    href: &'template str,
+    canonical_href: &'template str,
+}
+
+impl<'template> RedirectPageTemplate<'template> {
+    pub fn render(href: &'template str, canonical_href: &'template str) -> eyre::Result<String> {
+        Ok(Self {
+            href,
+            canonical_href,
+        }
+        .render()?)
+    }
+}
+
 impl ThreadsPageTemplate<'_> {
     pub fn render(
         threads_content: &str,
         page_title: &str,
         feed_href: &Option<SitePath>,
     ) -> eyre::Result<String> {
-        fix_relative_urls_in_html_document(
-            &ThreadsPageTemplate {
-                thread_page_meta: None,
-                threads_content,
-                page_title,
-                feed_href,
-            }
-            .render()?,
+        Self::render_collection(
+            threads_content,
+            page_title,
+            feed_href,
+            &[],
+            None,
+            &[],
+            CollectionLayout::List,
+            None,
         )
     }
 
+    pub fn render_tag(
+        threads_content: &str,
+        page_title: &str,
+        feed_href: &Option<SitePath>,
+        child_tags: &[String],
+        tag_page: Option<&TagPage>,
+    ) -> eyre::Result<String> {
+        Self::render_collection(
+            threads_content,
+            page_title,
+            feed_href,
+            child_tags,
+            tag_page,
+            &[],
+            CollectionLayout::List,
+            None,
+        )
+    }
+
+    pub fn render_index(
+        threads_content: &str,
+        page_title: &str,
+        feed_href: &Option<SitePath>,
+        authors: &[(Author, usize)],
+        layout: CollectionLayout,
+    ) -> eyre::Result<String> {
+        Self::render_collection(
+            threads_content,
+            page_title,
+            feed_href,
+            &[],
+            None,
+            authors,
+            layout,
+            None,
+        )
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    pub fn render_collection(
+        threads_content: &str,
+        page_title: &str,
+        feed_href: &Option<SitePath>,
+        child_tags: &[String],
+        tag_page: Option<&TagPage>,
+        authors: &[(Author, usize)],
+        layout: CollectionLayout,
+        pagination: Option<&Pagination>,
+    ) -> eyre::Result<String> {
+        fix_relative_urls_in_html_document(&Self::render_collection_without_fixing_relative_urls(
+            threads_content,
+            page_title,
+            feed_href,
+            child_tags,
+            tag_page,
+            authors,
+            layout,
+            pagination,
+        )?)
+    }
+
+    /// like [`Self::render_collection`], but returns the html before and after where the threads
+    /// go, for listing pages too big to build in memory (`--low-memory`). the caller writes the
+    /// threads in between, each from [`fix_relative_urls_in_threads_content`], along with their
+    /// `inline_hashes` for the content security policy.
+    #[allow(clippy::too_many_arguments)]
+    pub fn render_collection_around_threads(
+        page_title: &str,
+        feed_href: &Option<SitePath>,
+        child_tags: &[String],
+        tag_page: Option<&TagPage>,
+        authors: &[(Author, usize)],
+        layout: CollectionLayout,
+        pagination: Option<&Pagination>,
+        inline_hashes: &InlineHashes,
+    ) -> eyre::Result<(String, String)> {
+        const PLACEHOLDER: &str = "<!-- autost: threads go here -->";
+        let html = Self::render_collection_without_fixing_relative_urls(
+            PLACEHOLDER,
+            page_title,
+            feed_href,
+            child_tags,
+            tag_page,
+            authors,
+            layout,
+            pagination,
+        )?;
+        let dom = parse_html_document(html.as_bytes())?;
+        let dom = fix_relative_urls(dom)?;
+        let dom = add_content_security_policy(dom, inline_hashes)?;
+        let html = serialize_html_document(dom)?;
+        let (before, after) = html
+            .split_once(PLACEHOLDER)
+            .ok_or_eyre("BUG: threads placeholder went missing")?;
+
+        Ok((before.to_owned(), after.to_owned()))
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn render_collection_without_fixing_relative_urls(
+        threads_content: &str,
+        page_title: &str,
+        feed_href: &Option<SitePath>,
+        child_tags: &[String],
+        tag_page: Option<&TagPage>,
+        authors: &[(Author, usize)],
+        layout: CollectionLayout,
+        pagination: Option<&Pagination>,
+    ) -> eyre::Result<String> {
+        let page = ThreadsPageTemplate {
+            lang: SETTINGS.lang(None),
+            thread_page_meta: None,
+            threads_content,
+            page_title,
+            feed_href,
+            print_href: &None,
+            adjacent: &AdjacentThreads::default(),
+            series: None,
+            child_tags,
+            tag_page,
+            authors,
+            pagination,
+            fediverse_url: None,
+            comments: None,
+            source: None,
+        };
+        let html = match layout {
+            CollectionLayout::List => page.render()?,
+            CollectionLayout::Grid => ThreadsGridPageTemplate { page }.render()?,
+        };
+
+        Ok(html)
+    }
+
     pub fn render_single_thread(
         thread: &Thread,
         threads_content: &str,
         page_title: &str,
         feed_href: &Option<SitePath>,
+        parts: ThreadPageParts,
     ) -> eyre::Result<String> {
         let thread_page_meta = ThreadOrPostMetaTemplate::render(thread)?;
 
         fix_relative_urls_in_html_document(
             &ThreadsPageTemplate {
+                lang: SETTINGS.lang(Some(&thread.meta)),
                 thread_page_meta: Some(&thread_page_meta),
                 threads_content,
                 page_title,
                 feed_href,
+                print_href: parts.print_href,
+                adjacent: parts.adjacent,
+                series: parts.series,
+                child_tags: &[],
+                tag_page: None,
+                authors: &[],
+                pagination: None,
+                fediverse_url: thread.meta.fediverse_url.as_deref(),
+                comments: parts.comments,
+                source: parts.source,
             }
             .render()?,
         )
     }
 }
 
+impl ThreadPrintPageTemplate<'_> {
+    pub fn render(
+        thread: &Thread,
+        threads_content: &str,
+        page_title: &str,
+        canonical_href: &str,
+    ) -> eyre::Result<String> {
+        let html = ThreadPrintPageTemplate {
+            lang: SETTINGS.lang(Some(&thread.meta)),
+            threads_content,
+            page_title,
+            canonical_href,
+        }
+        .render()?;
+        let dom = parse_html_document(html.as_bytes())?;
+        let dom = fix_relative_urls(dom)?;
+        let dom = open_all_details(dom);
+        let dom = add_content_security_policy(dom, &InlineHashes::default())?;
+
+        serialize_html_document(dom)
+    }
+}
+
+impl AllInOnePageTemplate<'_> {
+    pub fn render(page_title: &str, sections: &[AllInOneSection]) -> eyre::Result<String> {
+        let stylesheet = match static_file_name("style.css") {
+            Some(filename) => Some(read_to_string(SitePath::ROOT.join(&filename)?)?),
+            None => None,
+        };
+        let html = AllInOnePageTemplate {
+            lang: SETTINGS.lang(None),
+            page_title,
+            stylesheet,
+            sections,
+        }
+        .render()?;
+        let dom = parse_html_document(html.as_bytes())?;
+        let dom = fix_relative_urls(dom)?;
+        let dom = add_content_security_policy(dom, &InlineHashes::default())?;
+
+        serialize_html_document(dom)
+    }
+}
+
 impl<'template> ThreadsContentTemplate<'template> {
     pub fn render_normal(thread: &'template Thread) -> eyre::Result<String> {
         fix_relative_urls_in_html_fragment(&Self::render_normal_without_fixing_relative_urls(
@@ -107,11 +667,40 @@ impl<'template> ThreadsContentTemplate<'template> {
     pub fn render_normal_without_fixing_relative_urls(
         thread: &'template Thread,
     ) -> eyre::Result<String> {
-        Ok(Self {
+        Self {
             thread,
             simple_mode: false,
+            read_more: false,
+            post_order: SETTINGS.thread_post_order(),
         }
-        .render()?)
+        .render_and_transform()
+    }
+
+    /// for listing pages, like the main page and tag pages. returns None if no posts in the
+    /// thread would be truncated, so you can use the normal version instead.
+    pub fn render_listing_without_fixing_relative_urls(
+        thread: &'template Thread,
+    ) -> eyre::Result<Option<String>> {
+        let mut truncated = false;
+        for post in thread.posts.iter() {
+            if read_more_excerpt(&post.safe_html, SETTINGS.read_more_after_chars)?.is_some() {
+                truncated = true;
+                break;
+            }
+        }
+        if !truncated {
+            return Ok(None);
+        }
+
+        Ok(Some(
+            Self {
+                thread,
+                simple_mode: false,
+                read_more: true,
+                post_order: SETTINGS.thread_post_order(),
+            }
+            .render_and_transform()?,
+        ))
     }
 
     fn render_simple(thread: &'template Thread) -> eyre::Result<String> {
@@ -119,10 +708,69 @@ impl<'template> ThreadsContentTemplate<'template> {
             &Self {
                 thread,
                 simple_mode: true,
+                read_more: false,
+                post_order: SETTINGS.thread_post_order(),
             }
-            .render()?,
+            .render_and_transform()?,
         )
     }
+
+    /// renders the template, then runs the registered [`crate::transform`]s.
+    fn render_and_transform(&self) -> eyre::Result<String> {
+        apply_html_transforms(self.render()?)
+    }
+
+    fn excerpt(&self, post: &TemplatedPost) -> eyre::Result<Option<String>> {
+        if !self.read_more {
+            return Ok(None);
+        }
+
+        read_more_excerpt(&post.safe_html, SETTINGS.read_more_after_chars)
+    }
+
+    /// atom feeds always have every post expanded.
+    fn collapsed_ancestors(&self) -> usize {
+        match SETTINGS.expanded_ancestors {
+            Some(expanded) if !self.simple_mode => self.thread.collapsed_ancestors(expanded),
+            _ => 0,
+        }
+    }
+
+    /// for `title_only_posts = "inline"`, the id that the thread’s own page redirects to.
+    fn anchor_id(&self) -> Option<String> {
+        if self.simple_mode
+            || SETTINGS.title_only_posts() != TitleOnlyPosts::Inline
+            || !self.thread.is_title_only()
+        {
+            return None;
+        }
+        let path = self.thread.path.as_ref()?;
+
+        path.anchor_id_with_slug(self.thread.meta.slug.as_deref())
+    }
+
+    /// for `title_only_posts = "placeholder"`, the body to show instead of nothing.
+    fn title_only_placeholder(&self) -> Option<&'static str> {
+        (SETTINGS.title_only_posts() == TitleOnlyPosts::Placeholder && self.thread.is_title_only())
+            .then(|| SETTINGS.title_only_placeholder())
+    }
+
+    fn posts_in_thread(&self) -> Vec<PostInThread> {
+        self.thread.posts_in_thread(self.post_order)
+    }
+
+    /// the positions of the collapsed ancestors in [`Thread::posts_in_thread`], which are the
+    /// oldest posts, so they come last if the posts are newest first.
+    fn collapsed_ancestors_range(&self) -> Range<usize> {
+        let collapsed = self.collapsed_ancestors();
+        match self.post_order {
+            ThreadPostOrder::OldestFirst => 0..collapsed,
+            ThreadPostOrder::NewestFirst => {
+                let len = self.thread.posts.len();
+                len - collapsed..len
+            }
+        }
+    }
 }
 
 impl<'template> ThreadOrPostHeaderTemplate<'template> {
@@ -142,40 +790,235 @@ impl<'template> ThreadOrPostHeaderTemplate<'template> {
     }
 }
 
-impl<'template> ThreadOrPostAuthorTemplate<'template> {
-    pub fn render(author: &'template Author) -> eyre::Result<String> {
-        fix_relative_urls_in_html_fragment(&Self { author }.render()?)
+impl ThreadOrPostAuthorTemplate<'_> {
+    pub fn render(author: &Author) -> eyre::Result<String> {
+        let avatar = SETTINGS
+            .self_project(Some(author))
+            .and_then(|project| project.avatar.as_deref());
+        let author = SETTINGS.self_project_author(author);
+
+        fix_relative_urls_in_html_fragment(
+            &ThreadOrPostAuthorTemplate {
+                author: &author,
+                avatar,
+            }
+            .render()?,
+        )
+    }
+}
+
+impl ColophonTemplate<'_> {
+    pub fn render(colophon: &Colophon, now: &str) -> eyre::Result<String> {
+        Ok(ColophonTemplate {
+            colophon,
+            version: env!("CARGO_PKG_VERSION"),
+            now,
+        }
+        .render()?)
+    }
+}
+
+impl<'template> CommentsTemplate<'template> {
+    pub fn render(comments: &'template [ArchivedComment]) -> eyre::Result<String> {
+        Ok(Self {
+            comments,
+            count: Some(count_comments(comments)),
+        }
+        .render()?)
+    }
+
+    fn render_replies(comments: &'template [ArchivedComment]) -> eyre::Result<String> {
+        Ok(Self {
+            comments,
+            count: None,
+        }
+        .render()?)
+    }
+
+    /// returns the sanitised body of the comment, or None if it was deleted.
+    fn body(comment: &ArchivedComment) -> eyre::Result<Option<String>> {
+        comment
+            .body
+            .as_deref()
+            .map(|body| Ok(TemplatedPost::filter(body, None)?.safe_html))
+            .transpose()
     }
 }
 
 impl<'template> ThreadOrPostMetaTemplate<'template> {
     pub fn render(thread: &'template Thread) -> eyre::Result<String> {
-        fix_relative_urls_in_html_fragment(&Self { thread }.render()?)
+        let json_ld = if SETTINGS.json_ld() {
+            Some(BlogPosting::new(thread)?.to_script_content()?)
+        } else {
+            None
+        };
+
+        fix_relative_urls_in_html_fragment(&Self { thread, json_ld }.render()?)
     }
 }
 
 impl<'template> AtomFeedTemplate<'template> {
     pub fn render(
         thread_refs: Vec<&'template Thread>,
+        feed_href: &'template SitePath,
         feed_title: &'template str,
         updated: &'template str,
     ) -> eyre::Result<String> {
-        Ok(Self {
-            thread_refs,
+        let entries = thread_refs
+            .into_iter()
+            .map(AtomFeedEntryTemplate::render)
+            .collect::<eyre::Result<Vec<_>>>()?
+            .join("");
+
+        Ok(AtomFeedTemplate {
+            entries: &entries,
+            feed_href,
             feed_title,
+            subtitle: None,
             updated,
         }
         .render()?)
     }
+
+    /// like [`Self::render`], but returns the xml before and after where the entries go, for
+    /// feeds too big to build in memory (`--low-memory`). the caller writes the entries in
+    /// between, each from [`AtomFeedEntryTemplate::render`].
+    pub fn render_around_entries(
+        feed_href: &'template SitePath,
+        feed_title: &'template str,
+        subtitle: Option<&'template str>,
+        updated: &'template str,
+    ) -> eyre::Result<(String, String)> {
+        const PLACEHOLDER: &str = "<!-- autost: entries go here -->";
+        let xml = AtomFeedTemplate {
+            entries: PLACEHOLDER,
+            feed_href,
+            feed_title,
+            subtitle,
+            updated,
+        }
+        .render()?;
+        let (before, after) = xml
+            .split_once(PLACEHOLDER)
+            .ok_or_eyre("BUG: entries placeholder went missing")?;
+
+        Ok((before.to_owned(), after.to_owned()))
+    }
+}
+
+impl<'feed> JsonFeed<'feed> {
+    /// returns the json before and after where the items go, so the caller can write the items
+    /// in between, each from [`JsonFeedItem::render`], separated by commas.
+    pub fn render_around_items(
+        feed_href: &SitePath,
+        feed_title: &'feed str,
+        subtitle: Option<&'feed str>,
+    ) -> eyre::Result<(String, String)> {
+        let json = serde_json::to_string(&Self {
+            version: "https://jsonfeed.org/version/1.1",
+            title: feed_title,
+            home_page_url: &SETTINGS.external_base_url,
+            feed_url: feed_href.external_url(),
+            description: subtitle,
+            authors: SETTINGS
+                .self_author
+                .iter()
+                .map(JsonFeedAuthor::new)
+                .collect(),
+            items: [],
+        })?;
+        let before = json
+            .strip_suffix("[]}")
+            .ok_or_eyre("BUG: items went missing")?;
+
+        Ok((format!("{before}["), "]}".to_owned()))
+    }
+}
+
+impl<'feed> JsonFeedItem<'feed> {
+    pub fn render(thread: &'feed Thread) -> eyre::Result<String> {
+        let url = thread.url_for_atom_permalink()?;
+        let date = |date: &Option<String>| {
+            date.as_deref().map(|date| match parse_date(date) {
+                Some(date) => date.to_rfc3339_opts(SecondsFormat::Secs, true),
+                None => date.to_owned(),
+            })
+        };
+        let item = Self {
+            id: thread
+                .atom_feed_entry_id()?
+                .or(url.clone())
+                .ok_or_eyre("thread has no path")?,
+            url,
+            title: thread
+                .meta
+                .title
+                .as_deref()
+                .map(|title| render_emoji(title, EmojiContext::Text)),
+            // json feed has no equivalent of `xml:base`, so the urls need to be absolute.
+            content_html: absolutise_urls_in_html_fragment(
+                &ThreadsContentTemplate::render_simple(thread)?,
+            )?,
+            date_published: date(&thread.meta.published),
+            date_modified: date(&thread.meta.updated),
+            authors: thread
+                .feed_authors()
+                .into_iter()
+                .map(JsonFeedAuthor::new)
+                .collect(),
+            tags: thread
+                .main_post()?
+                .meta
+                .tags
+                .iter()
+                .filter(|tag| !SETTINGS.tag_is_hidden(tag))
+                .map(|tag| render_emoji(tag, EmojiContext::Text))
+                .collect(),
+        };
+
+        Ok(serde_json::to_string(&item)?)
+    }
+}
+
+impl<'feed> JsonFeedAuthor<'feed> {
+    fn new(author: &'feed Author) -> Self {
+        Self {
+            name: &author.name,
+            url: &author.href,
+        }
+    }
+}
+
+impl<'template> AtomFeedEntryTemplate<'template> {
+    pub fn render(thread: &'template Thread) -> eyre::Result<String> {
+        // with a blank line between each entry.
+        Ok(format!("\n{}\n", Self { thread }.render()?))
+    }
 }
 
 fn fix_relative_urls_in_html_document(html: &str) -> eyre::Result<String> {
     let dom = parse_html_document(html.as_bytes())?;
     let dom = fix_relative_urls(dom)?;
+    let dom = add_content_security_policy(dom, &InlineHashes::default())?;
 
     serialize_html_document(dom)
 }
 
+/// fixes the relative urls in threads content for
+/// [`ThreadsPageTemplate::render_collection_around_threads`], returning the hashes of its inline
+/// scripts and styles too.
+pub fn fix_relative_urls_in_threads_content(html: &str) -> eyre::Result<(String, InlineHashes)> {
+    let dom = parse_html_fragment(html.as_bytes())?;
+    let dom = fix_relative_urls(dom)?;
+    let (_, scripts, styles) = find_head_and_inline_hashes(&dom)?;
+    let inline_hashes = InlineHashes {
+        scripts: scripts.into_iter().collect(),
+        styles: styles.into_iter().collect(),
+    };
+
+    Ok((serialize_html_fragment(dom)?, inline_hashes))
+}
+
 fn fix_relative_urls_in_html_fragment(html: &str) -> eyre::Result<String> {
     let dom = parse_html_fragment(html.as_bytes())?;
     let dom = fix_relative_urls(dom)?;
@@ -183,6 +1026,32 @@ fn fix_relative_urls_in_html_fragment(html: &str) -> eyre::Result<String> {
     serialize_html_fragment(dom)
 }
 
+/// makes the urls in the html absolute, under `external_base_url` (or `attachments_base_url` for
+/// attachments, if set), for output with no way to set a base url.
+fn absolutise_urls_in_html_fragment(html: &str) -> eyre::Result<String> {
+    let dom = parse_html_fragment(html.as_bytes())?;
+    let mut transform = Transform::new(dom.document.clone());
+    while transform.next(|kids, new_kids| {
+        for kid in kids {
+            if let NodeData::Element { name, attrs, .. } = &kid.data {
+                if let Some(attr_names) = html_attributes_with_urls().get(name) {
+                    for attr in attrs.borrow_mut().iter_mut() {
+                        if attr_names.contains(&attr.name) {
+                            let url = SETTINGS.attachments_base_url_absolutise(attr.value.to_str());
+                            let url = static_file_url(&url).unwrap_or(url);
+                            attr.value = SETTINGS.external_base_url_absolutise(&url).into();
+                        }
+                    }
+                }
+            }
+            new_kids.push(kid.clone());
+        }
+        Ok(())
+    })? {}
+
+    serialize_html_fragment(dom)
+}
+
 fn fix_relative_urls(dom: RcDom) -> eyre::Result<RcDom> {
     let mut transform = Transform::new(dom.document.clone());
     while transform.next(|kids, new_kids| {
@@ -191,11 +1060,8 @@ fn fix_relative_urls(dom: RcDom) -> eyre::Result<RcDom> {
                 if let Some(attr_names) = html_attributes_with_urls().get(name) {
                     for attr in attrs.borrow_mut().iter_mut() {
                         if attr_names.contains(&attr.name) {
-                            if let Some(url) =
-                                parse_path_relative_scheme_less_url_string(attr.value.to_str())
-                            {
-                                attr.value = SETTINGS.base_url_relativise(&url).into();
-                            }
+                            let url = SETTINGS.attachments_base_url_absolutise(attr.value.to_str());
+                            attr.value = static_file_url(&url).unwrap_or(url).into();
                         }
                     }
                 }
@@ -207,3 +1073,308 @@ fn fix_relative_urls(dom: RcDom) -> eyre::Result<RcDom> {
 
     Ok(dom)
 }
+
+/// if the given path-absolute url is for a static file, returns the url of its output file, which
+/// may have a hash in its filename (`hashed_static_files`).
+fn static_file_url(url: &str) -> Option<String> {
+    let filename = url.strip_prefix(&SETTINGS.base_url)?;
+    let output_filename = static_file_name(filename)?;
+
+    Some(format!("{}{output_filename}", SETTINGS.base_url))
+}
+
+/// hashes of inline scripts and styles, for threads written into a page after its content
+/// security policy (`--low-memory`).
+#[derive(Clone, Debug, Default)]
+pub struct InlineHashes {
+    scripts: BTreeSet<String>,
+    styles: BTreeSet<String>,
+}
+
+impl InlineHashes {
+    pub fn extend(&mut self, other: &Self) {
+        self.scripts.extend(other.scripts.iter().cloned());
+        self.styles.extend(other.styles.iter().cloned());
+    }
+}
+
+/// adds a `<meta http-equiv="Content-Security-Policy">` to the start of the `<head>`, if
+/// `[content_security_policy]` is set, allowing the inline scripts and styles in the page by hash,
+/// plus any `extra_hashes`.
+fn add_content_security_policy(mut dom: RcDom, extra_hashes: &InlineHashes) -> eyre::Result<RcDom> {
+    let (head, mut script_hashes, mut style_hashes) = find_head_and_inline_hashes(&dom)?;
+    script_hashes.extend(extra_hashes.scripts.iter().cloned());
+    style_hashes.extend(extra_hashes.styles.iter().cloned());
+    let Some(policy) = SETTINGS.content_security_policy(&script_hashes, &style_hashes) else {
+        return Ok(dom);
+    };
+    let head = head.ok_or_eyre("document has no <head>")?;
+
+    let meta = create_element(&mut dom, "meta");
+    if let NodeData::Element { attrs, .. } = &meta.data {
+        attrs.borrow_mut().extend([
+            Attribute {
+                name: QualName::attribute("http-equiv"),
+                value: "Content-Security-Policy".into(),
+            },
+            Attribute {
+                name: QualName::attribute("content"),
+                value: policy.into(),
+            },
+        ]);
+    }
+    head.children.borrow_mut().insert(0, meta);
+
+    Ok(dom)
+}
+
+/// returns the `<head>` (if any), and the hashes of the inline scripts and styles, in tree order.
+fn find_head_and_inline_hashes(
+    dom: &RcDom,
+) -> eyre::Result<(Option<Handle>, Vec<String>, Vec<String>)> {
+    let hash = |node: &Handle| -> eyre::Result<String> {
+        let digest = Sha256::digest(text_content(node.clone())?.as_bytes());
+        Ok(format!("'sha256-{}'", BASE64_STANDARD.encode(digest)))
+    };
+    let mut head = None;
+    let mut script_hashes = vec![];
+    let mut style_hashes = vec![];
+    for node in BreadthTraverse::elements(dom.document.clone()) {
+        let NodeData::Element { name, attrs, .. } = &node.data else {
+            unreachable!("guaranteed by BreadthTraverse::elements")
+        };
+        if name == &QualName::html("head") {
+            head.get_or_insert(node.clone());
+        } else if name == &QualName::html("script") {
+            if attrs.borrow().attr_str("src")?.is_none() {
+                script_hashes.push(hash(&node)?);
+            }
+        } else if name == &QualName::html("style") {
+            style_hashes.push(hash(&node)?);
+        }
+    }
+
+    Ok((head, script_hashes, style_hashes))
+}
+
+fn open_all_details(dom: RcDom) -> RcDom {
+    for node in BreadthTraverse::elements(dom.document.clone()) {
+        let NodeData::Element { name, attrs, .. } = &node.data else {
+            unreachable!("guaranteed by BreadthTraverse::elements")
+        };
+        if name == &QualName::html("details")
+            && attrs.borrow().attr_str("open").ok().flatten().is_none()
+        {
+            attrs.borrow_mut().push(Attribute {
+                name: QualName::attribute("open"),
+                value: "".into(),
+            });
+        }
+    }
+
+    dom
+}
+
+#[test]
+fn test_thread_print_page() -> eyre::Result<()> {
+    use crate::TemplatedPost;
+
+    let post = TemplatedPost::filter(
+        r#"<meta name="tags" content="cw: spoilers"><p>hello</p><details><summary>more</summary>world</details><details open>already</details>"#,
+        None,
+    )?;
+    let thread = Thread::try_from(post)?;
+    let threads_content =
+        ThreadsContentTemplate::render_normal_without_fixing_relative_urls(&thread)?;
+    let html = ThreadPrintPageTemplate::render(
+        &thread,
+        &threads_content,
+        "title",
+        "https://example.com/1.html",
+    )?;
+    assert!(html.contains(r#"<link rel="canonical" href="https://example.com/1.html">"#));
+    assert!(!html.contains("<nav>"));
+    assert!(!html.contains("<script"));
+    assert!(!html.contains("<details>"));
+    assert_eq!(html.matches("<details open=").count(), 2);
+    assert_eq!(
+        html.matches(r#"<details class="content-warning" open="">"#)
+            .count(),
+        1
+    );
+
+    Ok(())
+}
+
+#[test]
+fn test_emoji_in_titles_and_tags() -> eyre::Result<()> {
+    use crate::TemplatedPost;
+
+    let post = TemplatedPost::filter(
+        r#"<meta name="title" content="hi :eggbug:"><meta name="tags" content="eggbug :eggbug:">"#,
+        None,
+    )?;
+    let thread = Thread::try_from(post)?;
+    let html = ThreadsContentTemplate::render_normal_without_fixing_relative_urls(&thread)?;
+    assert!(html.contains(r#"hi <img class="emoji" "#));
+    assert!(html.contains(r#"<span class="p-category">eggbug <img class="emoji" "#));
+    assert_eq!(html.matches(r#"alt=":eggbug:""#).count(), 2);
+
+    let feed = AtomFeedTemplate::render(
+        vec![&thread],
+        &SitePath::ROOT.join("index.feed.xml")?,
+        "feed",
+        "2024-01-01T00:00:00Z",
+    )?;
+    assert!(feed.contains("<title>hi :eggbug:</title>"));
+    assert!(feed.contains(r#"<category term="eggbug :eggbug:" />"#));
+    assert!(!feed.contains(r#"class="emoji""#));
+
+    Ok(())
+}
+
+#[test]
+fn test_feed_categories() -> eyre::Result<()> {
+    use crate::TemplatedPost;
+
+    let post = TemplatedPost::filter(
+        r#"<meta name="tags" content="photography"><meta name="tags" content="a &quot;b&quot; &amp; <c>">"#,
+        None,
+    )?;
+    let thread = Thread::try_from(post)?;
+    let feed = AtomFeedTemplate::render(
+        vec![&thread],
+        &SitePath::ROOT.join("index.feed.xml")?,
+        "feed",
+        "2024-01-01T00:00:00Z",
+    )?;
+    assert!(feed.contains(r#"<category term="photography" />"#));
+    assert!(feed.contains(r#"<category term="a &quot;b&quot; &amp; &lt;c&gt;" />"#));
+    // `feed_tag_links` is off by default.
+    assert!(!feed.contains(r#"rel="related""#));
+
+    Ok(())
+}
+
+#[test]
+fn test_blog_posting() -> eyre::Result<()> {
+    use crate::TemplatedPost;
+
+    let post = TemplatedPost::filter(
+        r#"<meta name="title" content="</script><!--"><meta name="published" content="2024-01-01T00:00Z"><meta name="tags" content="photography">"#,
+        None,
+    )?;
+    let thread = Thread::try_from(post)?;
+    let json = BlogPosting::new(&thread)?.to_script_content()?;
+    assert!(!json.contains('<'));
+    assert!(json.contains(r#""@type":"BlogPosting""#));
+    assert!(json.contains(r#""datePublished":"2024-01-01T00:00Z""#));
+    assert!(json.contains(r#""keywords":["photography"]"#));
+    assert!(json.contains(r#""headline":"\u003c/script>\u003c!--""#));
+
+    let post = TemplatedPost::filter(
+        r#"<link rel="author" href="https://example.com/a"><link rel="contributor" href="https://example.com/b">"#,
+        None,
+    )?;
+    let json = BlogPosting::new(&Thread::try_from(post)?)?.to_script_content()?;
+    assert!(json.contains(r#""author":[{"#));
+    assert!(json.contains(r#""url":"https://example.com/b""#));
+
+    Ok(())
+}
+
+#[test]
+fn test_threads_page_layouts() -> eyre::Result<()> {
+    let threads_content = "<article class=\"thread\">hello</article>";
+    let list = ThreadsPageTemplate::render_index(
+        threads_content,
+        "title",
+        &None,
+        &[],
+        CollectionLayout::List,
+    )?;
+    let grid = ThreadsPageTemplate::render_index(
+        threads_content,
+        "title",
+        &None,
+        &[],
+        CollectionLayout::Grid,
+    )?;
+    assert!(!list.contains("threads-grid"));
+    assert!(list.contains(r#"<main id="content">"#));
+    assert!(grid.contains(r#"<main class="threads-grid" id="content">"#));
+    for page in [&list, &grid] {
+        // the skip link comes first, then the site header, then the threads.
+        let skip_link = page.find(r##"<a class="skip-link" href="#content">"##);
+        let header = page.find("<header>\n<nav aria-label=\"site\">");
+        let main = page.find("<main");
+        assert!(skip_link.is_some() && skip_link < header && header < main);
+    }
+    assert!(grid.contains(threads_content));
+    assert!(grid.contains("<title>title</title>"));
+    // the saved theme is applied before the page is shown.
+    assert!(grid.contains(r#"localStorage.getItem("autost-theme")"#));
+
+    Ok(())
+}
+
+#[test]
+fn test_embedded_source() -> eyre::Result<()> {
+    assert_eq!(
+        EmbeddedSource::comment("a --> b"),
+        EmbeddedSource::Comment("a - -> b".to_owned())
+    );
+    assert_eq!(
+        EmbeddedSource::script("<b>hi</b><script></SCRIPT>"),
+        EmbeddedSource::Script(r"<b>hi</b><script><\/SCRIPT>".to_owned())
+    );
+
+    let post = TemplatedPost::filter("<p>hello</p>", None)?;
+    let thread = Thread::try_from(post)?;
+    let source = EmbeddedSource::comment("# hello\n\n-- me");
+    let page = ThreadsPageTemplate::render_single_thread(
+        &thread,
+        "",
+        "",
+        &None,
+        ThreadPageParts {
+            print_href: &None,
+            adjacent: &AdjacentThreads::default(),
+            series: None,
+            comments: None,
+            source: Some(&source),
+        },
+    )?;
+    assert!(page.contains("<!-- source:\n# hello\n\n- - me\n-->"));
+
+    Ok(())
+}
+
+#[test]
+fn test_thread_post_order() -> eyre::Result<()> {
+    let posts = ["first", "second", "third"]
+        .into_iter()
+        .map(|text| TemplatedPost::filter(&format!("<p>{text}</p>"), None))
+        .collect::<eyre::Result<Vec<_>>>()?;
+    let thread = Thread::from_posts(posts, vec![])?;
+    let order = |post_order| -> eyre::Result<Vec<usize>> {
+        let html = ThreadsContentTemplate {
+            thread: &thread,
+            simple_mode: false,
+            read_more: false,
+            post_order,
+        }
+        .render()?;
+        ["first", "second", "third"]
+            .map(|text| html.find(&format!("<p>{text}</p>")))
+            .into_iter()
+            .map(|position| position.ok_or_eyre("post missing from thread"))
+            .collect()
+    };
+    let oldest_first = order(ThreadPostOrder::OldestFirst)?;
+    assert!(oldest_first[0] < oldest_first[1] && oldest_first[1] < oldest_first[2]);
+    let newest_first = order(ThreadPostOrder::NewestFirst)?;
+    assert!(newest_first[2] < newest_first[1] && newest_first[1] < newest_first[0]);
+
+    Ok(())
+}
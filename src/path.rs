@@ -6,6 +6,7 @@ use std::{
 };
 
 use jane_eyre::eyre::{self, bail, Context, OptionExt};
+use sha2::{digest::generic_array::functional::FunctionalSequence, Digest, Sha256};
 use url::Url;
 
 use crate::SETTINGS;
@@ -179,6 +180,16 @@ impl PostsPath {
             .expect("guaranteed by argument")
     }
 
+    /// returns the path of the cached copy of a remote post, like
+    /// `imported/remote-<sha256 of url>.html`, for `<link rel=references>` to posts on other sites.
+    pub fn remote_reference_path(url: &str) -> eyre::Result<Self> {
+        let mut hash = Sha256::new();
+        hash.update(url);
+        let hash = hash.finalize().map(|o| format!("{o:02x}")).join("");
+
+        Self::IMPORTED.join(&format!("remote-{hash}.html"))
+    }
+
     pub fn imported_post_path(post_id: usize) -> Self {
         Self::IMPORTED
             .join(&format!("{post_id}.html"))
@@ -200,19 +211,134 @@ impl PostsPath {
     }
 
     pub fn rendered_path(&self) -> eyre::Result<Option<SitePath>> {
+        self.rendered_path_with_slug(None)
+    }
+
+    /// returns the path of the print-friendly version of the rendered page, like
+    /// `site/10000000.print.html`, for `print_pages`.
+    pub fn rendered_print_path(&self) -> eyre::Result<Option<SitePath>> {
+        self.rendered_print_path_with_slug(None)
+    }
+
+    /// returns the path of the rendered page, like `site/10000000.html`, or `site/my-post.html`
+    /// if the post has a `slug`. if `clean_urls` is enabled, the page is `site/my-post/index.html`
+    /// instead, so its url can be `my-post/`.
+    pub fn rendered_path_with_slug(&self, slug: Option<&str>) -> eyre::Result<Option<SitePath>> {
+        self.rendered_path_with(slug, SETTINGS.clean_urls())
+    }
+
+    /// like [`Self::rendered_path_with_slug`], but always the `.html` form, like `site/my-post.html`,
+    /// even if `clean_urls` is enabled.
+    pub fn html_rendered_path_with_slug(
+        &self,
+        slug: Option<&str>,
+    ) -> eyre::Result<Option<SitePath>> {
+        self.rendered_path_with(slug, false)
+    }
+
+    fn rendered_path_with(
+        &self,
+        slug: Option<&str>,
+        clean_urls: bool,
+    ) -> eyre::Result<Option<SitePath>> {
+        match self.rendered_basename(slug) {
+            Some(basename) if clean_urls => Ok(Some(
+                SitePath::ROOT.join(&format!("{basename}/index.html"))?,
+            )),
+            Some(basename) => Ok(Some(SitePath::ROOT.join(&format!("{basename}.html"))?)),
+            None => Ok(None),
+        }
+    }
+
+    /// like [`Self::rendered_print_path`], but for a post with the given `slug`, if any.
+    pub fn rendered_print_path_with_slug(
+        &self,
+        slug: Option<&str>,
+    ) -> eyre::Result<Option<SitePath>> {
+        match self.rendered_basename(slug) {
+            Some(basename) => Ok(Some(
+                SitePath::ROOT.join(&format!("{basename}.print.html"))?,
+            )),
+            None => Ok(None),
+        }
+    }
+
+    fn rendered_basename<'path>(&'path self, slug: Option<&'path str>) -> Option<&'path str> {
         match self.kind {
-            PostsKind::Post { .. } => {
+            PostsKind::Post { .. } => Some(slug.unwrap_or_else(|| {
                 let (basename, _) = self
                     .filename()
                     .rsplit_once(".")
                     .expect("guaranteed by PostsKind::new");
-                let filename = format!("{basename}.html");
-                Ok(Some(SitePath::ROOT.join(&filename)?))
-            }
-            PostsKind::Other => Ok(None),
+                basename
+            })),
+            PostsKind::Other => None,
         }
     }
 
+    /// returns the path of the source of the rendered page, like `site/10000000.md`, for
+    /// `embed_source = "download"`.
+    pub fn rendered_source_path_with_slug(
+        &self,
+        slug: Option<&str>,
+    ) -> eyre::Result<Option<SitePath>> {
+        match self.rendered_basename(slug) {
+            Some(basename) => Ok(Some(SitePath::ROOT.join(&format!("{basename}.md"))?)),
+            None => Ok(None),
+        }
+    }
+
+    /// returns the path of the generated `og:image` card for the rendered page, like
+    /// `site/og/10000000.png`, for `[og_images]`.
+    pub fn rendered_og_image_path_with_slug(
+        &self,
+        slug: Option<&str>,
+    ) -> eyre::Result<Option<SitePath>> {
+        match self.rendered_basename(slug) {
+            Some(basename) => Ok(Some(SitePath::ROOT.join(&format!("og/{basename}.png"))?)),
+            None => Ok(None),
+        }
+    }
+
+    /// returns the id of the thread on pages with many threads, like `thread-10000000`, or
+    /// `thread-my-post` if the post has a `slug`.
+    pub fn anchor_id_with_slug(&self, slug: Option<&str>) -> Option<String> {
+        self.rendered_basename(slug)
+            .map(|basename| format!("thread-{basename}"))
+    }
+
+    /// returns the path of the markdown source of a chost, like `posts/10000000.source.txt` for
+    /// `posts/10000000.html`, if this is a post. it can’t end in `.md`, or it would be a post.
+    pub fn source_path(&self) -> eyre::Result<Option<Self>> {
+        let Some(basename) = self.rendered_basename(None) else {
+            return Ok(None);
+        };
+        let filename = format!("{basename}.source.txt");
+        match self.parent() {
+            Some(parent) => Ok(Some(parent.join(&filename)?)),
+            None => Ok(None),
+        }
+    }
+
+    /// returns the path of the archived comments on the post, like `posts/10000000.comments.json`
+    /// for `posts/10000000.html`, if this is a post.
+    pub fn comments_path(&self) -> eyre::Result<Option<Self>> {
+        let Some(basename) = self.rendered_basename(None) else {
+            return Ok(None);
+        };
+        let filename = format!("{basename}.comments.json");
+        match self.parent() {
+            Some(parent) => Ok(Some(parent.join(&filename)?)),
+            None => Ok(None),
+        }
+    }
+
+    /// returns true iff this is a post (`.html` or `.md`), rather than another file in the posts
+    /// directory, like archived comments.
+    pub fn is_post(&self) -> bool {
+        matches!(self.kind, PostsKind::Post { .. })
+    }
+
     pub fn is_markdown_post(&self) -> bool {
         matches!(
             self.kind,
@@ -223,6 +349,14 @@ impl PostsPath {
         )
     }
 
+    /// returns the number in the filename, like the chost id in `posts/123456.html` from
+    /// `autost cohost2autost`, if any.
+    pub fn post_id(&self) -> Option<usize> {
+        let (basename, _) = self.filename().split_once(".")?;
+
+        basename.parse().ok()
+    }
+
     pub fn basename(&self) -> Option<&str> {
         if let PostsKind::Post {
             in_imported_dir: true,
@@ -245,6 +379,11 @@ impl SitePath {
         LazyLock::new(|| Self::new(SiteKind::ROOT.into()).expect("guaranteed by argument"));
     pub const TAGGED: LazyLock<Self> =
         LazyLock::new(|| Self::ROOT.join("tagged").expect("guaranteed by argument"));
+    pub const SERIES: LazyLock<Self> =
+        LazyLock::new(|| Self::ROOT.join("series").expect("guaranteed by argument"));
+    /// the builds of the site, for `versioned_output`.
+    pub const VERSIONS: LazyLock<Self> =
+        LazyLock::new(|| Self::ROOT.join("v").expect("guaranteed by argument"));
     pub const ATTACHMENTS: LazyLock<Self> = LazyLock::new(|| {
         Self::ROOT
             .join("attachments")
@@ -256,6 +395,88 @@ impl SitePath {
             .expect("guaranteed by argument")
     });
 
+    /// returns the path of the threads page for `tag`, like `tagged/foo.html`. namespaced tags
+    /// like `project/autost` are written to subdirectories, like `tagged/project/autost.html`.
+    pub fn tag_page(tag: &str) -> eyre::Result<Self> {
+        Self::tag_path(tag, "html")
+    }
+
+    /// returns the path of the atom feed for `tag`, like `tagged/foo.feed.xml`.
+    pub fn tag_feed(tag: &str) -> eyre::Result<Self> {
+        Self::tag_path(tag, "feed.xml")
+    }
+
+    /// returns the path of the atom feed for threads in `tag` by the author with `handle`, like
+    /// `tagged/foo/by/bar.feed.xml`, for `[[tag_author_feeds]]`.
+    pub fn tag_author_feed(tag: &str, handle: &str) -> eyre::Result<Self> {
+        if handle.contains('/') {
+            bail!("handle is not a valid filename: {handle:?}");
+        }
+
+        Self::tag_path(&format!("{tag}/by/{handle}"), "feed.xml")
+    }
+
+    fn tag_path(tag: &str, extension: &str) -> eyre::Result<Self> {
+        // `RelativePath::new` rejects `..`, but would silently collapse `.` and empty components,
+        // which could make two different tags share a page.
+        if tag
+            .split('/')
+            .any(|component| component.is_empty() || component == "." || component == "..")
+        {
+            bail!("tag is not a valid filename: {tag:?}");
+        }
+
+        Self::TAGGED.join(&format!("{tag}.{extension}"))
+    }
+
+    /// returns the path of page `page` (from 1) of this listing page, like `all.2.html` for
+    /// `all.html`, for `page_size`. page 1 is this page itself.
+    pub fn listing_page(&self, page: usize) -> eyre::Result<Self> {
+        if page <= 1 {
+            return Ok(self.clone());
+        }
+        let Some(stem) = self.filename().strip_suffix(".html") else {
+            bail!("listing page is not html: {self:?}");
+        };
+        let parent = self.parent().ok_or_eyre("listing page has no parent")?;
+
+        parent.join(&format!("{stem}.{page}.html"))
+    }
+
+    /// returns the path of the json feed next to this atom feed, like `tagged/foo.feed.json` for
+    /// `tagged/foo.feed.xml`. feeds moved to a path not ending in `.xml` get `.json` added.
+    pub fn json_feed(&self) -> eyre::Result<Self> {
+        let filename = self.filename();
+        let stem = filename.strip_suffix(".xml").unwrap_or(filename);
+        let parent = self.parent().ok_or_eyre("atom feed has no parent")?;
+
+        parent.join(&format!("{stem}.json"))
+    }
+
+    /// returns the path of this file in the build `build_hash`, like `v/0123456789ab/index.html`
+    /// for `index.html`, for `versioned_output`.
+    pub fn versioned(&self, build_hash: &str) -> eyre::Result<Self> {
+        Self::VERSIONS.join(build_hash)?.join(&self.relative_path())
+    }
+
+    /// returns the path of the index page for the series `name`, like `series/foo.html`.
+    pub fn series_page(name: &str) -> eyre::Result<Self> {
+        Self::series_path(name, "html")
+    }
+
+    /// returns the path of the atom feed for the series `name`, like `series/foo.feed.xml`.
+    pub fn series_feed(name: &str) -> eyre::Result<Self> {
+        Self::series_path(name, "feed.xml")
+    }
+
+    fn series_path(name: &str, extension: &str) -> eyre::Result<Self> {
+        if name.is_empty() || name.contains('/') || name == "." || name == ".." {
+            bail!("series is not a valid filename: {name:?}");
+        }
+
+        Self::SERIES.join(&format!("{name}.{extension}"))
+    }
+
     /// creates a path from an attachment url in a rendered post, which is relative to
     /// the posts directory, but percent-encoded as a url.
     pub fn from_rendered_attachment_url(url: &str) -> eyre::Result<Self> {
@@ -274,11 +495,11 @@ impl SitePath {
     }
 
     pub fn internal_url(&self) -> String {
-        format!("{}{}", SETTINGS.base_url, self.relative_url())
+        format!("{}{}", SETTINGS.base_url, self.url_path())
     }
 
     pub fn external_url(&self) -> String {
-        format!("{}{}", SETTINGS.external_base_url, self.relative_url())
+        format!("{}{}", SETTINGS.external_base_url, self.url_path())
     }
 
     pub fn atom_feed_entry_id(&self) -> String {
@@ -286,6 +507,24 @@ impl SitePath {
         self.relative_url()
     }
 
+    /// like `relative_url`, but if `clean_urls` is enabled, pages like `my-post/index.html` are
+    /// linked as their directory, like `my-post/`.
+    fn url_path(&self) -> String {
+        self.url_path_with(SETTINGS.clean_urls())
+    }
+
+    fn url_path_with(&self, clean_urls: bool) -> String {
+        let result = self.relative_url();
+        if clean_urls && self.filename() == "index.html" {
+            return result
+                .strip_suffix("index.html")
+                .unwrap_or(&result)
+                .to_owned();
+        }
+
+        result
+    }
+
     pub fn rsync_deploy_line(&self) -> String {
         self.relative_path()
     }
@@ -389,6 +628,11 @@ impl<Kind: PathKind> RelativePath<Kind> {
         None
     }
 
+    /// returns true iff this path is inside `dir`, at any depth.
+    pub fn is_in(&self, dir: &Self) -> bool {
+        self.inner != dir.inner && self.inner.starts_with(&dir.inner)
+    }
+
     pub fn filename(&self) -> &str {
         self.inner
             .file_name()
@@ -585,3 +829,132 @@ fn test_is_path_relative_scheme_less_url_string() {
         Some("2script.js")
     );
 }
+
+#[test]
+fn test_rendered_path_with_slug() -> eyre::Result<()> {
+    let path = PostsPath::ROOT.join("10000000.md")?;
+    assert_eq!(
+        path.rendered_path_with_slug(None)?,
+        Some(SitePath::ROOT.join("10000000.html")?)
+    );
+    assert_eq!(
+        path.rendered_path_with_slug(Some("my-post"))?,
+        Some(SitePath::ROOT.join("my-post.html")?)
+    );
+    assert_eq!(
+        path.rendered_print_path_with_slug(Some("my-post"))?,
+        Some(SitePath::ROOT.join("my-post.print.html")?)
+    );
+    assert_eq!(
+        path.rendered_source_path_with_slug(Some("my-post"))?,
+        Some(SitePath::ROOT.join("my-post.md")?)
+    );
+    assert_eq!(
+        path.source_path()?,
+        Some(PostsPath::ROOT.join("10000000.source.txt")?)
+    );
+    assert_eq!(
+        PostsPath::ROOT
+            .join("10000000.txt")?
+            .rendered_path_with_slug(Some("my-post"))?,
+        None
+    );
+
+    let clean_path = path.rendered_path_with(Some("my-post"), true)?;
+    assert_eq!(clean_path, Some(SitePath::ROOT.join("my-post/index.html")?));
+    assert_eq!(
+        clean_path.map(|path| path.url_path_with(true)).as_deref(),
+        Some("my-post/")
+    );
+    assert_eq!(
+        SitePath::ROOT.join("index.html")?.url_path_with(true),
+        "",
+        "the main page is linked as the site root"
+    );
+    assert_eq!(
+        SitePath::ROOT.join("my-post.html")?.url_path_with(true),
+        "my-post.html"
+    );
+
+    Ok(())
+}
+
+#[test]
+fn test_versioned() -> eyre::Result<()> {
+    assert_eq!(
+        SitePath::ROOT
+            .join("tagged/photography.html")?
+            .versioned("0123456789ab")?,
+        SitePath::ROOT.join("v/0123456789ab/tagged/photography.html")?
+    );
+
+    Ok(())
+}
+
+#[test]
+fn test_listing_page() -> eyre::Result<()> {
+    let all = SitePath::ROOT.join("all.html")?;
+    assert_eq!(all.listing_page(1)?, all);
+    assert_eq!(all.listing_page(2)?, SitePath::ROOT.join("all.2.html")?);
+    assert_eq!(
+        SitePath::tag_page("project/autost")?.listing_page(3)?,
+        SitePath::ROOT.join("tagged/project/autost.3.html")?
+    );
+    assert!(SitePath::ROOT
+        .join("all.feed.xml")?
+        .listing_page(2)
+        .is_err());
+
+    Ok(())
+}
+
+#[test]
+fn test_json_feed() -> eyre::Result<()> {
+    assert_eq!(
+        SitePath::ROOT.join("index.feed.xml")?.json_feed()?,
+        SitePath::ROOT.join("index.feed.json")?
+    );
+    assert_eq!(
+        SitePath::tag_feed("project/autost")?.json_feed()?,
+        SitePath::ROOT.join("tagged/project/autost.feed.json")?
+    );
+    assert_eq!(
+        SitePath::ROOT.join("photos.atom")?.json_feed()?,
+        SitePath::ROOT.join("photos.atom.json")?
+    );
+
+    Ok(())
+}
+
+#[test]
+fn test_series_page() -> eyre::Result<()> {
+    assert_eq!(
+        SitePath::series_page("my tutorial")?,
+        SitePath::ROOT.join("series/my tutorial.html")?
+    );
+    assert_eq!(
+        SitePath::series_feed("my tutorial")?.internal_url(),
+        format!("{}series/my%20tutorial.feed.xml", SETTINGS.base_url)
+    );
+    assert!(SitePath::series_page("a/b").is_err());
+    assert!(SitePath::series_page("..").is_err());
+
+    Ok(())
+}
+
+#[test]
+fn test_tag_author_feed() -> eyre::Result<()> {
+    assert_eq!(
+        SitePath::tag_author_feed("photography", "staff")?,
+        SitePath::TAGGED.join("photography/by/staff.feed.xml")?
+    );
+    assert_eq!(
+        SitePath::tag_author_feed("project/autost", "staff")?,
+        SitePath::TAGGED.join("project/autost/by/staff.feed.xml")?
+    );
+    assert!(SitePath::tag_author_feed("photography", "../staff").is_err());
+    assert!(SitePath::tag_author_feed("photography", "").is_err());
+    assert!(SitePath::tag_author_feed("", "staff").is_err());
+
+    Ok(())
+}
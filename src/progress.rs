@@ -0,0 +1,92 @@
+//! progress reporting for commands that process many posts or files.
+
+use std::{
+    io::{stderr, IsTerminal, Write},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+use tracing::info;
+
+/// shows a progress bar with an eta when stderr is a terminal, or logs progress every few
+/// seconds when it isn’t (like in ci or when piped to a file).
+pub struct Progress {
+    what: &'static str,
+    total: usize,
+    done: AtomicUsize,
+    start: Instant,
+    is_terminal: bool,
+    last_log: Mutex<Instant>,
+}
+
+impl Progress {
+    const WIDTH: usize = 40;
+    const LOG_INTERVAL: Duration = Duration::from_secs(10);
+
+    pub fn new(what: &'static str, total: usize) -> Self {
+        let now = Instant::now();
+        Self {
+            what,
+            total,
+            done: AtomicUsize::new(0),
+            start: now,
+            is_terminal: stderr().is_terminal(),
+            last_log: Mutex::new(now),
+        }
+    }
+
+    pub fn increment(&self) {
+        let done = self.done.fetch_add(1, Ordering::Relaxed) + 1;
+        let eta = eta(self.start.elapsed(), done, self.total);
+        let what = self.what;
+        let total = self.total;
+
+        if self.is_terminal {
+            let filled = Self::WIDTH * done / total.max(1);
+            let bar = format!("{}{}", "#".repeat(filled), " ".repeat(Self::WIDTH - filled));
+            let mut stderr = stderr().lock();
+            let _ = write!(stderr, "\r{what}: [{bar}] {done}/{total}, eta {eta}\x1B[K");
+            if done == total {
+                let _ = writeln!(stderr);
+            }
+        } else {
+            let mut last_log = self.last_log.lock().unwrap();
+            if done == total || last_log.elapsed() >= Self::LOG_INTERVAL {
+                *last_log = Instant::now();
+                info!("{what}: {done}/{total}, eta {eta}");
+            }
+        }
+    }
+}
+
+/// estimates the time remaining, assuming the remaining items take as long as the others.
+fn eta(elapsed: Duration, done: usize, total: usize) -> String {
+    let remaining = total.saturating_sub(done) as u32;
+    let per_item = elapsed / done.max(1) as u32;
+
+    format_duration(per_item * remaining)
+}
+
+fn format_duration(duration: Duration) -> String {
+    let seconds = duration.as_secs();
+    let (hours, minutes, seconds) = (seconds / 3600, seconds / 60 % 60, seconds % 60);
+    if hours > 0 {
+        format!("{hours}h{minutes:02}m{seconds:02}s")
+    } else if minutes > 0 {
+        format!("{minutes}m{seconds:02}s")
+    } else {
+        format!("{seconds}s")
+    }
+}
+
+#[test]
+fn test_eta() {
+    assert_eq!(eta(Duration::from_secs(10), 1, 4), "30s");
+    assert_eq!(eta(Duration::from_secs(10), 10, 100), "1m30s");
+    assert_eq!(eta(Duration::from_secs(3600), 1, 3), "2h00m00s");
+    assert_eq!(eta(Duration::from_secs(10), 4, 4), "0s");
+    assert_eq!(eta(Duration::ZERO, 0, 4), "0s");
+}
@@ -0,0 +1,164 @@
+//! a cache of the html that `autost cohost2autost` renders from each cohost ast or markdown block,
+//! so converting chosts again can skip rendering the ones that haven’t changed.
+//!
+//! entries are kept in `render-cache/`, next to `attachments/`, named by a hash of
+//! [`RENDER_CACHE_VERSION`] and the source. each entry also lists the attachments its html needs,
+//! and is ignored if any of them are missing, so you can delete attachments without breaking
+//! your posts.
+
+use std::{
+    cell::{Cell, RefCell},
+    fs::{create_dir_all, exists, rename, File},
+    path::{Path, PathBuf},
+};
+
+use jane_eyre::eyre;
+use serde::{Deserialize, Serialize};
+use sha2::{digest::generic_array::functional::FunctionalSequence, Digest, Sha256};
+use tracing::{debug, trace};
+use uuid::Uuid;
+
+use crate::{attachments::AttachmentsContext, cohost::Cacheable, error, path::AttachmentsPath};
+
+pub const RENDER_CACHE_DIR: &str = "render-cache";
+
+/// bump this whenever the html rendered from the same source would change, like when the ast or
+/// markdown rendering changes, to invalidate every entry.
+const RENDER_CACHE_VERSION: u8 = 1;
+
+#[derive(Debug, Deserialize, PartialEq, Serialize)]
+struct RenderCacheEntry {
+    html: String,
+    attachments: Vec<PathBuf>,
+}
+
+/// returns the html rendered from `source` by `render`, from the render cache if possible.
+/// `kind` says what the source is, like `"ast"` or `"markdown"`, so different kinds of source
+/// never share entries.
+///
+/// the cache is only used if [`AttachmentsContext::uses_render_cache`] is true, and `render` is
+/// only given a context that records the attachments it needs. if any of those fail to cache,
+/// the result is not cached, so it can be rendered again next time.
+pub fn render_cached(
+    kind: &str,
+    source: &str,
+    context: &dyn AttachmentsContext,
+    render: impl FnOnce(&dyn AttachmentsContext) -> eyre::Result<String>,
+) -> eyre::Result<String> {
+    if !context.uses_render_cache() {
+        return render(context);
+    }
+
+    let path = cache_path(kind, source);
+    match load_entry(&path) {
+        Ok(Some(entry)) => return Ok(entry.html),
+        Ok(None) => trace!(?path, "render cache miss"),
+        Err(error) => debug!(?path, "failed to read render cache entry: {error:?}"),
+    }
+
+    let recording = RecordingAttachmentsContext {
+        inner: context,
+        attachments: RefCell::default(),
+        failed: Cell::new(false),
+    };
+    let html = render(&recording)?;
+    if !recording.failed.get() {
+        let entry = RenderCacheEntry {
+            html,
+            attachments: recording.attachments.into_inner(),
+        };
+        store_entry(&path, &entry)?;
+        return Ok(entry.html);
+    }
+
+    Ok(html)
+}
+
+fn cache_path(kind: &str, source: &str) -> PathBuf {
+    let mut hash = Sha256::new();
+    hash.update([RENDER_CACHE_VERSION]);
+    hash.update(kind);
+    hash.update([0]);
+    hash.update(source);
+    let hash = hash.finalize().map(|o| format!("{o:02x}")).join("");
+
+    Path::new(RENDER_CACHE_DIR).join(format!("{hash}.json"))
+}
+
+/// returns the entry, or None if there is none, or if any of its attachments are missing.
+fn load_entry(path: &Path) -> eyre::Result<Option<RenderCacheEntry>> {
+    if !exists(path)? {
+        return Ok(None);
+    }
+    let entry: RenderCacheEntry = serde_json::from_reader(File::open(path)?)?;
+    for attachment in entry.attachments.iter() {
+        if !exists(attachment)? {
+            debug!(
+                ?path,
+                ?attachment,
+                "render cache entry needs missing attachment"
+            );
+            return Ok(None);
+        }
+    }
+
+    Ok(Some(entry))
+}
+
+fn store_entry(path: &Path, entry: &RenderCacheEntry) -> eyre::Result<()> {
+    create_dir_all(RENDER_CACHE_DIR)?;
+    // write to a unique temporary file, so chosts converted in parallel can’t see partial entries.
+    let temp_path = path.with_extension(format!("{}.tmp", Uuid::new_v4()));
+    serde_json::to_writer(File::create(&temp_path)?, entry)?;
+    rename(&temp_path, path)?;
+
+    Ok(())
+}
+
+/// attachments context that records the attachments that were cached, and whether any failed.
+struct RecordingAttachmentsContext<'context> {
+    inner: &'context dyn AttachmentsContext,
+    attachments: RefCell<Vec<PathBuf>>,
+    failed: Cell<bool>,
+}
+
+impl RecordingAttachmentsContext<'_> {
+    fn record(&self, result: error::Result<AttachmentsPath>) -> error::Result<AttachmentsPath> {
+        match &result {
+            Ok(path) => self.attachments.borrow_mut().push(path.as_ref().to_owned()),
+            Err(_) => self.failed.set(true),
+        }
+
+        result
+    }
+}
+
+impl AttachmentsContext for RecordingAttachmentsContext<'_> {
+    fn store(&self, input_path: &Path) -> error::Result<AttachmentsPath> {
+        self.record(self.inner.store(input_path))
+    }
+
+    fn cache_imported(&self, url: &str, post_basename: &str) -> error::Result<AttachmentsPath> {
+        self.record(self.inner.cache_imported(url, post_basename))
+    }
+
+    fn cache_cohost_resource(&self, cacheable: &Cacheable) -> error::Result<AttachmentsPath> {
+        self.record(self.inner.cache_cohost_resource(cacheable))
+    }
+
+    fn cache_cohost_thumb(&self, id: &str) -> error::Result<AttachmentsPath> {
+        self.record(self.inner.cache_cohost_thumb(id))
+    }
+}
+
+#[test]
+fn test_cache_path() {
+    assert_eq!(cache_path("ast", "x"), cache_path("ast", "x"));
+    assert_ne!(cache_path("ast", "x"), cache_path("markdown", "x"));
+    assert_ne!(
+        cache_path("ast", "x"),
+        cache_path("as", "tx"),
+        "kind and source are separated"
+    );
+    assert!(cache_path("ast", "x").starts_with(RENDER_CACHE_DIR));
+}
@@ -0,0 +1,192 @@
+//! hooks for deciding which collections each thread goes in, for sites that use autost as a library.
+//!
+//! a router is set with [`set_collection_router`] before rendering, like in your own `main` before
+//! calling [`crate::command::render::main`]. it replaces [`default_collection_decision`], which
+//! decides whether a thread is interesting, excluded, or skipped based on your settings, but a
+//! router can call it to augment the built-in routing rather than replace it outright.
+//!
+//! routers are called once for each thread, after the thread is templated, on many threads in
+//! parallel. at that point, every field of [`Thread`] is available, including the
+//! [`Thread::meta`] of the thread and the [`crate::TemplatedPost::meta`] and
+//! [`crate::TemplatedPost::safe_html`] of each post, with tags already renamed and implied by
+//! `renamed_tags` and `implied_tags`. the thread has not been written yet, so its html has
+//! not been through any [`crate::transform`] hooks, and its relative urls have not been fixed.
+
+use std::sync::RwLock;
+
+use tracing::{debug, info};
+
+use crate::{settings::TransparentShares, Thread, SETTINGS};
+
+static COLLECTION_ROUTER: RwLock<Option<Box<dyn CollectionRouter>>> = RwLock::new(None);
+
+/// a router for threads. implemented for any `Fn(&Thread) -> CollectionDecision`.
+pub trait CollectionRouter: Send + Sync {
+    fn route(&self, thread: &Thread) -> CollectionDecision;
+}
+
+impl<F: Fn(&Thread) -> CollectionDecision + Send + Sync> CollectionRouter for F {
+    fn route(&self, thread: &Thread) -> CollectionDecision {
+        self(thread)
+    }
+}
+
+/// which collections a thread goes in.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CollectionDecision {
+    pub route: Route,
+    /// whether the thread also goes in `marked_interesting`, for threads on the
+    /// `interesting_archived_threads_list`.
+    pub marked_interesting: bool,
+    /// names of custom `[[collections]]` to add the thread to, in addition to any whose
+    /// conditions the thread matches. names that are not in your settings are ignored.
+    pub extra_collections: Vec<String>,
+}
+
+/// which of the built-in collections a thread goes in.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Route {
+    /// not in any collections, tag pages, or feeds, but still deployed.
+    Unlisted,
+    /// in `liked` only, and only deployed if `publish_liked_posts`.
+    Liked,
+    /// in `excluded`, and in `skipped_own` or `skipped_other` like any other thread that is not
+    /// interesting.
+    Excluded,
+    /// in `index`, and the tag pages, author feeds, and series it belongs in.
+    Interesting,
+    /// in `skipped_own`, for threads we had some input in at publish time.
+    SkippedOwn,
+    /// in `skipped_other`.
+    SkippedOther,
+}
+
+impl From<Route> for CollectionDecision {
+    fn from(route: Route) -> Self {
+        Self {
+            route,
+            marked_interesting: false,
+            extra_collections: vec![],
+        }
+    }
+}
+
+/// sets the router, replacing [`default_collection_decision`] or any router already set.
+pub fn set_collection_router(router: impl CollectionRouter + 'static) {
+    *COLLECTION_ROUTER.write().unwrap() = Some(Box::new(router));
+}
+
+/// decides which collections the thread goes in, with the router if one was set.
+pub fn decide_collections(thread: &Thread) -> CollectionDecision {
+    match COLLECTION_ROUTER.read().unwrap().as_ref() {
+        Some(router) => router.route(thread),
+        None => default_collection_decision(thread),
+    }
+}
+
+/// the built-in routing, based on your settings.
+pub fn default_collection_decision(thread: &Thread) -> CollectionDecision {
+    let path = &thread.path;
+    // unlisted threads take precedence over everything else, even if they would otherwise be
+    // interesting. they still need to be deployed, so their links work.
+    if thread.meta.is_unlisted {
+        return Route::Unlisted.into();
+    }
+    // liked threads are other people’s posts, so they are kept out of everything else.
+    if thread.meta.is_liked {
+        return Route::Liked.into();
+    }
+    let mut was_interesting = false;
+    let mut marked_interesting = false;
+    if let Some(excluded_by) = SETTINGS.thread_excluded_by_post(thread) {
+        info!("excluding thread {path:?}, because of excluded post {excluded_by:?}");
+        return Route::Excluded.into();
+    } else if let Some(redacted) = thread.posts.iter().find(|post| post.meta.is_redacted) {
+        info!(
+            "excluding thread {path:?}, because of redacted post {:?}",
+            redacted.path
+        );
+        return Route::Excluded.into();
+    } else if thread.meta.is_main_self_author(&SETTINGS) {
+        was_interesting = true;
+    } else if SETTINGS.thread_is_on_excluded_archived_list(thread) {
+        return Route::Excluded.into();
+    } else if SETTINGS.thread_is_on_interesting_archived_list(thread) {
+        marked_interesting = true;
+        was_interesting = true;
+    } else if thread
+        .meta
+        .tags
+        .iter()
+        .any(|tag| SETTINGS.tag_is_interesting(tag))
+    {
+        was_interesting = true;
+    }
+    if was_interesting
+        && thread.is_transparent_share()
+        && SETTINGS.transparent_shares() == TransparentShares::Hide
+    {
+        debug!("not adding transparent share to interesting collections: {path:?}");
+        was_interesting = false;
+    }
+    let route = if was_interesting {
+        Route::Interesting
+    } else if thread_has_own_input(thread) {
+        Route::SkippedOwn
+    } else {
+        Route::SkippedOther
+    };
+
+    CollectionDecision {
+        route,
+        marked_interesting,
+        extra_collections: vec![],
+    }
+}
+
+/// returns true iff the thread had some input from us at publish time, that is, if the last
+/// post was authored by us with content and/or tags.
+pub(crate) fn thread_has_own_input(thread: &Thread) -> bool {
+    thread.posts.last().is_some_and(|post| {
+        (!post.meta.is_transparent_share || !post.meta.tags.is_empty())
+            && post
+                .meta
+                .author
+                .as_ref()
+                .is_some_and(|author| SETTINGS.is_any_self_author(author))
+    })
+}
+
+#[test]
+fn test_default_collection_decision() -> jane_eyre::eyre::Result<()> {
+    use jane_eyre::eyre;
+
+    use crate::{path::PostsPath, TemplatedPost};
+
+    let decide = |html: &str| -> eyre::Result<CollectionDecision> {
+        let post = TemplatedPost::filter(html, Some(PostsPath::generated_post_path(1)))?;
+        Ok(default_collection_decision(&Thread::try_from(post)?))
+    };
+    assert_eq!(
+        decide(r#"<meta name="tags" content="photography">"#)?,
+        Route::Interesting.into()
+    );
+    assert_eq!(
+        decide(r#"<meta name="tags" content="cooking">"#)?,
+        Route::SkippedOther.into()
+    );
+    assert_eq!(
+        decide(r#"<meta name="is_unlisted"><meta name="tags" content="photography">"#)?,
+        Route::Unlisted.into()
+    );
+    assert_eq!(
+        decide(r#"<meta name="is_liked"><meta name="tags" content="photography">"#)?,
+        Route::Liked.into()
+    );
+    assert_eq!(
+        decide(r#"<meta name="is_redacted"><meta name="tags" content="photography">"#)?,
+        Route::Excluded.into()
+    );
+
+    Ok(())
+}
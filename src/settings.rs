@@ -1,35 +1,140 @@
 use std::{
-    collections::{BTreeSet, HashMap},
+    collections::{BTreeMap, BTreeSet, HashMap},
     fs::File,
-    io::{BufRead, BufReader, Read},
+    io::{self, BufRead, BufReader, Read},
     path::{Path, PathBuf},
+    sync::OnceLock,
+    time::Duration,
 };
 
-use jane_eyre::eyre::{self, bail};
+use chrono::{format::StrftimeItems, NaiveDate};
+use chrono_tz::Tz;
+use jane_eyre::eyre::{self, bail, Context, OptionExt};
 use serde::Deserialize;
-use tracing::warn;
+use sha2::{digest::generic_array::functional::FunctionalSequence, Digest, Sha256};
+use tracing::{debug, warn};
+use unicode_normalization::UnicodeNormalization;
+use url::{Host, Url};
 
-use crate::{path::parse_path_relative_scheme_less_url_string, Author, TemplatedPost, Thread};
+use crate::{
+    cohost::Bookmarks,
+    date::{DATE_FORMAT_LONG, DATE_FORMAT_SHORT},
+    dom::{parse_html_fragment, text_content_for_summaries},
+    emoji::{is_valid_shortcode, render_emoji, EmojiContext},
+    meta::is_valid_language_tag,
+    og_image::parse_hex_color,
+    path::{parse_path_relative_scheme_less_url_string, PostsPath},
+    render_markdown, Author, PostMeta, TemplatedPost, Thread,
+};
 
 #[derive(Deserialize)]
 pub struct Settings {
+    #[serde(alias = "base_path")]
     pub base_url: String,
     pub external_base_url: String,
+    attachments_base_url: Option<String>,
+    canonical_host: Option<String>,
+    /// the host in `external_base_url` before it was replaced with `canonical_host`.
+    #[serde(skip)]
+    original_external_host: Option<String>,
+    /// a hash of the settings file and any overrides, for telling if the settings have changed.
+    #[serde(skip)]
+    source_hash: String,
     pub server_port: Option<u16>,
     pub site_title: String,
+    title_separator: Option<String>,
+    title_order: Option<TitleOrder>,
+    pub head_html: Option<String>,
+    pub footer_html: Option<String>,
     pub other_self_authors: Vec<String>,
     pub interesting_tags: Vec<Vec<String>>,
     archived_thread_tags_path: Option<String>,
     pub archived_thread_tags: Option<HashMap<String, Vec<String>>>,
+    alt_text_overrides_path: Option<String>,
+    alt_text_overrides: Option<HashMap<String, String>>,
     pub interesting_output_filenames_list_path: Option<String>,
+    interesting_output_filenames_format: Option<OutputFilenamesFormat>,
     interesting_archived_threads_list_path: Option<String>,
     interesting_archived_threads_list: Option<Vec<String>>,
     excluded_archived_threads_list_path: Option<String>,
     excluded_archived_threads_list: Option<Vec<String>>,
+    cohost_bookmarks_path: Option<String>,
+    pub excluded_post_ids: Option<Vec<usize>>,
+    pub excluded_filenames: Option<Vec<String>>,
+    pinned_posts: Option<Vec<String>>,
+    publish_liked_posts: Option<bool>,
+    pub exclude_threads_with_excluded_posts: Option<bool>,
     pub self_author: Option<Author>,
+    self_projects: Option<Vec<SelfProject>>,
     pub renamed_tags: Option<HashMap<String, String>>,
     pub implied_tags: Option<HashMap<String, Vec<String>>>,
+    hidden_tags: Option<Vec<String>>,
+    footer_extra_meta: Option<Vec<String>>,
+    show_engagement_counts: Option<bool>,
+    cohost_comments: Option<bool>,
+    pub embed_source: Option<EmbedSource>,
     pub nav: Vec<NavLink>,
+    pub collection_feeds: Option<HashMap<String, String>>,
+    pub collection_layouts: Option<HashMap<String, CollectionLayout>>,
+    tag_pages: Option<HashMap<String, TagPage>>,
+    pub tag_author_feeds: Option<Vec<TagAuthorFeed>>,
+    pub collections: Option<Vec<CustomCollection>>,
+    pub render_unknown_cohost_blocks: Option<bool>,
+    display_timezone: Option<String>,
+    pub relative_dates: Option<bool>,
+    date_format: Option<String>,
+    lang: Option<String>,
+    json_ld: Option<bool>,
+    fediverse_creator: Option<String>,
+    index_authors: Option<bool>,
+    default_theme: Option<DefaultTheme>,
+    accent_color: Option<String>,
+    print_pages: Option<bool>,
+    clean_urls: Option<bool>,
+    hashed_static_files: Option<bool>,
+    versioned_output: Option<bool>,
+    adjacent_threads_collection: Option<String>,
+    search_index_collection: Option<String>,
+    series_pages: Option<bool>,
+    transcode_images: Option<bool>,
+    strip_exif: Option<bool>,
+    max_image_dimension: Option<usize>,
+    allow_plaintext_attachment_downloads: Option<bool>,
+    attachment_filename_max_bytes: Option<usize>,
+    localise_cohost_urls: Option<bool>,
+    cohost_post_links: Option<CohostPostLinks>,
+    content_warning_tag_prefix: Option<String>,
+    feed_content_warnings_only: Option<bool>,
+    feed_tag_links: Option<bool>,
+    pub redirects: Option<BTreeMap<String, String>>,
+    emoji: Option<BTreeMap<String, String>>,
+    redirect_formats: Option<Vec<RedirectFormat>>,
+    redirects_are_permanent: Option<bool>,
+    unparseable_dates_sort_as: Option<UnparseableDates>,
+    transparent_shares: Option<TransparentShares>,
+    transparent_share_label: Option<String>,
+    post_styles: Option<PostStyles>,
+    pub inline_images_max_bytes: Option<u64>,
+    pub read_more_after_chars: Option<usize>,
+    pub expanded_ancestors: Option<usize>,
+    thread_post_order: Option<ThreadPostOrder>,
+    title_only_posts: Option<TitleOnlyPosts>,
+    title_only_placeholder: Option<String>,
+    #[serde(alias = "feed_max_items")]
+    feed_max_entries: Option<usize>,
+    tag_feed_max_entries: Option<usize>,
+    page_size: Option<usize>,
+    updates_feed_days: Option<u64>,
+    http_connect_timeout_secs: Option<u64>,
+    http_read_timeout_secs: Option<u64>,
+    max_concurrent_downloads: Option<usize>,
+    redact_logged_in_only_chosts: Option<RedactLoggedInOnly>,
+    content_security_policy: Option<ContentSecurityPolicy>,
+    pub favicon: Option<Favicon>,
+    pub crawlers: Option<Crawlers>,
+    pub colophon: Option<Colophon>,
+    pub imported_attachment_hosts: Option<ImportedAttachmentHosts>,
+    pub og_images: Option<OgImages>,
 
     #[deprecated(since = "0.3.0", note = "use path_to_static")]
     path_to_autost: Option<String>,
@@ -42,15 +147,492 @@ pub struct TagDefinition {
     pub implies: Option<Vec<String>>,
 }
 
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum OutputFilenamesFormat {
+    /// one path per line, for `rsync --files-from`.
+    Lines,
+    /// a json array of paths.
+    Json,
+    /// paths terminated by nul characters, for `rsync --from0 --files-from`.
+    Nul,
+}
+
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum RedirectFormat {
+    /// html pages with `<meta http-equiv="refresh">`, at each old path.
+    Html,
+    /// a `_redirects` file, for netlify and other hosts that support it.
+    Netlify,
+    /// a `redirects.json` file, for your own tools.
+    Json,
+}
+
+/// one of your own projects, whose posts are styled differently from posts by other authors.
+#[derive(Deserialize)]
+pub struct SelfProject {
+    /// matched against the `href` of the post’s author.
+    pub href: String,
+    /// shown instead of the display name in the post.
+    pub display_name: Option<String>,
+    /// url of an image to show next to the author.
+    pub avatar: Option<String>,
+    /// css color for the post header, like `"#83254f"`.
+    pub color: Option<String>,
+}
+
+/// an atom feed for threads in an interesting tag by one author, at `tagged/<tag>/by/<handle>.feed.xml`.
+#[derive(Deserialize)]
+pub struct TagAuthorFeed {
+    pub tag: String,
+    /// matched against the `href` of the thread’s author.
+    pub author: String,
+    pub handle: String,
+}
+
+/// a collection page at `<name>.html`, like the built-in collections, for the threads that match
+/// all of the conditions that are set.
+#[derive(Debug, Deserialize)]
+pub struct CustomCollection {
+    pub name: String,
+    pub title: String,
+    /// the filename of the atom feed, if any, like in `[collection_feeds]`.
+    pub feed: Option<String>,
+    /// the thread has any of these tags.
+    pub tags: Option<Vec<String>>,
+    /// the `href` of the thread’s author is any of these.
+    pub authors: Option<Vec<String>>,
+    /// the newest post in the thread is on or after this day, like `autost render --since`.
+    since: Option<String>,
+    /// the newest post in the thread is on or before this day, like `autost render --until`.
+    until: Option<String>,
+    /// the thread is (or is not) on your main page.
+    pub interesting: Option<bool>,
+}
+
+impl CustomCollection {
+    pub fn since(&self) -> Option<NaiveDate> {
+        self.since.as_deref().and_then(|day| day.parse().ok())
+    }
+
+    pub fn until(&self) -> Option<NaiveDate> {
+        self.until.as_deref().and_then(|day| day.parse().ok())
+    }
+
+    /// collections of interesting threads are interesting themselves, so their pages and feeds
+    /// are included in `interesting_output_filenames_list_path`.
+    pub fn is_interesting(&self) -> bool {
+        self.interesting == Some(true)
+    }
+}
+
+/// `[favicon]`, copied into the site output directory, along with a web app manifest.
+#[derive(Debug, Deserialize)]
+pub struct Favicon {
+    /// path to a png, svg, or ico file, copied to `favicon.<ext>`.
+    icon: String,
+    /// path to a png file, copied to `apple-touch-icon.png`.
+    apple_touch_icon: Option<String>,
+    pub theme_color: Option<String>,
+    #[serde(skip)]
+    icons: OnceLock<Vec<FaviconIcon>>,
+}
+
+/// an icon in `[favicon]`, for `<link rel="icon">` and the web app manifest.
+#[derive(Debug)]
+pub struct FaviconIcon {
+    /// where to copy the icon from.
+    pub source: String,
+    /// where to copy the icon to, relative to the site output directory.
+    pub filename: String,
+    pub rel: &'static str,
+    pub media_type: &'static str,
+    /// like `32x32`, or `any` for svg icons. None if the size can’t be read.
+    pub sizes: Option<String>,
+}
+
+impl Favicon {
+    pub fn icons(&self) -> &[FaviconIcon] {
+        self.icons.get_or_init(|| {
+            let mut result = vec![];
+            for (source, rel, basename) in [
+                (Some(&self.icon), "icon", "favicon"),
+                (
+                    self.apple_touch_icon.as_ref(),
+                    "apple-touch-icon",
+                    "apple-touch-icon",
+                ),
+            ] {
+                let Some(source) = source else { continue };
+                // checked by [`Settings::load`].
+                let Some((extension, media_type)) = Self::media_type(source) else {
+                    continue;
+                };
+                let sizes = if extension == "svg" {
+                    Some("any".to_owned())
+                } else {
+                    imagesize::size(source)
+                        .ok()
+                        .map(|size| format!("{}x{}", size.width, size.height))
+                };
+                result.push(FaviconIcon {
+                    source: source.clone(),
+                    filename: format!("{basename}.{extension}"),
+                    rel,
+                    media_type,
+                    sizes,
+                });
+            }
+            result
+        })
+    }
+
+    /// returns the extension (lowercase) and mime type of an icon, or None if not supported.
+    fn media_type(path: &str) -> Option<(String, &'static str)> {
+        let (_, extension) = path.rsplit_once('.')?;
+        let extension = extension.to_ascii_lowercase();
+        let media_type = match &*extension {
+            "png" => "image/png",
+            "svg" => "image/svg+xml",
+            "ico" => "image/x-icon",
+            _ => return None,
+        };
+
+        Some((extension, media_type))
+    }
+}
+
+/// `[crawlers]`, for `robots.txt` and `llms.txt` in the site output directory.
+#[derive(Debug, Default, Deserialize)]
+pub struct Crawlers {
+    /// groups of rules for `robots.txt`, which is only written if there are any.
+    #[serde(default)]
+    pub robots: Vec<RobotsGroup>,
+    /// summary of the site for `llms.txt`, which is only written if this is set.
+    pub llms_summary: Option<String>,
+}
+
+/// `[tag_pages]`, the description and banner image at the top of the page for one tag.
+#[derive(Debug, Default, Deserialize)]
+pub struct TagPage {
+    /// markdown or html, which is sanitised, like `"photos i took"`.
+    pub description: Option<String>,
+    /// url of a banner image, relative to `base_url`, like `"attachments/banner.jpg"`.
+    pub image: Option<String>,
+}
+
+impl TagPage {
+    /// returns the `description` rendered as markdown and sanitised.
+    pub fn description_html(&self) -> Option<String> {
+        self.description
+            .as_deref()
+            .map(|description| ammonia::clean(&render_markdown(description)))
+    }
+
+    /// returns the `description` as plain text, for the `<subtitle>` of the tag feed.
+    pub fn description_text(&self) -> eyre::Result<Option<String>> {
+        let Some(html) = self.description_html() else {
+            return Ok(None);
+        };
+        let dom = parse_html_fragment(html.as_bytes())?;
+
+        Ok(Some(text_content_for_summaries(dom.document.clone())?))
+    }
+}
+
+/// `[colophon]`, for `humans.txt` and `colophon.html` in the site output directory.
+#[derive(Debug, Default, Deserialize)]
+pub struct Colophon {
+    /// the person or people behind the site, like `"eggbug"`.
+    pub owner: Option<String>,
+    /// how to reach the owner, like an email address or url.
+    pub contact: Option<String>,
+    /// tools used to make the site, other than autost, like `["neovim"]`.
+    #[serde(default)]
+    pub tools: Vec<String>,
+    /// also write `colophon.html`, in the normal site layout.
+    #[serde(default)]
+    pub page: bool,
+}
+
+/// `[imported_attachment_hosts]`, the hosts that `autost import` may download attachments from.
+#[derive(Debug, Default, Deserialize)]
+pub struct ImportedAttachmentHosts {
+    /// if not empty, only these hosts are allowed, like `["example.com", "*.example.net"]`.
+    #[serde(default)]
+    pub allow: Vec<String>,
+    /// these hosts are never allowed, even if they are in `allow`.
+    #[serde(default)]
+    pub block: Vec<String>,
+}
+
+impl ImportedAttachmentHosts {
+    /// returns true iff attachments may be downloaded from the host.
+    pub fn allows(&self, host: &str) -> bool {
+        let matches = |pattern: &String| host_matches_pattern(host, pattern);
+        (self.allow.is_empty() || self.allow.iter().any(matches)) && !self.block.iter().any(matches)
+    }
+}
+
+/// returns true iff the host is the pattern, or if the pattern is like `*.example.com`, a
+/// subdomain of `example.com` at any depth (but not `example.com` itself).
+fn host_matches_pattern(host: &str, pattern: &str) -> bool {
+    let host = host.to_ascii_lowercase();
+    let pattern = pattern.to_ascii_lowercase();
+    match pattern.strip_prefix("*.") {
+        Some(domain) => host
+            .strip_suffix(domain)
+            .is_some_and(|subdomain| subdomain.len() > 1 && subdomain.ends_with('.')),
+        None => host == pattern,
+    }
+}
+
+/// `[og_images]`, generated `og:image` cards for threads without an image of their own.
+#[derive(Debug, Deserialize)]
+pub struct OgImages {
+    /// path to a truetype font (`.ttf`) for the text.
+    pub font: String,
+    /// like `"#83254f"` or `"#fff"`.
+    background: Option<String>,
+    /// like `"#83254f"` or `"#fff"`.
+    foreground: Option<String>,
+}
+
+impl OgImages {
+    pub fn background(&self) -> &str {
+        self.background.as_deref().unwrap_or("#83254f")
+    }
+
+    pub fn foreground(&self) -> &str {
+        self.foreground.as_deref().unwrap_or("#ffffff")
+    }
+}
+
+/// `[[crawlers.robots]]`, the rules for one user agent in `robots.txt`.
+#[derive(Debug, Deserialize)]
+pub struct RobotsGroup {
+    /// like `"*"` or `"GPTBot"`.
+    pub user_agent: String,
+    /// paths as they appear in `robots.txt`, like `"/tagged/"`.
+    #[serde(default)]
+    pub allow: Vec<String>,
+    #[serde(default)]
+    pub disallow: Vec<String>,
+}
+
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum CollectionLayout {
+    /// one thread after another, like the main page.
+    #[default]
+    List,
+    /// threads side by side in a grid, for collections with lots of images.
+    Grid,
+}
+
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum TransparentShares {
+    /// show transparent shares like any other thread, with an empty post at the end.
+    #[default]
+    Show,
+    /// show only the shared posts, with a “shared by” line instead of the empty post.
+    Collapse,
+    /// leave transparent shares out of the main page, tag pages, and their feeds.
+    Hide,
+}
+
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum EmbedSource {
+    /// in an html comment at the end of the page.
+    Comment,
+    /// in a `<script type="text/markdown">` at the end of the page.
+    Script,
+    /// in a separate file, like `site/10000000.md`, linked at the end of the page.
+    Download,
+}
+
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum CohostPostLinks {
+    /// leave links to chosts as they are.
+    #[default]
+    Keep,
+    /// replace links to chosts in the archive with links to their pages.
+    Local,
+    /// like `Local`, but also add a “cohost is gone” note after links to any other chosts.
+    LocalWithNote,
+}
+
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum PostStyles {
+    /// keep `<style>` elements in posts, but only let them style their own post.
+    #[default]
+    Scoped,
+    /// remove `<style>` elements from posts.
+    Stripped,
+}
+
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum DefaultTheme {
+    /// the light or dark theme, depending on the reader’s system setting.
+    Auto,
+    Light,
+    Dark,
+}
+
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum TitleOrder {
+    /// the page title, then the site title, like “my post — ao!!”.
+    #[default]
+    PageFirst,
+    /// the site title, then the page title, like “ao!! — my post”.
+    SiteFirst,
+}
+
+/// the order of the posts in a thread, for `thread_post_order`.
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum ThreadPostOrder {
+    /// the posts that were shared or replied to, oldest first, then the main post, like cohost.
+    #[default]
+    OldestFirst,
+    /// the main post, then the posts it shared or replied to, newest first.
+    NewestFirst,
+}
+
+/// how to show posts with a title but no body, for `title_only_posts`.
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum TitleOnlyPosts {
+    /// on their own page, like any other post.
+    #[default]
+    Page,
+    /// only on the main page, with their own page redirecting there.
+    Inline,
+    /// on their own page, with `title_only_placeholder` as their body.
+    Placeholder,
+}
+
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum UnparseableDates {
+    Oldest,
+    Newest,
+}
+
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum RedactLoggedInOnly {
+    /// archive the content of all logged-in-only chosts.
+    None,
+    /// archive the content of your own logged-in-only chosts only.
+    Others,
+    /// archive the content of no logged-in-only chosts.
+    All,
+}
+
+/// extra sources for the `Content-Security-Policy` of each page, on top of the baseline policy.
+#[derive(Debug, Default, Deserialize)]
+pub struct ContentSecurityPolicy {
+    img_src: Option<Vec<String>>,
+    media_src: Option<Vec<String>>,
+    script_src: Option<Vec<String>>,
+    style_src: Option<Vec<String>>,
+    font_src: Option<Vec<String>>,
+}
+
 #[derive(Deserialize)]
 pub struct NavLink {
     pub href: String,
     pub text: String,
 }
 
+/// prefix of environment variables that override settings, like `AUTOST_SITE_TITLE`.
+const ENV_PREFIX: &str = "AUTOST_";
+
+/// settings overridden with `--set key=value`, which take precedence over the environment.
+static CLI_OVERRIDES: OnceLock<Vec<(String, String)>> = OnceLock::new();
+
+/// sets the settings overridden with `--set key=value`. must be called before [`crate::SETTINGS`]
+/// is first used.
+pub fn set_cli_overrides(overrides: &[String]) -> eyre::Result<()> {
+    let overrides = overrides
+        .iter()
+        .map(|entry| {
+            let (key, value) = entry
+                .split_once("=")
+                .ok_or_eyre("--set must be in the form key=value")?;
+            Ok((key.to_owned(), value.to_owned()))
+        })
+        .collect::<eyre::Result<Vec<_>>>()?;
+    if CLI_OVERRIDES.set(overrides).is_err() {
+        bail!("settings overrides were already set");
+    }
+
+    Ok(())
+}
+
+/// returns the settings overridden by environment variables, as `(key, value)` pairs.
+///
+/// `AUTOST_SITE_TITLE` overrides `site_title`, and a double underscore separates the keys of
+/// tables, so `AUTOST_SELF_AUTHOR__NAME` overrides `name` in `[self_author]`.
+fn env_overrides(vars: impl Iterator<Item = (String, String)>) -> Vec<(String, String)> {
+    let mut result = vars
+        .filter_map(|(name, value)| {
+            let key = name.strip_prefix(ENV_PREFIX)?;
+            Some((key.to_lowercase().replace("__", "."), value))
+        })
+        .collect::<Vec<_>>();
+    // make the order deterministic, in case two variables override the same setting.
+    result.sort();
+
+    result
+}
+
+/// overrides the setting at `key` (like `site_title` or `self_author.name`) in `table`.
+///
+/// `value` is parsed as a toml value if possible (like `100`, `true`, or `["html"]`), or
+/// treated as a string otherwise.
+fn apply_override(table: &mut toml::Table, key: &str, value: &str) -> eyre::Result<()> {
+    let value = toml::from_str::<toml::Table>(&format!("value = {value}"))
+        .ok()
+        .and_then(|mut parsed| parsed.remove("value"))
+        .unwrap_or_else(|| toml::Value::String(value.to_owned()));
+    let mut keys = key.split(".").collect::<Vec<_>>();
+    let last = keys.pop().filter(|key| !key.is_empty());
+    let Some(last) = last else {
+        bail!("setting name is empty: {key:?}");
+    };
+
+    let mut table = table;
+    for key in keys {
+        let entry = table
+            .entry(key)
+            .or_insert_with(|| toml::Value::Table(toml::Table::default()));
+        let Some(inner) = entry.as_table_mut() else {
+            bail!("setting is not a table: {key:?}");
+        };
+        table = inner;
+    }
+    table.insert(last.to_owned(), value);
+
+    Ok(())
+}
+
 impl Settings {
+    /// loads `autost.toml`, with any settings overridden by environment variables, then by
+    /// `--set key=value`.
     pub fn load_default() -> eyre::Result<Self> {
-        Self::load("autost.toml")
+        let mut overrides = env_overrides(std::env::vars());
+        overrides.extend(CLI_OVERRIDES.get().into_iter().flatten().cloned());
+
+        Self::load_with_overrides("autost.toml", &overrides)
     }
 
     pub fn load_example() -> eyre::Result<Self> {
@@ -58,21 +640,126 @@ impl Settings {
     }
 
     pub fn load(path: impl AsRef<Path>) -> eyre::Result<Self> {
-        let mut result = String::default();
-        File::open(path)?.read_to_string(&mut result)?;
-        let mut result: Settings = toml::from_str(&result)?;
+        Self::load_with_overrides(path, &[])
+    }
 
-        if !result.base_url.starts_with("/") {
-            bail!("base_url setting must start with slash!");
-        }
-        if result.base_url.starts_with("//") {
-            bail!("base_url setting must not start with two slashes!");
+    fn load_with_overrides(
+        path: impl AsRef<Path>,
+        overrides: &[(String, String)],
+    ) -> eyre::Result<Self> {
+        let mut source = String::default();
+        File::open(path)?.read_to_string(&mut source)?;
+        let mut result: Settings = if overrides.is_empty() {
+            toml::from_str(&source)?
+        } else {
+            let mut table: toml::Table = toml::from_str(&source)?;
+            for (key, value) in overrides {
+                apply_override(&mut table, key, value)?;
+            }
+            table.try_into()?
+        };
+        let mut hash = Sha256::new();
+        hash.update(&source);
+        for (key, value) in overrides {
+            hash.update(format!("\0{key}\0{value}"));
         }
-        if !result.base_url.ends_with("/") {
-            bail!("base_url setting must end with slash!");
+        result.source_hash = hash.finalize().map(|o| format!("{o:02x}")).join("");
+
+        result.normalise_tags();
+        let base_url = normalise_base_url(&result.base_url);
+        if base_url != result.base_url {
+            warn!(
+                "base_url setting should be {base_url:?}, not {:?}",
+                result.base_url
+            );
+            result.base_url = base_url;
         }
         if !result.external_base_url.ends_with("/") {
-            bail!("external_base_url setting must end with slash!");
+            warn!(
+                "external_base_url setting should end with slash: {:?}",
+                result.external_base_url
+            );
+            result.external_base_url.push('/');
+        }
+        if let Some(canonical_host) = result.canonical_host.as_deref() {
+            let (external_base_url, original_host) =
+                with_canonical_host(&result.external_base_url, canonical_host)?;
+            if external_base_url != result.external_base_url {
+                result.external_base_url = external_base_url;
+                result.original_external_host = Some(original_host);
+            }
+        }
+        if let Some(attachments_base_url) = result.attachments_base_url.as_mut() {
+            if !attachments_base_url.ends_with("/") {
+                warn!(
+                    "attachments_base_url setting should end with slash: {attachments_base_url:?}"
+                );
+                attachments_base_url.push('/');
+            }
+        }
+        for shortcode in result.emoji.iter().flat_map(|emoji| emoji.keys()) {
+            if !is_valid_shortcode(shortcode) {
+                bail!("emoji: bad shortcode {shortcode:?}, must be like \"eggbug\" without colons");
+            }
+        }
+        for project in result.self_projects.iter().flatten() {
+            if let Some(color) = project.color.as_deref() {
+                if color.contains([';', '{', '}']) {
+                    bail!("self_projects: bad color for {:?}: {color:?}", project.href);
+                }
+            }
+        }
+        if let Some(favicon) = result.favicon.as_ref() {
+            if Favicon::media_type(&favicon.icon).is_none() {
+                bail!("favicon: icon must be png, svg, or ico: {:?}", favicon.icon);
+            }
+            if let Some(apple_touch_icon) = favicon.apple_touch_icon.as_deref() {
+                if Favicon::media_type(apple_touch_icon).map(|(_, media_type)| media_type)
+                    != Some("image/png")
+                {
+                    bail!("favicon: apple_touch_icon must be png: {apple_touch_icon:?}");
+                }
+            }
+        }
+        if let Some(crawlers) = result.crawlers.as_ref() {
+            for group in crawlers.robots.iter() {
+                if group.user_agent.is_empty() {
+                    bail!("crawlers: user_agent must not be empty");
+                }
+                for value in [&group.user_agent]
+                    .into_iter()
+                    .chain(&group.allow)
+                    .chain(&group.disallow)
+                {
+                    if value.contains(['\n', '\r', '#']) {
+                        bail!("crawlers: bad robots.txt value: {value:?}");
+                    }
+                }
+            }
+        }
+        if let Some(hosts) = result.imported_attachment_hosts.as_ref() {
+            for pattern in hosts.allow.iter().chain(&hosts.block) {
+                if pattern.trim_start_matches("*.").contains(['*', '/', ':']) {
+                    bail!("imported_attachment_hosts: bad host {pattern:?}, must be like \"example.com\" or \"*.example.com\"");
+                }
+            }
+        }
+        if let Some(og_images) = result.og_images.as_ref() {
+            for color in [og_images.background(), og_images.foreground()] {
+                if parse_hex_color(color).is_none() {
+                    bail!("og_images: bad color {color:?}, must be like \"#83254f\" or \"#fff\"");
+                }
+            }
+        }
+        for collection in result.collections.iter().flatten() {
+            for day in [&collection.since, &collection.until].into_iter().flatten() {
+                if day.parse::<NaiveDate>().is_err() {
+                    bail!(
+                        "collections: bad day for {:?}: {day:?} (expected yyyy-mm-dd)",
+                        collection.name
+                    );
+                }
+            }
         }
         if let Some(path) = result.archived_thread_tags_path.as_ref() {
             let entries = BufReader::new(File::open(path)?)
@@ -91,6 +778,20 @@ impl Settings {
                 .collect();
             result.archived_thread_tags = Some(entries);
         }
+        if let Some(path) = result.alt_text_overrides_path.as_ref() {
+            let entries = BufReader::new(File::open(path)?)
+                .lines()
+                .collect::<Result<Vec<_>, _>>()?;
+            let mut overrides = entries
+                .iter()
+                .filter(|entry| !entry.starts_with('#'))
+                .filter_map(|entry| entry.split_once(" "))
+                .map(|(attachment_id, alt)| (attachment_id.to_owned(), alt.trim().to_owned()))
+                .collect::<HashMap<_, _>>();
+            // overrides in autost.toml take precedence over the file.
+            overrides.extend(result.alt_text_overrides.take().into_iter().flatten());
+            result.alt_text_overrides = Some(overrides);
+        }
         if let Some(path) = result.interesting_archived_threads_list_path.as_ref() {
             let list = BufReader::new(File::open(path)?)
                 .lines()
@@ -103,6 +804,69 @@ impl Settings {
                 .collect::<Result<Vec<_>, _>>()?;
             result.excluded_archived_threads_list = Some(list);
         }
+        if let Some(path) = result.cohost_bookmarks_path.clone() {
+            match File::open(&path) {
+                Ok(file) => {
+                    let bookmarks = serde_json::from_reader(BufReader::new(file))
+                        .wrap_err_with(|| format!("failed to parse cohost bookmarks: {path:?}"))?;
+                    result.merge_cohost_bookmarks(bookmarks);
+                }
+                Err(error) if error.kind() == io::ErrorKind::NotFound => {
+                    warn!("cohost_bookmarks_path setting points to a file that does not exist; ignoring: {path:?}");
+                }
+                Err(error) => Err(error)?,
+            }
+        }
+        if let Some(lang) = result.lang.as_deref() {
+            if !is_valid_language_tag(lang) {
+                bail!("lang setting is not a valid language tag: {lang}");
+            }
+        }
+        if result.transcode_images == Some(true) && !cfg!(feature = "heif") {
+            warn!("transcode_images setting needs autost to be built with the heif feature; heif images will not be transcoded");
+        }
+        if result.attachment_filename_max_bytes() < 32 {
+            bail!("attachment_filename_max_bytes setting must be at least 32");
+        }
+        if result.page_size() == 0 {
+            bail!("page_size setting must be at least 1");
+        }
+        if result.max_concurrent_downloads() == 0 {
+            bail!("max_concurrent_downloads setting must be at least 1");
+        }
+        if let Some(color) = result.accent_color() {
+            // the color goes into an inline `<style>`, so it must not be able to escape it.
+            if color.is_empty()
+                || !color
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || "#(),.%-/ ".contains(c))
+            {
+                bail!("accent_color setting is not a css color: {color:?}");
+            }
+        }
+        if let Some(creator) = result.fediverse_creator() {
+            let valid = creator
+                .strip_prefix('@')
+                .and_then(|handle| handle.split_once('@'))
+                .is_some_and(|(user, host)| {
+                    !user.is_empty() && !host.is_empty() && !host.contains(['@', '/', ' '])
+                });
+            if !valid {
+                bail!(
+                    "fediverse_creator setting must be like \"@user@example.social\": {creator:?}"
+                );
+            }
+        }
+        if let Some(timezone) = result.display_timezone.as_deref() {
+            if timezone.parse::<Tz>().is_err() {
+                bail!("display_timezone setting is not a known timezone: {timezone}");
+            }
+        }
+        if let Some(format) = result.date_format() {
+            if StrftimeItems::new(format).parse().is_err() {
+                bail!("date_format setting is not a valid chrono format string: {format:?}");
+            }
+        }
         #[allow(deprecated)]
         if result.path_to_autost.is_some() {
             warn!("path_to_autost setting is deprecated; use path_to_static instead");
@@ -126,137 +890,805 @@ impl Settings {
         }
     }
 
+    /// makes path-relative urls relative to `base_url`, and root-relative urls under `base_url`
+    /// if they aren’t already. fragment-only urls, like `#content`, stay within the page.
     pub fn base_url_relativise(&self, url: &str) -> String {
-        if let Some(url) = parse_path_relative_scheme_less_url_string(url) {
+        if url.starts_with("#") {
+            url.to_owned()
+        } else if let Some(url) = parse_path_relative_scheme_less_url_string(url) {
             format!("{}{}", self.base_url, url)
+        } else if let Some(path) = url.strip_prefix("/").filter(|path| !path.starts_with("/")) {
+            if url.starts_with(&self.base_url) {
+                url.to_owned()
+            } else {
+                format!("{}{}", self.base_url, path)
+            }
         } else {
             url.to_owned()
         }
     }
 
-    pub fn server_port(&self) -> u16 {
-        self.server_port.unwrap_or(8420)
+    pub fn attachments_base_url(&self) -> Option<&str> {
+        self.attachments_base_url.as_deref()
     }
 
-    pub fn page_title(&self, title: Option<&str>) -> String {
-        match title {
-            Some(title) => format!("{} — {}", title, self.site_title),
-            None => self.site_title.clone(),
+    /// like [`Self::base_url_relativise`], but if `attachments_base_url` is set, urls of
+    /// attachments are made absolute under `attachments_base_url` instead.
+    pub fn attachments_base_url_absolutise(&self, url: &str) -> String {
+        let url = self.base_url_relativise(url);
+        let Some(attachments_base_url) = self.attachments_base_url.as_deref() else {
+            return url;
+        };
+        match url
+            .strip_prefix(&self.base_url)
+            .and_then(|path| path.strip_prefix("attachments/"))
+        {
+            Some(path) => format!("{attachments_base_url}{path}"),
+            None => url,
         }
     }
 
-    pub fn is_main_self_author(&self, author: &Author) -> bool {
-        // compare href only, ignoring other fields
-        self.self_author
-            .as_ref()
-            .map_or(false, |a| a.href == author.href)
+    /// makes path-relative and root-relative urls absolute, under `external_base_url` (or
+    /// `attachments_base_url` for attachments, if set).
+    pub fn external_base_url_absolutise(&self, url: &str) -> String {
+        let url = self.attachments_base_url_absolutise(url);
+        match url.strip_prefix(&self.base_url) {
+            Some(path) if url.starts_with("/") => format!("{}{}", self.external_base_url, path),
+            _ => url,
+        }
     }
 
-    pub fn is_any_self_author(&self, author: &Author) -> bool {
-        // compare href only, ignoring other fields
-        self.is_main_self_author(author)
-            || self.other_self_authors.iter().any(|a| *a == author.href)
+    /// returns the image url for the emoji shortcode (without colons), if any.
+    pub fn emoji_url(&self, shortcode: &str) -> Option<&str> {
+        self.emoji.as_ref()?.get(shortcode).map(|url| &**url)
     }
 
-    pub fn tag_is_interesting(&self, tag: &str) -> bool {
-        self.interesting_tags_iter()
-            .find(|&interesting_tag| interesting_tag == tag)
-            .is_some()
+    pub fn canonical_host(&self) -> Option<&str> {
+        self.canonical_host.as_deref()
     }
 
-    pub fn interesting_tags_iter(&self) -> impl Iterator<Item = &str> {
-        self.interesting_tags.iter().flatten().map(|tag| &**tag)
+    /// hosts that should redirect to `canonical_host`: the host `external_base_url` had before
+    /// it was replaced, and `canonical_host` with or without `www.`.
+    pub fn non_canonical_hosts(&self) -> Vec<String> {
+        if self.canonical_host.is_none() {
+            return vec![];
+        }
+        let mut result = vec![];
+        if let Some(host) = self.original_external_host.clone() {
+            result.push(host);
+        }
+        let Ok(url) = Url::parse(&self.external_base_url) else {
+            return result;
+        };
+        if let Some(Host::Domain(domain)) = url.host() {
+            let domain = match domain.strip_prefix("www.") {
+                Some(domain) => domain.to_owned(),
+                None if domain.contains('.') => format!("www.{domain}"),
+                None => return result,
+            };
+            let other = match url.port() {
+                Some(port) => format!("{domain}:{port}"),
+                None => domain,
+            };
+            if !result.contains(&other) {
+                result.push(other);
+            }
+        }
+
+        result
     }
 
-    pub fn interesting_tag_groups_iter(&self) -> impl Iterator<Item = &[String]> {
-        self.interesting_tags.iter().map(|tag| &**tag)
+    pub fn interesting_output_filenames_format(&self) -> OutputFilenamesFormat {
+        self.interesting_output_filenames_format
+            .unwrap_or(OutputFilenamesFormat::Lines)
     }
 
-    pub fn thread_is_on_interesting_archived_list(&self, thread: &Thread) -> bool {
-        self.interesting_archived_threads_list
-            .as_ref()
-            .zip(thread.meta.archived.as_ref())
-            .is_some_and(|(list, archived)| list.iter().any(|x| x == archived))
+    pub fn redirect_formats(&self) -> &[RedirectFormat] {
+        self.redirect_formats
+            .as_deref()
+            .unwrap_or(&[RedirectFormat::Html])
     }
 
-    pub fn thread_is_on_excluded_archived_list(&self, thread: &Thread) -> bool {
-        self.excluded_archived_threads_list
-            .as_ref()
-            .zip(thread.meta.archived.as_ref())
-            .is_some_and(|(list, archived)| list.iter().any(|x| x == archived))
+    pub fn redirect_status(&self) -> u16 {
+        if self.redirects_are_permanent.unwrap_or(true) {
+            301
+        } else {
+            302
+        }
     }
 
-    pub fn extra_archived_thread_tags(&self, post: &TemplatedPost) -> &[String] {
-        self.archived_thread_tags
-            .as_ref()
-            .zip(post.meta.archived.as_ref())
-            .and_then(|(tags, archived)| tags.get(archived))
-            .map(|result| &**result)
-            .unwrap_or(&[])
+    pub fn redact_logged_in_only_chosts(&self) -> RedactLoggedInOnly {
+        self.redact_logged_in_only_chosts
+            .unwrap_or(RedactLoggedInOnly::None)
     }
 
-    pub fn resolve_tags(&self, tags: Vec<String>) -> Vec<String> {
-        let mut seen = BTreeSet::default();
-        let mut result = tags;
-        let mut old_len = 0;
+    pub fn updates_feed_days(&self) -> Option<u64> {
+        self.updates_feed_days
+    }
 
-        // loop until we fail to add any more tags.
-        while result.len() > old_len {
-            let old = result;
-            old_len = old.len();
-            result = vec![];
-            for tag in old {
-                let tag = self.renamed_tag(tag);
-                if seen.insert(tag.clone()) {
-                    // prepend implied tags, such that more general tags go first.
-                    result.extend(self.implied_tags_shallow(&tag).to_vec());
-                }
-                result.push(tag);
-            }
-        }
+    /// returns the `Content-Security-Policy` for a page, if `[content_security_policy]` is set.
+    ///
+    /// `script_hashes` and `style_hashes` are sources like `'sha256-...'` for the inline
+    /// `<script>` and `<style>` elements in the page.
+    pub fn content_security_policy(
+        &self,
+        script_hashes: &[String],
+        style_hashes: &[String],
+    ) -> Option<String> {
+        let csp = self.content_security_policy.as_ref()?;
+        let attachments = self.attachments_base_url.as_slice();
+        let extra = |sources: &Option<Vec<String>>| sources.clone().unwrap_or_default();
+        let directives: [(&str, Vec<String>); 10] = [
+            ("default-src", vec!["'self'".to_owned()]),
+            (
+                "img-src",
+                [
+                    vec!["'self'".to_owned(), "data:".to_owned()],
+                    attachments.to_vec(),
+                    extra(&csp.img_src),
+                ]
+                .concat(),
+            ),
+            (
+                "media-src",
+                [
+                    vec!["'self'".to_owned()],
+                    attachments.to_vec(),
+                    extra(&csp.media_src),
+                ]
+                .concat(),
+            ),
+            (
+                "script-src",
+                [
+                    vec!["'self'".to_owned()],
+                    script_hashes.to_vec(),
+                    extra(&csp.script_src),
+                ]
+                .concat(),
+            ),
+            // posts use style attributes, which can’t be hashed, so allow them here...
+            (
+                "style-src",
+                [
+                    vec!["'self'".to_owned(), "'unsafe-inline'".to_owned()],
+                    extra(&csp.style_src),
+                ]
+                .concat(),
+            ),
+            // ...but only allow known <style> elements.
+            (
+                "style-src-elem",
+                [
+                    vec!["'self'".to_owned()],
+                    style_hashes.to_vec(),
+                    extra(&csp.style_src),
+                ]
+                .concat(),
+            ),
+            (
+                "font-src",
+                [vec!["'self'".to_owned()], extra(&csp.font_src)].concat(),
+            ),
+            ("connect-src", vec!["'self'".to_owned()]),
+            ("object-src", vec!["'none'".to_owned()]),
+            ("base-uri", vec!["'none'".to_owned()]),
+        ];
 
-        let old = result;
-        let mut result = vec![];
-        for tag in old {
-            if !result.contains(&tag) {
-                result.push(tag);
-            }
-        }
+        Some(
+            directives
+                .into_iter()
+                .map(|(name, sources)| format!("{name} {}", sources.join(" ")))
+                .collect::<Vec<_>>()
+                .join("; "),
+        )
+    }
 
-        result
+    /// how long to wait when connecting, when downloading attachments and other resources.
+    pub fn http_connect_timeout(&self) -> Duration {
+        Duration::from_secs(self.http_connect_timeout_secs.unwrap_or(30))
     }
 
-    fn renamed_tag(&self, tag: String) -> String {
-        if let Some(tags) = &self.renamed_tags {
-            if let Some(result) = tags.get(&tag) {
-                return result.clone();
-            }
-        }
+    /// how long to wait for a response or its body, when downloading attachments and other
+    /// resources.
+    pub fn http_read_timeout(&self) -> Duration {
+        Duration::from_secs(self.http_read_timeout_secs.unwrap_or(30))
+    }
 
-        tag
+    /// a hash of the settings file and any overrides, which changes whenever the settings might
+    /// have.
+    pub fn source_hash(&self) -> &str {
+        &self.source_hash
     }
 
-    fn implied_tags_shallow(&self, tag: &str) -> &[String] {
-        if let Some(tags) = &self.implied_tags {
-            if let Some(result) = tags.get(tag) {
-                return result;
-            }
+    /// how many attachments and other resources to download at once.
+    pub fn max_concurrent_downloads(&self) -> usize {
+        self.max_concurrent_downloads.unwrap_or(8)
+    }
+
+    /// how many of the newest threads to include in each atom feed, where zero means all of them.
+    pub fn feed_max_entries(&self) -> usize {
+        match self.feed_max_entries {
+            None | Some(0) => usize::MAX,
+            Some(max) => max,
         }
+    }
 
-        &[]
+    /// how many threads to show on each page of a collection or tag page.
+    pub fn page_size(&self) -> usize {
+        self.page_size.unwrap_or(20)
     }
 
-    pub fn path_to_static(&self) -> Option<PathBuf> {
-        #[allow(deprecated)]
-        if let Some(path_to_autost) = self.path_to_autost.as_deref() {
-            return Some(Path::new(path_to_autost).join("static"));
-        }
-        if let Some(path_to_static) = self.path_to_static.as_deref() {
-            return Some(path_to_static.into());
+    /// like [`Settings::feed_max_entries`], but for `tagged/<tag>.feed.xml`.
+    pub fn tag_feed_max_entries(&self) -> usize {
+        match self.tag_feed_max_entries {
+            None => self.feed_max_entries(),
+            Some(0) => usize::MAX,
+            Some(max) => max,
         }
-        None
     }
-}
+
+    pub fn transparent_shares(&self) -> TransparentShares {
+        self.transparent_shares.unwrap_or_default()
+    }
+
+    pub fn post_styles(&self) -> PostStyles {
+        self.post_styles.unwrap_or_default()
+    }
+
+    pub fn transparent_share_label(&self) -> &str {
+        self.transparent_share_label
+            .as_deref()
+            .unwrap_or("shared by")
+    }
+
+    /// returns true iff the thread’s empty transparent share post should be replaced with a
+    /// “shared by” line (`transparent_shares = "collapse"`).
+    pub fn collapse_transparent_share(&self, thread: &Thread) -> bool {
+        self.transparent_shares() == TransparentShares::Collapse
+            && thread.posts.len() > 1
+            && thread.is_transparent_share()
+    }
+
+    pub fn default_theme(&self) -> DefaultTheme {
+        self.default_theme.unwrap_or(DefaultTheme::Auto)
+    }
+
+    /// returns the `<html data-theme>` for the `default_theme`, or None to follow the reader’s
+    /// system setting.
+    pub fn default_data_theme(&self) -> Option<&'static str> {
+        match self.default_theme() {
+            DefaultTheme::Auto => None,
+            DefaultTheme::Light => Some("light"),
+            DefaultTheme::Dark => Some("dark"),
+        }
+    }
+
+    pub fn accent_color(&self) -> Option<&str> {
+        self.accent_color.as_deref()
+    }
+
+    pub fn unparseable_dates_sort_as(&self) -> UnparseableDates {
+        self.unparseable_dates_sort_as
+            .unwrap_or(UnparseableDates::Oldest)
+    }
+
+    pub fn display_timezone(&self) -> Option<Tz> {
+        self.display_timezone
+            .as_deref()
+            .map(|timezone| timezone.parse().expect("guaranteed by Settings::load"))
+    }
+
+    pub fn relative_dates(&self) -> bool {
+        self.relative_dates.unwrap_or(false)
+    }
+
+    /// the chrono format string for dates in html output, with `"long"` and `"short"` presets.
+    pub fn date_format(&self) -> Option<&str> {
+        self.date_format
+            .as_deref()
+            .map(|date_format| match date_format {
+                "long" => DATE_FORMAT_LONG,
+                "short" => DATE_FORMAT_SHORT,
+                date_format => date_format,
+            })
+    }
+
+    /// returns the language of a post or thread, falling back to the site-wide `lang`.
+    pub fn lang<'meta>(&'meta self, meta: Option<&'meta PostMeta>) -> Option<&'meta str> {
+        meta.and_then(|meta| meta.lang.as_deref())
+            .or(self.lang.as_deref())
+    }
+
+    pub fn localise_cohost_urls(&self) -> bool {
+        self.localise_cohost_urls.unwrap_or(true)
+    }
+
+    pub fn cohost_post_links(&self) -> CohostPostLinks {
+        self.cohost_post_links.unwrap_or_default()
+    }
+
+    pub fn index_authors(&self) -> bool {
+        self.index_authors.unwrap_or(false)
+    }
+
+    pub fn print_pages(&self) -> bool {
+        self.print_pages.unwrap_or(false)
+    }
+
+    pub fn clean_urls(&self) -> bool {
+        self.clean_urls.unwrap_or(false)
+    }
+
+    /// returns the `extra` metadata of the post to show in its footer, in the order of
+    /// `footer_extra_meta`.
+    pub fn footer_extra_meta<'meta>(&self, meta: &'meta PostMeta) -> Vec<(&'meta str, &'meta str)> {
+        self.footer_extra_meta
+            .iter()
+            .flatten()
+            .filter_map(|key| meta.extra.get_key_value(key))
+            .map(|(key, value)| (&**key, &**value))
+            .collect()
+    }
+
+    /// returns the engagement counts of the post to show in its footer, like “3 likes”, if any.
+    pub fn footer_engagement(&self, meta: &PostMeta) -> Option<String> {
+        self.show_engagement_counts()
+            .then(|| meta.engagement.summary())
+            .flatten()
+    }
+
+    pub fn show_engagement_counts(&self) -> bool {
+        self.show_engagement_counts.unwrap_or(false)
+    }
+
+    pub fn cohost_comments(&self) -> bool {
+        self.cohost_comments.unwrap_or(false)
+    }
+
+    pub fn hashed_static_files(&self) -> bool {
+        self.hashed_static_files.unwrap_or(false)
+    }
+
+    pub fn versioned_output(&self) -> bool {
+        self.versioned_output.unwrap_or(false)
+    }
+
+    pub fn transcode_images(&self) -> bool {
+        self.transcode_images.unwrap_or(false)
+    }
+
+    pub fn strip_exif(&self) -> bool {
+        self.strip_exif.unwrap_or(true)
+    }
+
+    pub fn max_image_dimension(&self) -> Option<usize> {
+        self.max_image_dimension.filter(|&max| max > 0)
+    }
+
+    pub fn thread_post_order(&self) -> ThreadPostOrder {
+        self.thread_post_order.unwrap_or_default()
+    }
+
+    pub fn title_only_posts(&self) -> TitleOnlyPosts {
+        self.title_only_posts.unwrap_or_default()
+    }
+
+    pub fn title_only_placeholder(&self) -> &str {
+        self.title_only_placeholder
+            .as_deref()
+            .unwrap_or("this post has no text.")
+    }
+
+    pub fn allow_plaintext_attachment_downloads(&self) -> bool {
+        self.allow_plaintext_attachment_downloads.unwrap_or(true)
+    }
+
+    pub fn attachment_filename_max_bytes(&self) -> usize {
+        self.attachment_filename_max_bytes.unwrap_or(200)
+    }
+
+    pub fn adjacent_threads_collection(&self) -> Option<&str> {
+        self.adjacent_threads_collection.as_deref()
+    }
+
+    /// the collection whose threads go in `search-index.json`.
+    pub fn search_index_collection(&self) -> &str {
+        self.search_index_collection.as_deref().unwrap_or("index")
+    }
+
+    pub fn series_pages(&self) -> bool {
+        self.series_pages.unwrap_or(false)
+    }
+
+    pub fn json_ld(&self) -> bool {
+        self.json_ld.unwrap_or(true)
+    }
+
+    pub fn fediverse_creator(&self) -> Option<&str> {
+        self.fediverse_creator.as_deref()
+    }
+
+    pub fn server_port(&self) -> u16 {
+        self.server_port.unwrap_or(8420)
+    }
+
+    /// returns the `<title>` for a page, or the title of a feed, joining `title` and `site_title`
+    /// with the `title_separator` in the `title_order`. pages without a title, like the main page or an
+    /// untitled post, get the site title alone.
+    pub fn page_title(&self, title: Option<&str>) -> String {
+        let separator = self.title_separator.as_deref().unwrap_or(" — ");
+        let title = title.map(str::trim).filter(|title| !title.is_empty());
+        match (title, self.site_title.as_str()) {
+            (None, site_title) => site_title.to_owned(),
+            (Some(title), "") => render_emoji(title, EmojiContext::Text),
+            (Some(title), site_title) => {
+                let title = render_emoji(title, EmojiContext::Text);
+                match self.title_order.unwrap_or_default() {
+                    TitleOrder::PageFirst => format!("{title}{separator}{site_title}"),
+                    TitleOrder::SiteFirst => format!("{site_title}{separator}{title}"),
+                }
+            }
+        }
+    }
+
+    pub fn is_main_self_author(&self, author: &Author) -> bool {
+        // compare href only, ignoring other fields
+        self.self_author
+            .as_ref()
+            .map_or(false, |a| a.href == author.href)
+    }
+
+    pub fn is_any_self_author(&self, author: &Author) -> bool {
+        // compare href only, ignoring other fields
+        self.is_main_self_author(author)
+            || self.other_self_authors.iter().any(|a| *a == author.href)
+            || self.self_project(Some(author)).is_some()
+    }
+
+    /// returns the `[[self_projects]]` entry for the author, if any.
+    pub fn self_project(&self, author: Option<&Author>) -> Option<&SelfProject> {
+        let author = author?;
+        self.self_projects
+            .iter()
+            .flatten()
+            .find(|project| project.href == author.href)
+    }
+
+    /// returns the author, with the display name from their `[[self_projects]]` entry, if any.
+    pub fn self_project_author(&self, author: &Author) -> Author {
+        let mut result = author.clone();
+        if let Some(display_name) = self
+            .self_project(Some(author))
+            .and_then(|project| project.display_name.as_ref())
+        {
+            result.display_name = display_name.clone();
+        }
+
+        result
+    }
+
+    /// returns true iff the tag, or any of its parents, is in `interesting_tags`. for example,
+    /// if `project` is interesting, then `project/autost` is also interesting.
+    pub fn tag_is_interesting(&self, tag: &str) -> bool {
+        let mut tag = Some(tag);
+        while let Some(current) = tag {
+            if self
+                .interesting_tags_iter()
+                .any(|interesting_tag| interesting_tag == current)
+            {
+                return true;
+            }
+            tag = tag_parent(current);
+        }
+
+        false
+    }
+
+    /// returns true iff the tag matches any of the `hidden_tags`, where `*` matches anything,
+    /// like `cw:*`. hidden tags are still on the posts, but are not shown or counted anywhere.
+    pub fn tag_is_hidden(&self, tag: &str) -> bool {
+        self.hidden_tags
+            .iter()
+            .flatten()
+            .any(|pattern| glob_matches(pattern, tag))
+    }
+
+    /// returns true iff the tag gets a tag page, that is, it’s interesting and not hidden.
+    pub fn tag_has_page(&self, tag: &str) -> bool {
+        self.tag_is_interesting(tag) && !self.tag_is_hidden(tag)
+    }
+
+    pub fn interesting_tags_iter(&self) -> impl Iterator<Item = &str> {
+        self.interesting_tags.iter().flatten().map(|tag| &**tag)
+    }
+
+    pub fn interesting_tag_groups_iter(&self) -> impl Iterator<Item = &[String]> {
+        self.interesting_tags.iter().map(|tag| &**tag)
+    }
+
+    /// returns true iff `autost import` may download attachments from the host, according to
+    /// `[imported_attachment_hosts]`. all hosts are allowed if that section is absent.
+    pub fn imported_attachment_host_is_allowed(&self, host: &str) -> bool {
+        self.imported_attachment_hosts
+            .as_ref()
+            .map_or(true, |hosts| hosts.allows(host))
+    }
+
+    pub fn thread_is_on_interesting_archived_list(&self, thread: &Thread) -> bool {
+        self.interesting_archived_threads_list
+            .as_ref()
+            .zip(thread.meta.archived.as_ref())
+            .is_some_and(|(list, archived)| list.iter().any(|x| x == archived))
+    }
+
+    pub fn thread_is_on_excluded_archived_list(&self, thread: &Thread) -> bool {
+        self.excluded_archived_threads_list
+            .as_ref()
+            .zip(thread.meta.archived.as_ref())
+            .is_some_and(|(list, archived)| list.iter().any(|x| x == archived))
+    }
+
+    /// returns true iff the post is in `excluded_post_ids` (by the number in its filename, like
+    /// chosts converted by cohost2autost) or `excluded_filenames`.
+    pub fn post_is_excluded(&self, path: &PostsPath) -> bool {
+        let filename = path.filename();
+        let id = path.post_id();
+        self.excluded_filenames
+            .iter()
+            .flatten()
+            .any(|x| x == filename)
+            || id.is_some_and(|id| self.excluded_post_ids.iter().flatten().any(|x| *x == id))
+    }
+
+    pub fn publish_liked_posts(&self) -> bool {
+        self.publish_liked_posts.unwrap_or(false)
+    }
+
+    /// returns the position of the post in `pinned_posts`, if it’s there.
+    pub fn pinned_post_position(&self, path: &PostsPath) -> Option<usize> {
+        let filename = path.filename();
+        self.pinned_posts
+            .iter()
+            .flatten()
+            .position(|x| x == filename)
+    }
+
+    /// returns the path of the post that excludes the thread, if any. this is the thread’s own
+    /// post, or with `exclude_threads_with_excluded_posts`, any post in the thread.
+    pub fn thread_excluded_by_post<'thread>(
+        &self,
+        thread: &'thread Thread,
+    ) -> Option<&'thread PostsPath> {
+        if self.exclude_threads_with_excluded_posts.unwrap_or(false) {
+            thread
+                .posts
+                .iter()
+                .flat_map(|post| post.path.as_ref())
+                .chain(thread.path.as_ref())
+                .find(|path| self.post_is_excluded(path))
+        } else {
+            thread
+                .path
+                .as_ref()
+                .filter(|path| self.post_is_excluded(path))
+        }
+    }
+
+    pub fn extra_archived_thread_tags(&self, post: &TemplatedPost) -> &[String] {
+        self.archived_thread_tags
+            .as_ref()
+            .zip(post.meta.archived.as_ref())
+            .and_then(|(tags, archived)| tags.get(archived))
+            .map(|result| &**result)
+            .unwrap_or(&[])
+    }
+
+    /// returns the description and banner image for the page of `tag`, if any (`[tag_pages]`).
+    pub fn tag_page(&self, tag: &str) -> Option<&TagPage> {
+        self.tag_pages.as_ref().and_then(|pages| pages.get(tag))
+    }
+
+    /// returns the alt text to use for a cohost image attachment instead of its `altText`, from
+    /// `alt_text_overrides` or `alt_text_overrides_path`.
+    pub fn alt_text_override(&self, attachment_id: &str) -> Option<&str> {
+        self.alt_text_overrides
+            .as_ref()
+            .and_then(|overrides| overrides.get(attachment_id))
+            .map(|alt| &**alt)
+    }
+
+    /// returns the content warnings for a post, from its `content_warning` and any tags that
+    /// start with the `content_warning_tag_prefix` (which is stripped).
+    pub fn content_warnings(&self, meta: &PostMeta) -> Vec<String> {
+        let prefix = self.content_warning_tag_prefix();
+        let tag_cws = meta
+            .tags
+            .iter()
+            .filter_map(|tag| tag.strip_prefix(prefix))
+            .map(|cw| cw.trim().to_owned())
+            .filter(|cw| !cw.is_empty());
+
+        meta.content_warning
+            .iter()
+            .cloned()
+            .chain(tag_cws)
+            .collect()
+    }
+
+    pub fn content_warning_tag_prefix(&self) -> &str {
+        self.content_warning_tag_prefix.as_deref().unwrap_or("cw:")
+    }
+
+    pub fn feed_content_warnings_only(&self) -> bool {
+        self.feed_content_warnings_only.unwrap_or(false)
+    }
+
+    pub fn feed_tag_links(&self) -> bool {
+        self.feed_tag_links.unwrap_or(false)
+    }
+
+    pub fn render_unknown_cohost_blocks(&self) -> bool {
+        self.render_unknown_cohost_blocks.unwrap_or(true)
+    }
+
+    /// adds the tags you bookmarked on cohost to `interesting_tags`, each in its own group, and
+    /// the chosts you bookmarked to the interesting archived threads list, unless they are
+    /// already there.
+    fn merge_cohost_bookmarks(&mut self, bookmarks: Bookmarks) {
+        let mut tags = 0;
+        for tag in bookmarks.bookmarkedTags {
+            let tag = normalise_tag(&tag);
+            if !self
+                .interesting_tags_iter()
+                .any(|interesting| interesting == tag)
+            {
+                self.interesting_tags.push(vec![tag]);
+                tags += 1;
+            }
+        }
+        let mut posts = 0;
+        if !bookmarks.bookmarkedPosts.is_empty() {
+            let list = self
+                .interesting_archived_threads_list
+                .get_or_insert_with(Vec::new);
+            for url in bookmarks.bookmarkedPosts {
+                if !list.contains(&url) {
+                    list.push(url);
+                    posts += 1;
+                }
+            }
+        }
+        debug!(
+            "added {tags} interesting tags and {posts} interesting chosts from cohost bookmarks"
+        );
+    }
+
+    /// normalises the tags in settings to nfc, like [`normalise_tag`], so they match the tags in
+    /// posts regardless of how either was written.
+    fn normalise_tags(&mut self) {
+        let normalise_all = |tags: &mut Vec<String>| {
+            for tag in tags.iter_mut() {
+                *tag = normalise_tag(tag);
+            }
+        };
+        self.interesting_tags.iter_mut().for_each(normalise_all);
+        self.hidden_tags.iter_mut().for_each(normalise_all);
+        for collection in self.collections.iter_mut().flatten() {
+            collection.tags.iter_mut().for_each(normalise_all);
+        }
+        for feed in self.tag_author_feeds.iter_mut().flatten() {
+            feed.tag = normalise_tag(&feed.tag);
+        }
+        if let Some(tags) = self.renamed_tags.take() {
+            let tags = tags
+                .into_iter()
+                .map(|(old, new)| (normalise_tag(&old), normalise_tag(&new)));
+            self.renamed_tags = Some(tags.collect());
+        }
+        if let Some(pages) = self.tag_pages.take() {
+            let pages = pages
+                .into_iter()
+                .map(|(tag, page)| (normalise_tag(&tag), page));
+            self.tag_pages = Some(pages.collect());
+        }
+        if let Some(tags) = self.implied_tags.take() {
+            let tags = tags.into_iter().map(|(tag, mut implied)| {
+                normalise_all(&mut implied);
+                (normalise_tag(&tag), implied)
+            });
+            self.implied_tags = Some(tags.collect());
+        }
+    }
+
+    /// returns the tags after renaming them (`renamed_tags`) and adding any tags they imply
+    /// (`implied_tags` and namespaces), with tags normalised to nfc and duplicates removed.
+    pub fn resolve_tags(&self, tags: Vec<String>) -> Vec<String> {
+        let mut seen = BTreeSet::default();
+        let mut result = tags
+            .iter()
+            .map(|tag| normalise_tag(tag))
+            .collect::<Vec<_>>();
+        let mut old_len = 0;
+
+        // loop until we fail to add any more tags.
+        while result.len() > old_len {
+            let old = result;
+            old_len = old.len();
+            result = vec![];
+            for tag in old {
+                let tag = self.renamed_tag(tag);
+                if seen.insert(tag.clone()) {
+                    // prepend implied tags, such that more general tags go first.
+                    // namespaced tags like `project/autost` imply their parent, like `project`.
+                    result.extend(tag_parent(&tag).map(ToOwned::to_owned));
+                    result.extend(self.implied_tags_shallow(&tag).to_vec());
+                }
+                result.push(tag);
+            }
+        }
+
+        let old = result;
+        let mut result = vec![];
+        for tag in old {
+            if !result.contains(&tag) {
+                result.push(tag);
+            }
+        }
+
+        result
+    }
+
+    /// returns each tag in `renamed_tags`, with the tag it resolves to (`resolve_tags`), sorted.
+    /// tags that resolve to themselves are skipped, and so are tags that only differ in case,
+    /// since their pages would overwrite each other on case-insensitive filesystems.
+    pub fn tag_aliases(&self) -> Vec<(String, String)> {
+        let mut result = self
+            .renamed_tags
+            .iter()
+            .flatten()
+            .filter_map(|(alias, _)| {
+                // the renamed tag goes last, after any tags it implies.
+                let tag = self.resolve_tags(vec![alias.clone()]).pop()?;
+                (tag.to_lowercase() != alias.to_lowercase()).then(|| (alias.clone(), tag))
+            })
+            .collect::<Vec<_>>();
+        result.sort();
+
+        result
+    }
+
+    fn renamed_tag(&self, tag: String) -> String {
+        if let Some(tags) = &self.renamed_tags {
+            if let Some(result) = tags.get(&tag) {
+                return result.clone();
+            }
+        }
+
+        tag
+    }
+
+    fn implied_tags_shallow(&self, tag: &str) -> &[String] {
+        if let Some(tags) = &self.implied_tags {
+            if let Some(result) = tags.get(tag) {
+                return result;
+            }
+        }
+
+        &[]
+    }
+
+    pub fn path_to_static(&self) -> Option<PathBuf> {
+        #[allow(deprecated)]
+        if let Some(path_to_autost) = self.path_to_autost.as_deref() {
+            return Some(Path::new(path_to_autost).join("static"));
+        }
+        if let Some(path_to_static) = self.path_to_static.as_deref() {
+            return Some(path_to_static.into());
+        }
+        None
+    }
+}
 
 #[test]
 fn test_example() -> eyre::Result<()> {
@@ -265,6 +1697,134 @@ fn test_example() -> eyre::Result<()> {
     Ok(())
 }
 
+#[test]
+fn test_overrides() -> eyre::Result<()> {
+    let vars = [
+        ("AUTOST_SITE_TITLE", "from env"),
+        ("AUTOST_FEED_MAX_ENTRIES", "20"),
+        ("AUTOST_SELF_AUTHOR__NAME", "staff"),
+        ("COHOST_COOKIE", "not a setting"),
+    ];
+    let mut overrides = env_overrides(
+        vars.into_iter()
+            .map(|(name, value)| (name.to_owned(), value.to_owned())),
+    );
+    assert_eq!(
+        overrides,
+        [
+            ("feed_max_entries".to_owned(), "20".to_owned()),
+            ("self_author.name".to_owned(), "staff".to_owned()),
+            ("site_title".to_owned(), "from env".to_owned()),
+        ]
+    );
+
+    // later overrides take precedence, so cli overrides win over the environment.
+    overrides.push(("site_title".to_owned(), "from cli".to_owned()));
+    overrides.push(("redirect_formats".to_owned(), r#"["json"]"#.to_owned()));
+    let settings = Settings::load_with_overrides("autost.toml.example", &overrides)?;
+    assert_eq!(settings.site_title, "from cli");
+    assert_eq!(settings.feed_max_entries(), 20);
+    assert_eq!(settings.redirect_formats(), [RedirectFormat::Json]);
+    let self_author = settings.self_author.as_ref().expect("set in example");
+    assert_eq!(self_author.name, "staff");
+    assert_eq!(self_author.display_name, "eggbug");
+
+    assert!(apply_override(&mut toml::Table::default(), "", "").is_err());
+    assert!(Settings::load_with_overrides(
+        "autost.toml.example",
+        &[("site_title".to_owned(), "[1]".to_owned())]
+    )
+    .is_err());
+
+    Ok(())
+}
+
+#[test]
+fn test_feed_max_entries() -> eyre::Result<()> {
+    let settings = Settings::load_with_overrides("autost.toml.example", &[])?;
+    assert_eq!(settings.feed_max_entries(), usize::MAX);
+    let settings = Settings::load_with_overrides(
+        "autost.toml.example",
+        &[("feed_max_items".to_owned(), "50".to_owned())],
+    )?;
+    assert_eq!(settings.feed_max_entries(), 50);
+    assert_eq!(settings.tag_feed_max_entries(), 50);
+    let settings = Settings::load_with_overrides(
+        "autost.toml.example",
+        &[
+            ("feed_max_entries".to_owned(), "0".to_owned()),
+            ("tag_feed_max_entries".to_owned(), "0".to_owned()),
+        ],
+    )?;
+    assert_eq!(settings.feed_max_entries(), usize::MAX);
+    assert_eq!(settings.tag_feed_max_entries(), usize::MAX);
+
+    Ok(())
+}
+
+#[test]
+fn test_page_title() -> eyre::Result<()> {
+    let mut settings = Settings::load_example()?;
+    settings.site_title = "ao!!".to_owned();
+    assert_eq!(settings.page_title(Some("my post")), "my post — ao!!");
+    assert_eq!(settings.page_title(None), "ao!!");
+    assert_eq!(settings.page_title(Some("")), "ao!!");
+    assert_eq!(settings.page_title(Some("  ")), "ao!!");
+
+    settings.title_separator = Some(" | ".to_owned());
+    settings.title_order = Some(TitleOrder::SiteFirst);
+    assert_eq!(
+        settings.page_title(Some("#photography")),
+        "ao!! | #photography"
+    );
+    assert_eq!(settings.page_title(None), "ao!!");
+
+    settings.site_title = "".to_owned();
+    assert_eq!(settings.page_title(Some("my post")), "my post");
+
+    Ok(())
+}
+
+#[test]
+fn test_alt_text_override() -> eyre::Result<()> {
+    let mut settings = Settings::load_example()?;
+    assert_eq!(
+        settings.alt_text_override("44444444-4444-4444-4444-444444444444"),
+        None
+    );
+
+    settings.alt_text_overrides = Some(HashMap::from([(
+        "44444444-4444-4444-4444-444444444444".to_owned(),
+        "eggbug, smiling".to_owned(),
+    )]));
+    assert_eq!(
+        settings.alt_text_override("44444444-4444-4444-4444-444444444444"),
+        Some("eggbug, smiling")
+    );
+
+    Ok(())
+}
+
+#[test]
+fn test_tag_page() -> eyre::Result<()> {
+    let mut page = TagPage::default();
+    assert_eq!(page.description_html(), None);
+    assert_eq!(page.description_text()?, None);
+
+    page.description = Some("photos *i* took\n<script>alert(1)</script>".to_owned());
+    let html = page.description_html().expect("description is set");
+    assert!(html.contains("<em>i</em>"));
+    assert!(!html.contains("script"));
+    assert_eq!(page.description_text()?.as_deref(), Some("photos i took"));
+
+    let mut settings = Settings::load_example()?;
+    settings.tag_pages = Some(HashMap::from([("cafe\u{301}".to_owned(), page)]));
+    settings.normalise_tags();
+    assert!(settings.tag_page("caf\u{e9}").is_some());
+
+    Ok(())
+}
+
 #[test]
 fn test_resolve_tags() -> eyre::Result<()> {
     let mut settings = Settings::load_example()?;
@@ -293,6 +1853,401 @@ fn test_resolve_tags() -> eyre::Result<()> {
         settings.resolve_tags(vec!["Foo".to_owned()]),
         ["bar", "deep tag", "foo", "baz"]
     );
+    // namespaced tags imply their parents, most general first.
+    assert_eq!(
+        settings.resolve_tags(vec!["project/autost/docs".to_owned()]),
+        ["project", "project/autost", "project/autost/docs"]
+    );
+    // tags are normalised to nfc, so different forms of the same tag are merged, and renamed.
+    settings.renamed_tags = Some([("caf\u{E9}".to_owned(), "coffee".to_owned())].into());
+    assert_eq!(
+        settings.resolve_tags(vec!["cafe\u{301}".to_owned(), "caf\u{E9}".to_owned()]),
+        ["coffee"]
+    );
+
+    Ok(())
+}
+
+#[test]
+fn test_tag_aliases() -> eyre::Result<()> {
+    let mut settings = Settings::load_example()?;
+    settings.renamed_tags = Some(
+        [
+            ("photos".to_owned(), "photography".to_owned()),
+            ("Laptop stickers".to_owned(), "laptop stickers".to_owned()),
+            ("same".to_owned(), "same".to_owned()),
+            ("bird pics".to_owned(), "bird photography".to_owned()),
+        ]
+        .into_iter()
+        .collect(),
+    );
+    settings.implied_tags = Some(
+        [(
+            "bird photography".to_owned(),
+            vec!["photography".to_owned()],
+        )]
+        .into_iter()
+        .collect(),
+    );
+    assert_eq!(
+        settings.tag_aliases(),
+        [
+            ("bird pics".to_owned(), "bird photography".to_owned()),
+            ("photos".to_owned(), "photography".to_owned()),
+        ]
+    );
+
+    Ok(())
+}
+
+#[test]
+fn test_tag_is_interesting() -> eyre::Result<()> {
+    let mut settings = Settings::load_example()?;
+    settings.interesting_tags = vec![vec!["project".to_owned()]];
+    assert!(settings.tag_is_interesting("project"));
+    assert!(settings.tag_is_interesting("project/autost"));
+    assert!(settings.tag_is_interesting("project/autost/docs"));
+    assert!(!settings.tag_is_interesting("projects"));
+    assert!(!settings.tag_is_interesting("other/project"));
+
+    settings.interesting_tags = vec![vec!["project/autost".to_owned()]];
+    assert!(!settings.tag_is_interesting("project"));
+    assert!(settings.tag_is_interesting("project/autost"));
+    assert!(!settings.tag_is_interesting("project/website"));
+
+    Ok(())
+}
+
+/// returns true iff the text matches the pattern, where `*` matches any number of characters.
+fn glob_matches(pattern: &str, text: &str) -> bool {
+    let mut parts = pattern.split('*').collect::<Vec<_>>();
+    let first = parts.remove(0);
+    let Some(mut rest) = text.strip_prefix(first) else {
+        return false;
+    };
+    let Some(last) = parts.pop() else {
+        return rest.is_empty();
+    };
+    for part in parts {
+        let Some(i) = rest.find(part) else {
+            return false;
+        };
+        rest = &rest[i + part.len()..];
+    }
+
+    rest.ends_with(last)
+}
+
+#[test]
+fn test_merge_cohost_bookmarks() -> eyre::Result<()> {
+    let mut settings = Settings::load_example()?;
+    settings.interesting_tags = vec![vec!["photography".to_owned()]];
+    settings.merge_cohost_bookmarks(serde_json::from_str(
+        r#"{
+            "bookmarkedTags": ["photography", "cafe\u0301"],
+            "bookmarkedPosts": ["https://cohost.org/project/post/123456-slug"]
+        }"#,
+    )?);
+    assert_eq!(
+        settings.interesting_tags,
+        [vec!["photography".to_owned()], vec!["caf\u{E9}".to_owned()]]
+    );
+    assert!(settings.tag_is_interesting("caf\u{E9}"));
+    assert_eq!(
+        settings.interesting_archived_threads_list,
+        Some(vec![
+            "https://cohost.org/project/post/123456-slug".to_owned()
+        ])
+    );
+
+    // both fields are optional.
+    settings.merge_cohost_bookmarks(serde_json::from_str("{}")?);
+
+    Ok(())
+}
+
+#[test]
+fn test_imported_attachment_host_is_allowed() -> eyre::Result<()> {
+    let mut settings = Settings::load_example()?;
+    assert!(settings.imported_attachment_host_is_allowed("example.com"));
+
+    settings.imported_attachment_hosts = Some(ImportedAttachmentHosts {
+        allow: vec!["example.com".to_owned(), "*.example.net".to_owned()],
+        block: vec!["tracker.example.net".to_owned()],
+    });
+    assert!(settings.imported_attachment_host_is_allowed("example.com"));
+    assert!(settings.imported_attachment_host_is_allowed("EXAMPLE.com"));
+    assert!(!settings.imported_attachment_host_is_allowed("www.example.com"));
+    assert!(settings.imported_attachment_host_is_allowed("cdn.example.net"));
+    assert!(settings.imported_attachment_host_is_allowed("a.cdn.example.net"));
+    assert!(!settings.imported_attachment_host_is_allowed("example.net"));
+    assert!(!settings.imported_attachment_host_is_allowed("badexample.net"));
+    assert!(!settings.imported_attachment_host_is_allowed("tracker.example.net"));
+
+    // an empty allow list allows everything not blocked.
+    settings.imported_attachment_hosts = Some(ImportedAttachmentHosts {
+        allow: vec![],
+        block: vec!["*.example.net".to_owned()],
+    });
+    assert!(settings.imported_attachment_host_is_allowed("example.org"));
+    assert!(!settings.imported_attachment_host_is_allowed("cdn.example.net"));
+
+    Ok(())
+}
+
+#[test]
+fn test_footer_extra_meta() -> eyre::Result<()> {
+    let mut settings = Settings::load_example()?;
+    let meta = PostMeta {
+        extra: BTreeMap::from([
+            ("location".to_owned(), "Perth".to_owned()),
+            ("app".to_owned(), "eggbug".to_owned()),
+            ("numLikes".to_owned(), "3".to_owned()),
+        ]),
+        ..Default::default()
+    };
+    assert!(settings.footer_extra_meta(&meta).is_empty());
+
+    settings.footer_extra_meta = Some(vec![
+        "location".to_owned(),
+        "missing".to_owned(),
+        "app".to_owned(),
+    ]);
+    assert_eq!(
+        settings.footer_extra_meta(&meta),
+        [("location", "Perth"), ("app", "eggbug")]
+    );
+
+    Ok(())
+}
+
+#[test]
+fn test_tag_is_hidden() -> eyre::Result<()> {
+    let mut settings = Settings::load_example()?;
+    assert!(!settings.tag_is_hidden("cw: spoilers"));
+
+    settings.hidden_tags = Some(vec![
+        "cw:*".to_owned(),
+        "internal".to_owned(),
+        "*/wip".to_owned(),
+        "a*b*c".to_owned(),
+    ]);
+    assert!(settings.tag_is_hidden("cw: spoilers"));
+    assert!(settings.tag_is_hidden("cw:"));
+    assert!(!settings.tag_is_hidden("not cw: spoilers"));
+    assert!(settings.tag_is_hidden("internal"));
+    assert!(!settings.tag_is_hidden("internals"));
+    assert!(settings.tag_is_hidden("project/wip"));
+    assert!(!settings.tag_is_hidden("project/wip/docs"));
+    assert!(settings.tag_is_hidden("abc"));
+    assert!(settings.tag_is_hidden("a-b-c"));
+    assert!(!settings.tag_is_hidden("a-c-b"));
+    assert!(!settings.tag_is_hidden("ab"));
+
+    settings.interesting_tags = vec![vec!["internal".to_owned(), "photography".to_owned()]];
+    assert!(!settings.tag_has_page("internal"));
+    assert!(settings.tag_has_page("photography"));
+
+    Ok(())
+}
+
+/// normalises a tag to unicode nfc, so tags that look the same, like “café” written with `é` or
+/// with `e` and a combining accent, are the same tag with the same tag page.
+pub fn normalise_tag(tag: &str) -> String {
+    tag.nfc().collect()
+}
+
+/// returns the parent of a namespaced tag, like `project` for `project/autost`, or None if the tag
+/// has no parent or is not a valid namespaced tag (like `/foo` or `foo//bar`).
+pub fn tag_parent(tag: &str) -> Option<&str> {
+    let (parent, child) = tag.rsplit_once('/')?;
+    let is_valid = |component: &str| !component.is_empty() && component != "." && component != "..";
+    if is_valid(child) && parent.split('/').all(is_valid) {
+        Some(parent)
+    } else {
+        None
+    }
+}
+
+#[test]
+fn test_tag_parent() {
+    assert_eq!(tag_parent("project/autost"), Some("project"));
+    assert_eq!(tag_parent("a/b/c"), Some("a/b"));
+    assert_eq!(tag_parent("project"), None);
+    assert_eq!(tag_parent("/project"), None);
+    assert_eq!(tag_parent("project/"), None);
+    assert_eq!(tag_parent("a//b"), None);
+    assert_eq!(tag_parent("../b"), None);
+}
+
+#[test]
+fn test_content_warnings() -> eyre::Result<()> {
+    let mut settings = Settings::load_example()?;
+    let mut meta = PostMeta::default();
+    meta.tags = vec!["cw: eye contact".to_owned(), "photography".to_owned()];
+    assert_eq!(settings.content_warnings(&meta), ["eye contact"]);
+
+    meta.content_warning = Some("food".to_owned());
+    assert_eq!(settings.content_warnings(&meta), ["food", "eye contact"]);
+
+    settings.content_warning_tag_prefix = Some("cw/".to_owned());
+    assert_eq!(settings.content_warnings(&meta), ["food"]);
+
+    Ok(())
+}
+
+/// returns `external_base_url` with its host (and port) replaced by `canonical_host`, which must
+/// be a url authority like `example.com` or `example.com:8443`, and the host it had before.
+fn with_canonical_host(
+    external_base_url: &str,
+    canonical_host: &str,
+) -> eyre::Result<(String, String)> {
+    let Ok(mut url) = Url::parse(external_base_url) else {
+        bail!("external_base_url is not an absolute url: {external_base_url:?}");
+    };
+    let original_host = match (url.host_str(), url.port()) {
+        (Some(host), Some(port)) => format!("{host}:{port}"),
+        (Some(host), None) => host.to_owned(),
+        (None, _) => bail!("external_base_url has no host: {external_base_url:?}"),
+    };
+    // parse with the same scheme, so default ports are handled the same way.
+    let authority = match Url::parse(&format!("{}://{canonical_host}/", url.scheme())) {
+        Ok(authority)
+            if authority.host_str().is_some()
+                && authority.username().is_empty()
+                && authority.password().is_none()
+                && authority.path() == "/"
+                && authority.query().is_none()
+                && authority.fragment().is_none() =>
+        {
+            authority
+        }
+        _ => bail!("canonical_host must be a host like \"example.com\": {canonical_host:?}"),
+    };
+    if url.set_host(authority.host_str()).is_err() || url.set_port(authority.port()).is_err() {
+        bail!("canonical_host can’t be used with external_base_url: {canonical_host:?}");
+    }
+
+    Ok((url.to_string(), original_host))
+}
+
+#[test]
+fn test_with_canonical_host() -> eyre::Result<()> {
+    assert_eq!(
+        with_canonical_host("https://www.example.com/blog/", "example.com")?,
+        (
+            "https://example.com/blog/".to_owned(),
+            "www.example.com".to_owned()
+        )
+    );
+    assert_eq!(
+        with_canonical_host("http://localhost:8420/", "example.com:8080")?.0,
+        "http://example.com:8080/"
+    );
+    assert_eq!(
+        with_canonical_host("https://example.com/", "Example.COM:443")?.0,
+        "https://example.com/"
+    );
+    assert!(with_canonical_host("https://example.com/", "example.com/blog").is_err());
+    assert!(with_canonical_host("https://example.com/", "user@example.com").is_err());
+    assert!(with_canonical_host("https://example.com/", "https://example.com").is_err());
+    assert!(with_canonical_host("https://example.com/", "").is_err());
+    assert!(with_canonical_host("https://example.com/", "example.com:99999").is_err());
+
+    Ok(())
+}
+
+/// returns `base_url` with exactly one leading slash and one trailing slash.
+fn normalise_base_url(base_url: &str) -> String {
+    let path = base_url.trim_matches('/');
+    if path.is_empty() {
+        "/".to_owned()
+    } else {
+        format!("/{path}/")
+    }
+}
+
+#[test]
+fn test_normalise_base_url() {
+    assert_eq!(normalise_base_url("/"), "/");
+    assert_eq!(normalise_base_url(""), "/");
+    assert_eq!(normalise_base_url("//"), "/");
+    assert_eq!(normalise_base_url("/blog/"), "/blog/");
+    assert_eq!(normalise_base_url("blog"), "/blog/");
+    assert_eq!(normalise_base_url("//blog//"), "/blog/");
+    assert_eq!(normalise_base_url("/blog/posts"), "/blog/posts/");
+}
+
+#[test]
+fn test_base_url_relativise() -> eyre::Result<()> {
+    let mut settings = Settings::load_example()?;
+    settings.base_url = "/blog/".to_owned();
+    settings.external_base_url = "https://example.com/blog/".to_owned();
+    assert_eq!(
+        settings.base_url_relativise("tagged/foo.html"),
+        "/blog/tagged/foo.html"
+    );
+    assert_eq!(
+        settings.base_url_relativise("/tagged/foo.html"),
+        "/blog/tagged/foo.html"
+    );
+    assert_eq!(
+        settings.base_url_relativise("/blog/tagged/foo.html"),
+        "/blog/tagged/foo.html"
+    );
+    assert_eq!(settings.base_url_relativise("#content"), "#content");
+    assert_eq!(
+        settings.base_url_relativise("//example.net/"),
+        "//example.net/"
+    );
+    assert_eq!(
+        settings.base_url_relativise("https://example.net/"),
+        "https://example.net/"
+    );
+    assert_eq!(
+        settings.external_base_url_absolutise("attachments/a.png"),
+        "https://example.com/blog/attachments/a.png"
+    );
+    assert_eq!(
+        settings.external_base_url_absolutise("https://example.net/a.png"),
+        "https://example.net/a.png"
+    );
+    assert_eq!(
+        settings.attachments_base_url_absolutise("attachments/a.png"),
+        "/blog/attachments/a.png"
+    );
+
+    settings.attachments_base_url = Some("https://cdn.example.com/".to_owned());
+    assert_eq!(
+        settings.attachments_base_url_absolutise("attachments/a.png"),
+        "https://cdn.example.com/a.png"
+    );
+    assert_eq!(
+        settings.attachments_base_url_absolutise("/blog/attachments/emoji/eggbug.png"),
+        "https://cdn.example.com/emoji/eggbug.png"
+    );
+    assert_eq!(
+        settings.attachments_base_url_absolutise("tagged/attachments/foo.html"),
+        "/blog/tagged/attachments/foo.html"
+    );
+    assert_eq!(
+        settings.external_base_url_absolutise("attachments/a.png"),
+        "https://cdn.example.com/a.png"
+    );
+
+    Ok(())
+}
+
+#[test]
+fn test_post_is_excluded() -> eyre::Result<()> {
+    let mut settings = Settings::load_example()?;
+    settings.excluded_post_ids = Some(vec![123]);
+    settings.excluded_filenames = Some(vec!["hello.md".to_owned()]);
+    assert!(settings.post_is_excluded(&PostsPath::generated_post_path(123)));
+    assert!(settings.post_is_excluded(&PostsPath::references_post_path(456, 123)));
+    assert!(!settings.post_is_excluded(&PostsPath::generated_post_path(1234)));
+    assert!(settings.post_is_excluded(&PostsPath::ROOT.join("hello.md")?));
+    assert!(!settings.post_is_excluded(&PostsPath::ROOT.join("hello.html")?));
 
     Ok(())
 }
@@ -313,3 +2268,103 @@ fn test_base_url_path_components() -> eyre::Result<()> {
 
     Ok(())
 }
+
+#[test]
+fn test_collapse_transparent_share() -> eyre::Result<()> {
+    let mut settings = Settings::load_example()?;
+    let shared = TemplatedPost::filter("<p>original</p>", None)?;
+    let share = TemplatedPost::filter(r#"<meta name="is_transparent_share">"#, None)?;
+    let mut thread = Thread::try_from(share.clone())?;
+    thread.posts = vec![shared.clone(), share];
+    let mut not_share = Thread::try_from(shared.clone())?;
+    not_share.posts = vec![shared.clone(), shared];
+    assert!(thread.is_transparent_share());
+    assert!(!not_share.is_transparent_share());
+
+    assert!(!settings.collapse_transparent_share(&thread));
+    settings.transparent_shares = Some(TransparentShares::Collapse);
+    assert!(settings.collapse_transparent_share(&thread));
+    assert!(!settings.collapse_transparent_share(&not_share));
+
+    // a transparent share with nothing to show is left alone.
+    thread.posts.remove(0);
+    assert!(!settings.collapse_transparent_share(&thread));
+
+    Ok(())
+}
+
+#[test]
+fn test_content_security_policy() -> eyre::Result<()> {
+    let mut settings = Settings::load_example()?;
+    assert_eq!(settings.content_security_policy(&[], &[]), None);
+
+    settings.content_security_policy = Some(ContentSecurityPolicy {
+        img_src: Some(vec!["https://img.example".to_owned()]),
+        ..Default::default()
+    });
+    settings.attachments_base_url = Some("https://cdn.example.com/".to_owned());
+    let policy = settings
+        .content_security_policy(&["'sha256-abc'".to_owned()], &["'sha256-def'".to_owned()])
+        .ok_or_eyre("policy should be set")?;
+    assert!(policy.starts_with("default-src 'self'; "));
+    assert!(
+        policy.contains("; img-src 'self' data: https://cdn.example.com/ https://img.example; ")
+    );
+    assert!(policy.contains("; media-src 'self' https://cdn.example.com/; "));
+    assert!(policy.contains("; script-src 'self' 'sha256-abc'; "));
+    assert!(policy.contains("; style-src 'self' 'unsafe-inline'; "));
+    assert!(policy.contains("; style-src-elem 'self' 'sha256-def'; "));
+    assert!(policy.ends_with("; object-src 'none'; base-uri 'none'"));
+
+    Ok(())
+}
+
+#[test]
+fn test_self_projects() -> eyre::Result<()> {
+    let mut settings = Settings::load_example()?;
+    let author = Author {
+        href: "https://cohost.org/eggbug2".to_owned(),
+        name: "eggbug2".to_owned(),
+        display_name: "eggbug".to_owned(),
+        display_handle: "@eggbug2".to_owned(),
+    };
+    assert!(!settings.is_any_self_author(&author));
+    assert_eq!(settings.self_project_author(&author), author);
+
+    settings.self_projects = Some(vec![SelfProject {
+        href: author.href.clone(),
+        display_name: Some("eggbug²".to_owned()),
+        avatar: None,
+        color: Some("#83254f".to_owned()),
+    }]);
+    assert!(settings.is_any_self_author(&author));
+    assert!(settings.self_project(None).is_none());
+    assert_eq!(
+        settings.self_project_author(&author).display_name,
+        "eggbug²"
+    );
+    assert_eq!(settings.self_project_author(&author).href, author.href);
+
+    Ok(())
+}
+
+#[test]
+fn test_favicon() -> eyre::Result<()> {
+    let favicon: Favicon = toml::from_str(
+        r##"icon = "static/Favicon.SVG"
+apple_touch_icon = "missing.png"
+theme_color = "#83254f""##,
+    )?;
+    let icons = favicon.icons();
+    assert_eq!(icons.len(), 2);
+    assert_eq!(icons[0].filename, "favicon.svg");
+    assert_eq!(icons[0].rel, "icon");
+    assert_eq!(icons[0].media_type, "image/svg+xml");
+    assert_eq!(icons[0].sizes.as_deref(), Some("any"));
+    assert_eq!(icons[1].filename, "apple-touch-icon.png");
+    assert_eq!(icons[1].rel, "apple-touch-icon");
+    assert_eq!(icons[1].sizes, None);
+    assert_eq!(Favicon::media_type("favicon.gif"), None);
+
+    Ok(())
+}
@@ -0,0 +1,146 @@
+//! hooks for post-processing the html of each thread, for sites that use autost as a library.
+//!
+//! transforms are registered with [`register_html_transform`] before rendering, like in your own
+//! `main` before calling [`crate::command::render::main`]. each thread’s html is passed through
+//! every transform, in the order they were registered, with each transform getting the output of
+//! the one before it. this happens after post content is sanitised and the thread is templated,
+//! but before relative urls are fixed, so transforms see urls like `attachments/...` relative to
+//! the site root, and any html they add is trusted as is.
+//!
+//! transforms run on every version of a thread: post pages, print pages, listing pages (where the
+//! thread may be truncated with “read more”), atom feeds, and the compose preview. they may run
+//! more than once for the same thread, and on many threads in parallel, so they should be pure.
+
+use std::{rc::Rc, sync::RwLock};
+
+use html5ever::Attribute;
+use jane_eyre::eyre;
+use markup5ever_rcdom::{Handle, NodeData};
+
+use crate::dom::{
+    create_element, parse_html_fragment, serialize_html_fragment, AttrsRefExt, DepthTraverse,
+    HandleExt, QualName, QualNameExt,
+};
+
+static HTML_TRANSFORMS: RwLock<Vec<Box<dyn HtmlTransform>>> = RwLock::new(vec![]);
+
+/// a transform for the html of each thread. implemented for any `Fn(&str) -> String`.
+pub trait HtmlTransform: Send + Sync {
+    fn transform(&self, html: &str) -> eyre::Result<String>;
+}
+
+impl<F: Fn(&str) -> String + Send + Sync> HtmlTransform for F {
+    fn transform(&self, html: &str) -> eyre::Result<String> {
+        Ok(self(html))
+    }
+}
+
+/// adds a transform to run after any transforms already registered.
+pub fn register_html_transform(transform: impl HtmlTransform + 'static) {
+    HTML_TRANSFORMS.write().unwrap().push(Box::new(transform));
+}
+
+/// runs the registered transforms on the html, in the order they were registered.
+pub fn apply_html_transforms(html: String) -> eyre::Result<String> {
+    let transforms = HTML_TRANSFORMS.read().unwrap();
+    let mut result = html;
+    for transform in transforms.iter() {
+        result = transform.transform(&result)?;
+    }
+
+    Ok(result)
+}
+
+/// wraps each `<table>` in a `<div class="table-wrapper">`, so wide tables scroll sideways
+/// instead of making the whole page wider.
+pub struct WrapTables;
+
+impl HtmlTransform for WrapTables {
+    fn transform(&self, html: &str) -> eyre::Result<String> {
+        let mut dom = parse_html_fragment(html.as_bytes())?;
+        let tables = DepthTraverse::elements(dom.document.clone())
+            .filter(|node| is_element(node, "table"))
+            .collect::<Vec<_>>();
+        for table in tables {
+            let Some(parent) = table.parent.take().and_then(|parent| parent.upgrade()) else {
+                continue;
+            };
+            let is_wrapped = is_element(&parent, "div")
+                && parent.attrs().is_some_and(|attrs| {
+                    attrs.attr_str("class").ok() == Some(Some("table-wrapper"))
+                });
+            if is_wrapped {
+                table.parent.set(Some(Rc::downgrade(&parent)));
+                continue;
+            }
+            let wrapper = create_element(&mut dom, "div");
+            wrapper
+                .attrs()
+                .expect("guaranteed by create_element")
+                .push(Attribute {
+                    name: QualName::attribute("class"),
+                    value: "table-wrapper".into(),
+                });
+            for kid in parent.children.borrow_mut().iter_mut() {
+                if Rc::ptr_eq(kid, &table) {
+                    *kid = wrapper.clone();
+                }
+            }
+            wrapper.parent.set(Some(Rc::downgrade(&parent)));
+            table.parent.set(Some(Rc::downgrade(&wrapper)));
+            wrapper.children.borrow_mut().push(table);
+        }
+
+        serialize_html_fragment(dom)
+    }
+}
+
+/// adds `loading="lazy"` to each `<img>` without a `loading` attribute, so images further down
+/// long pages are only loaded when scrolled to.
+pub struct LazyLoadImages;
+
+impl HtmlTransform for LazyLoadImages {
+    fn transform(&self, html: &str) -> eyre::Result<String> {
+        let dom = parse_html_fragment(html.as_bytes())?;
+        for img in
+            DepthTraverse::elements(dom.document.clone()).filter(|node| is_element(node, "img"))
+        {
+            let mut attrs = img.attrs().expect("guaranteed by is_element");
+            if attrs.attr_str("loading")?.is_none() {
+                attrs.push(Attribute {
+                    name: QualName::attribute("loading"),
+                    value: "lazy".into(),
+                });
+            }
+        }
+
+        serialize_html_fragment(dom)
+    }
+}
+
+fn is_element(node: &Handle, html_local_name: &str) -> bool {
+    matches!(&node.data, NodeData::Element { name, .. } if name == &QualName::html(html_local_name))
+}
+
+#[test]
+fn test_wrap_tables() -> eyre::Result<()> {
+    assert_eq!(
+        WrapTables.transform("<p>a</p><table><tr><td>b</td></tr></table>")?,
+        r#"<p>a</p><div class="table-wrapper"><table><tbody><tr><td>b</td></tr></tbody></table></div>"#
+    );
+    let wrapped =
+        r#"<div class="table-wrapper"><table><tbody><tr><td>b</td></tr></tbody></table></div>"#;
+    assert_eq!(WrapTables.transform(wrapped)?, wrapped);
+
+    Ok(())
+}
+
+#[test]
+fn test_lazy_load_images() -> eyre::Result<()> {
+    assert_eq!(
+        LazyLoadImages.transform(r#"<img src="a.png"><img src="b.png" loading="eager">"#)?,
+        r#"<img src="a.png" loading="lazy"><img src="b.png" loading="eager">"#
+    );
+
+    Ok(())
+}
@@ -71,8 +71,38 @@ if (compose) {
     });
 }
 
+addThemeToggle();
 checkAutostServer();
 
+function addThemeToggle() {
+    const navUl = document.querySelector("nav > ul");
+    if (!navUl) return;
+
+    // the theme is `<html data-theme>` if chosen (see theme.html), otherwise the system setting.
+    const root = document.documentElement;
+    const systemDark = matchMedia("(prefers-color-scheme: dark)");
+    const isDark = () => (root.dataset.theme ?? (systemDark.matches ? "dark" : "light")) == "dark";
+
+    const li = document.createElement("li");
+    const button = document.createElement("button");
+    button.type = "button";
+    button.className = "theme";
+    button.textContent = "dark mode";
+    const update = () => button.setAttribute("aria-pressed", isDark());
+    button.addEventListener("click", () => {
+        const theme = isDark() ? "light" : "dark";
+        root.dataset.theme = theme;
+        try {
+            localStorage.setItem("autost-theme", theme);
+        } catch {}
+        update();
+    });
+    systemDark.addEventListener("change", update);
+    update();
+    li.append(button);
+    navUl.append(li);
+}
+
 async function checkAutostServer() {
     // if /compose exists, we are using the autost server.
     const composeUrl = `${document.body.dataset.baseUrl}compose`;
@@ -0,0 +1,17 @@
+// opens any <details> around the element that the url fragment points to, so links into
+// collapsed posts or collapsed parts of posts still work.
+function openDetailsForFragment() {
+    if (location.hash.length < 2) return;
+    const id = decodeURIComponent(location.hash.slice(1));
+    const target = document.getElementById(id) ?? document.getElementById(`user-content-${id}`);
+    if (!target) return;
+    let details = target.closest("details");
+    if (!details) return;
+    while (details) {
+        details.open = true;
+        details = details.parentElement?.closest("details");
+    }
+    target.scrollIntoView();
+}
+addEventListener("hashchange", openDetailsForFragment);
+openDetailsForFragment();
@@ -0,0 +1,56 @@
+//! helpers shared by the integration tests.
+
+use std::{
+    fs::remove_dir_all,
+    ops::Deref,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+use jane_eyre::eyre;
+
+/// runs the autost binary in `dir`, failing if it exits unsuccessfully.
+pub fn autost(dir: &Path, args: &[&str]) -> eyre::Result<()> {
+    let status = Command::new(env!("CARGO_BIN_EXE_autost"))
+        .args(args)
+        .current_dir(dir)
+        .status()?;
+    eyre::ensure!(status.success(), "autost {args:?} failed: {status}");
+
+    Ok(())
+}
+
+/// a new site made by `autost new` in a temporary directory, which is deleted on drop, even if
+/// the test fails.
+pub struct TempSite {
+    dir: PathBuf,
+}
+
+impl TempSite {
+    pub fn new(name: &str) -> eyre::Result<Self> {
+        let dir = std::env::temp_dir().join(format!("autost-{name}-{}", uuid::Uuid::new_v4()));
+        // create it first, so the directory is deleted even if `autost new` fails partway.
+        let result = Self { dir };
+        autost(
+            Path::new("."),
+            &["new", result.dir.to_str().expect("temp dir is utf-8")],
+        )?;
+
+        Ok(result)
+    }
+}
+
+impl Deref for TempSite {
+    type Target = Path;
+
+    fn deref(&self) -> &Path {
+        &self.dir
+    }
+}
+
+impl Drop for TempSite {
+    fn drop(&mut self) {
+        // the directory may not exist if `autost new` failed.
+        _ = remove_dir_all(&self.dir);
+    }
+}
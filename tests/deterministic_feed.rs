@@ -0,0 +1,54 @@
+//! renders a site twice without changing anything, and checks that the feeds are the same.
+
+mod common;
+
+use std::fs::{read_to_string, write};
+
+use jane_eyre::eyre;
+
+use crate::common::{autost, TempSite};
+
+#[test]
+fn test_render_twice_same_feed() -> eyre::Result<()> {
+    let dir = TempSite::new("same-feed")?;
+    write(
+        dir.join("posts/2.md"),
+        r#"<meta name="title" content="edited">
+<meta name="published" content="2024-01-01T00:00Z">
+<meta name="updated" content="2030-01-02T03:04Z">
+<link rel="author" href="https://example.com" name="eggbug">
+
+this post was edited.
+"#,
+    )?;
+    autost(&dir, &["render"])?;
+    let first = read_to_string(dir.join("site/index.feed.xml"))?;
+    let first_json = read_to_string(dir.join("site/index.feed.json"))?;
+    autost(&dir, &["render"])?;
+    let second = read_to_string(dir.join("site/index.feed.xml"))?;
+    let second_json = read_to_string(dir.join("site/index.feed.json"))?;
+    assert_eq!(first, second);
+    assert_eq!(first_json, second_json);
+
+    // the feed was last updated when its newest entry was.
+    let (head, _) = first.split_once("<entry>").expect("feed has entries");
+    assert!(
+        head.contains("<updated>2030-01-02T03:04:00.000Z</updated>"),
+        "{head}"
+    );
+
+    // the json feed has the same threads, with absolute urls.
+    let json: serde_json::Value = serde_json::from_str(&first_json)?;
+    assert_eq!(json["version"], "https://jsonfeed.org/version/1.1");
+    assert_eq!(json["feed_url"], "https://example.com/index.feed.json");
+    let edited = json["items"]
+        .as_array()
+        .expect("items is an array")
+        .iter()
+        .find(|item| item["title"] == "edited")
+        .expect("feed has the edited post");
+    assert_eq!(edited["date_published"], "2024-01-01T00:00:00Z");
+    assert_eq!(edited["date_modified"], "2030-01-02T03:04:00Z");
+
+    Ok(())
+}
@@ -0,0 +1,32 @@
+//! renders a site with no posts, like a new site after deleting the sample post.
+
+mod common;
+
+use std::fs::{read_dir, read_to_string, remove_file};
+
+use autost::dom::parse_xml;
+use jane_eyre::eyre;
+
+use crate::common::{autost, TempSite};
+
+#[test]
+fn test_render_empty_site() -> eyre::Result<()> {
+    let dir = TempSite::new("empty-site")?;
+    for entry in read_dir(dir.join("posts"))? {
+        remove_file(entry?.path())?;
+    }
+    autost(&dir, &["render"])?;
+
+    let index = read_to_string(dir.join("site/index.html"))?;
+    assert!(index.contains("no posts yet"));
+
+    let feed = read_to_string(dir.join("site/index.feed.xml"))?;
+    let dom = parse_xml(feed.as_bytes())?;
+    assert!(dom.errors.is_empty(), "{:?}", dom.errors);
+    for element in ["<id>", "<title>", "<updated>"] {
+        assert!(feed.contains(element), "feed has no {element}");
+    }
+    assert!(!feed.contains("<entry>"));
+
+    Ok(())
+}
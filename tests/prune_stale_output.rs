@@ -0,0 +1,39 @@
+//! renders a site, deletes a post, and checks that `--prune` deletes its page but not the files
+//! that were put in the site by hand.
+
+mod common;
+
+use std::fs::{exists, read_to_string, remove_file, write};
+
+use jane_eyre::eyre;
+
+use crate::common::{autost, TempSite};
+
+#[test]
+fn test_render_prune_keeps_user_files() -> eyre::Result<()> {
+    let dir = TempSite::new("prune")?;
+    write(
+        dir.join("posts/2.md"),
+        "<meta name=\"published\" content=\"2024-01-01T00:00Z\">\n\na post to delete.\n",
+    )?;
+    autost(&dir, &["render"])?;
+    let site = dir.join("site");
+    write(site.join("about.html"), "<!doctype html>about me")?;
+    write(site.join("google0123456789abcdef.html"), "verification")?;
+
+    remove_file(dir.join("posts/2.md"))?;
+    autost(&dir, &["render", "--prune"])?;
+    assert!(!exists(site.join("2.html"))?);
+    assert!(exists(site.join("10000000.html"))?);
+    assert_eq!(
+        read_to_string(site.join("about.html"))?,
+        "<!doctype html>about me"
+    );
+    assert!(exists(site.join("google0123456789abcdef.html"))?);
+
+    // pruning again keeps them too.
+    autost(&dir, &["render", "--prune"])?;
+    assert!(exists(site.join("about.html"))?);
+
+    Ok(())
+}
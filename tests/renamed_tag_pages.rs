@@ -0,0 +1,51 @@
+//! renders a site with a renamed tag, and checks that the old tag page and feeds still work.
+
+mod common;
+
+use std::fs::{exists, read_to_string, write};
+
+use jane_eyre::eyre;
+
+use crate::common::{autost, TempSite};
+
+#[test]
+fn test_render_renamed_tag_pages() -> eyre::Result<()> {
+    let dir = TempSite::new("renamed-tags")?;
+    let settings = read_to_string(dir.join("autost.toml"))?.replace(
+        "[renamed_tags]\n",
+        "[renamed_tags]\n\"photos\" = \"photography\"\n\"same\" = \"same\"\n",
+    );
+    // one post per page, so the tag has a second page.
+    write(
+        dir.join("autost.toml"),
+        format!("page_size = 1\n{settings}"),
+    )?;
+    write(
+        dir.join("posts/2.md"),
+        "<meta name=\"published\" content=\"2024-01-01T00:00Z\">\n<meta name=\"tags\" content=\"photos\">\n\na photo.\n",
+    )?;
+    write(
+        dir.join("posts/3.md"),
+        "<meta name=\"published\" content=\"2024-01-02T00:00Z\">\n<meta name=\"tags\" content=\"photos\">\n\nanother photo.\n",
+    )?;
+    autost(&dir, &["render"])?;
+
+    // the old tag page redirects to the new one, and the old feeds are copies of the new ones.
+    let site = dir.join("site");
+    let redirect = read_to_string(site.join("tagged/photos.html"))?;
+    assert!(redirect.contains("tagged/photography.html"), "{redirect}");
+    let redirect = read_to_string(site.join("tagged/photos.2.html"))?;
+    assert!(redirect.contains("tagged/photography.2.html"), "{redirect}");
+    assert!(!exists(site.join("tagged/photos.3.html"))?);
+    assert_eq!(
+        read_to_string(site.join("tagged/photos.feed.xml"))?,
+        read_to_string(site.join("tagged/photography.feed.xml"))?
+    );
+    assert_eq!(
+        read_to_string(site.join("tagged/photos.feed.json"))?,
+        read_to_string(site.join("tagged/photography.feed.json"))?
+    );
+    assert!(!exists(site.join("tagged/same.html"))?);
+
+    Ok(())
+}
@@ -0,0 +1,77 @@
+//! renders a site twice, and checks that post pages are only written again if they changed.
+
+mod common;
+
+use std::{
+    fs::{read_to_string, write, OpenOptions},
+    io::Write,
+};
+
+use jane_eyre::eyre;
+
+use crate::common::{autost, TempSite};
+
+#[test]
+fn test_render_skips_unchanged_pages() -> eyre::Result<()> {
+    let dir = TempSite::new("skip-pages")?;
+    write(
+        dir.join("posts/2.md"),
+        "<meta name=\"published\" content=\"2024-01-01T00:00Z\">\n\nan old post.\n",
+    )?;
+    autost(&dir, &["render"])?;
+
+    // mark the pages, so we can tell if they were written again.
+    let page = dir.join("site/2.html");
+    let other_page = dir.join("site/10000000.html");
+    for path in [&page, &other_page] {
+        writeln!(
+            OpenOptions::new().append(true).open(path)?,
+            "<!-- marker -->"
+        )?;
+    }
+    autost(&dir, &["render"])?;
+    assert!(read_to_string(&page)?.contains("<!-- marker -->"));
+
+    // editing a post writes its page again, but not the others.
+    write(
+        dir.join("posts/2.md"),
+        "<meta name=\"published\" content=\"2024-01-01T00:00Z\">\n\nan edited post.\n",
+    )?;
+    autost(&dir, &["render"])?;
+    assert!(!read_to_string(&page)?.contains("<!-- marker -->"));
+    assert!(read_to_string(&page)?.contains("an edited post."));
+    assert!(read_to_string(&other_page)?.contains("<!-- marker -->"));
+
+    autost(&dir, &["render", "--force"])?;
+    assert!(!read_to_string(&other_page)?.contains("<!-- marker -->"));
+
+    Ok(())
+}
+
+#[test]
+fn test_render_versioned_output_writes_every_page() -> eyre::Result<()> {
+    let dir = TempSite::new("skip-versioned")?;
+    let settings = read_to_string(dir.join("autost.toml"))?;
+    write(
+        dir.join("autost.toml"),
+        format!("versioned_output = true\n{settings}"),
+    )?;
+    write(
+        dir.join("posts/2.md"),
+        "<meta name=\"published\" content=\"2024-01-01T00:00Z\">\n\nan old post.\n",
+    )?;
+    autost(&dir, &["render"])?;
+    write(
+        dir.join("posts/3.md"),
+        "<meta name=\"published\" content=\"2024-01-02T00:00Z\">\n\na new post.\n",
+    )?;
+    autost(&dir, &["render"])?;
+
+    // the top-level page is a redirect, but the new build has the page itself, not a copy of
+    // the redirect from the last build.
+    let current = read_to_string(dir.join("site/v/current"))?;
+    let page = read_to_string(dir.join(format!("site/v/{}/2.html", current.trim())))?;
+    assert!(page.contains("an old post."), "{page}");
+
+    Ok(())
+}